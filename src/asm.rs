@@ -0,0 +1,393 @@
+use crate::chip8::MEMORY_START;
+use std::{collections::HashMap, fmt};
+
+/*
+Assembler
+The inverse of the disassembler: turns a line written with the mnemonics of Cowgod's Chip-8
+technical reference into an opcode, e.g. "LD VA, 0x05" -> 0x6A05.
+- Mnemonics and registers are case insensitive
+- Numbers are decimal or hexadecimal with a 0x prefix
+- Anything after a ';' is a comment
+- "DW 0x1234" emits a raw data word
+Everything printed by disasm::disassemble assembles back to the same opcode.
+
+Whole programs are assembled with assemble_program, one instruction per line, loaded at 0x200.
+They can also use:
+- Labels, "name:" on its own line or before an instruction, given as operands instead of
+  addresses, e.g. "JP loop". Labels are case sensitive and can't be a register or operand name
+- "DB 0x3C, 0x42" to emit bytes, e.g. for sprites
+*/
+#[derive(Debug, PartialEq, Eq)]
+pub enum AsmError {
+    Empty,
+    UnknownInstruction(String),
+    InvalidOperands(String),
+    InvalidNumber(String),
+    OutOfRange(String),
+    InvalidLabel(String),
+    DuplicateLabel(String),
+    UnknownLabel(String),
+}
+
+impl fmt::Display for AsmError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AsmError::Empty => write!(f, "No instruction"),
+            AsmError::UnknownInstruction(name) => write!(f, "Unknown instruction: {}", name),
+            AsmError::InvalidOperands(line) => write!(f, "Invalid operands: {}", line),
+            AsmError::InvalidNumber(value) => write!(f, "Invalid number: {}", value),
+            AsmError::OutOfRange(value) => write!(f, "Value out of range: {}", value),
+            AsmError::InvalidLabel(label) => write!(f, "Invalid label: {}", label),
+            AsmError::DuplicateLabel(label) => write!(f, "Label defined twice: {}", label),
+            AsmError::UnknownLabel(label) => write!(f, "Unknown label: {}", label),
+        }
+    }
+}
+
+impl std::error::Error for AsmError {}
+
+// An error in a program, with the line it was found on, counted from 1
+#[derive(Debug, PartialEq, Eq)]
+pub struct ProgramError {
+    pub line: usize,
+    pub error: AsmError,
+}
+
+impl fmt::Display for ProgramError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Line {}: {}", self.line, self.error)
+    }
+}
+
+impl std::error::Error for ProgramError {}
+
+// An assembled program and the addresses of its labels
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Program {
+    pub bytes: Vec<u8>,
+    pub labels: HashMap<String, u16>,
+}
+
+// An operand, classified so the instructions can be matched on their shape
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Operand {
+    V(u16),
+    Number(u16),
+    I,
+    IndirectI,
+    Dt,
+    St,
+    K,
+    F,
+    B,
+    // The RPL user flags of SCHIP
+    R,
+}
+
+pub fn assemble(line: &str) -> Result<u16, AsmError> {
+    assemble_line(line, &HashMap::new())
+}
+
+pub fn assemble_program(source: &str) -> Result<Program, ProgramError> {
+    // First pass for the addresses of the labels, instructions take 2 bytes and DB one per value
+    let mut labels = HashMap::new();
+    let mut statements = Vec::new();
+    let mut addr = MEMORY_START;
+    for (index, line) in source.lines().enumerate() {
+        let error = |error| ProgramError {
+            line: index + 1,
+            error,
+        };
+        let mut text = line.split(';').next().unwrap_or("").trim();
+        if let Some((label, rest)) = text.split_once(':') {
+            let label = label.trim();
+            if !is_label(label) {
+                return Err(error(AsmError::InvalidLabel(label.to_string())));
+            }
+            if labels.insert(label.to_string(), addr as u16).is_some() {
+                return Err(error(AsmError::DuplicateLabel(label.to_string())));
+            }
+            text = rest.trim();
+        }
+        if text.is_empty() {
+            continue;
+        }
+        addr += match data_bytes(text) {
+            Some(values) => values.split(',').count(),
+            None => 2,
+        };
+        statements.push((index + 1, text));
+    }
+
+    let mut bytes = Vec::new();
+    for (line, text) in statements {
+        let error = |error| ProgramError { line, error };
+        match data_bytes(text) {
+            Some(values) => {
+                for value in values.split(',') {
+                    let value = parse_value(value.trim(), &labels).and_then(byte);
+                    bytes.push(value.map_err(error)? as u8);
+                }
+            }
+            None => {
+                let opcode = assemble_line(text, &labels).map_err(error)?;
+                bytes.extend_from_slice(&opcode.to_be_bytes());
+            }
+        }
+    }
+    Ok(Program { bytes, labels })
+}
+
+// The values of a DB statement
+fn data_bytes(text: &str) -> Option<&str> {
+    let (mnemonic, values) = text.split_once(char::is_whitespace)?;
+    mnemonic.eq_ignore_ascii_case("DB").then_some(values)
+}
+
+fn is_label(text: &str) -> bool {
+    let mut chars = text.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+fn assemble_line(line: &str, labels: &HashMap<String, u16>) -> Result<u16, AsmError> {
+    let line = line.split(';').next().unwrap_or("").trim();
+    if line.is_empty() {
+        return Err(AsmError::Empty);
+    }
+    let (mnemonic, rest) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+    let mnemonic = mnemonic.to_ascii_uppercase();
+    let operands = if rest.trim().is_empty() {
+        Vec::new()
+    } else {
+        rest.split(',')
+            .map(|operand| parse_operand(operand.trim(), labels))
+            .collect::<Result<Vec<_>, _>>()?
+    };
+
+    use Operand::*;
+    let opcode = match (mnemonic.as_str(), operands.as_slice()) {
+        ("CLS", []) => 0x00E0,
+        ("RET", []) => 0x00EE,
+        ("EXIT", []) => 0x00FD,
+        ("SYS", [Number(nnn)]) => address(*nnn)?,
+        ("JP", [Number(nnn)]) => 0x1000 | address(*nnn)?,
+        ("CALL", [Number(nnn)]) => 0x2000 | address(*nnn)?,
+        ("SE", [V(x), Number(kk)]) => 0x3000 | x << 8 | byte(*kk)?,
+        ("SNE", [V(x), Number(kk)]) => 0x4000 | x << 8 | byte(*kk)?,
+        ("SE", [V(x), V(y)]) => 0x5000 | x << 8 | y << 4,
+        ("LD", [V(x), Number(kk)]) => 0x6000 | x << 8 | byte(*kk)?,
+        ("ADD", [V(x), Number(kk)]) => 0x7000 | x << 8 | byte(*kk)?,
+        ("LD", [V(x), V(y)]) => 0x8000 | x << 8 | y << 4,
+        ("OR", [V(x), V(y)]) => 0x8001 | x << 8 | y << 4,
+        ("AND", [V(x), V(y)]) => 0x8002 | x << 8 | y << 4,
+        ("XOR", [V(x), V(y)]) => 0x8003 | x << 8 | y << 4,
+        ("ADD", [V(x), V(y)]) => 0x8004 | x << 8 | y << 4,
+        ("SUB", [V(x), V(y)]) => 0x8005 | x << 8 | y << 4,
+        ("SHR", [V(x), V(y)]) => 0x8006 | x << 8 | y << 4,
+        ("SUBN", [V(x), V(y)]) => 0x8007 | x << 8 | y << 4,
+        ("SHL", [V(x), V(y)]) => 0x800E | x << 8 | y << 4,
+        // SHR Vx and SHL Vx, the shorter form in the reference
+        ("SHR", [V(x)]) => 0x8006 | x << 8 | x << 4,
+        ("SHL", [V(x)]) => 0x800E | x << 8 | x << 4,
+        ("SNE", [V(x), V(y)]) => 0x9000 | x << 8 | y << 4,
+        ("LD", [I, Number(nnn)]) => 0xA000 | address(*nnn)?,
+        ("JP", [V(0), Number(nnn)]) => 0xB000 | address(*nnn)?,
+        ("RND", [V(x), Number(kk)]) => 0xC000 | x << 8 | byte(*kk)?,
+        ("DRW", [V(x), V(y), Number(n)]) => {
+            if *n > 0xF {
+                return Err(AsmError::OutOfRange(n.to_string()));
+            }
+            0xD000 | x << 8 | y << 4 | n
+        }
+        ("SKP", [V(x)]) => 0xE09E | x << 8,
+        ("SKNP", [V(x)]) => 0xE0A1 | x << 8,
+        ("LD", [V(x), Dt]) => 0xF007 | x << 8,
+        ("LD", [V(x), K]) => 0xF00A | x << 8,
+        ("LD", [Dt, V(x)]) => 0xF015 | x << 8,
+        ("LD", [St, V(x)]) => 0xF018 | x << 8,
+        ("ADD", [I, V(x)]) => 0xF01E | x << 8,
+        ("LD", [F, V(x)]) => 0xF029 | x << 8,
+        ("LD", [B, V(x)]) => 0xF033 | x << 8,
+        ("LD", [IndirectI, V(x)]) => 0xF055 | x << 8,
+        ("LD", [V(x), IndirectI]) => 0xF065 | x << 8,
+        ("LD", [R, V(x)]) => 0xF075 | x << 8,
+        ("LD", [V(x), R]) => 0xF085 | x << 8,
+        ("DW", [Number(word)]) => *word,
+        (
+            "CLS" | "RET" | "EXIT" | "SYS" | "JP" | "CALL" | "SE" | "SNE" | "LD" | "ADD" | "OR"
+            | "AND" | "XOR" | "SUB" | "SHR" | "SUBN" | "SHL" | "RND" | "DRW" | "SKP" | "SKNP"
+            | "DW",
+            _,
+        ) => return Err(AsmError::InvalidOperands(line.to_string())),
+        _ => return Err(AsmError::UnknownInstruction(mnemonic)),
+    };
+    Ok(opcode)
+}
+
+fn parse_operand(operand: &str, labels: &HashMap<String, u16>) -> Result<Operand, AsmError> {
+    let upper = operand.to_ascii_uppercase();
+    let operand = match upper.as_str() {
+        "I" => Operand::I,
+        "[I]" => Operand::IndirectI,
+        "DT" => Operand::Dt,
+        "ST" => Operand::St,
+        "K" => Operand::K,
+        "F" => Operand::F,
+        "B" => Operand::B,
+        "R" => Operand::R,
+        _ => match upper.strip_prefix('V') {
+            Some(register) if register.len() == 1 => u16::from_str_radix(register, 16)
+                .map(Operand::V)
+                .map_err(|_| AsmError::InvalidOperands(operand.to_string()))?,
+            _ => Operand::Number(parse_value(operand, labels)?),
+        },
+    };
+    Ok(operand)
+}
+
+// A number or the address of a label
+fn parse_value(value: &str, labels: &HashMap<String, u16>) -> Result<u16, AsmError> {
+    if !is_label(value) {
+        return parse_number(value);
+    }
+    labels
+        .get(value)
+        .copied()
+        .ok_or_else(|| AsmError::UnknownLabel(value.to_string()))
+}
+
+fn parse_number(value: &str) -> Result<u16, AsmError> {
+    let lower = value.to_ascii_lowercase();
+    let number = match lower.strip_prefix("0x") {
+        Some(hex) => u32::from_str_radix(hex, 16),
+        None => lower.parse(),
+    }
+    .map_err(|_| AsmError::InvalidNumber(value.to_string()))?;
+    u16::try_from(number).map_err(|_| AsmError::OutOfRange(value.to_string()))
+}
+
+fn address(nnn: u16) -> Result<u16, AsmError> {
+    if nnn > 0xFFF {
+        return Err(AsmError::OutOfRange(format!("{:#X}", nnn)));
+    }
+    Ok(nnn)
+}
+
+fn byte(kk: u16) -> Result<u16, AsmError> {
+    if kk > 0xFF {
+        return Err(AsmError::OutOfRange(format!("{:#X}", kk)));
+    }
+    Ok(kk)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::disasm::disassemble;
+
+    #[test]
+    fn test_assemble() {
+        assert_eq!(assemble("CLS"), Ok(0x00E0));
+        assert_eq!(assemble("EXIT"), Ok(0x00FD));
+        assert_eq!(assemble("ld va, 5"), Ok(0x6A05));
+        assert_eq!(assemble("  LD I, 0x22A ; sprite"), Ok(0xA22A));
+        assert_eq!(assemble("DRW V0, V1, 5"), Ok(0xD015));
+        assert_eq!(assemble("SHR V3"), Ok(0x8336));
+        assert_eq!(assemble("LD [I], V5"), Ok(0xF555));
+        assert_eq!(assemble("LD R, V7"), Ok(0xF775));
+        assert_eq!(assemble("LD V3, R"), Ok(0xF385));
+        assert_eq!(assemble("JP V0, 0x300"), Ok(0xB300));
+        assert_eq!(assemble("DW 0xFFFF"), Ok(0xFFFF));
+    }
+
+    #[test]
+    fn test_assemble_errors() {
+        assert_eq!(assemble(" ; nothing"), Err(AsmError::Empty));
+        assert_eq!(
+            assemble("MOV V0, V1"),
+            Err(AsmError::UnknownInstruction("MOV".to_string()))
+        );
+        assert!(matches!(
+            assemble("LD V0"),
+            Err(AsmError::InvalidOperands(_))
+        ));
+        assert!(matches!(
+            assemble("JP V1, 0x200"),
+            Err(AsmError::InvalidOperands(_))
+        ));
+        assert!(matches!(
+            assemble("LD V0, 256"),
+            Err(AsmError::OutOfRange(_))
+        ));
+        assert!(matches!(
+            assemble("JP 0xZZZ"),
+            Err(AsmError::InvalidNumber(_))
+        ));
+    }
+
+    #[test]
+    fn test_assemble_program() {
+        let source = "\
+            start: LD I, sprite ; labels can be used before they are defined
+            loop:
+              DRW V0, V1, 3
+              JP loop
+            sprite: DB 0x3C, 0x42, 0x3C
+              DW 0x1234";
+        let program = assemble_program(source).unwrap();
+        assert_eq!(
+            program.bytes,
+            [0xA2, 0x06, 0xD0, 0x13, 0x12, 0x02, 0x3C, 0x42, 0x3C, 0x12, 0x34]
+        );
+        assert_eq!(program.labels["start"], 0x200);
+        assert_eq!(program.labels["sprite"], 0x206);
+
+        assert_eq!(
+            assemble_program("CLS\nJP end"),
+            Err(ProgramError {
+                line: 2,
+                error: AsmError::UnknownLabel("end".to_string())
+            })
+        );
+        assert!(matches!(
+            assemble_program("a:\na: CLS"),
+            Err(ProgramError {
+                line: 2,
+                error: AsmError::DuplicateLabel(_)
+            })
+        ));
+        assert!(matches!(
+            assemble_program("1st: CLS"),
+            Err(ProgramError {
+                error: AsmError::InvalidLabel(_),
+                ..
+            })
+        ));
+        assert!(matches!(
+            assemble_program("DB 256"),
+            Err(ProgramError {
+                error: AsmError::OutOfRange(_),
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn test_disassembly_round_trip() {
+        for opcode in 0..=0xFFFF {
+            // 5xyn and 9xyn are executed as 5xy0 and 9xy0 whatever n is
+            if matches!(opcode & 0xF000, 0x5000 | 0x9000) && opcode & 0xF != 0 {
+                continue;
+            }
+            assert_eq!(
+                assemble(&disassemble(opcode)),
+                Ok(opcode),
+                "{:#06X}",
+                opcode
+            );
+        }
+    }
+}
@@ -0,0 +1,422 @@
+#[cfg(feature = "audio")]
+use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+use std::io::{self, Write};
+#[cfg(feature = "audio")]
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Mutex},
+};
+
+/*
+Chip-8 has a single buzzer that sounds while the sound timer (ST) is greater than 0.
+Audio output is abstracted behind the AudioSink trait so that frontends and embedders
+can supply their own audio without touching the core:
+- NullSink discards everything, for headless runs or when sound is disabled
+- TerminalBell rings the terminal bell when a beep starts, the fallback for terminals
+- CpalSink plays the beep on the sound card, with the audio feature
+- Sample based backends receive a generated square wave through push_samples, only generated for
+  sinks that want samples
+A sink can be turned off as an Option, and a pair of sinks both get the beep.
+*/
+pub const SAMPLE_RATE: u32 = 44100;
+// Pitch of the generated square wave
+const BEEP_FREQUENCY: u32 = 440;
+const BEEP_VOLUME: f32 = 0.25;
+
+pub trait AudioSink {
+    // Called once when the sound timer becomes active
+    fn start_beep(&mut self);
+    // Called once when the sound timer reaches 0
+    fn stop_beep(&mut self);
+    // Called with SAMPLE_RATE mono samples while the beep is active, sinks that don't play raw samples can ignore them
+    fn push_samples(&mut self, samples: &[f32]) {
+        let _ = samples;
+    }
+    // Whether push_samples plays the samples, so they're only generated for the sinks that do
+    fn wants_samples(&self) -> bool {
+        false
+    }
+}
+
+impl<T: AudioSink + ?Sized> AudioSink for &mut T {
+    fn start_beep(&mut self) {
+        (**self).start_beep();
+    }
+
+    fn stop_beep(&mut self) {
+        (**self).stop_beep();
+    }
+
+    fn push_samples(&mut self, samples: &[f32]) {
+        (**self).push_samples(samples);
+    }
+
+    fn wants_samples(&self) -> bool {
+        (**self).wants_samples()
+    }
+}
+
+// A sink that may be turned off
+impl<T: AudioSink> AudioSink for Option<T> {
+    fn start_beep(&mut self) {
+        if let Some(sink) = self {
+            sink.start_beep();
+        }
+    }
+
+    fn stop_beep(&mut self) {
+        if let Some(sink) = self {
+            sink.stop_beep();
+        }
+    }
+
+    fn push_samples(&mut self, samples: &[f32]) {
+        if let Some(sink) = self {
+            sink.push_samples(samples);
+        }
+    }
+
+    fn wants_samples(&self) -> bool {
+        self.as_ref().is_some_and(AudioSink::wants_samples)
+    }
+}
+
+// Two sinks fed the same beep, e.g. played and recorded
+impl<A: AudioSink, B: AudioSink> AudioSink for (A, B) {
+    fn start_beep(&mut self) {
+        self.0.start_beep();
+        self.1.start_beep();
+    }
+
+    fn stop_beep(&mut self) {
+        self.0.stop_beep();
+        self.1.stop_beep();
+    }
+
+    fn push_samples(&mut self, samples: &[f32]) {
+        self.0.push_samples(samples);
+        self.1.push_samples(samples);
+    }
+
+    fn wants_samples(&self) -> bool {
+        self.0.wants_samples() || self.1.wants_samples()
+    }
+}
+
+pub struct NullSink;
+
+impl AudioSink for NullSink {
+    fn start_beep(&mut self) {}
+
+    fn stop_beep(&mut self) {}
+}
+
+pub struct TerminalBell;
+
+impl AudioSink for TerminalBell {
+    fn start_beep(&mut self) {
+        // BEL is a control character, so it doesn't disturb what is drawn on the terminal
+        let mut stdout = io::stdout();
+        let _ = stdout.write_all(b"\x07");
+        let _ = stdout.flush();
+    }
+
+    fn stop_beep(&mut self) {}
+}
+
+/*
+CpalSink
+Plays the square wave of the buzzer on the default output device with cpal, behind the audio
+feature as it needs the ALSA library on Linux. The samples pushed each frame are queued for the
+callback of the device, which plays silence when the queue runs dry; at most MAX_QUEUED samples
+are kept, so a frame late or early only delays the sound by a few milliseconds rather than it
+drifting behind the game.
+*/
+#[cfg(feature = "audio")]
+pub struct CpalSink {
+    queue: Arc<Mutex<VecDeque<f32>>>,
+    // Plays as long as it's kept
+    _stream: cpal::Stream,
+}
+
+// A tenth of a second
+#[cfg(feature = "audio")]
+const MAX_QUEUED: usize = SAMPLE_RATE as usize / 10;
+
+#[cfg(feature = "audio")]
+impl CpalSink {
+    // Open the default output device at SAMPLE_RATE, an error when there is none or it can't
+    pub fn new() -> Result<Self, String> {
+        let device = cpal::default_host()
+            .default_output_device()
+            .ok_or("No audio output device")?;
+        let channels = device
+            .default_output_config()
+            .map_err(|e| e.to_string())?
+            .channels();
+        let config = cpal::StreamConfig {
+            channels,
+            sample_rate: cpal::SampleRate(SAMPLE_RATE),
+            buffer_size: cpal::BufferSize::Default,
+        };
+        let queue = Arc::new(Mutex::new(VecDeque::new()));
+        let source = queue.clone();
+        let stream = device
+            .build_output_stream(
+                &config,
+                move |data: &mut [f32], _: &cpal::OutputCallbackInfo| {
+                    let mut queue = source.lock().unwrap();
+                    // The same sample on every channel
+                    for frame in data.chunks_mut(channels as usize) {
+                        frame.fill(queue.pop_front().unwrap_or(0.0));
+                    }
+                },
+                |_| {},
+                None,
+            )
+            .map_err(|e| e.to_string())?;
+        stream.play().map_err(|e| e.to_string())?;
+        Ok(CpalSink {
+            queue,
+            _stream: stream,
+        })
+    }
+}
+
+#[cfg(feature = "audio")]
+impl AudioSink for CpalSink {
+    fn start_beep(&mut self) {}
+
+    fn stop_beep(&mut self) {
+        self.queue.lock().unwrap().clear();
+    }
+
+    fn push_samples(&mut self, samples: &[f32]) {
+        let mut queue = self.queue.lock().unwrap();
+        queue.extend(samples);
+        let excess = queue.len().saturating_sub(MAX_QUEUED);
+        queue.drain(..excess);
+    }
+
+    fn wants_samples(&self) -> bool {
+        true
+    }
+}
+
+/*
+Buzzer follows the sound timer and drives an AudioSink:
+- start_beep / stop_beep are called on transitions of ST between 0 and non-zero
+- push_samples is called with a square wave for as long as ST is non-zero, if the sink wants
+  samples
+The phase of the square wave is kept across calls so consecutive frames join up without clicks.
+
+A short sound effect like LD ST, 1 may start and end between two frames and would never be heard,
+or only for a single frame, which is too short to be audible. Like the original hardware, where
+the buzzer takes a moment to sound, a beep lasts at least a minimum number of frames:
+- observe is called after every cycle, so that ST being set at all between frames starts a beep
+- once started, the beep continues for at least min_frames, even if ST has already reached 0
+*/
+pub struct Buzzer {
+    playing: bool,
+    phase: u32,
+    min_frames: u32,
+    remaining: u32,
+    triggered: bool,
+}
+
+impl Default for Buzzer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Buzzer {
+    pub fn new() -> Self {
+        Buzzer {
+            playing: false,
+            phase: 0,
+            min_frames: 0,
+            remaining: 0,
+            triggered: false,
+        }
+    }
+
+    pub fn min_frames(mut self, frames: u32) -> Self {
+        self.min_frames = frames;
+        self
+    }
+
+    pub fn is_playing(&self) -> bool {
+        self.playing
+    }
+
+    // Called after each cycle with the current sound timer, to catch beeps shorter than a frame
+    pub fn observe(&mut self, st: u8) {
+        self.triggered |= st > 0;
+    }
+
+    // Called once per frame with the current sound timer and the number of samples in a frame
+    pub fn update(&mut self, st: u8, samples: usize, sink: &mut dyn AudioSink) {
+        let sound_on = st > 0 || self.triggered || self.remaining > 0;
+        self.triggered = false;
+        if sound_on && !self.playing {
+            sink.start_beep();
+            self.remaining = self.min_frames;
+        } else if !sound_on && self.playing {
+            sink.stop_beep();
+            self.phase = 0;
+        }
+        self.playing = sound_on;
+
+        if self.playing {
+            self.remaining = self.remaining.saturating_sub(1);
+            let period = SAMPLE_RATE / BEEP_FREQUENCY;
+            if !sink.wants_samples() {
+                self.phase = (self.phase + samples as u32) % period;
+                return;
+            }
+            let wave: Vec<f32> = (0..samples)
+                .map(|_| {
+                    let sample = if self.phase < period / 2 {
+                        BEEP_VOLUME
+                    } else {
+                        -BEEP_VOLUME
+                    };
+                    self.phase = (self.phase + 1) % period;
+                    sample
+                })
+                .collect();
+            sink.push_samples(&wave);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Default)]
+    struct RecordingSink {
+        starts: usize,
+        stops: usize,
+        samples: Vec<f32>,
+    }
+
+    impl AudioSink for RecordingSink {
+        fn start_beep(&mut self) {
+            self.starts += 1;
+        }
+
+        fn stop_beep(&mut self) {
+            self.stops += 1;
+        }
+
+        fn push_samples(&mut self, samples: &[f32]) {
+            self.samples.extend_from_slice(samples);
+        }
+
+        fn wants_samples(&self) -> bool {
+            true
+        }
+    }
+
+    #[test]
+    fn test_buzzer_transitions() {
+        let mut buzzer = Buzzer::new();
+        let mut sink = RecordingSink::default();
+
+        buzzer.update(0, 10, &mut sink);
+        assert_eq!(sink.starts, 0);
+        assert!(sink.samples.is_empty());
+
+        buzzer.update(2, 10, &mut sink);
+        buzzer.update(1, 10, &mut sink);
+        assert_eq!(sink.starts, 1);
+        assert_eq!(sink.samples.len(), 20);
+        assert!(buzzer.is_playing());
+
+        buzzer.update(0, 10, &mut sink);
+        assert_eq!(sink.stops, 1);
+        assert_eq!(sink.samples.len(), 20);
+        assert!(!buzzer.is_playing());
+    }
+
+    #[test]
+    fn test_buzzer_min_frames() {
+        let mut buzzer = Buzzer::new().min_frames(3);
+        let mut sink = RecordingSink::default();
+
+        // ST set to 1 and back to 0 between two frames
+        buzzer.observe(1);
+        buzzer.observe(0);
+        for _ in 0..3 {
+            buzzer.update(0, 10, &mut sink);
+            assert!(buzzer.is_playing());
+        }
+        buzzer.update(0, 10, &mut sink);
+        assert!(!buzzer.is_playing());
+        assert_eq!(sink.starts, 1);
+        assert_eq!(sink.stops, 1);
+        assert_eq!(sink.samples.len(), 30);
+
+        // Longer beeps are not affected
+        buzzer.update(5, 10, &mut sink);
+        for _ in 0..4 {
+            buzzer.update(1, 10, &mut sink);
+        }
+        assert!(buzzer.is_playing());
+        buzzer.update(0, 10, &mut sink);
+        assert!(!buzzer.is_playing());
+    }
+
+    #[test]
+    fn test_combined_sinks() {
+        let mut buzzer = Buzzer::new();
+        let mut played = RecordingSink::default();
+        let mut recorded = RecordingSink::default();
+        buzzer.update(1, 10, &mut (&mut played, Some(&mut recorded)));
+        buzzer.update(1, 10, &mut (&mut played, None::<RecordingSink>));
+        assert_eq!(played.starts, 1);
+        assert_eq!(played.samples.len(), 20);
+        assert_eq!(recorded.starts, 1);
+        assert_eq!(recorded.samples, played.samples[..10]);
+    }
+
+    #[test]
+    fn test_sinks_without_samples() {
+        struct Bell(usize);
+
+        impl AudioSink for Bell {
+            fn start_beep(&mut self) {
+                self.0 += 1;
+            }
+
+            fn stop_beep(&mut self) {}
+
+            fn push_samples(&mut self, _: &[f32]) {
+                panic!("Samples were generated for a sink that doesn't play them");
+            }
+        }
+
+        let mut buzzer = Buzzer::new();
+        let mut bell = Bell(0);
+        buzzer.update(1, 10, &mut bell);
+        buzzer.update(1, 10, &mut (&mut bell, None::<RecordingSink>));
+        assert_eq!(bell.0, 1);
+        assert!(!(&mut bell, None::<RecordingSink>).wants_samples());
+        assert!((NullSink, Some(RecordingSink::default())).wants_samples());
+    }
+
+    #[test]
+    fn test_buzzer_square_wave() {
+        let mut buzzer = Buzzer::new();
+        let mut sink = RecordingSink::default();
+        let period = (SAMPLE_RATE / BEEP_FREQUENCY) as usize;
+
+        buzzer.update(1, period, &mut sink);
+        assert!(sink.samples[..period / 2].iter().all(|s| *s == BEEP_VOLUME));
+        assert!(sink.samples[period / 2..]
+            .iter()
+            .all(|s| *s == -BEEP_VOLUME));
+    }
+}
@@ -0,0 +1,376 @@
+use crate::{browser, diagnostic::Diagnostic, read_rom, DEFAULT_IPF};
+use rayon::prelude::*;
+use rustc8::{
+    chip8::{Chip8, Chip8Error, Quirks, MAX_STACK_DEPTH, STACK_DEPTH},
+    harness::TestHarness,
+};
+use serde_json::json;
+use std::{
+    io::{self, Write},
+    panic::{self, AssertUnwindSafe},
+    path::Path,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+const BATCH_USAGE: &str =
+    "Usage: cargo run batch <directory> [--frames N] [--quirks chip8|schip|xochip] [--stack-depth N] [--jobs N]";
+// Frames run for each ROM, 10 seconds of emulated time
+const DEFAULT_FRAMES: u32 = 600;
+
+// How a ROM ended its run
+#[derive(Debug, PartialEq)]
+enum Outcome {
+    // Ran every frame or until the program ended, with the number of pixels lit at the end
+    Ran {
+        pixels: usize,
+        waiting_for_key: bool,
+        halted: bool,
+    },
+    // Stopped on an error of the core, e.g. an invalid opcode
+    Error {
+        frame: u32,
+        error: Chip8Error,
+    },
+    // The core panicked, a bug of the emulator rather than of the ROM
+    Panic {
+        frame: u32,
+        message: String,
+    },
+    // The file couldn't be read or loaded
+    Unreadable(Diagnostic),
+}
+
+struct Report {
+    name: String,
+    outcome: Outcome,
+    cycles: u64,
+    // The deepest the CALLs were nested, see Chip8::stack_high_water
+    stack: usize,
+}
+
+// What the run of a ROM is tried with
+#[derive(Clone, Copy)]
+struct Settings {
+    quirks: Quirks,
+    stack_depth: usize,
+    frames: u32,
+}
+
+/*
+Batch runs, `rustc8 batch <directory>`
+Runs every ROM of a directory headlessly for a number of frames, to check a whole corpus for
+regressions: ROMs that stop on an error, crash the emulator or end on an empty screen stand
+out in the summary table, which also gives the most levels of the stack each ROM used. ROMs run in parallel, one core instance per ROM on a pool of worker
+threads (one per CPU by default, set with --jobs), with the progress shown as they finish.
+RND is seeded, so runs are the same every time.
+With --json-errors the table is replaced by a line of JSON for each ROM that didn't run to the
+end, with the code of the error (see diagnostic), e.g.
+{"code":"invalid-opcode","frame":12,"message":"Invalid opcode 0xFFFF at 0x2A0","rom":"a.ch8"}
+*/
+pub fn run(args: &[String], json_errors: bool) -> Result<(), Diagnostic> {
+    let mut dir = None;
+    let mut settings = Settings {
+        quirks: Quirks::default(),
+        stack_depth: STACK_DEPTH,
+        frames: DEFAULT_FRAMES,
+    };
+    let mut jobs = 0;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or(format!("Missing value for {}", arg));
+        match arg.as_str() {
+            "--frames" => {
+                let value = value()?;
+                settings.frames = value
+                    .parse()
+                    .map_err(|_| format!("Invalid number of frames: {}", value))?;
+            }
+            "--quirks" => {
+                let name = value()?;
+                settings.quirks = Quirks::from_preset(name)
+                    .ok_or_else(|| format!("Unknown quirks preset: {}", name))?;
+            }
+            "--stack-depth" => {
+                let value = value()?;
+                settings.stack_depth = value
+                    .parse()
+                    .ok()
+                    .filter(|depth| (1..=MAX_STACK_DEPTH).contains(depth))
+                    .ok_or_else(|| {
+                        format!("Invalid stack depth, 1 to {}: {}", MAX_STACK_DEPTH, value)
+                    })?;
+            }
+            "--jobs" => {
+                let value = value()?;
+                jobs = value
+                    .parse()
+                    .ok()
+                    .filter(|jobs| *jobs > 0)
+                    .ok_or_else(|| format!("Invalid number of jobs: {}", value))?;
+            }
+            _ => dir = Some(arg),
+        }
+    }
+    let dir = Path::new(dir.ok_or(BATCH_USAGE)?);
+    let mut paths = Vec::new();
+    browser::find_roms(dir, &mut paths)
+        .map_err(|e| Diagnostic::io(format!("Failed to read {}: {}", dir.display(), e)))?;
+    if paths.is_empty() {
+        let message = format!("No ROMs found in {}", dir.display());
+        return Err(Diagnostic::new("not-found", message));
+    }
+    paths.sort();
+
+    // 0 lets rayon use one thread per CPU
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(jobs)
+        .build()
+        .map_err(|e| Diagnostic::io(e.to_string()))?;
+    // Panics are reported in the table, not printed over the progress
+    let hook = panic::take_hook();
+    panic::set_hook(Box::new(|_| {}));
+    let done = AtomicUsize::new(0);
+    let reports: Vec<Report> = pool.install(|| {
+        paths
+            .par_iter()
+            .map(|path| {
+                let report = run_file(dir, path, settings);
+                let done = done.fetch_add(1, Ordering::Relaxed) + 1;
+                eprint!("\r[{}/{}] {:<40.40}", done, paths.len(), report.name);
+                let _ = io::stderr().flush();
+                report
+            })
+            .collect()
+    });
+    panic::set_hook(hook);
+    eprintln!();
+
+    match json_errors {
+        true => print!("{}", json_report(&reports)),
+        false => print!("{}", summary(&reports)),
+    }
+    Ok(())
+}
+
+fn run_file(dir: &Path, path: &Path, settings: Settings) -> Report {
+    let name = path
+        .strip_prefix(dir)
+        .unwrap_or(path)
+        .to_string_lossy()
+        .into_owned();
+    match read_rom(&path.to_string_lossy()) {
+        Ok(rom) => Report {
+            name,
+            ..run_rom(&rom, settings)
+        },
+        Err(diagnostic) => Report {
+            name,
+            outcome: Outcome::Unreadable(diagnostic),
+            cycles: 0,
+            stack: 0,
+        },
+    }
+}
+
+// Run the ROM like the frontend does, without input, returning the report of the run without
+// the name of the ROM
+fn run_rom(rom: &[u8], settings: Settings) -> Report {
+    let report = |outcome, chip8: &Chip8| Report {
+        name: String::new(),
+        outcome,
+        cycles: chip8.cycles_executed(),
+        stack: chip8.stack_high_water(),
+    };
+    if let Err(e) = Chip8::new().load_rom(rom) {
+        let diagnostic = Diagnostic::new("rom-format", e.to_string());
+        return report(Outcome::Unreadable(diagnostic), &Chip8::new());
+    }
+    let mut harness = TestHarness::new(rom)
+        .quirks(settings.quirks)
+        .stack_depth(settings.stack_depth)
+        .ipf(DEFAULT_IPF);
+    for frame in 0..settings.frames {
+        let result = panic::catch_unwind(AssertUnwindSafe(|| harness.step()));
+        match result {
+            Ok(Ok(())) => {}
+            Ok(Err(error)) => return report(Outcome::Error { frame, error }, &harness.chip8),
+            Err(payload) => {
+                let message = payload
+                    .downcast_ref::<&str>()
+                    .map(|message| message.to_string())
+                    .or_else(|| payload.downcast_ref::<String>().cloned())
+                    .unwrap_or_default();
+                return report(Outcome::Panic { frame, message }, &harness.chip8);
+            }
+        }
+        // Nothing changes after the end of the program
+        if harness.chip8.is_halted() {
+            break;
+        }
+    }
+    let chip8 = &harness.chip8;
+    let outcome = Outcome::Ran {
+        pixels: chip8.display.iter().filter(|pixel| **pixel != 0).count(),
+        waiting_for_key: chip8.is_waiting_for_key(),
+        halted: chip8.is_halted(),
+    };
+    report(outcome, chip8)
+}
+
+// A table of the reports followed by the totals
+fn summary(reports: &[Report]) -> String {
+    let rows: Vec<[String; 4]> = reports
+        .iter()
+        .map(|report| {
+            let result = match &report.outcome {
+                Outcome::Ran { pixels: 0, .. } => "ok, blank screen".to_string(),
+                Outcome::Ran {
+                    pixels,
+                    halted: true,
+                    ..
+                } => format!("ok, {} pixels, finished", pixels),
+                Outcome::Ran {
+                    pixels,
+                    waiting_for_key: true,
+                    ..
+                } => format!("ok, {} pixels, waiting for a key", pixels),
+                Outcome::Ran { pixels, .. } => format!("ok, {} pixels", pixels),
+                Outcome::Error { frame, error } => format!("error on frame {}: {}", frame, error),
+                Outcome::Panic { frame, message } => {
+                    format!("emulator panicked on frame {}: {}", frame, message)
+                }
+                Outcome::Unreadable(diagnostic) => format!("not loaded: {}", diagnostic),
+            };
+            [
+                report.name.clone(),
+                report.cycles.to_string(),
+                report.stack.to_string(),
+                result,
+            ]
+        })
+        .collect();
+    let header = [
+        "ROM".to_string(),
+        "Cycles".to_string(),
+        "Stack".to_string(),
+        "Result".to_string(),
+    ];
+    let width = |column: usize| {
+        rows.iter()
+            .chain([&header])
+            .map(|row| row[column].chars().count())
+            .max()
+            .unwrap_or(0)
+    };
+    let (name_width, cycles_width, stack_width) = (width(0), width(1), width(2));
+
+    let mut text = String::new();
+    for row in [&header].into_iter().chain(&rows) {
+        text += &format!(
+            "{:<name_width$}  {:>cycles_width$}  {:>stack_width$}  {}\n",
+            row[0], row[1], row[2], row[3]
+        );
+    }
+    let count = |matches: fn(&Outcome) -> bool| {
+        reports
+            .iter()
+            .filter(|report| matches(&report.outcome))
+            .count()
+    };
+    text += &format!(
+        "\n{} ROMs: {} ran, {} errors, {} panics, {} not loaded\n",
+        reports.len(),
+        count(|outcome| matches!(outcome, Outcome::Ran { .. })),
+        count(|outcome| matches!(outcome, Outcome::Error { .. })),
+        count(|outcome| matches!(outcome, Outcome::Panic { .. })),
+        count(|outcome| matches!(outcome, Outcome::Unreadable(_))),
+    );
+    text
+}
+
+// A line of JSON for each ROM that stopped early or wasn't loaded
+fn json_report(reports: &[Report]) -> String {
+    let mut text = String::new();
+    for report in reports {
+        let mut error = match &report.outcome {
+            Outcome::Ran { .. } => continue,
+            Outcome::Error { frame, error } => {
+                json!({"code": error.code(), "message": error.to_string(), "frame": frame})
+            }
+            Outcome::Panic { frame, message } => {
+                json!({"code": "panic", "message": message, "frame": frame})
+            }
+            Outcome::Unreadable(diagnostic) => {
+                json!({"code": diagnostic.code, "message": diagnostic.message})
+            }
+        };
+        error["rom"] = json!(report.name);
+        text += &format!("{}\n", error);
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_run_rom() {
+        let settings = Settings {
+            quirks: Quirks::default(),
+            stack_depth: STACK_DEPTH,
+            frames: 2,
+        };
+        // LD F, V0; DRW V0, V0, 5; ADD V1, 1; JP 0x204
+        let rom = [0xF0, 0x29, 0xD0, 0x05, 0x71, 0x01, 0x12, 0x04];
+        let report = run_rom(&rom, settings);
+        assert_eq!(
+            report.outcome,
+            Outcome::Ran {
+                pixels: 14,
+                waiting_for_key: false,
+                halted: false,
+            }
+        );
+        assert_eq!(report.cycles, 2 * DEFAULT_IPF as u64);
+
+        // LD F, V0; DRW V0, V0, 5; JP 0x204, the run ends with the program
+        let report = run_rom(&[0xF0, 0x29, 0xD0, 0x05, 0x12, 0x04], settings);
+        assert!(matches!(report.outcome, Outcome::Ran { halted: true, .. }));
+        assert_eq!(report.cycles, 2);
+
+        // CALL 0x204; JP 0x202; CALL 0x208; RET; RET
+        let rom = [0x22, 0x04, 0x12, 0x02, 0x22, 0x08, 0x00, 0xEE, 0x00, 0xEE];
+        assert_eq!(run_rom(&rom, settings).stack, 2);
+        let shallow = Settings {
+            stack_depth: 1,
+            ..settings
+        };
+        let report = run_rom(&rom, shallow);
+        assert!(matches!(report.outcome, Outcome::Error { .. }));
+        assert_eq!(report.stack, 1);
+
+        // Empty files aren't run
+        let outcome = run_rom(&[], settings).outcome;
+        assert!(matches!(outcome, Outcome::Unreadable(d) if d.message == "ROM is empty"));
+
+        let outcome = run_rom(&[0xFF, 0xFF], settings).outcome;
+        assert!(matches!(outcome, Outcome::Error { frame: 0, .. }));
+
+        let reports = [Report {
+            name: "pong.ch8".to_string(),
+            outcome,
+            cycles: 0,
+            stack: 0,
+        }];
+        let text = summary(&reports);
+        assert!(text.starts_with(
+            "ROM       Cycles  Stack  Result\npong.ch8       0      0  error on frame 0"
+        ));
+        assert!(text.ends_with("1 ROMs: 0 ran, 1 errors, 0 panics, 0 not loaded\n"));
+        assert_eq!(
+            json_report(&reports),
+            "{\"code\":\"invalid-opcode\",\"frame\":0,\"message\":\"Invalid opcode 0xFFFF at 0x200\",\"rom\":\"pong.ch8\"}\n"
+        );
+    }
+}
@@ -0,0 +1,355 @@
+use crate::{
+    config, db,
+    i18n::{tr, trf},
+    task::{Progress, Task},
+};
+use ratatui::{
+    crossterm::event::{self, Event, KeyCode, KeyEventKind},
+    layout::{Constraint, Layout},
+    style::{Modifier, Style},
+    text::Line,
+    widgets::{Block, List, ListState, Paragraph},
+    DefaultTerminal,
+};
+use rustc8::gzip;
+use std::{
+    cmp::Reverse,
+    io,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+// Files shown in the browser, the extensions used by the platforms and a few common generic ones
+const ROM_EXTENSIONS: &[&str] = &["ch8", "c8", "sc8", "xo8", "mc8", "rom"];
+// How often the screen is redrawn while a background task runs
+const TASK_REDRAW_INTERVAL: Duration = Duration::from_millis(80);
+
+/*
+ROM browser
+Shown when a directory is given instead of a ROM file. It lists the ROMs found in the directory
+and its subdirectories, with their title from the ROM database when it has been downloaded.
+Typing filters the list incrementally with a fuzzy match on the file name and the title,
+so "spcinv" finds "Space Invaders". The list is filled in the background, see run.
+*/
+struct Entry {
+    path: PathBuf,
+    name: String,
+    title: Option<String>,
+}
+
+struct Browser {
+    entries: Vec<Entry>,
+    query: String,
+    // Indices of the entries matching the query, best match first
+    matches: Vec<usize>,
+    state: ListState,
+}
+
+impl Browser {
+    fn new(entries: Vec<Entry>) -> Self {
+        let mut browser = Browser {
+            entries: Vec::new(),
+            query: String::new(),
+            matches: Vec::new(),
+            state: ListState::default(),
+        };
+        browser.set_entries(entries);
+        browser
+    }
+
+    // Replace the entries, keeping the query
+    fn set_entries(&mut self, mut entries: Vec<Entry>) {
+        entries.sort_by(|a, b| a.name.cmp(&b.name));
+        self.entries = entries;
+        self.filter();
+    }
+
+    fn filter(&mut self) {
+        let mut scored: Vec<(i32, usize)> = self
+            .entries
+            .iter()
+            .enumerate()
+            .filter_map(|(index, entry)| {
+                let name = fuzzy_score(&self.query, &entry.name);
+                let title = entry
+                    .title
+                    .as_deref()
+                    .and_then(|title| fuzzy_score(&self.query, title));
+                Some((name.max(title)?, index))
+            })
+            .collect();
+        // Stable sort, so equal scores stay in name order
+        scored.sort_by_key(|(score, _)| Reverse(*score));
+        self.matches = scored.into_iter().map(|(_, index)| index).collect();
+        self.state.select((!self.matches.is_empty()).then_some(0));
+    }
+
+    fn push(&mut self, c: char) {
+        self.query.push(c);
+        self.filter();
+    }
+
+    fn pop(&mut self) {
+        self.query.pop();
+        self.filter();
+    }
+
+    fn selected(&self) -> Option<&Entry> {
+        let index = self.matches.get(self.state.selected()?)?;
+        self.entries.get(*index)
+    }
+}
+
+/*
+Fuzzy match: every character of the query must appear in the text in order, ignoring case.
+Returns None when it doesn't match, otherwise a score where higher is a better match:
+- consecutive characters score more than scattered ones
+- characters at the start of a word score more
+An empty query matches everything with the same score.
+*/
+fn fuzzy_score(query: &str, text: &str) -> Option<i32> {
+    let text: Vec<char> = text.chars().flat_map(char::to_lowercase).collect();
+    let mut score = 0;
+    let mut position = 0;
+    let mut previous: Option<usize> = None;
+    for c in query.chars().flat_map(char::to_lowercase) {
+        if c.is_whitespace() {
+            continue;
+        }
+        let found = position + text[position..].iter().position(|t| *t == c)?;
+        score += 1;
+        if previous.is_some_and(|previous| previous + 1 == found) {
+            score += 5;
+        }
+        if found == 0 || !text[found - 1].is_alphanumeric() {
+            score += 3;
+        }
+        previous = Some(found);
+        position = found + 1;
+    }
+    Some(score)
+}
+
+// ROM files of the directory and its subdirectories, also used by `rustc8 batch`
+pub fn find_roms(dir: &Path, paths: &mut Vec<PathBuf>) -> io::Result<()> {
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            find_roms(&path, paths)?;
+            continue;
+        }
+        let is_rom = gzip::strip_extension(&path)
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .is_some_and(|extension| {
+                ROM_EXTENSIONS.contains(&extension.to_ascii_lowercase().as_str())
+            });
+        if is_rom {
+            paths.push(path);
+        }
+    }
+    Ok(())
+}
+
+// The entries of the directory with their titles, None if the scan was cancelled
+fn scan(dir: &Path, progress: &Progress) -> io::Result<Option<Vec<Entry>>> {
+    let mut paths = Vec::new();
+    find_roms(dir, &mut paths)?;
+    let database = db::load(&config::storage());
+    progress.set_total(paths.len());
+    let mut entries = Vec::new();
+    for path in paths {
+        if progress.is_cancelled() {
+            return Ok(None);
+        }
+        let name = path
+            .strip_prefix(dir)
+            .unwrap_or(&path)
+            .to_string_lossy()
+            .into_owned();
+        // Finding the title reads the whole ROM to checksum it
+        let title = database
+            .as_ref()
+            .and_then(|database| database.find_rom(&path))
+            .map(|program| program.title.clone());
+        entries.push(Entry { path, name, title });
+        progress.advance();
+    }
+    Ok(Some(entries))
+}
+
+fn spawn_scan(dir: &str) -> Task<io::Result<Option<Vec<Entry>>>> {
+    let dir = PathBuf::from(dir);
+    Task::spawn(tr("Looking for ROMs"), move |progress| scan(&dir, progress))
+}
+
+// Download the ROM database, returning the number of programs
+fn spawn_update() -> Task<Result<usize, String>> {
+    Task::spawn(tr("Downloading the ROM database"), |progress| {
+        let json = db::download()?;
+        if progress.is_cancelled() {
+            return Err(tr("Cancelled").to_string());
+        }
+        Ok(db::save(&mut config::storage(), &json)?.len())
+    })
+}
+
+/*
+Show the browser until a ROM is picked, None if it was closed with Esc.
+The directory is scanned in the background, Esc while it runs closes the browser. F5 downloads
+the ROM database, then scans again to show the titles.
+*/
+pub fn run(terminal: &mut DefaultTerminal, dir: &str) -> io::Result<Option<PathBuf>> {
+    let mut browser = Browser::new(Vec::new());
+    let mut scan = Some(spawn_scan(dir));
+    let mut update = None;
+    let mut message = None;
+
+    loop {
+        if let Some(result) = scan.as_ref().and_then(Task::poll) {
+            scan = None;
+            match result? {
+                Some(entries) => browser.set_entries(entries),
+                None => return Ok(None),
+            }
+        }
+        if let Some(result) = update.as_ref().and_then(Task::poll) {
+            update = None;
+            message = Some(match result {
+                Ok(programs) => {
+                    scan = Some(spawn_scan(dir));
+                    trf("Updated the ROM database, {} programs", &[&programs])
+                }
+                Err(e) => e,
+            });
+        }
+        let status = scan
+            .as_ref()
+            .map(Task::status)
+            .or_else(|| update.as_ref().map(Task::status))
+            .or_else(|| message.clone())
+            .unwrap_or_else(|| tr("F5: download the ROM database for the titles").to_string());
+
+        terminal.draw(|frame| {
+            let [search_area, list_area, status_area] = Layout::vertical([
+                Constraint::Length(3),
+                Constraint::Fill(1),
+                Constraint::Length(1),
+            ])
+            .areas(frame.area());
+            frame.render_widget(
+                Paragraph::new(format!("{}_", browser.query)).block(
+                    Block::bordered()
+                        .title(tr(" Search (type to filter, Enter to play, Esc to quit) ")),
+                ),
+                search_area,
+            );
+
+            let items: Vec<Line> = browser
+                .matches
+                .iter()
+                .map(|index| {
+                    let entry = &browser.entries[*index];
+                    match &entry.title {
+                        Some(title) => Line::raw(format!("{}  ({})", entry.name, title)),
+                        None => Line::raw(entry.name.clone()),
+                    }
+                })
+                .collect();
+            let list = List::new(items)
+                .block(Block::bordered().title(trf(
+                    " {} of {} ROMs in {} ",
+                    &[&browser.matches.len(), &browser.entries.len(), &dir],
+                )))
+                .highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+            frame.render_stateful_widget(list, list_area, &mut browser.state);
+            frame.render_widget(Paragraph::new(format!(" {}", status)), status_area);
+        })?;
+
+        // Keep the spinner turning while a task runs
+        let busy = scan.is_some() || update.is_some();
+        if busy && !event::poll(TASK_REDRAW_INTERVAL)? {
+            continue;
+        }
+        if let Event::Key(key) = event::read()? {
+            if key.kind != KeyEventKind::Press {
+                continue;
+            }
+            match key.code {
+                KeyCode::Esc => match update.take() {
+                    Some(task) => {
+                        task.cancel();
+                        message = Some(tr("Cancelled the download").to_string());
+                    }
+                    None => {
+                        if let Some(task) = &scan {
+                            task.cancel();
+                        }
+                        return Ok(None);
+                    }
+                },
+                KeyCode::F(5) if update.is_none() => {
+                    update = Some(spawn_update());
+                }
+                KeyCode::Enter => {
+                    if let Some(entry) = browser.selected() {
+                        return Ok(Some(entry.path.clone()));
+                    }
+                }
+                KeyCode::Up => browser.state.select_previous(),
+                KeyCode::Down => browser.state.select_next(),
+                KeyCode::Backspace => browser.pop(),
+                KeyCode::Char(c) => browser.push(c),
+                _ => {}
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(name: &str, title: Option<&str>) -> Entry {
+        Entry {
+            path: PathBuf::from(name),
+            name: name.to_string(),
+            title: title.map(str::to_string),
+        }
+    }
+
+    #[test]
+    fn test_fuzzy_score() {
+        assert_eq!(fuzzy_score("", "pong.ch8"), Some(0));
+        assert!(fuzzy_score("png", "pong.ch8").is_some());
+        assert!(fuzzy_score("gnp", "pong.ch8").is_none());
+        assert!(fuzzy_score("PONG", "pong.ch8").is_some());
+        // Consecutive and word start matches rank first
+        assert!(fuzzy_score("inv", "invaders.ch8") > fuzzy_score("inv", "tinyvm.ch8"));
+        assert!(fuzzy_score("si", "Space Invaders") > fuzzy_score("si", "Basic"));
+    }
+
+    #[test]
+    fn test_filter() {
+        let mut browser = Browser::new(vec![
+            entry("tetris.ch8", None),
+            entry("invaders.ch8", Some("Space Invaders")),
+            entry("pong.ch8", Some("Pong")),
+        ]);
+        assert_eq!(browser.matches, [0, 1, 2]);
+        assert_eq!(browser.selected().unwrap().name, "invaders.ch8");
+
+        for c in "spcinv".chars() {
+            browser.push(c);
+        }
+        assert_eq!(browser.matches, [0]);
+        browser.pop();
+        browser.pop();
+        browser.pop();
+        browser.pop();
+        browser.pop();
+        assert_eq!(browser.selected().unwrap().name, "invaders.ch8");
+        browser.push('z');
+        assert!(browser.selected().is_none());
+    }
+}
@@ -0,0 +1,203 @@
+use crate::chip8::Chip8;
+#[cfg(feature = "std")]
+use crate::chip8::TimerEvent;
+#[cfg(feature = "std")]
+use std::sync::{Arc, Mutex};
+
+/*
+Bus
+What the interpreter goes through to reach the memory, the keypad, the display and the sound, so
+experimenters can attach virtual peripherals without forking the interpreter loop, e.g. a serial
+port at a memory-mapped address or a keypad driven by a script. Attach one with
+Chip8::attach_bus.
+Every method is given what the classic CHIP-8 would use and defaults to what it does with it, so
+a peripheral only overrides the parts it adds to and passes the rest through. ClassicBus overrides
+nothing and behaves like a machine without a bus.
+- read and write: the memory accesses of instructions, including the instruction fetch, after the
+  memory hooks. Addresses are within memory, past the end only the hooks are asked
+- key: whether a key is held, for SKP, SKNP and LD Vx, K, and for is_waiting_for_key, so it may
+  be asked without an instruction running
+- draw: after CLS, DRW or a redraw changed the display
+- sound: when the buzzer turns on or off, with the timer observers
+The bus is shared by the clones of the machine, as the hooks are. Attaching one needs std, without
+it the machine is always the classic CHIP-8.
+*/
+pub trait Bus: Send {
+    fn read(&mut self, memory: &[u8], addr: u16) -> u8 {
+        memory[addr as usize]
+    }
+
+    fn write(&mut self, memory: &mut [u8], addr: u16, value: u8) {
+        memory[addr as usize] = value;
+    }
+
+    fn key(&mut self, keyboard: &[bool; 16], key: u8) -> bool {
+        keyboard[key as usize]
+    }
+
+    fn draw(&mut self, _display: &[u8]) {}
+
+    fn sound(&mut self, _on: bool) {}
+}
+
+#[cfg(feature = "std")]
+pub type SharedBus = Arc<Mutex<dyn Bus>>;
+
+// The bus of the classic CHIP-8: memory, the keyboard array, and a display and buzzer that are
+// read by the host
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ClassicBus;
+
+impl Bus for ClassicBus {}
+
+impl Chip8 {
+    // Route the memory, keypad, display and sound through the bus, replacing any attached before
+    #[cfg(feature = "std")]
+    pub fn attach_bus<B: Bus + 'static>(&mut self, bus: B) -> Arc<Mutex<B>> {
+        let bus = Arc::new(Mutex::new(bus));
+        self.bus = Some(bus.clone());
+        bus
+    }
+
+    #[cfg(feature = "std")]
+    pub fn detach_bus(&mut self) {
+        self.bus = None;
+    }
+
+    pub(crate) fn bus_read(&self, addr: usize) -> u8 {
+        #[cfg(feature = "std")]
+        if let Some(bus) = &self.bus {
+            return bus.lock().unwrap().read(&self.memory, addr as u16);
+        }
+        self.memory[addr]
+    }
+
+    pub(crate) fn bus_write(&mut self, addr: usize, value: u8) {
+        #[cfg(feature = "std")]
+        if let Some(bus) = &self.bus {
+            bus.lock()
+                .unwrap()
+                .write(&mut self.memory, addr as u16, value);
+            return;
+        }
+        self.memory[addr] = value;
+    }
+
+    pub(crate) fn is_key_down(&self, key: u8) -> bool {
+        #[cfg(feature = "std")]
+        if let Some(bus) = &self.bus {
+            return bus.lock().unwrap().key(&self.keyboard, key);
+        }
+        self.keyboard[key as usize]
+    }
+
+    pub(crate) fn bus_draw(&self) {
+        #[cfg(feature = "std")]
+        if let Some(bus) = &self.bus {
+            bus.lock().unwrap().draw(&self.display);
+        }
+    }
+
+    #[cfg(feature = "std")]
+    pub(crate) fn bus_timer_event(&self, event: TimerEvent) {
+        let on = match event {
+            TimerEvent::SoundStarted => true,
+            TimerEvent::SoundStopped => false,
+            TimerEvent::DelayExpired => return,
+        };
+        if let Some(bus) = &self.bus {
+            bus.lock().unwrap().sound(on);
+        }
+    }
+}
+
+// The bus is attached through a SharedBus, which needs std
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use super::*;
+
+    // A serial port at 0xF00, keys held by the peripheral and a count of the frames and beeps
+    #[derive(Default)]
+    struct Serial {
+        sent: Vec<u8>,
+        key: Option<u8>,
+        draws: usize,
+        sound: Vec<bool>,
+    }
+
+    impl Bus for Serial {
+        fn read(&mut self, memory: &[u8], addr: u16) -> u8 {
+            match addr {
+                0xF00 => 0x42,
+                _ => memory[addr as usize],
+            }
+        }
+
+        fn write(&mut self, memory: &mut [u8], addr: u16, value: u8) {
+            match addr {
+                0xF00 => self.sent.push(value),
+                _ => memory[addr as usize] = value,
+            }
+        }
+
+        fn key(&mut self, keyboard: &[bool; 16], key: u8) -> bool {
+            self.key == Some(key) || keyboard[key as usize]
+        }
+
+        fn draw(&mut self, _display: &[u8]) {
+            self.draws += 1;
+        }
+
+        fn sound(&mut self, on: bool) {
+            self.sound.push(on);
+        }
+    }
+
+    #[test]
+    fn test_bus() {
+        let mut chip8 = Chip8::new();
+        #[rustfmt::skip]
+        chip8.load_rom(&[
+            0xAF, 0x00, // LD I, 0xF00
+            0xF0, 0x65, // LD V0, [I]
+            0x70, 0x01, // ADD V0, 1
+            0xF0, 0x55, // LD [I], V0
+            0x61, 0x07, // LD V1, 7
+            0xE1, 0x9E, // SKP V1
+            0x00, 0x00,
+            0xE1, 0xA1, // SKNP V1
+            0xF1, 0x18, // LD ST, V1
+            0x00, 0xE0, // CLS
+        ]).unwrap();
+        let serial = chip8.attach_bus(Serial {
+            key: Some(7),
+            ..Default::default()
+        });
+        for _ in 0..9 {
+            chip8.run_cycle().unwrap();
+        }
+        let serial = serial.lock().unwrap();
+        assert_eq!(serial.sent, vec![0x43]);
+        assert_eq!(chip8.memory[0xF00], 0);
+        assert_eq!(chip8.pc, 0x214);
+        assert_eq!(serial.draws, 1);
+        assert_eq!(serial.sound, vec![true]);
+        drop(serial);
+
+        // The classic bus behaves like no bus at all
+        let mut classic = Chip8::new();
+        classic
+            .load_rom(&[0xA3, 0x00, 0xF0, 0x55, 0xE0, 0x9E])
+            .unwrap();
+        classic.attach_bus(ClassicBus);
+        classic.v[0] = 0x12;
+        classic.keyboard[0x12 & 0xF] = true;
+        for _ in 0..3 {
+            classic.run_cycle().unwrap();
+        }
+        assert_eq!(classic.memory[0x300], 0x12);
+        assert_eq!(classic.pc, 0x208);
+        classic.detach_bus();
+        assert!(classic.bus.is_none());
+    }
+}
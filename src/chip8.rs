@@ -1,23 +1,60 @@
+#[cfg(feature = "std")]
+use crate::{bus::SharedBus, step::SharedStepObserver};
+use crate::{
+    disasm,
+    step::{Step, StepObserver},
+};
+use alloc::{vec, vec::Vec};
+use core::{fmt, ops::Range};
+#[cfg(feature = "std")]
+use std::{
+    ops::RangeInclusive,
+    sync::{Arc, Mutex},
+};
+
 /*
 Chip-8 specifications:
-- 4k memory
+- 4k memory, 64k on XO-CHIP, see MemoryLayout
 - 16 8-bit registers
 - 16-bit index register
 - 16-bit program counter
-- 16 levels of stack
+- 16 levels of stack, more on some interpreters, see MemoryLayout
 - 8-bit stack pointer
 - 8-bit delay timer
 - 8-bit sound timer
-- 64x32 pixel monochrome display
+- 64x32 pixel monochrome display, 64x64 for HiRes CHIP-8 programs, see enable_hires, and
+  256x192 with 256 colors in the MEGA-CHIP mode, see megachip
 - 16-key hexadecimal keyboard
 */
 const MEMORY_SIZE: usize = 4096;
-const REGISTERS_SIZE: usize = 16;
-const STACK_SIZE: usize = 16;
+// Most memory addressable with the 16-bit I and program counter
+pub const MAX_MEMORY_SIZE: usize = 0x10000;
+pub(crate) const REGISTERS_SIZE: usize = 16;
+// Levels of the stack of the original interpreters
+pub const STACK_DEPTH: usize = 16;
+// Most levels a layout can give the stack
+pub const MAX_STACK_DEPTH: usize = 256;
 pub const DISPLAY_HEIGHT: usize = 32;
 pub const DISPLAY_WIDTH: usize = 64;
-const DISPLAY_SIZE: usize = DISPLAY_HEIGHT * DISPLAY_WIDTH;
-const KEYBOARD_SIZE: usize = 16;
+pub(crate) const DISPLAY_SIZE: usize = DISPLAY_HEIGHT * DISPLAY_WIDTH;
+pub const HIRES_DISPLAY_HEIGHT: usize = 64;
+// The first instruction of HiRes CHIP-8 programs, and where they really start, see enable_hires
+const HIRES_JUMP: [u8; 2] = [0x12, 0x60];
+const HIRES_PROGRAM_START: u16 = 0x2C0;
+// SYS call of the routine of the HiRes patch clearing both pages of the display
+const HIRES_CLS: u16 = 0x0230;
+pub const MEGACHIP_DISPLAY_WIDTH: usize = 256;
+pub const MEGACHIP_DISPLAY_HEIGHT: usize = 192;
+pub(crate) const MEGACHIP_DISPLAY_SIZE: usize = MEGACHIP_DISPLAY_WIDTH * MEGACHIP_DISPLAY_HEIGHT;
+pub(crate) const KEYBOARD_SIZE: usize = 16;
+// Frames a key is held by tap_key, for hosts that only see presses, about a sixth of a second
+pub const TAP_FRAMES: u32 = 10;
+// The delay and sound timers count down at 60Hz on the original interpreters
+pub const DEFAULT_TIMER_HZ: u32 = 60;
+// Size of the font, 16 sprites of 5 bytes
+pub const FONT_SIZE: usize = 80;
+// RPL user flags saved by Fx75, 8 on SCHIP (V0 to V7), XO-CHIP extends them to all 16 registers
+pub const RPL_FLAGS_SIZE: usize = 16;
 
 /*
 Chip-8 draws graphics on screen through the use of sprites.
@@ -33,7 +70,7 @@ For example, the hexadecimal digit 0 is represented by the following sprite:
 0x90  10010000
 0xF0  11110000
 */
-const CHAR_SPRITES: [u8; 80] = [
+const CHAR_SPRITES: [u8; FONT_SIZE] = [
     0xF0, 0x90, 0x90, 0x90, 0xF0, // 0
     0x20, 0x60, 0x20, 0x20, 0x70, // 1
     0xF0, 0x10, 0xF0, 0x80, 0xF0, // 2
@@ -77,8 +114,439 @@ Memory Map:
 |  interpreter  |
 +---------------+= 0x000 (0) Start of Chip-8 RAM
 */
-const MEMORY_START: usize = 0x200;
+pub const MEMORY_START: usize = 0x200;
+pub const ETI_660_START: usize = 0x600;
+
+/*
+Memory Layout
+How big the memory is and where the program and the font go, the map above by default:
+- size: bytes of memory, up to MAX_MEMORY_SIZE, e.g. 64k for XO-CHIP programs
+- program_start: where the ROM is loaded and starts, e.g. ETI_660_START for the ETI-660
+- font_addr: where the font sprites are, below program_start, see Chip8::set_font_addr
+- stack_depth: how many CALLs can be nested, STACK_DEPTH on the original interpreters and up to
+  MAX_STACK_DEPTH for those that allowed more. CALL past it is a StackOverflow
+A machine is created with a layout by Chip8::with_layout, load_rom fails with RomError when the
+ROM doesn't fit between program_start and the end of memory.
+*/
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MemoryLayout {
+    pub size: usize,
+    pub program_start: usize,
+    pub font_addr: u16,
+    pub stack_depth: usize,
+}
+
+impl MemoryLayout {
+    pub const CHIP8: MemoryLayout = MemoryLayout {
+        size: MEMORY_SIZE,
+        program_start: MEMORY_START,
+        font_addr: 0,
+        stack_depth: STACK_DEPTH,
+    };
+    pub const ETI_660: MemoryLayout = MemoryLayout {
+        program_start: ETI_660_START,
+        ..MemoryLayout::CHIP8
+    };
+    pub const XO_CHIP: MemoryLayout = MemoryLayout {
+        size: MAX_MEMORY_SIZE,
+        ..MemoryLayout::CHIP8
+    };
+
+    // Bytes available for the ROM
+    pub fn program_space(&self) -> usize {
+        self.size.saturating_sub(self.program_start)
+    }
+}
+
+impl Default for MemoryLayout {
+    fn default() -> Self {
+        MemoryLayout::CHIP8
+    }
+}
+
+/*
+SYS Handler
+0nnn (SYS addr) called a machine code routine of the host computer (e.g. the COSMAC VIP),
+which can't be emulated, so modern interpreters don't execute it.
+The handler decides what to do when a ROM uses it, none of them jump to nnn:
+- Ignore: treat it as a no-op and continue with the next instruction, as most interpreters do,
+  which also gets ROMs past stray data that decodes as 0nnn
+- Warn: ignore it like Ignore, but keep the address and nnn of the last call in sys_call for the
+  host to report, see take_sys_call
+- Error: stop with an error, useful to catch ROMs that rely on host routines
+- Callback: call a user-registered function with the machine and nnn,
+  e.g. to experiment with hybrid ROMs that expect services from the host
+*/
+#[cfg(feature = "std")]
+pub type SysCallback = Arc<Mutex<dyn FnMut(&mut Chip8, u16) + Send>>;
+
+#[derive(Clone, Default)]
+pub enum SysHandler {
+    #[default]
+    Ignore,
+    Warn,
+    Error,
+    #[cfg(feature = "std")]
+    Callback(SysCallback),
+}
+
+/*
+Memory Bounds
+What I-relative accesses (DRW, Fx33, Fx55 and Fx65) do with addresses past the end of memory,
+e.g. a ROM that sets I near 0xFFF in 4K of memory:
+- Error: stop with MemoryOutOfBounds before the instruction changes anything
+- Wrap: the address wraps around to the start of memory, as on interpreters that mask it to 12 bits
+- Saturate: the address is clamped to the last address, the last byte is read or written again
+Addresses handled by a memory hook are in bounds whatever the policy. The instruction fetch
+always fails past the end of memory.
+*/
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum MemoryBounds {
+    #[default]
+    Error,
+    Wrap,
+    Saturate,
+}
+
+impl MemoryBounds {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "error" => Some(MemoryBounds::Error),
+            "wrap" => Some(MemoryBounds::Wrap),
+            "saturate" => Some(MemoryBounds::Saturate),
+            _ => None,
+        }
+    }
+}
+
+/*
+Memory-mapped I/O Hooks
+Embedders can attach read and write hooks to address ranges, e.g. to treat 0xF00-0xFFF as a
+virtual peripheral. Every memory access made by an instruction (including the instruction fetch)
+goes through the hooks:
+- A read in a hooked range returns the value given by the read hook instead of memory
+- A write in a hooked range is given to the write hook and memory is left unchanged
+When several hooks cover the same address the first one registered wins.
+*/
+#[cfg(feature = "std")]
+pub type ReadHook = Arc<Mutex<dyn FnMut(u16) -> u8 + Send>>;
+#[cfg(feature = "std")]
+pub type WriteHook = Arc<Mutex<dyn FnMut(u16, u8) + Send>>;
+
+#[cfg(feature = "std")]
+#[derive(Clone)]
+pub enum MemoryHook {
+    Read(RangeInclusive<u16>, ReadHook),
+    Write(RangeInclusive<u16>, WriteHook),
+}
+
+/*
+Timer Events
+Hosts can subscribe to the changes of the timers rather than sampling dt and st, e.g. to start and
+stop a sound or show an indicator:
+- SoundStarted: st went from 0 to a value, the buzzer turns on
+- SoundStopped: st reached 0, counting down or set by LD ST, Vx
+- DelayExpired: dt reached 0
+Observers are called after the instruction or the timer tick that made the change.
+*/
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TimerEvent {
+    SoundStarted,
+    SoundStopped,
+    DelayExpired,
+}
+
+#[cfg(feature = "std")]
+pub type TimerObserver = Arc<Mutex<dyn FnMut(TimerEvent) + Send>>;
+
+/*
+Errors
+Raised by an instruction the interpreter can't execute, the machine is left as it was before it
+apart from the pc, which has moved to the next instruction so a host can choose to carry on.
+- InvalidOpcode: the opcode isn't a Chip-8 instruction, usually data executed by mistake
+- UnsupportedSys: a SYS call with the Error SysHandler
+- StackOverflow / StackUnderflow: CALL with all the levels of the stack in use, or RET without
+  a CALL to return from
+- MemoryOutOfBounds: the instruction reads or writes past the end of memory (target), e.g. Fx55
+  with I near 0xFFF and the Error MemoryBounds. Addresses handled by a memory hook are in
+  bounds. When the program counter itself runs past the end, the instruction can't be fetched
+  and pc stays where it is.
+- SpriteOutOfBounds: DRW reads its sprite past the end of memory, a MemoryOutOfBounds telling
+  the value of I and the height of the sprite, to help find where I was set wrong
+- SpritePastRom: with strict_sprites, DRW reads its sprite from memory (target) past the end of
+  the ROM that the program never wrote, usually garbage after a bad I
+Each error also has a code that stays the same between versions, unlike the messages, for hosts
+that sort errors, e.g. "invalid-opcode".
+*/
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Chip8Error {
+    InvalidOpcode {
+        addr: u16,
+        opcode: u16,
+    },
+    UnsupportedSys {
+        addr: u16,
+        nnn: u16,
+    },
+    StackOverflow {
+        addr: u16,
+    },
+    StackUnderflow {
+        addr: u16,
+    },
+    MemoryOutOfBounds {
+        addr: u16,
+        target: usize,
+    },
+    SpriteOutOfBounds {
+        addr: u16,
+        i: u16,
+        height: u8,
+    },
+    SpritePastRom {
+        addr: u16,
+        i: u16,
+        height: u8,
+        target: u16,
+    },
+}
+
+impl fmt::Display for Chip8Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Chip8Error::InvalidOpcode { addr, opcode } => {
+                write!(f, "Invalid opcode {:#06X} at {:#05X}", opcode, addr)
+            }
+            Chip8Error::UnsupportedSys { addr, nnn } => {
+                write!(f, "Unsupported SYS call {:#05X} at {:#05X}", nnn, addr)
+            }
+            Chip8Error::StackOverflow { addr } => {
+                write!(f, "Stack overflow, CALL at {:#05X} with a full stack", addr)
+            }
+            Chip8Error::StackUnderflow { addr } => {
+                write!(
+                    f,
+                    "Stack underflow, RET at {:#05X} with an empty stack",
+                    addr
+                )
+            }
+            Chip8Error::MemoryOutOfBounds { addr, target } => {
+                write!(
+                    f,
+                    "Memory access out of bounds at {:#05X}, address {:#05X}",
+                    addr, target
+                )
+            }
+            Chip8Error::SpriteOutOfBounds { addr, i, height } => {
+                write!(
+                    f,
+                    "Sprite of {} rows at I {:#05X}, drawn at {:#05X}, reads past the end of memory",
+                    height, i, addr
+                )
+            }
+            Chip8Error::SpritePastRom {
+                addr,
+                i,
+                height,
+                target,
+            } => {
+                write!(
+                    f,
+                    "Sprite of {} rows at I {:#05X}, drawn at {:#05X}, reads {:#05X} past the end of the ROM, never written by the program",
+                    height, i, addr, target
+                )
+            }
+        }
+    }
+}
+
+impl Chip8Error {
+    pub fn code(&self) -> &'static str {
+        match self {
+            Chip8Error::InvalidOpcode { .. } => "invalid-opcode",
+            Chip8Error::UnsupportedSys { .. } => "unsupported-sys",
+            Chip8Error::StackOverflow { .. } => "stack-overflow",
+            Chip8Error::StackUnderflow { .. } => "stack-underflow",
+            Chip8Error::MemoryOutOfBounds { .. } | Chip8Error::SpriteOutOfBounds { .. } => {
+                "memory-out-of-bounds"
+            }
+            Chip8Error::SpritePastRom { .. } => "sprite-past-rom",
+        }
+    }
+}
+
+impl core::error::Error for Chip8Error {}
+
+// Why a ROM can't be loaded, see Chip8::load_rom
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RomError {
+    // The file has no bytes, usually a failed download or copy
+    Empty,
+    // The ROM has more bytes than fit after the program start (available)
+    TooLarge { size: usize, available: usize },
+}
+
+impl fmt::Display for RomError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RomError::Empty => write!(f, "ROM is empty"),
+            RomError::TooLarge { size, available } => write!(
+                f,
+                "ROM is too large: {} bytes, {} fit in memory",
+                size, available
+            ),
+        }
+    }
+}
+
+impl core::error::Error for RomError {}
+
+/*
+Damage
+The rectangle of the display that changed since the damage was last taken, so renderers can
+redraw that region rather than the whole frame. DRW grows it to cover every pixel it flipped and
+CLS damages the whole display. A sprite wrapping around an edge damages the rows or columns in
+between as well, the rectangle is only guaranteed to cover the changes.
+x and y are the top left pixel, right and bottom are exclusive.
+*/
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Damage {
+    pub x: usize,
+    pub y: usize,
+    pub right: usize,
+    pub bottom: usize,
+}
 
+impl Damage {
+    // The whole 64x32 display, see Chip8::full_damage for the display of a machine
+    pub const FULL: Damage = Damage {
+        x: 0,
+        y: 0,
+        right: DISPLAY_WIDTH,
+        bottom: DISPLAY_HEIGHT,
+    };
+
+    pub fn width(&self) -> usize {
+        self.right - self.x
+    }
+
+    pub fn height(&self) -> usize {
+        self.bottom - self.y
+    }
+
+    pub fn contains(&self, x: usize, y: usize) -> bool {
+        (self.x..self.right).contains(&x) && (self.y..self.bottom).contains(&y)
+    }
+
+    // The smallest rectangle covering both
+    pub fn union(self, other: Damage) -> Damage {
+        Damage {
+            x: self.x.min(other.x),
+            y: self.y.min(other.y),
+            right: self.right.max(other.right),
+            bottom: self.bottom.max(other.bottom),
+        }
+    }
+
+    fn pixel(x: usize, y: usize) -> Damage {
+        Damage {
+            x,
+            y,
+            right: x + 1,
+            bottom: y + 1,
+        }
+    }
+}
+
+/*
+Quirks
+Behaviours that differ between interpreters, which ROMs may depend on.
+- shift_vy: 8xy6 and 8xyE shift Vy and store the result in Vx, as the COSMAC VIP did, rather
+  than shifting Vx in place as SUPER-CHIP does
+- increment_i: Fx55 and Fx65 leave I pointing after the last register stored or loaded (I + x + 1)
+- vf_reset: 8xy1, 8xy2 and 8xy3 reset VF to 0, a side effect of how the VIP ran them
+- jump_vx: Bnnn jumps to nnn + Vx, x being the high nibble of nnn (Bxnn), as on SUPER-CHIP
+- display_wait: DRW waits for the vertical blank before drawing, as the VIP drew in its display
+  interrupt, which limits programs to one sprite per frame. See vblank
+- wrap_x / wrap_y: whether DRW wraps the parts of a sprite that go past the right / bottom edge
+  around to the opposite side, or clips them. Some variants clip vertically but wrap horizontally.
+  The starting coordinates always wrap, e.g. drawing at x = 70 starts at x = 6.
+The defaults are the behaviour this interpreter always had. The presets are the behaviours of
+the main platforms, as the community test suite expects them.
+*/
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Quirks {
+    pub shift_vy: bool,
+    pub increment_i: bool,
+    pub vf_reset: bool,
+    pub jump_vx: bool,
+    pub display_wait: bool,
+    pub wrap_x: bool,
+    pub wrap_y: bool,
+}
+
+impl Default for Quirks {
+    fn default() -> Self {
+        Quirks {
+            shift_vy: false,
+            increment_i: false,
+            vf_reset: false,
+            jump_vx: false,
+            display_wait: false,
+            wrap_x: true,
+            wrap_y: true,
+        }
+    }
+}
+
+impl Quirks {
+    // The original interpreter of the COSMAC VIP
+    pub const CHIP8: Quirks = Quirks {
+        shift_vy: true,
+        increment_i: true,
+        vf_reset: true,
+        jump_vx: false,
+        display_wait: true,
+        wrap_x: false,
+        wrap_y: false,
+    };
+
+    // SUPER-CHIP 1.1 on the HP48
+    pub const SCHIP: Quirks = Quirks {
+        shift_vy: false,
+        increment_i: false,
+        vf_reset: false,
+        jump_vx: true,
+        display_wait: false,
+        wrap_x: false,
+        wrap_y: false,
+    };
+
+    // XO-CHIP, as implemented by Octo
+    pub const XOCHIP: Quirks = Quirks {
+        shift_vy: true,
+        increment_i: true,
+        vf_reset: false,
+        jump_vx: false,
+        display_wait: false,
+        wrap_x: true,
+        wrap_y: true,
+    };
+
+    // Names of the presets, as accepted by from_preset
+    pub const PRESETS: [&'static str; 3] = ["chip8", "schip", "xochip"];
+
+    pub fn from_preset(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "chip8" | "chip-8" => Some(Quirks::CHIP8),
+            "schip" | "superchip" | "super-chip" => Some(Quirks::SCHIP),
+            "xochip" | "xo-chip" => Some(Quirks::XOCHIP),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone)]
 pub struct Chip8 {
     // Index Register
     // Used to store memory addresses
@@ -89,7 +557,8 @@ pub struct Chip8 {
     pub pc: u16,
     // Memory
     // Stores the program, data (e.g. sprites), and stack
-    pub memory: [u8; MEMORY_SIZE],
+    // Sized by the MemoryLayout the machine was created with
+    pub memory: Vec<u8>,
     // Registers
     // Used for temporary data storage
     // V0 to VE are general purpose registers
@@ -99,7 +568,8 @@ pub struct Chip8 {
     // Store return addresses when subroutines are called
     // When a subroutine is called (CALL addr), the program counter is pushed onto the stack, and the program counter is set to addr
     // When a subroutine returns (RET), the program counter is popped from the stack, and the program counter is set to the popped value
-    pub stack: [u16; STACK_SIZE],
+    // Only the stack_depth of the layout is used, see MemoryLayout
+    pub stack: [u16; MAX_STACK_DEPTH],
     // Stack Pointer
     // Points to the top of the stack
     // Incremented when a value is pushed onto the stack
@@ -107,8 +577,8 @@ pub struct Chip8 {
     pub sp: u16,
     // Delay Timer
     // Used for timing events
-    // Decremented at a rate of 60Hz
-    // When dt is greater than 0, it is decremented by 1 every cycle (1/60 second)
+    // Decremented at the rate of the timer clock, 60Hz by default
+    // When dt is greater than 0, it is decremented by 1 every tick of the timer clock (1/60 second)
     // When dt is set to 0, it remains at 0
     pub dt: u8,
     // Sound Timer
@@ -120,75 +590,759 @@ pub struct Chip8 {
     // Represents the state of the Chip-8 hexadecimal keyboard (16 keys 0x0 to 0xF)
     // Instructions that interact with the keyboard will check this array
     pub keyboard: [bool; KEYBOARD_SIZE],
+    // Frames left before each key tapped with tap_key is released, 0 for keys held until key_up
+    pub(crate) key_release: [u32; KEYBOARD_SIZE],
+    // The key Fx0A saw pressed, it completes once the key is released
+    pub(crate) key_wait: Option<u8>,
+    // RPL User Flags
+    // Registers saved by Fx75 and restored by Fx85, the HP48 kept them between programs, so games
+    // use them for high scores. Nothing keeps them across runs in the core, a host persists them
+    pub rpl_flags: [u8; RPL_FLAGS_SIZE],
     // Display Array
-    // Represents the state of the Chip-8 64x32 pixel display
+    // Represents the state of the Chip-8 64x32 pixel display, display_width pixels per row and
+    // display_height rows, see display_size
+    // A pixel is 0 when unlit, otherwise the index of its color: 1 on the monochrome displays,
+    // an entry of the palette in the MEGA-CHIP mode
     // Instructions like DRW will update this array to draw sprites on the display
-    pub display: [bool; DISPLAY_SIZE],
-    pub is_drawing: bool,
+    pub display: Vec<u8>,
+    // Frame Generation
+    // Counts the instructions that drew on the display and the redraws asked for, and the count
+    // last seen by take_frame
+    pub(crate) frame_generation: u64,
+    pub(crate) taken_generation: u64,
+    // Damage
+    // The region of the display changed since take_damage was last called, see Damage
+    pub damage: Option<Damage>,
+    // The address and nnn of the last SYS call ignored with the Warn SysHandler, see take_sys_call
+    pub sys_call: Option<(u16, u16)>,
+    // SYS Handler
+    // Decides how 0nnn instructions are handled
+    pub sys_handler: SysHandler,
+    // Memory Bounds
+    // Decides what I-relative accesses past the end of memory do
+    pub memory_bounds: MemoryBounds,
+    // Strict Sprites
+    // DRW stops with SpritePastRom when it reads memory that isn't the font, the ROM or written
+    // by the program, off by default as some programs draw garbage on purpose, e.g. as noise
+    pub strict_sprites: bool,
+    // Addresses the ROM was loaded at and the bytes written by instructions, see strict_sprites
+    pub(crate) rom: Range<usize>,
+    pub(crate) written: Vec<u64>,
+    // The ROM as it was loaded, put back by reset
+    pub(crate) rom_image: Vec<u8>,
+    // Memory Hooks
+    // Read and write hooks for memory-mapped I/O
+    #[cfg(feature = "std")]
+    pub memory_hooks: Vec<MemoryHook>,
+    // Timer Observers
+    // Called when the timers change, see TimerEvent
+    #[cfg(feature = "std")]
+    pub timer_observers: Vec<TimerObserver>,
+    // Step Observers
+    // Called before and after every instruction, see StepObserver
+    #[cfg(feature = "std")]
+    pub step_observers: Vec<SharedStepObserver>,
+    // Bus
+    // The peripherals attached with attach_bus, the classic CHIP-8 when none is, see Bus
+    #[cfg(feature = "std")]
+    pub bus: Option<SharedBus>,
+    // Memory Layout
+    // The size of the memory and where the program starts, see MemoryLayout
+    pub(crate) layout: MemoryLayout,
+    // Font Address
+    // Where the font sprites are in memory, 0x000 by default, see set_font_addr
+    pub font_addr: u16,
+    // Quirks
+    // Interpreter specific behaviours, see Quirks
+    pub quirks: Quirks,
+    // Timer Clock
+    // Frequency in Hz of the clock driving dt and st, see clock_timers
+    pub timer_hz: u32,
+    // Progress towards the next timer tick, in units of 1/timer_hz of a source clock period
+    pub(crate) timer_accumulator: u64,
+    // Set by vblank and cleared by the next instruction, see the display_wait quirk
+    pub(crate) vblank: bool,
+    // State of the generator used by RND once seeded, see seed_rng
+    pub(crate) rng: Option<u64>,
+    // Instructions executed and frames ended since the machine was created, see cycles_executed
+    pub(crate) cycles_executed: u64,
+    pub(crate) frames_elapsed: u64,
+    // The most levels of the stack used at once since the machine was created, see stack_high_water
+    pub(crate) stack_high_water: usize,
+    // MEGA-CHIP
+    // The state of the extension once enabled, see megachip
+    #[cfg(feature = "megachip")]
+    pub megachip: Option<crate::megachip::MegaChip>,
+}
+
+// Why run_for returned, see Chip8::run_for
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Stop {
+    // Ran every instruction it was given
+    Budget,
+    // The last instruction drew on the display, e.g. DRW, CLS or the 0230 of HiRes CHIP-8
+    Drawn,
+    // Nothing happens until a key is pressed, see is_waiting_for_key
+    WaitingForKey,
+    // Nothing happens until the next frame, see is_waiting_for_vblank
+    WaitingForVblank,
+    // The program has ended, see is_halted
+    Halted,
+}
+
+// The width and height of a display of so many pixels: the 256x192 display of MEGA-CHIP, or
+// DISPLAY_WIDTH pixels per row
+pub fn display_size(pixels: usize) -> (usize, usize) {
+    match pixels {
+        MEGACHIP_DISPLAY_SIZE => (MEGACHIP_DISPLAY_WIDTH, MEGACHIP_DISPLAY_HEIGHT),
+        _ => (DISPLAY_WIDTH, pixels / DISPLAY_WIDTH),
+    }
+}
+
+// Whether the ROM is a HiRes CHIP-8 program, which starts with a jump to 0x260, see
+// Chip8::enable_hires
+pub fn is_hires_rom(rom: &[u8]) -> bool {
+    rom.starts_with(&HIRES_JUMP)
+}
+
+// Whether opcode is 1nnn (JP) to addr, which it can't be past the 12 bits of nnn
+fn is_jump_to(opcode: u16, addr: usize) -> bool {
+    addr <= 0xFFF && opcode & 0xF000 == 0x1000 && (opcode & 0x0FFF) as usize == addr
+}
+
+impl Default for Chip8 {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Chip8 {
     pub fn new() -> Self {
+        Self::with_layout(MemoryLayout::default())
+    }
+
+    // A machine with another memory layout, panics if the layout doesn't hold the font
+    pub fn with_layout(layout: MemoryLayout) -> Self {
+        assert!(
+            (MEMORY_START..=MAX_MEMORY_SIZE).contains(&layout.size),
+            "The memory must be {:#05X} to {:#X} bytes",
+            MEMORY_START,
+            MAX_MEMORY_SIZE
+        );
+        assert!(
+            layout.program_start <= layout.size,
+            "The program must start in memory"
+        );
+        assert!(
+            (1..=MAX_STACK_DEPTH).contains(&layout.stack_depth),
+            "The stack must have 1 to {} levels",
+            MAX_STACK_DEPTH
+        );
         let mut chip8 = Chip8 {
             i: 0,
-            pc: MEMORY_START as u16,
-            memory: [0; MEMORY_SIZE],
+            pc: layout.program_start as u16,
+            memory: vec![0; layout.size],
             v: [0; REGISTERS_SIZE],
-            stack: [0; STACK_SIZE],
+            stack: [0; MAX_STACK_DEPTH],
             sp: 0,
             dt: 0,
             st: 0,
             keyboard: [false; KEYBOARD_SIZE],
-            display: [false; DISPLAY_SIZE],
-            is_drawing: false,
+            key_release: [0; KEYBOARD_SIZE],
+            key_wait: None,
+            rpl_flags: [0; RPL_FLAGS_SIZE],
+            display: vec![0; DISPLAY_SIZE],
+            frame_generation: 0,
+            taken_generation: 0,
+            damage: None,
+            sys_call: None,
+            sys_handler: SysHandler::default(),
+            memory_bounds: MemoryBounds::default(),
+            strict_sprites: false,
+            rom: layout.program_start..layout.program_start,
+            written: vec![0; layout.size.div_ceil(64)],
+            rom_image: Vec::new(),
+            #[cfg(feature = "std")]
+            memory_hooks: Vec::new(),
+            #[cfg(feature = "std")]
+            timer_observers: Vec::new(),
+            #[cfg(feature = "std")]
+            step_observers: Vec::new(),
+            #[cfg(feature = "std")]
+            bus: None,
+            layout,
+            font_addr: 0,
+            quirks: Quirks::default(),
+            timer_hz: DEFAULT_TIMER_HZ,
+            timer_accumulator: 0,
+            vblank: true,
+            rng: None,
+            cycles_executed: 0,
+            stack_high_water: 0,
+            frames_elapsed: 0,
+            #[cfg(feature = "megachip")]
+            megachip: None,
         };
 
         // Load the character sprites into memory
-        for i in 0..CHAR_SPRITES.len() {
-            chip8.memory[i] = CHAR_SPRITES[i];
+        chip8.memory[..CHAR_SPRITES.len()].copy_from_slice(&CHAR_SPRITES);
+        if layout.font_addr != 0 {
+            chip8.set_font_addr(layout.font_addr);
         }
 
         chip8
     }
 
-    pub fn load_rom(&mut self, rom: &[u8]) {
-        // Load the ROM into memory
-        for i in 0..rom.len() {
-            self.memory[MEMORY_START + i] = rom[i];
+    /*
+    HiRes CHIP-8
+    A variant of the VIP interpreter with a 64x64 display over two pages of memory, used by early
+    hybrid ROMs such as Hires Invaders. Their programs carry the patch of the interpreter from
+    0x202, and start with 1260, a jump into it, which sets up the taller display and goes on to
+    the program at 0x2C0. The patch is VIP machine code, so instead the display is set up here
+    and the jump made to skip it, and 0230, the SYS call of its routine clearing the display,
+    clears it like CLS. The font stays where the layout puts it, as the program can't tell.
+    Call it after loading a program for which is_hires_rom is true.
+    */
+    pub fn enable_hires(&mut self) {
+        self.display = vec![0; DISPLAY_WIDTH * HIRES_DISPLAY_HEIGHT];
+        self.skip_hires_patch();
+        self.redraw_all();
+    }
+
+    fn skip_hires_patch(&mut self) {
+        let start = self.layout.program_start;
+        if self.memory.get(start..start + 2) == Some(&HIRES_JUMP[..]) {
+            self.memory[start..start + 2]
+                .copy_from_slice(&(0x1000 | HIRES_PROGRAM_START).to_be_bytes());
         }
     }
 
-    pub fn get_display_data(&self) -> Vec<bool> {
+    /*
+    Soft reset: the program starts again with the registers, stack, timers, keys and display
+    cleared, and memory as load_rom left it, without the ROM having to be loaded again. What the
+    program wrote is undone, including variables kept in the program itself; a machine whose
+    program wasn't loaded with load_rom keeps its memory as it is.
+    The HiRes display stays, MEGA-CHIP goes back to the 64x32 display. The settings, callbacks
+    and the generator of RND carry on, as do cycles_executed, frames_elapsed and stack_high_water.
+    */
+    pub fn reset(&mut self) {
+        if !self.rom_image.is_empty() {
+            self.memory.fill(0);
+            let font = self.font_addr as usize;
+            self.memory[font..font + FONT_SIZE].copy_from_slice(&CHAR_SPRITES);
+            self.memory[self.rom.clone()].copy_from_slice(&self.rom_image);
+            self.written.fill(0);
+            if self.is_hires() {
+                self.skip_hires_patch();
+            }
+        }
+        self.pc = self.layout.program_start as u16;
+        self.i = 0;
+        self.v = [0; REGISTERS_SIZE];
+        self.stack = [0; MAX_STACK_DEPTH];
+        self.sp = 0;
+        self.dt = 0;
+        self.st = 0;
+        self.timer_accumulator = 0;
+        self.reset_all_keys();
+        let size = match self.is_hires() {
+            true => DISPLAY_WIDTH * HIRES_DISPLAY_HEIGHT,
+            false => DISPLAY_SIZE,
+        };
+        self.display = vec![0; size];
+        self.sys_call = None;
+        self.vblank = true;
+        #[cfg(feature = "megachip")]
+        if self.megachip.is_some() {
+            self.enable_megachip();
+        }
+        self.redraw_all();
+    }
+
+    pub fn is_hires(&self) -> bool {
+        self.display_height() == HIRES_DISPLAY_HEIGHT
+    }
+
+    // Pixels per row, DISPLAY_WIDTH or MEGACHIP_DISPLAY_WIDTH
+    pub fn display_width(&self) -> usize {
+        display_size(self.display.len()).0
+    }
+
+    // Rows of the display, DISPLAY_HEIGHT, HIRES_DISPLAY_HEIGHT or MEGACHIP_DISPLAY_HEIGHT
+    pub fn display_height(&self) -> usize {
+        display_size(self.display.len()).1
+    }
+
+    // The whole display
+    pub fn full_damage(&self) -> Damage {
+        Damage {
+            right: self.display_width(),
+            bottom: self.display_height(),
+            ..Damage::FULL
+        }
+    }
+
+    pub(crate) fn clear_display(&mut self) {
+        self.display.fill(0);
+        self.display_changed();
+        self.damage = Some(self.full_damage());
+    }
+
+    pub fn layout(&self) -> MemoryLayout {
+        MemoryLayout {
+            font_addr: self.font_addr,
+            ..self.layout
+        }
+    }
+
+    /*
+    Move the font to another address of the interpreter area, Fx29 points I to the sprites there.
+    Interpreters put it in different places, e.g. 0x050 for many modern ones and 0x1B0 for some
+    of the HP48, which ROMs reading the font bytes directly may depend on. The SUPER-CHIP large
+    font (Fx30) isn't implemented.
+    The font has to fit below the program start, the bytes it used are cleared.
+    */
+    pub fn set_font_addr(&mut self, addr: u16) {
+        let start = addr as usize;
+        let program_start = self.layout.program_start;
+        assert!(
+            start + FONT_SIZE <= program_start,
+            "The font must be below the program, at {:#05X} or lower",
+            program_start.saturating_sub(FONT_SIZE)
+        );
+        let old = self.font_addr as usize;
+        self.memory[old..old + FONT_SIZE].fill(0);
+        self.memory[start..start + FONT_SIZE].copy_from_slice(&CHAR_SPRITES);
+        self.font_addr = addr;
+    }
+
+    // Load the ROM at the program start of the layout and start it there
+    // Nothing is loaded if the ROM is empty or doesn't fit
+    pub fn load_rom(&mut self, rom: &[u8]) -> Result<(), RomError> {
+        let addr = self.layout.program_start;
+        let available = self.layout.program_space();
+        if rom.is_empty() {
+            return Err(RomError::Empty);
+        }
+        if rom.len() > available {
+            return Err(RomError::TooLarge {
+                size: rom.len(),
+                available,
+            });
+        }
+        self.memory[addr..addr + rom.len()].copy_from_slice(rom);
+        self.rom = addr..addr + rom.len();
+        self.rom_image = rom.to_vec();
+        self.pc = addr as u16;
+        Ok(())
+    }
+
+    // Register a callback for 0nnn instructions, called with the machine and nnn
+    #[cfg(feature = "std")]
+    pub fn on_sys_call<F>(&mut self, callback: F)
+    where
+        F: FnMut(&mut Chip8, u16) + Send + 'static,
+    {
+        self.sys_handler = SysHandler::Callback(Arc::new(Mutex::new(callback)));
+    }
+
+    // Register a read hook for a range of addresses, called with the address being read
+    #[cfg(feature = "std")]
+    pub fn on_memory_read<F>(&mut self, range: RangeInclusive<u16>, hook: F)
+    where
+        F: FnMut(u16) -> u8 + Send + 'static,
+    {
+        let hook = Arc::new(Mutex::new(hook));
+        self.memory_hooks.push(MemoryHook::Read(range, hook));
+    }
+
+    // Register a write hook for a range of addresses, called with the address and the value written
+    #[cfg(feature = "std")]
+    pub fn on_memory_write<F>(&mut self, range: RangeInclusive<u16>, hook: F)
+    where
+        F: FnMut(u16, u8) + Send + 'static,
+    {
+        let hook = Arc::new(Mutex::new(hook));
+        self.memory_hooks.push(MemoryHook::Write(range, hook));
+    }
+
+    // Register an observer of the timers, called with each TimerEvent
+    #[cfg(feature = "std")]
+    pub fn on_timer_event<F>(&mut self, observer: F)
+    where
+        F: FnMut(TimerEvent) + Send + 'static,
+    {
+        self.timer_observers.push(Arc::new(Mutex::new(observer)));
+    }
+
+    // Tell the observers how the timers changed from the given values
+    #[cfg(feature = "std")]
+    fn notify_timers(&self, dt: u8, st: u8) {
+        let events = [
+            (st == 0 && self.st > 0, TimerEvent::SoundStarted),
+            (st > 0 && self.st == 0, TimerEvent::SoundStopped),
+            (dt > 0 && self.dt == 0, TimerEvent::DelayExpired),
+        ];
+        for (_, event) in events.iter().filter(|(changed, _)| *changed) {
+            for observer in &self.timer_observers {
+                observer.lock().unwrap()(*event);
+            }
+            self.bus_timer_event(*event);
+        }
+    }
+
+    // No observers without std
+    #[cfg(not(feature = "std"))]
+    fn notify_timers(&self, _dt: u8, _st: u8) {}
+
+    // Read a byte of memory on behalf of an instruction, going through the read hooks and the bus
+    pub(crate) fn read_memory(&self, addr: usize) -> u8 {
+        #[cfg(feature = "std")]
+        for hook in &self.memory_hooks {
+            if let MemoryHook::Read(range, hook) = hook {
+                if range.contains(&(addr as u16)) {
+                    return hook.lock().unwrap()(addr as u16);
+                }
+            }
+        }
+        self.bus_read(addr)
+    }
+
+    // Write a byte of memory on behalf of an instruction, going through the write hooks and the bus
+    fn write_memory(&mut self, addr: usize, value: u8) {
+        #[cfg(feature = "std")]
+        for hook in &self.memory_hooks {
+            if let MemoryHook::Write(range, hook) = hook {
+                if range.contains(&(addr as u16)) {
+                    hook.lock().unwrap()(addr as u16, value);
+                    return;
+                }
+            }
+        }
+        self.bus_write(addr, value);
+        self.mark_written(addr);
+    }
+
+    pub(crate) fn mark_written(&mut self, addr: usize) {
+        self.written[addr / 64] |= 1 << (addr % 64);
+    }
+
+    fn is_written(&self, addr: usize) -> bool {
+        addr < self.memory.len() && self.written[addr / 64] & (1 << (addr % 64)) != 0
+    }
+
+    /*
+    Make RND deterministic. Once seeded, RND draws from a generator (SplitMix64) kept in the
+    machine, so machines seeded alike get the same numbers and a clone carries on the same
+    sequence. Unseeded, RND uses the random generator of the thread, or without the rand feature,
+    e.g. on boards with no source of entropy, the machine generator seeded with 0.
+    */
+    pub fn seed_rng(&mut self, seed: u64) {
+        self.rng = Some(seed);
+    }
+
+    fn random_byte(&mut self) -> u8 {
+        #[cfg(feature = "rand")]
+        let Some(state) = &mut self.rng
+        else {
+            return rand::random();
+        };
+        #[cfg(not(feature = "rand"))]
+        let state = self.rng.get_or_insert(0);
+        *state = state.wrapping_add(0x9E3779B97F4A7C15);
+        let mut z = *state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D049BB133111EB);
+        ((z ^ (z >> 31)) >> 56) as u8
+    }
+
+    pub fn get_display_data(&self) -> Vec<u8> {
         self.display.to_vec()
     }
 
-    pub fn set_key(&mut self, key: u8) {
+    /*
+    The display if it has been drawn on since the last call, none otherwise, for frontends to
+    present each new frame once. Drawing includes DRW and CLS even when they leave the pixels as
+    they were, and redraw_all, e.g. after loading a state. Frontends with several readers of the
+    display compare frame_generation instead, it changes whenever a frame would be returned.
+    */
+    pub fn take_frame(&mut self) -> Option<&[u8]> {
+        if self.taken_generation == self.frame_generation {
+            return None;
+        }
+        self.taken_generation = self.frame_generation;
+        Some(&self.display)
+    }
+
+    pub fn frame_generation(&self) -> u64 {
+        self.frame_generation
+    }
+
+    // The region changed since the last call, none if the display hasn't changed
+    pub fn take_damage(&mut self) -> Option<Damage> {
+        self.damage.take()
+    }
+
+    // The last SYS call ignored with the Warn SysHandler since it was last taken, (address, nnn)
+    pub fn take_sys_call(&mut self) -> Option<(u16, u16)> {
+        self.sys_call.take()
+    }
+
+    // After an instruction or a redraw changed the display
+    pub(crate) fn display_changed(&mut self) {
+        self.frame_generation += 1;
+        self.bus_draw();
+    }
+
+    // Damage the whole display and ask for a redraw, after replacing the display from outside
+    pub fn redraw_all(&mut self) {
+        self.display_changed();
+        self.damage = Some(self.full_damage());
+    }
+
+    pub(crate) fn damage_pixel(&mut self, x: usize, y: usize) {
+        let pixel = Damage::pixel(x, y);
+        self.damage = Some(self.damage.map_or(pixel, |damage| damage.union(pixel)));
+    }
+
+    // Hash of the full machine state, two machines with the same hash are in the same state
+    // FNV-1a is used as it is simple, fast and stable across platforms and versions
+    pub fn state_hash(&self) -> u64 {
+        const FNV_OFFSET: u64 = 0xCBF29CE484222325;
+        const FNV_PRIME: u64 = 0x100000001B3;
+
+        let mut hash = FNV_OFFSET;
+        let mut write = |byte: u8| {
+            hash ^= byte as u64;
+            hash = hash.wrapping_mul(FNV_PRIME);
+        };
+
+        self.i.to_be_bytes().into_iter().for_each(&mut write);
+        self.pc.to_be_bytes().into_iter().for_each(&mut write);
+        self.memory.iter().copied().for_each(&mut write);
+        self.v.iter().copied().for_each(&mut write);
+        self.stack
+            .iter()
+            .flat_map(|addr| addr.to_be_bytes())
+            .for_each(&mut write);
+        self.sp.to_be_bytes().into_iter().for_each(&mut write);
+        write(self.dt);
+        write(self.st);
+        self.keyboard.iter().for_each(|key| write(*key as u8));
+        self.rpl_flags.iter().copied().for_each(&mut write);
+        self.display.iter().copied().for_each(&mut write);
+        // Unseeded numbers differ between runs anyway
+        if let Some(state) = self.rng {
+            state.to_be_bytes().into_iter().for_each(&mut write);
+        }
+        hash
+    }
+
+    /*
+    Keypad
+    Keys are held from key_down to key_up, like the keys of a real keypad, and the program sees
+    them held for as long as they are. Hosts that are only told about presses, e.g. terminals
+    without the kitty keyboard protocol, tap keys instead: tap_key holds a key for a number of
+    frames (vblanks) and releases it by itself, and tapping it again before then, e.g. with the
+    key repeat, keeps it held.
+    */
+    pub fn key_down(&mut self, key: u8) {
+        self.keyboard[key as usize] = true;
+        self.key_release[key as usize] = 0;
+    }
+
+    pub fn key_up(&mut self, key: u8) {
+        self.keyboard[key as usize] = false;
+        self.key_release[key as usize] = 0;
+    }
+
+    pub fn tap_key(&mut self, key: u8, frames: u32) {
         self.keyboard[key as usize] = true;
+        self.key_release[key as usize] = frames.max(1);
     }
 
     pub fn reset_all_keys(&mut self) {
-        for i in 0..KEYBOARD_SIZE {
-            self.keyboard[i] = false;
+        self.keyboard = [false; KEYBOARD_SIZE];
+        self.key_release = [0; KEYBOARD_SIZE];
+        self.key_wait = None;
+    }
+
+    // Release the tapped keys whose time is up, called at every vblank
+    fn release_tapped_keys(&mut self) {
+        for key in 0..KEYBOARD_SIZE {
+            if self.key_release[key] > 0 {
+                self.key_release[key] -= 1;
+                if self.key_release[key] == 0 {
+                    self.keyboard[key] = false;
+                }
+            }
+        }
+    }
+
+    /*
+    True when the program can't make progress until a key is pressed, so a host can stop running
+    cycles and sleep until there is input:
+    - blocked on Fx0A (LD Vx, K), but not once it saw a key pressed and waits for its release
+    - or spinning in a key wait loop, SKP Vx followed by a jump back to it
+    Memory is read without the hooks, checking doesn't count as an access by the program.
+    */
+    pub fn is_waiting_for_key(&self) -> bool {
+        if self.key_wait.is_some() || (0..KEYBOARD_SIZE as u8).any(|key| self.is_key_down(key)) {
+            return false;
+        }
+        let pc = self.pc as usize;
+        let opcode_at = |addr: usize| disasm::opcode_at(&self.memory, addr);
+        let is_skp = |opcode: u16| opcode & 0xF0FF == 0xE09E;
+        match opcode_at(pc) {
+            Some(opcode) if opcode & 0xF0FF == 0xF00A => true,
+            Some(opcode) if is_skp(opcode) => {
+                opcode_at(pc + 2).is_some_and(|jump| is_jump_to(jump, pc))
+            }
+            Some(opcode) if pc >= 2 && is_jump_to(opcode, pc - 2) => {
+                opcode_at(pc - 2).is_some_and(is_skp)
+            }
+            _ => false,
         }
     }
 
-    pub fn run_cycle(&mut self) {
+    /*
+    True when the program has ended and will never run anything else: it exited with 00FD (EXIT)
+    or jumps to itself with 1nnn, the usual way CHIP-8 programs end. Hosts can stop running
+    cycles and tell the user, the timers still count down.
+    */
+    pub fn is_halted(&self) -> bool {
+        let pc = self.pc as usize;
+        disasm::opcode_at(&self.memory, pc)
+            .is_some_and(|opcode| opcode == 0x00FD || is_jump_to(opcode, pc))
+    }
+
+    /*
+    Vertical blank, called by the host at the end of every frame.
+    With the display_wait quirk DRW only runs as the first instruction of a frame: elsewhere it
+    waits, and run_cycle does nothing until the next vblank. Hosts can check is_waiting_for_vblank
+    to end the frame early rather than running empty cycles.
+    */
+    pub fn vblank(&mut self) {
+        self.vblank = true;
+        self.frames_elapsed += 1;
+        self.release_tapped_keys();
+    }
+
+    pub fn is_waiting_for_vblank(&self) -> bool {
+        let pc = self.pc as usize;
+        self.quirks.display_wait
+            && !self.vblank
+            && disasm::opcode_at(&self.memory, pc).is_some_and(|opcode| opcode & 0xF000 == 0xD000)
+    }
+
+    // Executes one instruction, an error leaves the pc past the instruction that caused it
+    pub fn run_cycle(&mut self) -> Result<(), Chip8Error> {
+        self.step_with(&mut ())
+    }
+
+    // run_cycle, with the observer told about the instruction as well, see StepObserver
+    pub fn step_with(&mut self, observer: &mut dyn StepObserver) -> Result<(), Chip8Error> {
+        if self.is_waiting_for_vblank() {
+            return Ok(());
+        }
+        self.vblank = false;
+
         // Fetch the opcode
-        let opcode1 = (self.memory[self.pc as usize] as u16) << 8;
-        let opcode2 = self.memory[self.pc as usize + 1] as u16;
+        let pc = self.pc as usize;
+        if let Some(target) = self.unmapped(pc..pc + 2, false) {
+            return Err(Chip8Error::MemoryOutOfBounds {
+                addr: self.pc,
+                target,
+            });
+        }
+        let opcode1 = (self.read_memory(self.pc as usize) as u16) << 8;
+        let opcode2 = self.read_memory(self.pc as usize + 1) as u16;
         let opcode = opcode1 | opcode2;
+        let step = Step {
+            pc: self.pc,
+            opcode,
+        };
+        self.notify_before_step(step, observer);
 
         // Increment the program counter
-        self.pc += 2;
+        self.pc = self.pc.wrapping_add(2);
+        self.cycles_executed += 1;
 
         // Process the opcode
-        self.process_opcode(opcode);
+        let (dt, st) = (self.dt, self.st);
+        let result = self.process_opcode(opcode);
+        self.notify_timers(dt, st);
+        if result.is_ok() {
+            self.notify_after_step(step, observer);
+        }
+        result
+    }
+
+    // Instructions executed, including those that failed, since the machine was created
+    pub fn cycles_executed(&self) -> u64 {
+        self.cycles_executed
+    }
+
+    // Frames ended with vblank since the machine was created
+    pub fn frames_elapsed(&self) -> u64 {
+        self.frames_elapsed
+    }
+
+    // The deepest the program nested its CALLs since the machine was created, to tell ROM authors
+    // how much of the stack they need
+    pub fn stack_high_water(&self) -> usize {
+        self.stack_high_water
+    }
 
-        // Update the timers
-        self.update_timers();
+    /*
+    Run at most budget instructions, stopping early after an instruction that drew on the display,
+    i.e. changed frame_generation, or when the program waits for a key or the next frame, and can't
+    do anything more until the host presses a key or calls vblank, or when it has ended. This lets frontends schedule the
+    core precisely, e.g. present each frame as soon as it's drawn. cycles_executed tells how many
+    instructions ran.
+    */
+    pub fn run_for(&mut self, budget: u32) -> Result<Stop, Chip8Error> {
+        for _ in 0..budget {
+            if self.is_waiting_for_vblank() {
+                return Ok(Stop::WaitingForVblank);
+            }
+            if self.is_waiting_for_key() {
+                return Ok(Stop::WaitingForKey);
+            }
+            if self.is_halted() {
+                return Ok(Stop::Halted);
+            }
+            let generation = self.frame_generation;
+            self.run_cycle()?;
+            if self.frame_generation != generation {
+                return Ok(Stop::Drawn);
+            }
+        }
+        Ok(Stop::Budget)
     }
 
-    fn process_opcode(&mut self, opcode: u16) {
+    /*
+    Timer clock
+    The timers are not tied to instruction execution, they count down with a clock of their own
+    so they keep the same pace whatever the number of instructions run per frame.
+    The host drives it with a source clock, e.g. once per frame at 60Hz, and the timer clock is
+    derived from it with a fractional divider: every source period adds timer_hz to an accumulator,
+    and the timers tick once for every source_hz accumulated. With timer_hz equal to source_hz
+    this ticks exactly once per call, a 30Hz timer ticks every other call of a 60Hz source, and
+    the remainder is carried over so uneven ratios don't drift.
+    */
+    pub fn clock_timers(&mut self, source_hz: u32) {
+        let source_hz = source_hz.max(1) as u64;
+        self.timer_accumulator += self.timer_hz as u64;
+        while self.timer_accumulator >= source_hz {
+            self.timer_accumulator -= source_hz;
+            let (dt, st) = (self.dt, self.st);
+            self.update_timers();
+            self.notify_timers(dt, st);
+        }
+    }
+
+    fn process_opcode(&mut self, opcode: u16) -> Result<(), Chip8Error> {
         // Variables to store the values of the opcode
         // x - A 4-bit value, the lower 4 bits of the high byte of the instruction
         // y - A 4-bit value, the upper 4 bits of the low byte of the instruction
@@ -202,30 +1356,68 @@ impl Chip8 {
         let x = ((opcode >> 8) & 0x000F) as usize;
         let y = ((opcode >> 4) & 0x000F) as usize;
         let kk = (opcode & 0x00FF) as u8;
-        let nnn = (opcode & 0x0FFF) as u16;
+        let nnn = opcode & 0x0FFF;
         let nibble = (opcode & 0x000F) as u8;
 
+        // The instructions of MEGA-CHIP once it's enabled, see megachip
+        #[cfg(feature = "megachip")]
+        if self.megachip.is_some() && self.megachip_opcode(opcode)? {
+            return Ok(());
+        }
+
         // Mask to extract the most significant nibble to determine the type of instruction
         match opcode & 0xF000 {
             0x0000 => {
-                match nibble {
-                    0x0000 => {
+                match opcode {
+                    0x00E0 => {
                         // 00E0 - CLS
                         // Clear the display
-                        self.display = [false; DISPLAY_SIZE];
-                        self.is_drawing = true;
+                        self.clear_display();
                     }
-                    0x000E => {
+                    HIRES_CLS if self.is_hires() => {
+                        // 0230 - CLS of HiRes CHIP-8, see enable_hires
+                        self.clear_display();
+                    }
+                    0x00EE => {
                         // 00EE - RET
                         // Return from a subroutine
+                        if self.sp == 0 {
+                            return Err(Chip8Error::StackUnderflow {
+                                addr: self.pc.wrapping_sub(2),
+                            });
+                        }
                         self.sp -= 1;
                         self.pc = self.stack[self.sp as usize];
                     }
+                    0x00FD => {
+                        // 00FD - EXIT (SUPER-CHIP)
+                        // Exit the interpreter
+                        // The program stays on this instruction, see is_halted
+                        self.pc = self.pc.wrapping_sub(2);
+                    }
                     _ => {
                         // 0nnn - SYS addr
                         // Jump to a machine code routine at nnn
                         // This instruction is only used on the old computers on which Chip-8 was originally implemented.
-                        self.pc = nnn;
+                        // See SysHandler for how it is handled
+                        match &self.sys_handler {
+                            SysHandler::Ignore => {}
+                            SysHandler::Warn => {
+                                self.sys_call = Some((self.pc.wrapping_sub(2), nnn));
+                            }
+                            SysHandler::Error => {
+                                return Err(Chip8Error::UnsupportedSys {
+                                    addr: self.pc.wrapping_sub(2),
+                                    nnn,
+                                });
+                            }
+                            #[cfg(feature = "std")]
+                            SysHandler::Callback(callback) => {
+                                let callback = callback.clone();
+                                let mut callback = callback.lock().unwrap();
+                                callback(self, nnn);
+                            }
+                        }
                     }
                 }
             }
@@ -237,29 +1429,35 @@ impl Chip8 {
             0x2000 => {
                 // 2nnn - CALL addr
                 // Call subroutine at nnn
+                if self.sp as usize >= self.layout.stack_depth {
+                    return Err(Chip8Error::StackOverflow {
+                        addr: self.pc.wrapping_sub(2),
+                    });
+                }
                 self.stack[self.sp as usize] = self.pc; // Store the current pc on the stack so that RET can return to it later
                 self.sp += 1; // Increment the stack pointer
+                self.stack_high_water = self.stack_high_water.max(self.sp as usize);
                 self.pc = nnn; // Set the pc to the address of the subroutine so that it is executed next
             }
             0x3000 => {
                 // 3xkk - SE Vx, byte
                 // Skip next instruction if Vx = kk
                 if self.v[x] == kk {
-                    self.pc += 2;
+                    self.pc = self.pc.wrapping_add(2);
                 }
             }
             0x4000 => {
                 // 4xkk - SNE Vx, byte
                 // Skip next instruction if Vx != kk
                 if self.v[x] != kk {
-                    self.pc += 2;
+                    self.pc = self.pc.wrapping_add(2);
                 }
             }
             0x5000 => {
                 // 5xy0 - SE Vx, Vy
                 // Skip next instruction if Vx = Vy
                 if self.v[x] == self.v[y] {
-                    self.pc += 2;
+                    self.pc = self.pc.wrapping_add(2);
                 }
             }
             0x6000 => {
@@ -283,16 +1481,25 @@ impl Chip8 {
                         // 8xy1 - OR Vx, Vy
                         // Set Vx = Vx OR Vy
                         self.v[x] |= self.v[y];
+                        if self.quirks.vf_reset {
+                            self.v[0x000F] = 0;
+                        }
                     }
                     0x0002 => {
                         // 8xy2 - AND Vx, Vy
                         // Set Vx = Vx AND Vy
                         self.v[x] &= self.v[y];
+                        if self.quirks.vf_reset {
+                            self.v[0x000F] = 0;
+                        }
                     }
                     0x0003 => {
                         // 8xy3 - XOR Vx, Vy
                         // Set Vx = Vx XOR Vy
                         self.v[x] ^= self.v[y];
+                        if self.quirks.vf_reset {
+                            self.v[0x000F] = 0;
+                        }
                     }
                     0x0004 => {
                         // 8xy4 - ADD Vx, Vy
@@ -322,8 +1529,14 @@ impl Chip8 {
                         // 8xy6 - SHR Vx {, Vy}
                         // Set Vx = Vx SHR 1
                         // If LSB of Vx is 1, then VF is set to 1, otherwise 0. Then Vx is divided by 2.
-                        self.v[0x000F] = self.v[x] & 0x1; // v[x] & 0x1 gets LSB
-                        self.v[x] >>= 1; // Divide by 2 is equivalent to right shift by 1 as each bit represents a power of 2
+                        // With the shift_vy quirk Vy is shifted instead, and the result stored in Vx.
+                        let value = if self.quirks.shift_vy {
+                            self.v[y]
+                        } else {
+                            self.v[x]
+                        };
+                        self.v[0x000F] = value & 0x1; // value & 0x1 gets LSB
+                        self.v[x] = value >> 1; // Divide by 2 is equivalent to right shift by 1 as each bit represents a power of 2
                     }
                     0x0007 => {
                         // 8xy7 - SUBN Vx, Vy
@@ -340,13 +1553,18 @@ impl Chip8 {
                         // 8xyE - SHL Vx {, Vy}
                         // Set Vx = Vx SHL 1
                         // If MSB of Vx is 1, then VF is set to 1, otherwise 0. Then Vx is multiplied by 2.
-                        self.v[0x000F] = (self.v[x] & 0x80) >> 7; // v[x] & 0x80 gets MSB, right shift by 7 to move to LSB
-                        self.v[x] <<= 1; // Multiply by 2 is equivalent to left shift by 1 as each bit represents a power of 2
+                        // With the shift_vy quirk Vy is shifted instead, as for 8xy6.
+                        let value = if self.quirks.shift_vy {
+                            self.v[y]
+                        } else {
+                            self.v[x]
+                        };
+                        self.v[0x000F] = (value & 0x80) >> 7; // value & 0x80 gets MSB, right shift by 7 to move to LSB
+                        self.v[x] = value << 1; // Multiply by 2 is equivalent to left shift by 1 as each bit represents a power of 2
                     }
                     _ => {
                         // Invalid opcode
-                        // Panic!
-                        panic!("Invalid opcode: {:#X}", opcode);
+                        return Err(self.invalid_opcode(opcode));
                     }
                 }
             }
@@ -354,7 +1572,7 @@ impl Chip8 {
                 // 9xy0 - SNE Vx, Vy
                 // Skip next instruction if Vx != Vy
                 if self.v[x] != self.v[y] {
-                    self.pc += 2;
+                    self.pc = self.pc.wrapping_add(2);
                 }
             }
             0xA000 => {
@@ -365,13 +1583,18 @@ impl Chip8 {
             0xB000 => {
                 // Bnnn - JP V0, addr
                 // Jump to location nnn + V0
-                self.pc = nnn + self.v[0] as u16;
+                // With the jump_vx quirk this is Bxnn, a jump to xnn + Vx
+                let offset = if self.quirks.jump_vx {
+                    self.v[x]
+                } else {
+                    self.v[0]
+                };
+                self.pc = nnn + offset as u16;
             }
             0xC000 => {
                 // Cxkk - RND Vx, byte
                 // Set Vx = random byte AND kk
-                let random: u8 = rand::random();
-                self.v[x] = random & kk;
+                self.v[x] = self.random_byte() & kk;
             }
             0xD000 => {
                 // Dxyn - DRW Vx, Vy, nibble
@@ -380,56 +1603,65 @@ impl Chip8 {
                 // These bytes are then displayed as sprites on screen at coordinates (Vx, Vy).
                 // Sprites are XORed onto the existing screen.
                 // If this causes any pixels to be erased, VF is set to 1, otherwise it is set to 0.
-                // If the sprite is positioned so part of it is outside the coordinates of the display, it wraps around to the opposite side of the screen.
+                // If the sprite is positioned so part of it is outside the coordinates of the display, it wraps around to the opposite side of the screen,
+                // or is clipped on the axes where wrapping is disabled by the quirks.
                 let size = nibble as usize;
-                let x = self.v[x] as usize;
-                let y = self.v[y] as usize;
+                self.check_sprite(size)?;
+                let height = self.display_height();
+                let x = self.v[x] as usize % DISPLAY_WIDTH;
+                let y = self.v[y] as usize % height;
 
                 self.v[0x000F] = 0; // Reset collision flag
 
                 for line in 0..size {
                     // Loop through each line of the sprite to draw in display
-                    let buffer = self.memory[self.i as usize + line]; // Read each byte of the sprite from memory, representing a line of 8 pixels
+                    let buffer = self.read_memory(self.bounded(self.i as usize + line, false)); // Read each byte of the sprite from memory, representing a line of 8 pixels
                     for pixel in 0..8 {
                         // Loop through each pixel in the line
                         if (buffer & (0x80 >> pixel)) != 0 {
                             // Check if the pixel is set
+                            // Skip the pixel if it's past an edge that clips
+                            if (!self.quirks.wrap_x && x + pixel >= DISPLAY_WIDTH)
+                                || (!self.quirks.wrap_y && y + line >= height)
+                            {
+                                continue;
+                            }
                             // Calculate the index of the pixel in the display array
                             // x is the starting x coord, pixel is the current pixel in the line, (x + pixel) % DISPLAY_WIDTH wraps around the display
-                            // y is the starting y coord, line is the current line, (y + line) % DISPLAY_HEIGHT wraps around the display
-                            let i = (x + pixel) % DISPLAY_WIDTH
-                                + ((y + line) % DISPLAY_HEIGHT) * DISPLAY_WIDTH;
-                            if self.display[i] {
+                            // y is the starting y coord, line is the current line, (y + line) % height wraps around the display
+                            let (pixel_x, pixel_y) =
+                                ((x + pixel) % DISPLAY_WIDTH, (y + line) % height);
+                            let i = pixel_x + pixel_y * DISPLAY_WIDTH;
+                            if self.display[i] != 0 {
                                 self.v[0x000F] = 1; // Collision detected
                             }
-                            self.display[i] ^= true; // XOR the pixel value
+                            self.display[i] ^= 1; // XOR the pixel value
+                            self.damage_pixel(pixel_x, pixel_y);
                         }
                     }
                 }
-                self.is_drawing = true;
+                self.display_changed();
             }
             0xE000 => {
                 match kk {
                     0x009E => {
                         // Ex9E - SKP Vx
                         // Skip next instruction if key with the value of Vx is pressed
-                        if self.keyboard[self.v[x] as usize] {
-                            self.pc += 2;
+                        // Only the low nibble of Vx selects the key, there are 16 of them
+                        if self.is_key_down(self.v[x] & 0xF) {
+                            self.pc = self.pc.wrapping_add(2);
                         }
                     }
                     0x00A1 => {
                         // ExA1 - SKNP Vx
                         // Skip next instruction if key with the value of Vx is not pressed
-                        if !self.keyboard[self.v[x] as usize] {
-                            self.pc += 2;
-                        } else {
-                            self.reset_all_keys();
+                        if !self.is_key_down(self.v[x] & 0xF) {
+                            self.pc = self.pc.wrapping_add(2);
                         }
                     }
                     _ => {
                         // Invalid opcode
-                        // Panic!
-                        panic!("Invalid opcode: {:#X}", opcode);
+                        return Err(self.invalid_opcode(opcode));
                     }
                 }
             }
@@ -443,21 +1675,20 @@ impl Chip8 {
                     0x000A => {
                         // Fx0A - LD Vx, K
                         // Wait for a key press, store the value of the key in Vx
-                        // This is a blocking operation, this is implemented by moving the pc back by 2 if no key is pressed
-                        let mut is_blocking = true;
-
-                        for i in 0..KEYBOARD_SIZE {
-                            if self.keyboard[i] {
-                                self.v[x] = i as u8;
-                                is_blocking = false;
-                                break;
+                        // As on the COSMAC VIP the key is stored once it is released, so a key
+                        // held down is only read once
+                        // This is a blocking operation, this is implemented by moving the pc back by 2 until then
+                        match self.key_wait {
+                            Some(key) if !self.is_key_down(key) => {
+                                self.v[x] = key;
+                                self.key_wait = None;
+                            }
+                            Some(_) => self.pc = self.pc.wrapping_sub(2),
+                            None => {
+                                self.key_wait =
+                                    (0..KEYBOARD_SIZE as u8).find(|key| self.is_key_down(*key));
+                                self.pc = self.pc.wrapping_sub(2);
                             }
-                        }
-
-                        if is_blocking {
-                            self.pc -= 2;
-                        } else {
-                            self.reset_all_keys();
                         }
                     }
                     0x0015 => {
@@ -478,41 +1709,165 @@ impl Chip8 {
                     0x0029 => {
                         // Fx29 - LD F, Vx
                         // Set I = location of sprite for digit Vx
-                        self.i = self.v[x] as u16 * 5; // * 5 because each sprite is 5 bytes long
+                        self.i = self.font_addr + self.v[x] as u16 * 5; // * 5 because each sprite is 5 bytes long
                     }
                     0x0033 => {
                         // Fx33 - LD B, Vx
                         // Store Binary-Coded Decimal (BCD) representation of Vx in memory locations I, I+1, and I+2
-                        self.memory[self.i as usize] = self.v[x] / 100; // Hundreds digit, x is u8 so no need to mask
-                        self.memory[self.i as usize + 1] = (self.v[x] / 10) % 10; // Tens digit
-                        self.memory[self.i as usize + 2] = self.v[x] % 10; // Ones digit
+                        let i = self.i as usize;
+                        self.check_memory(i, 3, true)?;
+                        // Hundreds, tens and ones digits, x is u8 so no need to mask
+                        let digits = [self.v[x] / 100, (self.v[x] / 10) % 10, self.v[x] % 10];
+                        for (offset, digit) in digits.into_iter().enumerate() {
+                            self.write_memory(self.bounded(i + offset, true), digit);
+                        }
                     }
                     0x0055 => {
                         // Fx55 - LD [I], Vx
                         // Store registers V0 through Vx in memory starting at location I
+                        self.check_memory(self.i as usize, x + 1, true)?;
                         for i in 0..=x {
-                            self.memory[self.i as usize + i] = self.v[i];
+                            self.write_memory(self.bounded(self.i as usize + i, true), self.v[i]);
+                        }
+                        if self.quirks.increment_i {
+                            self.i = self.i.wrapping_add(x as u16 + 1);
                         }
                     }
                     0x0065 => {
                         // Fx65 - LD Vx, [I]
                         // Read registers V0 through Vx from memory starting at location I
+                        self.check_memory(self.i as usize, x + 1, false)?;
                         for i in 0..=x {
-                            self.v[i] = self.memory[self.i as usize + i];
+                            self.v[i] = self.read_memory(self.bounded(self.i as usize + i, false));
                         }
+                        if self.quirks.increment_i {
+                            self.i = self.i.wrapping_add(x as u16 + 1);
+                        }
+                    }
+                    0x0075 => {
+                        // Fx75 - LD R, Vx (SCHIP)
+                        // Store registers V0 through Vx in the RPL user flags
+                        self.rpl_flags[..=x].copy_from_slice(&self.v[..=x]);
+                    }
+                    0x0085 => {
+                        // Fx85 - LD Vx, R (SCHIP)
+                        // Read registers V0 through Vx from the RPL user flags
+                        self.v[..=x].copy_from_slice(&self.rpl_flags[..=x]);
                     }
                     _ => {
                         // Invalid opcode
-                        // Panic!
-                        panic!("Invalid opcode: {:#X}", opcode);
+                        return Err(self.invalid_opcode(opcode));
                     }
                 }
             }
             _ => {
                 // Invalid opcode
-                // Panic!
-                panic!("Invalid opcode: {:#X}", opcode);
+                return Err(self.invalid_opcode(opcode));
+            }
+        }
+        Ok(())
+    }
+
+    // The pc has already moved past the instruction being executed
+    // The first address of the range past the end of memory and not handled by a hook
+    fn unmapped(&self, range: Range<usize>, write: bool) -> Option<usize> {
+        range
+            .into_iter()
+            .find(|addr| *addr >= self.memory.len() && !self.is_hooked(*addr, write))
+    }
+
+    // Whether a memory hook handles reads, or writes, of the address
+    #[cfg(feature = "std")]
+    fn is_hooked(&self, addr: usize, write: bool) -> bool {
+        self.memory_hooks.iter().any(|hook| {
+            let hooked = |range: &RangeInclusive<u16>| {
+                u16::try_from(addr).is_ok_and(|addr| range.contains(&addr))
+            };
+            match hook {
+                MemoryHook::Read(range, _) => !write && hooked(range),
+                MemoryHook::Write(range, _) => write && hooked(range),
             }
+        })
+    }
+
+    // No hooks without std
+    #[cfg(not(feature = "std"))]
+    fn is_hooked(&self, _addr: usize, _write: bool) -> bool {
+        false
+    }
+
+    // Check the len bytes from start before accessing them, so a failed instruction changes nothing
+    // Only the Error policy fails, the others move the address with bounded
+    pub(crate) fn check_memory(
+        &self,
+        start: usize,
+        len: usize,
+        write: bool,
+    ) -> Result<(), Chip8Error> {
+        if self.memory_bounds != MemoryBounds::Error {
+            return Ok(());
+        }
+        match self.unmapped(start..start + len, write) {
+            Some(target) => Err(Chip8Error::MemoryOutOfBounds {
+                addr: self.pc.wrapping_sub(2),
+                target,
+            }),
+            None => Ok(()),
+        }
+    }
+
+    // Check the rows of the sprite DRW is about to read, like check_memory and for strict_sprites
+    fn check_sprite(&self, height: usize) -> Result<(), Chip8Error> {
+        let (addr, i, start) = (self.pc.wrapping_sub(2), self.i, self.i as usize);
+        if self.memory_bounds == MemoryBounds::Error
+            && self.unmapped(start..start + height, false).is_some()
+        {
+            return Err(Chip8Error::SpriteOutOfBounds {
+                addr,
+                i,
+                height: height as u8,
+            });
+        }
+        if !self.strict_sprites {
+            return Ok(());
+        }
+        let font = self.font_addr as usize..self.font_addr as usize + FONT_SIZE;
+        let garbage = (start..start + height)
+            .map(|addr| self.bounded(addr, false))
+            .find(|addr| {
+                !font.contains(addr)
+                    && !self.rom.contains(addr)
+                    && !self.is_written(*addr)
+                    && !self.is_hooked(*addr, false)
+            });
+        match garbage {
+            Some(target) => Err(Chip8Error::SpritePastRom {
+                addr,
+                i,
+                height: height as u8,
+                target: target as u16,
+            }),
+            None => Ok(()),
+        }
+    }
+
+    // The address an I-relative access goes to under the memory bounds policy
+    pub(crate) fn bounded(&self, addr: usize, write: bool) -> usize {
+        if self.unmapped(addr..addr + 1, write).is_none() {
+            return addr;
+        }
+        match self.memory_bounds {
+            // Already rejected by check_memory
+            MemoryBounds::Error => addr,
+            MemoryBounds::Wrap => addr % self.memory.len(),
+            MemoryBounds::Saturate => self.memory.len() - 1,
+        }
+    }
+
+    fn invalid_opcode(&self, opcode: u16) -> Chip8Error {
+        Chip8Error::InvalidOpcode {
+            addr: self.pc.wrapping_sub(2),
+            opcode,
         }
     }
 
@@ -528,8 +1883,10 @@ impl Chip8 {
 }
 
 #[cfg(test)]
+#[allow(clippy::identity_op, clippy::bool_assert_comparison)]
 mod tests {
     use super::*;
+    use alloc::string::ToString;
 
     #[test]
     fn test_new() {
@@ -538,35 +1895,825 @@ mod tests {
         assert_eq!(chip8.pc, MEMORY_START as u16);
         assert_eq!(chip8.memory.len(), MEMORY_SIZE);
         assert_eq!(chip8.v, [0; REGISTERS_SIZE]);
-        assert_eq!(chip8.stack, [0; STACK_SIZE]);
+        assert_eq!(chip8.stack, [0; MAX_STACK_DEPTH]);
         assert_eq!(chip8.sp, 0);
         assert_eq!(chip8.dt, 0);
         assert_eq!(chip8.st, 0);
         assert_eq!(chip8.keyboard, [false; KEYBOARD_SIZE]);
-        assert_eq!(chip8.display, [false; DISPLAY_SIZE]);
+        assert!(chip8.display.iter().all(|pixel| *pixel == 0));
     }
 
     #[test]
     fn test_load_rom() {
         let mut chip8 = Chip8::new();
         let rom = vec![0x00, 0xE0, 0x00, 0xEE];
-        chip8.load_rom(&rom);
+        chip8.load_rom(&rom).unwrap();
         assert_eq!(chip8.memory[MEMORY_START], 0x00);
         assert_eq!(chip8.memory[MEMORY_START + 1], 0xE0);
         assert_eq!(chip8.memory[MEMORY_START + 2], 0x00);
         assert_eq!(chip8.memory[MEMORY_START + 3], 0xEE);
+
+        let mut chip8 = Chip8::with_layout(MemoryLayout::ETI_660);
+        chip8.load_rom(&rom).unwrap();
+        assert_eq!(chip8.pc, 0x600);
+        assert_eq!(chip8.memory[ETI_660_START + 1], 0xE0);
+        assert_eq!(chip8.memory[MEMORY_START + 1], 0x00);
+
+        // The ROM has to fit after the program start
+        let rom = vec![0; MEMORY_SIZE - ETI_660_START + 1];
+        assert_eq!(
+            chip8.load_rom(&rom),
+            Err(RomError::TooLarge {
+                size: 2561,
+                available: 2560
+            })
+        );
+        assert_eq!(chip8.load_rom(&[]), Err(RomError::Empty));
+        assert_eq!(chip8.pc, 0x600);
+        let mut chip8 = Chip8::with_layout(MemoryLayout::XO_CHIP);
+        chip8.load_rom(&rom).unwrap();
+        assert_eq!(chip8.memory.len(), 0x10000);
+    }
+
+    #[test]
+    fn test_state_hash() {
+        let mut chip8 = Chip8::new();
+        let snapshot = chip8.clone();
+        assert_eq!(chip8.state_hash(), snapshot.state_hash());
+
+        chip8.display[0] = 1;
+        assert_ne!(chip8.state_hash(), snapshot.state_hash());
+
+        chip8.display[0] = 0;
+        chip8.v[0xF] = 1;
+        assert_ne!(chip8.state_hash(), snapshot.state_hash());
+
+        chip8.v[0xF] = 0;
+        chip8.seed_rng(1);
+        assert_ne!(chip8.state_hash(), snapshot.state_hash());
+    }
+
+    #[test]
+    fn test_seed_rng() {
+        let run = |seed| {
+            let mut chip8 = Chip8::new();
+            chip8.seed_rng(seed);
+            (0..8)
+                .map(|_| {
+                    chip8.process_opcode(0xC0FF).unwrap();
+                    chip8.v[0]
+                })
+                .collect::<Vec<u8>>()
+        };
+        assert_eq!(run(1), run(1));
+        assert_ne!(run(1), run(2));
+    }
+
+    #[test]
+    fn test_sys_handler() {
+        let mut chip8 = Chip8::new();
+
+        // Ignored by default
+        chip8.process_opcode(0x0123).unwrap();
+        assert_eq!(chip8.pc, MEMORY_START as u16);
+
+        // Warn ignores it too, and keeps the call for the host
+        chip8.sys_handler = SysHandler::Warn;
+        chip8.load_rom(&[0x0A, 0xBC]).unwrap();
+        chip8.run_cycle().unwrap();
+        assert_eq!(chip8.pc, 0x202);
+        assert_eq!(chip8.take_sys_call(), Some((0x200, 0xABC)));
+        assert_eq!(chip8.take_sys_call(), None);
+        chip8.pc = MEMORY_START as u16;
+
+        // The callback receives nnn and can access the machine
+        #[cfg(feature = "std")]
+        {
+            chip8.on_sys_call(|chip8, addr| chip8.i = addr);
+            chip8.process_opcode(0x0456).unwrap();
+            assert_eq!(chip8.i, 0x0456);
+            assert_eq!(chip8.pc, MEMORY_START as u16);
+        }
+    }
+
+    #[test]
+    fn test_rpl_flags() {
+        let mut chip8 = Chip8::new();
+        chip8.v[..4].copy_from_slice(&[1, 2, 3, 4]);
+        // Fx75 saves V0 to V2, Fx85 restores V0 and V1
+        chip8.process_opcode(0xF275).unwrap();
+        assert_eq!(chip8.rpl_flags[..4], [1, 2, 3, 0]);
+        chip8.v = [0; REGISTERS_SIZE];
+        chip8.process_opcode(0xF185).unwrap();
+        assert_eq!(chip8.v[..3], [1, 2, 0]);
+        // I and memory are left alone
+        assert_eq!(chip8.i, 0);
+    }
+
+    #[test]
+    fn test_sys_handler_error() {
+        let mut chip8 = Chip8::new();
+        chip8.sys_handler = SysHandler::Error;
+        chip8.load_rom(&[0x01, 0x23]).unwrap();
+        assert_eq!(
+            chip8.run_cycle(),
+            Err(Chip8Error::UnsupportedSys {
+                addr: 0x200,
+                nnn: 0x123
+            })
+        );
+        assert_eq!(chip8.pc, 0x202);
+    }
+
+    #[test]
+    fn test_invalid_opcode() {
+        let mut chip8 = Chip8::new();
+        chip8.load_rom(&[0xFF, 0xFF, 0x60, 0x01]).unwrap();
+        let error = chip8.run_cycle().unwrap_err();
+        assert_eq!(
+            error,
+            Chip8Error::InvalidOpcode {
+                addr: 0x200,
+                opcode: 0xFFFF
+            }
+        );
+        assert_eq!(error.to_string(), "Invalid opcode 0xFFFF at 0x200");
+        assert_eq!(error.code(), "invalid-opcode");
+        // Carrying on runs the next instruction
+        chip8.run_cycle().unwrap();
+        assert_eq!(chip8.v[0], 1);
+    }
+
+    #[test]
+    fn test_stack_errors() {
+        let mut chip8 = Chip8::new();
+        // RET; CALL 0x202, which calls itself until the stack is full
+        chip8.load_rom(&[0x00, 0xEE, 0x22, 0x02]).unwrap();
+        assert_eq!(
+            chip8.run_cycle(),
+            Err(Chip8Error::StackUnderflow { addr: 0x200 })
+        );
+        for _ in 0..STACK_DEPTH {
+            chip8.run_cycle().unwrap();
+        }
+        let error = chip8.run_cycle().unwrap_err();
+        assert_eq!(error, Chip8Error::StackOverflow { addr: 0x202 });
+        assert_eq!(
+            error.to_string(),
+            "Stack overflow, CALL at 0x202 with a full stack"
+        );
+        assert_eq!(error.code(), "stack-overflow");
+        assert_eq!(chip8.sp, STACK_DEPTH as u16);
+        assert_eq!(chip8.stack_high_water(), STACK_DEPTH);
+
+        // Interpreters with a deeper stack
+        let mut chip8 = Chip8::with_layout(MemoryLayout {
+            stack_depth: 64,
+            ..MemoryLayout::CHIP8
+        });
+        chip8.load_rom(&[0x22, 0x00]).unwrap();
+        for _ in 0..64 {
+            chip8.run_cycle().unwrap();
+        }
+        assert!(matches!(
+            chip8.run_cycle(),
+            Err(Chip8Error::StackOverflow { .. })
+        ));
+        assert_eq!(chip8.stack_high_water(), 64);
+    }
+
+    #[test]
+    fn test_memory_out_of_bounds() {
+        let mut chip8 = Chip8::new();
+        chip8.i = 0xFFE;
+        chip8.v[0] = 0xAB;
+        // DRW V0, V0, 3 reads 0xFFE to 0x1000 and draws nothing
+        chip8.pc = 0x202;
+        let error = chip8.process_opcode(0xD003).unwrap_err();
+        assert_eq!(
+            error,
+            Chip8Error::SpriteOutOfBounds {
+                addr: 0x200,
+                i: 0xFFE,
+                height: 3
+            }
+        );
+        assert_eq!(
+            error.to_string(),
+            "Sprite of 3 rows at I 0xFFE, drawn at 0x200, reads past the end of memory"
+        );
+        assert_eq!(error.code(), "memory-out-of-bounds");
+        assert!(chip8.display.iter().all(|pixel| *pixel == 0));
+        // Fx55 and Fx33 write nothing, Fx65 loads nothing
+        assert!(chip8.process_opcode(0xF255).is_err());
+        assert!(chip8.process_opcode(0xF033).is_err());
+        assert_eq!(chip8.memory[0xFFE..], [0, 0]);
+        assert!(chip8.process_opcode(0xF265).is_err());
+        assert_eq!(chip8.v[0], 0xAB);
+        // In bounds up to the last byte
+        chip8.process_opcode(0xF155).unwrap();
+        assert_eq!(chip8.memory[0xFFE], 0xAB);
+
+        // Running past the end of memory can't fetch the next instruction
+        chip8.pc = 0xFFF;
+        assert_eq!(
+            chip8.run_cycle(),
+            Err(Chip8Error::MemoryOutOfBounds {
+                addr: 0xFFF,
+                target: 0x1000
+            })
+        );
+        assert_eq!(chip8.pc, 0xFFF);
+
+        // Addresses handled by a hook are in bounds
+        #[cfg(feature = "std")]
+        {
+            chip8.on_memory_read(0x1000..=0x1FFF, |_| 0xFF);
+            chip8.process_opcode(0xD003).unwrap();
+            assert!(chip8.process_opcode(0xF255).is_err());
+        }
+    }
+
+    #[test]
+    fn test_strict_sprites() {
+        let mut chip8 = Chip8::new();
+        chip8.strict_sprites = true;
+        // DRW V0, V0, 2 with the sprite in the last byte of the ROM
+        chip8.load_rom(&[0xD0, 0x02, 0xFF]).unwrap();
+        chip8.i = 0x202;
+        let error = chip8.run_cycle().unwrap_err();
+        assert_eq!(
+            error,
+            Chip8Error::SpritePastRom {
+                addr: 0x200,
+                i: 0x202,
+                height: 2,
+                target: 0x203
+            }
+        );
+        assert_eq!(error.code(), "sprite-past-rom");
+        assert!(chip8.display.iter().all(|pixel| *pixel == 0));
+
+        // Memory the program wrote, and the font, can be drawn
+        chip8.process_opcode(0xF155).unwrap();
+        chip8.i = 0x202;
+        chip8.process_opcode(0xD002).unwrap();
+        chip8.i = 0;
+        chip8.process_opcode(0xD005).unwrap();
+        chip8.strict_sprites = false;
+        chip8.i = 0x300;
+        chip8.process_opcode(0xD002).unwrap();
+    }
+
+    #[test]
+    fn test_memory_bounds() {
+        let mut chip8 = Chip8::new();
+        chip8.memory_bounds = MemoryBounds::Wrap;
+        chip8.v[0] = 1;
+        chip8.v[1] = 2;
+        chip8.v[2] = 3;
+        // Fx55 with I = 0xFFE writes V2 to 0x000
+        chip8.i = 0xFFE;
+        chip8.process_opcode(0xF255).unwrap();
+        assert_eq!(chip8.memory[0xFFE..], [1, 2]);
+        assert_eq!(chip8.memory[0x000], 3);
+        // Fx65 reads them back
+        chip8.i = 0xFFE;
+        chip8.process_opcode(0xF565).unwrap();
+        assert_eq!(chip8.v[..3], [1, 2, 3]);
+        // Fx33 of 123 with I = 0xFFF
+        chip8.i = 0xFFF;
+        chip8.v[0] = 123;
+        chip8.process_opcode(0xF033).unwrap();
+        assert_eq!(chip8.memory[0xFFF], 1);
+        assert_eq!(chip8.memory[..2], [2, 3]);
+
+        // Saturate reads and writes the last byte again
+        let mut chip8 = Chip8::new();
+        chip8.memory_bounds = MemoryBounds::Saturate;
+        chip8.memory[0xFFF] = 0x80;
+        chip8.i = 0xFFF;
+        chip8.process_opcode(0xD013).unwrap();
+        assert_eq!(chip8.display[0], 1);
+        assert_eq!(chip8.display[DISPLAY_WIDTH], 1);
+        assert_eq!(chip8.display[2 * DISPLAY_WIDTH], 1);
+        chip8.v[0] = 123;
+        chip8.process_opcode(0xF033).unwrap();
+        assert_eq!(chip8.memory[0xFFF], 3);
+        assert_eq!(chip8.memory[0xFFE], 0);
+
+        // A hook past the end takes the access whatever the policy
+        #[cfg(feature = "std")]
+        {
+            chip8.on_memory_read(0x1000..=0x1000, |_| 0x01);
+            chip8.process_opcode(0xF165).unwrap();
+            assert_eq!(chip8.v[..2], [3, 0x01]);
+        }
+
+        assert_eq!(MemoryBounds::from_name("wrap"), Some(MemoryBounds::Wrap));
+        assert_eq!(MemoryBounds::from_name("clamp"), None);
+    }
+
+    #[test]
+    fn test_end_of_memory() {
+        // The pc wraps to 0 past the last instruction of 64K of memory
+        let mut chip8 = Chip8::with_layout(MemoryLayout::XO_CHIP);
+        chip8.memory[0xFFFE..].copy_from_slice(&[0x60, 0x07]);
+        chip8.pc = 0xFFFE;
+        chip8.run_cycle().unwrap();
+        assert_eq!(chip8.v[0], 7);
+        assert_eq!(chip8.pc, 0x0000);
+
+        // As does a skip over it, SE V0, 7
+        chip8.memory[0xFFFC..0xFFFE].copy_from_slice(&[0x30, 0x07]);
+        chip8.pc = 0xFFFC;
+        chip8.run_cycle().unwrap();
+        assert_eq!(chip8.pc, 0x0000);
+
+        // EXIT stays on the last instruction
+        chip8.memory[0xFFFE..].copy_from_slice(&[0x00, 0xFD]);
+        chip8.pc = 0xFFFE;
+        chip8.run_cycle().unwrap();
+        assert_eq!(chip8.pc, 0xFFFE);
+        assert!(chip8.is_halted());
+
+        // As does LD V1, K, while no key is pressed and then while the key is held down
+        chip8.memory[0xFFFE..].copy_from_slice(&[0xF1, 0x0A]);
+        chip8.run_cycle().unwrap();
+        assert_eq!(chip8.pc, 0xFFFE);
+        chip8.key_down(5);
+        chip8.run_cycle().unwrap();
+        assert_eq!(chip8.pc, 0xFFFE);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_memory_hooks() {
+        let mut chip8 = Chip8::new();
+        let written = Arc::new(Mutex::new(Vec::new()));
+        let log = written.clone();
+        chip8.on_memory_read(0xF00..=0xFFF, |addr| (addr & 0xFF) as u8);
+        chip8.on_memory_write(0xF00..=0xFFF, move |addr, value| {
+            log.lock().unwrap().push((addr, value))
+        });
+
+        // Fx65 reads through the read hook
+        chip8.i = 0xF10;
+        chip8.process_opcode(0xF165).unwrap();
+        assert_eq!(chip8.v[0], 0x10);
+        assert_eq!(chip8.v[1], 0x11);
+
+        // Fx55 writes through the write hook and leaves memory unchanged
+        chip8.v[0] = 0xAB;
+        chip8.process_opcode(0xF055).unwrap();
+        assert_eq!(*written.lock().unwrap(), vec![(0xF10, 0xAB)]);
+        assert_eq!(chip8.memory[0xF10], 0);
+
+        // Accesses outside the hooked range go to memory
+        chip8.i = 0x300;
+        chip8.process_opcode(0xF055).unwrap();
+        assert_eq!(chip8.memory[0x300], 0xAB);
+        assert_eq!(written.lock().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_drw_wrap_quirks() {
+        // Draw the font sprite for 0 (4 pixels wide, 5 high) at the bottom right corner
+        let mut chip8 = Chip8::new();
+        chip8.v[0] = 62;
+        chip8.v[1] = 30;
+        chip8.quirks.wrap_x = true;
+        chip8.quirks.wrap_y = false;
+        chip8.process_opcode(0xD015).unwrap();
+
+        let pixel = |chip8: &Chip8, x: usize, y: usize| chip8.display[y * DISPLAY_WIDTH + x] != 0;
+        assert!(pixel(&chip8, 62, 30));
+        // Wrapped horizontally to the left edge
+        assert!(pixel(&chip8, 1, 30));
+        // Clipped vertically, nothing at the top
+        assert!(!pixel(&chip8, 62, 0));
+        assert!(!pixel(&chip8, 1, 0));
+
+        chip8.display.fill(0);
+        chip8.quirks = Quirks::default();
+        chip8.process_opcode(0xD015).unwrap();
+        assert!(pixel(&chip8, 1, 30));
+        assert!(pixel(&chip8, 62, 0));
+
+        // Starting coordinates wrap even when clipping
+        chip8.display.fill(0);
+        chip8.quirks.wrap_x = false;
+        chip8.quirks.wrap_y = false;
+        chip8.v[0] = 64 + 2;
+        chip8.v[1] = 32 + 3;
+        chip8.process_opcode(0xD015).unwrap();
+        assert!(pixel(&chip8, 2, 3));
+    }
+
+    #[test]
+    fn test_quirks() {
+        let mut chip8 = Chip8::new();
+        chip8.v[1] = 0x03;
+        chip8.v[2] = 0x81;
+        chip8.v[0xF] = 7;
+
+        // 8126 - SHR V1, V2 shifts V1 in place by default, V2 with shift_vy
+        chip8.process_opcode(0x8126).unwrap();
+        assert_eq!((chip8.v[1], chip8.v[0xF]), (0x01, 1));
+        chip8.quirks = Quirks::CHIP8;
+        chip8.process_opcode(0x812E).unwrap();
+        assert_eq!((chip8.v[1], chip8.v[0xF]), (0x02, 1));
+
+        // 8121 - OR V1, V2, 8122 - AND and 8123 - XOR reset VF with vf_reset only
+        chip8.process_opcode(0x8121).unwrap();
+        assert_eq!((chip8.v[1], chip8.v[0xF]), (0x83, 0));
+        chip8.v[0xF] = 1;
+        chip8.process_opcode(0x8122).unwrap();
+        assert_eq!((chip8.v[1], chip8.v[0xF]), (0x81, 0));
+        chip8.v[0xF] = 1;
+        chip8.process_opcode(0x8123).unwrap();
+        assert_eq!((chip8.v[1], chip8.v[0xF]), (0x00, 0));
+        chip8.quirks.vf_reset = false;
+        chip8.v[0xF] = 1;
+        chip8.process_opcode(0x8121).unwrap();
+        assert_eq!((chip8.v[1], chip8.v[0xF]), (0x81, 1));
+        chip8.quirks = Quirks::CHIP8;
+
+        // Fx55 and Fx65 move I past the registers with increment_i
+        chip8.i = 0x300;
+        chip8.process_opcode(0xF255).unwrap();
+        assert_eq!(chip8.i, 0x303);
+        chip8.quirks = Quirks::default();
+        chip8.process_opcode(0xF265).unwrap();
+        assert_eq!(chip8.i, 0x303);
+
+        // B220 jumps to 0x220 + V0, or 0x220 + V2 with jump_vx
+        chip8.v[0] = 0x10;
+        chip8.v[2] = 0x30;
+        chip8.process_opcode(0xB220).unwrap();
+        assert_eq!(chip8.pc, 0x230);
+        chip8.quirks = Quirks::SCHIP;
+        chip8.process_opcode(0xB220).unwrap();
+        assert_eq!(chip8.pc, 0x250);
+
+        assert_eq!(Quirks::from_preset("XO-CHIP"), Some(Quirks::XOCHIP));
+        assert!(Quirks::PRESETS
+            .iter()
+            .all(|name| Quirks::from_preset(name).is_some()));
+        assert_eq!(Quirks::from_preset("chip48"), None);
+    }
+
+    #[test]
+    fn test_font_addr() {
+        let mut chip8 = Chip8::new();
+        chip8.set_font_addr(0x50);
+        assert_eq!(chip8.memory[..0x50], [0; 0x50]);
+        assert_eq!(chip8.memory[0x50..0x55], [0xF0, 0x90, 0x90, 0x90, 0xF0]);
+        // Fx29 - LD F, V0 points I to the sprite for 1
+        chip8.v[0] = 1;
+        chip8.process_opcode(0xF029).unwrap();
+        assert_eq!(chip8.i, 0x55);
+        assert_eq!(chip8.memory[0x55], 0x20);
+    }
+
+    #[test]
+    fn test_damage() {
+        let mut chip8 = Chip8::new();
+        assert_eq!(chip8.take_damage(), None);
+        // DRW V0, V1, 5 with the 0 glyph at (10, 4) damages the 4x5 pixels it sets
+        chip8.v[0] = 10;
+        chip8.v[1] = 4;
+        chip8.process_opcode(0xD015).unwrap();
+        let damage = chip8.take_damage().unwrap();
+        assert_eq!(
+            (damage.x, damage.y, damage.width(), damage.height()),
+            (10, 4, 4, 5)
+        );
+        assert!(damage.contains(13, 8) && !damage.contains(14, 8));
+        assert_eq!(chip8.take_damage(), None);
+
+        // Two sprites damage the rectangle covering both
+        chip8.process_opcode(0xD015).unwrap();
+        chip8.v[0] = 30;
+        chip8.v[1] = 0;
+        chip8.process_opcode(0xD015).unwrap();
+        assert_eq!(
+            chip8.take_damage(),
+            Some(Damage {
+                x: 10,
+                y: 0,
+                right: 34,
+                bottom: 9
+            })
+        );
+
+        chip8.process_opcode(0x00E0).unwrap();
+        assert_eq!(chip8.take_damage(), Some(Damage::FULL));
+    }
+
+    #[test]
+    fn test_run_for() {
+        let mut chip8 = Chip8::new();
+        // LD V0, 1; ADD V0, 1; DRW V0, V0, 1; LD V1, K; an invalid opcode
+        chip8
+            .load_rom(&[0x60, 0x01, 0x70, 0x01, 0xD0, 0x01, 0xF1, 0x0A, 0xFF, 0xFF])
+            .unwrap();
+        assert_eq!(chip8.run_for(1), Ok(Stop::Budget));
+        assert_eq!(chip8.run_for(10), Ok(Stop::Drawn));
+        assert_eq!(chip8.cycles_executed(), 3);
+        assert_eq!(chip8.run_for(10), Ok(Stop::WaitingForKey));
+        assert_eq!(chip8.cycles_executed(), 3);
+        chip8.key_down(2);
+        assert_eq!(chip8.run_for(1), Ok(Stop::Budget));
+        chip8.key_up(2);
+        assert_eq!(chip8.run_for(1), Ok(Stop::Budget));
+        assert_eq!(chip8.v[1], 2);
+
+        chip8.vblank();
+        chip8.vblank();
+        assert_eq!(chip8.frames_elapsed(), 2);
+
+        // Errors are returned as run_cycle returns them
+        assert!(chip8.run_for(1).is_err());
+        assert_eq!(chip8.cycles_executed(), 6);
+    }
+
+    #[test]
+    fn test_halted() {
+        let mut chip8 = Chip8::new();
+        // LD V0, 1; EXIT
+        chip8.load_rom(&[0x60, 0x01, 0x00, 0xFD]).unwrap();
+        assert!(!chip8.is_halted());
+        assert_eq!(chip8.run_for(10), Ok(Stop::Halted));
+        assert_eq!(chip8.cycles_executed(), 1);
+        // EXIT stays where it is when run anyway
+        chip8.run_cycle().unwrap();
+        assert_eq!(chip8.pc, 0x202);
+        assert!(chip8.is_halted());
+
+        // JP to itself, but not a jump elsewhere
+        let mut chip8 = Chip8::new();
+        chip8.load_rom(&[0x12, 0x02, 0x12, 0x02]).unwrap();
+        assert!(!chip8.is_halted());
+        assert_eq!(chip8.run_for(10), Ok(Stop::Halted));
+        assert_eq!(chip8.pc, 0x202);
+
+        // Past 0xFFF a jump can't be to itself, JP 0x200 at 0x1200 isn't
+        let mut chip8 = Chip8::with_layout(MemoryLayout::XO_CHIP);
+        chip8.memory[0x1200..0x1202].copy_from_slice(&[0x12, 0x00]);
+        chip8.pc = 0x1200;
+        assert!(!chip8.is_halted());
+    }
+
+    #[test]
+    fn test_display_wait() {
+        let mut chip8 = Chip8::new();
+        chip8.quirks.display_wait = true;
+        // DRW V0, V0, 1 twice
+        chip8.load_rom(&[0xD0, 0x01, 0xD0, 0x01]).unwrap();
+        // The first instruction of a frame draws straight away
+        chip8.run_cycle().unwrap();
+        assert_eq!(chip8.pc, 0x202);
+        // The next one waits for the vblank
+        assert!(chip8.is_waiting_for_vblank());
+        chip8.run_cycle().unwrap();
+        assert_eq!(chip8.pc, 0x202);
+        chip8.vblank();
+        assert!(!chip8.is_waiting_for_vblank());
+        chip8.run_cycle().unwrap();
+        assert_eq!(chip8.pc, 0x204);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_timer_events() {
+        let mut chip8 = Chip8::new();
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let recorded = events.clone();
+        chip8.on_timer_event(move |event| recorded.lock().unwrap().push(event));
+        // LD V0, 2; LD ST, V0; LD DT, V0
+        chip8
+            .load_rom(&[0x60, 0x02, 0xF0, 0x18, 0xF0, 0x15])
+            .unwrap();
+        for _ in 0..3 {
+            chip8.run_cycle().unwrap();
+        }
+        assert_eq!(*events.lock().unwrap(), [TimerEvent::SoundStarted]);
+
+        chip8.clock_timers(60);
+        assert_eq!(events.lock().unwrap().len(), 1);
+        chip8.clock_timers(60);
+        assert_eq!(
+            *events.lock().unwrap(),
+            [
+                TimerEvent::SoundStarted,
+                TimerEvent::SoundStopped,
+                TimerEvent::DelayExpired
+            ]
+        );
+    }
+
+    #[test]
+    fn test_clock_timers() {
+        let mut chip8 = Chip8::new();
+        // Executing instructions doesn't count the timers down
+        chip8.dt = 10;
+        chip8.st = 10;
+        chip8.memory[MEMORY_START] = 0x12;
+        chip8.memory[MEMORY_START + 1] = 0x00;
+        chip8.run_cycle().unwrap();
+        chip8.run_cycle().unwrap();
+        assert_eq!(chip8.dt, 10);
+
+        // Once per source period when both clocks run at 60Hz
+        chip8.clock_timers(60);
+        assert_eq!((chip8.dt, chip8.st), (9, 9));
+
+        // A 30Hz timer clock ticks every other period
+        chip8.timer_hz = 30;
+        chip8.clock_timers(60);
+        assert_eq!(chip8.dt, 9);
+        chip8.clock_timers(60);
+        assert_eq!(chip8.dt, 8);
+
+        // Faster timer clocks tick several times per period
+        chip8.timer_hz = 120;
+        chip8.clock_timers(60);
+        assert_eq!(chip8.dt, 6);
+
+        // Uneven ratios carry the remainder over, 50Hz on a 60Hz source ticks 5 times in 6 periods
+        chip8.timer_hz = 50;
+        chip8.dt = 10;
+        for _ in 0..6 {
+            chip8.clock_timers(60);
+        }
+        assert_eq!(chip8.dt, 5);
+    }
+
+    #[test]
+    fn test_is_waiting_for_key() {
+        let mut chip8 = Chip8::new();
+        // LD V0, K
+        chip8.load_rom(&[0xF0, 0x0A]).unwrap();
+        assert!(chip8.is_waiting_for_key());
+        chip8.key_down(3);
+        assert!(!chip8.is_waiting_for_key());
+
+        // SKP V1; JP 0x200, waiting on both instructions of the loop
+        let mut chip8 = Chip8::new();
+        chip8.load_rom(&[0xE1, 0x9E, 0x12, 0x00]).unwrap();
+        assert!(chip8.is_waiting_for_key());
+        chip8.run_cycle().unwrap();
+        assert_eq!(chip8.pc, 0x202);
+        assert!(chip8.is_waiting_for_key());
+
+        // A loop that also does something else isn't a key wait
+        let mut chip8 = Chip8::new();
+        chip8
+            .load_rom(&[0xE1, 0x9E, 0x70, 0x01, 0x12, 0x00])
+            .unwrap();
+        assert!(!chip8.is_waiting_for_key());
+
+        // SKP V1; JP 0x200 at 0x1200 jumps elsewhere
+        let mut chip8 = Chip8::with_layout(MemoryLayout::XO_CHIP);
+        chip8.memory[0x1200..0x1204].copy_from_slice(&[0xE1, 0x9E, 0x12, 0x00]);
+        chip8.pc = 0x1200;
+        assert!(!chip8.is_waiting_for_key());
+        chip8.pc = 0x1202;
+        assert!(!chip8.is_waiting_for_key());
+    }
+
+    #[test]
+    fn test_hires() {
+        // JP 0x260, the patch of the interpreter, then the program at 0x2C0
+        let mut rom = vec![0x12, 0x60];
+        rom.resize(0xC0, 0);
+        // LD V0, 40; LD I, 0x2D0; DRW V0, V0, 1; SYS 0x230
+        rom.extend_from_slice(&[0x60, 0x28, 0xA2, 0xD0, 0xD0, 0x01, 0x02, 0x30]);
+        rom.resize(0xD1, 0x80);
+        assert!(is_hires_rom(&rom));
+        assert!(!is_hires_rom(&[0x12, 0x00]));
+
+        let mut chip8 = Chip8::new();
+        chip8.load_rom(&rom).unwrap();
+        chip8.enable_hires();
+        assert_eq!(chip8.display_height(), HIRES_DISPLAY_HEIGHT);
+        assert_eq!(chip8.take_damage(), Some(chip8.full_damage()));
+        chip8.run_cycle().unwrap();
+        assert_eq!(chip8.pc, HIRES_PROGRAM_START);
+        for _ in 0..3 {
+            chip8.run_cycle().unwrap();
+        }
+        // Drawn below the 32 rows of the usual display, rather than wrapped
+        assert_eq!(chip8.display[40 * DISPLAY_WIDTH + 40], 1);
+        chip8.run_cycle().unwrap();
+        assert!(chip8.display.iter().all(|pixel| *pixel == 0));
+
+        // The CLS of HiRes CHIP-8 stops run_for like the other drawing instructions
+        chip8.reset();
+        assert_eq!(chip8.run_for(4), Ok(Stop::Drawn));
+        assert_eq!(chip8.pc, HIRES_PROGRAM_START + 6);
+        assert_eq!(chip8.run_for(4), Ok(Stop::Drawn));
+        assert_eq!(chip8.pc, HIRES_PROGRAM_START + 8);
+
+        // 0230 is only a SYS call on the usual display
+        let mut chip8 = Chip8::new();
+        chip8.sys_handler = SysHandler::Warn;
+        chip8.display[0] = 1;
+        chip8.process_opcode(HIRES_CLS).unwrap();
+        assert_eq!(chip8.display[0], 1);
+        assert_eq!(chip8.take_sys_call().map(|(_, nnn)| nnn), Some(0x230));
+    }
+
+    #[test]
+    fn test_take_frame() {
+        let mut chip8 = Chip8::new();
+        // LD V0, 1; DRW V0, V0, 5; CLS
+        chip8
+            .load_rom(&[0x60, 0x01, 0xD0, 0x05, 0x00, 0xE0])
+            .unwrap();
+        assert!(chip8.take_frame().is_none());
+        chip8.run_cycle().unwrap();
+        assert!(chip8.take_frame().is_none());
+
+        chip8.run_cycle().unwrap();
+        let generation = chip8.frame_generation();
+        assert_eq!(chip8.take_frame().map(|display| display[65]), Some(1));
+        // Only once
+        assert!(chip8.take_frame().is_none());
+        assert_eq!(chip8.frame_generation(), generation);
+
+        chip8.run_cycle().unwrap();
+        assert!(chip8
+            .take_frame()
+            .is_some_and(|display| display.iter().all(|p| *p == 0)));
+        chip8.redraw_all();
+        assert!(chip8.take_frame().is_some());
+    }
+
+    #[test]
+    fn test_reset() {
+        let mut chip8 = Chip8::new();
+        // CALL 0x206; LD V0, 5; DRW V0, V0, 5; ADD V1, 1
+        chip8
+            .load_rom(&[0x22, 0x06, 0x00, 0x00, 0x00, 0x00, 0x60, 0x05, 0xD0, 0x05])
+            .unwrap();
+        for _ in 0..3 {
+            chip8.run_cycle().unwrap();
+        }
+        chip8.st = 10;
+        chip8.keyboard[3] = true;
+        chip8.memory[0x300] = 0xAA;
+        chip8.memory[0x202] = 0xBB;
+        chip8.mark_written(0x202);
+        let cycles = chip8.cycles_executed();
+
+        chip8.reset();
+        assert_eq!(chip8.pc, 0x200);
+        assert_eq!((chip8.sp, chip8.i, chip8.v[0], chip8.st), (0, 0, 0, 0));
+        assert!(!chip8.keyboard[3]);
+        assert!(chip8.display.iter().all(|pixel| *pixel == 0));
+        assert_eq!(chip8.take_damage(), Some(Damage::FULL));
+        // Memory goes back to the ROM as loaded, the program runs again
+        assert_eq!(chip8.memory[0x300], 0);
+        assert_eq!(chip8.memory[0x202], 0x00);
+        assert!(!chip8.is_written(0x202));
+        assert_eq!(chip8.memory[..FONT_SIZE], CHAR_SPRITES);
+        assert_eq!(chip8.cycles_executed(), cycles);
+        chip8.run_cycle().unwrap();
+        assert_eq!(chip8.pc, 0x206);
+
+        chip8.enable_hires();
+        chip8.reset();
+        assert!(chip8.is_hires());
+
+        // A HiRes program skips the patch of the interpreter again
+        let mut chip8 = Chip8::new();
+        chip8.load_rom(&[0x12, 0x60, 0x00, 0xE0]).unwrap();
+        chip8.enable_hires();
+        chip8.reset();
+        assert_eq!(
+            chip8.memory[0x200..0x202],
+            (0x1000 | HIRES_PROGRAM_START).to_be_bytes()
+        );
+
+        // Without a ROM loaded, memory is left as it is
+        let mut chip8 = Chip8::new();
+        chip8.memory[0x200..0x202].copy_from_slice(&[0x12, 0x00]);
+        chip8.reset();
+        assert_eq!(chip8.memory[0x200..0x202], [0x12, 0x00]);
     }
 
     #[test]
     fn test_run_cycle() {
         let mut chip8 = Chip8::new();
         // test opcode 0x00E0
-        chip8.display = [true; DISPLAY_SIZE];
+        chip8.display.fill(1);
         chip8.memory[MEMORY_START] = 0x00;
         chip8.memory[MEMORY_START + 1] = 0xE0;
 
-        chip8.run_cycle();
-        assert_eq!(chip8.display, [false; DISPLAY_SIZE]);
+        chip8.run_cycle().unwrap();
+        assert!(chip8.display.iter().all(|pixel| *pixel == 0));
     }
 
     #[test]
@@ -575,27 +2722,27 @@ mod tests {
 
         // 0x00E0 - CLS
         // Clear the display
-        chip8.display = [true; DISPLAY_SIZE];
-        chip8.process_opcode(0x00E0);
-        assert_eq!(chip8.display, [false; DISPLAY_SIZE]);
+        chip8.display.fill(1);
+        chip8.process_opcode(0x00E0).unwrap();
+        assert!(chip8.display.iter().all(|pixel| *pixel == 0));
 
         // 0x00EE - RET
         // Return from a subroutine
         chip8.sp = 1;
         chip8.stack[0] = 0x0200;
-        chip8.process_opcode(0x00EE);
+        chip8.process_opcode(0x00EE).unwrap();
 
         assert_eq!(chip8.pc, 0x0200);
         assert_eq!(chip8.sp, 0);
 
         // 0x1nnn - JP addr
         // Jump to location nnn
-        chip8.process_opcode(0x1200);
+        chip8.process_opcode(0x1200).unwrap();
         assert_eq!(chip8.pc, 0x0200);
 
         // 0x2nnn - CALL addr
         // Call subroutine at nnn
-        chip8.process_opcode(0x2200);
+        chip8.process_opcode(0x2200).unwrap();
         assert_eq!(chip8.pc, 0x0200);
         assert_eq!(chip8.stack[0], 0x0200);
         assert_eq!(chip8.sp, 1);
@@ -603,20 +2750,20 @@ mod tests {
         // 0x3xkk - SE Vx, byte
         // Skip next instruction if Vx = kk
         chip8.v[0] = 0x01;
-        chip8.process_opcode(0x3001);
+        chip8.process_opcode(0x3001).unwrap();
         assert_eq!(chip8.pc, 0x0202);
 
         // 0x4xkk - SNE Vx, byte
         // Skip next instruction if Vx != kk
         chip8.v[0] = 0x01;
-        chip8.process_opcode(0x4002);
+        chip8.process_opcode(0x4002).unwrap();
         assert_eq!(chip8.pc, 0x0204);
 
         // 0x5xy0 - SE Vx, Vy
         // Skip next instruction if Vx = Vy
         chip8.v[0] = 0x01;
         chip8.v[1] = 0x01;
-        chip8.process_opcode(0x5010);
+        chip8.process_opcode(0x5010).unwrap();
         assert_eq!(chip8.pc, 0x0206);
 
         // reset pc
@@ -624,48 +2771,48 @@ mod tests {
 
         // 0x6xkk - LD Vx, byte
         // Set Vx = kk
-        chip8.process_opcode(0x6001);
+        chip8.process_opcode(0x6001).unwrap();
         assert_eq!(chip8.v[0], 0x01);
 
         // 0x7xkk - ADD Vx, byte
         // Set Vx = Vx + kk
         chip8.v[0] = 0x01;
-        chip8.process_opcode(0x7001);
+        chip8.process_opcode(0x7001).unwrap();
         assert_eq!(chip8.v[0], 0x02);
 
         // 0x8xy0 - LD Vx, Vy
         // Set Vx = Vy
         chip8.v[0] = 0x01;
         chip8.v[1] = 0x02;
-        chip8.process_opcode(0x8010);
+        chip8.process_opcode(0x8010).unwrap();
         assert_eq!(chip8.v[0], 0x02);
 
         // 0x8xy1 - OR Vx, Vy
         // Set Vx = Vx OR Vy
         chip8.v[0] = 0x01;
         chip8.v[1] = 0x02;
-        chip8.process_opcode(0x8011);
+        chip8.process_opcode(0x8011).unwrap();
         assert_eq!(chip8.v[0], 0x03);
 
         // 0x8xy2 - AND Vx, Vy
         // Set Vx = Vx AND Vy
         chip8.v[0] = 0x01;
         chip8.v[1] = 0x02;
-        chip8.process_opcode(0x8012);
+        chip8.process_opcode(0x8012).unwrap();
         assert_eq!(chip8.v[0], 0x00);
 
         // 0x8xy3 - XOR Vx, Vy
         // Set Vx = Vx XOR Vy
         chip8.v[0] = 0x01;
         chip8.v[1] = 0x02;
-        chip8.process_opcode(0x8013);
+        chip8.process_opcode(0x8013).unwrap();
         assert_eq!(chip8.v[0], 0x03);
 
         // 0x8xy4 - ADD Vx, Vy
         // Set Vx = Vx + Vy, set VF = carry
         chip8.v[0] = 0xFF;
         chip8.v[1] = 0x01;
-        chip8.process_opcode(0x8014);
+        chip8.process_opcode(0x8014).unwrap();
         assert_eq!(chip8.v[0], 0x00);
         assert_eq!(chip8.v[0x000F], 1);
 
@@ -673,14 +2820,14 @@ mod tests {
         // Set Vx = Vx - Vy, set VF = NOT borrow
         chip8.v[0] = 0x02;
         chip8.v[1] = 0x01;
-        chip8.process_opcode(0x8015);
+        chip8.process_opcode(0x8015).unwrap();
         assert_eq!(chip8.v[0], 0x01);
         assert_eq!(chip8.v[0x000F], 1);
 
         // 0x8xy6 - SHR Vx {, Vy}
         // Set Vx = Vx SHR 1
         chip8.v[0] = 0x03;
-        chip8.process_opcode(0x8006);
+        chip8.process_opcode(0x8006).unwrap();
         assert_eq!(chip8.v[0], 0x01);
         assert_eq!(chip8.v[0x000F], 1);
 
@@ -688,14 +2835,14 @@ mod tests {
         // Set Vx = Vy - Vx, set VF = NOT borrow
         chip8.v[0] = 0x01;
         chip8.v[1] = 0x02;
-        chip8.process_opcode(0x8017);
+        chip8.process_opcode(0x8017).unwrap();
         assert_eq!(chip8.v[0], 0x01);
         assert_eq!(chip8.v[0x000F], 1);
 
         // 0x8xyE - SHL Vx {, Vy}
         // Set Vx = Vx SHL 1
         chip8.v[0] = 0x01;
-        chip8.process_opcode(0x800E);
+        chip8.process_opcode(0x800E).unwrap();
         assert_eq!(chip8.v[0], 0x02);
         assert_eq!(chip8.v[0x000F], 0);
 
@@ -706,12 +2853,12 @@ mod tests {
         // Skip next instruction if Vx != Vy
         chip8.v[0] = 0x01;
         chip8.v[1] = 0x02;
-        chip8.process_opcode(0x9010);
+        chip8.process_opcode(0x9010).unwrap();
         assert_eq!(chip8.pc, 0x0202);
 
         // 0xAnnn - LD I, addr
         // Set I = nnn
-        chip8.process_opcode(0xA123);
+        chip8.process_opcode(0xA123).unwrap();
         assert_eq!(chip8.i, 0x0123);
 
         // reset pc
@@ -720,7 +2867,7 @@ mod tests {
         // 0xBnnn - JP V0, addr
         // Jump to location nnn + V0
         chip8.v[0] = 0x01;
-        chip8.process_opcode(0xB123);
+        chip8.process_opcode(0xB123).unwrap();
         assert_eq!(chip8.pc, 0x0124);
 
         // reset pc
@@ -729,42 +2876,42 @@ mod tests {
         // 0xCxkk - RND Vx, byte
         // Set Vx = random byte AND kk
         let old_vx = chip8.v[0];
-        chip8.process_opcode(0xC0FF);
+        chip8.process_opcode(0xC0FF).unwrap();
         assert_ne!(chip8.v[0], old_vx);
 
         // 0xDxyn - DRW Vx, Vy, nibble
         chip8.i = 0x0;
         chip8.v[0] = 0x0;
         chip8.v[1] = 0x1;
-        chip8.process_opcode(0xD015);
+        chip8.process_opcode(0xD015).unwrap();
 
         // Row 0 (y = 1)
-        assert_eq!(chip8.display[0 + 1 * DISPLAY_WIDTH], true);
-        assert_eq!(chip8.display[1 + 1 * DISPLAY_WIDTH], true);
-        assert_eq!(chip8.display[2 + 1 * DISPLAY_WIDTH], true);
-        assert_eq!(chip8.display[3 + 1 * DISPLAY_WIDTH], true);
+        assert_eq!(chip8.display[0 + 1 * DISPLAY_WIDTH], 1);
+        assert_eq!(chip8.display[1 + 1 * DISPLAY_WIDTH], 1);
+        assert_eq!(chip8.display[2 + 1 * DISPLAY_WIDTH], 1);
+        assert_eq!(chip8.display[3 + 1 * DISPLAY_WIDTH], 1);
 
         // Row 1 (y = 2)
-        assert_eq!(chip8.display[0 + 2 * DISPLAY_WIDTH], true);
-        assert_eq!(chip8.display[3 + 2 * DISPLAY_WIDTH], true);
+        assert_eq!(chip8.display[0 + 2 * DISPLAY_WIDTH], 1);
+        assert_eq!(chip8.display[3 + 2 * DISPLAY_WIDTH], 1);
 
         // Row 2 (y = 3)
-        assert_eq!(chip8.display[0 + 3 * DISPLAY_WIDTH], true);
-        assert_eq!(chip8.display[3 + 3 * DISPLAY_WIDTH], true);
+        assert_eq!(chip8.display[0 + 3 * DISPLAY_WIDTH], 1);
+        assert_eq!(chip8.display[3 + 3 * DISPLAY_WIDTH], 1);
 
         // Row 3 (y = 4)
-        assert_eq!(chip8.display[0 + 4 * DISPLAY_WIDTH], true);
-        assert_eq!(chip8.display[3 + 4 * DISPLAY_WIDTH], true);
+        assert_eq!(chip8.display[0 + 4 * DISPLAY_WIDTH], 1);
+        assert_eq!(chip8.display[3 + 4 * DISPLAY_WIDTH], 1);
 
         // Row 4 (y = 5)
-        assert_eq!(chip8.display[0 + 5 * DISPLAY_WIDTH], true);
-        assert_eq!(chip8.display[1 + 5 * DISPLAY_WIDTH], true);
-        assert_eq!(chip8.display[2 + 5 * DISPLAY_WIDTH], true);
-        assert_eq!(chip8.display[3 + 5 * DISPLAY_WIDTH], true);
+        assert_eq!(chip8.display[0 + 5 * DISPLAY_WIDTH], 1);
+        assert_eq!(chip8.display[1 + 5 * DISPLAY_WIDTH], 1);
+        assert_eq!(chip8.display[2 + 5 * DISPLAY_WIDTH], 1);
+        assert_eq!(chip8.display[3 + 5 * DISPLAY_WIDTH], 1);
 
         assert_eq!(chip8.v[0x000F], 0); // No collision detected
 
-        chip8.process_opcode(0xD015); // Draw the same sprite again
+        chip8.process_opcode(0xD015).unwrap(); // Draw the same sprite again
         assert_eq!(chip8.v[0x000F], 1); // Collision detected
 
         // reset pc
@@ -774,14 +2921,14 @@ mod tests {
         // Skip next instruction if key with the value of Vx is pressed
         chip8.keyboard[0] = true;
         chip8.v[0] = 0x00;
-        chip8.process_opcode(0xE09E);
+        chip8.process_opcode(0xE09E).unwrap();
         assert_eq!(chip8.pc, 0x0202);
 
         // 0xExA1 - SKNP Vx
         // Skip next instruction if key with the value of Vx is not pressed
         chip8.keyboard[0] = false;
         chip8.v[0] = 0x00;
-        chip8.process_opcode(0xE0A1);
+        chip8.process_opcode(0xE0A1).unwrap();
         assert_eq!(chip8.pc, 0x0204);
 
         // reset pc
@@ -790,18 +2937,22 @@ mod tests {
         // 0xFx07 - LD Vx, DT
         // Set Vx = delay timer value
         chip8.dt = 0x01;
-        chip8.process_opcode(0xF007);
+        chip8.process_opcode(0xF007).unwrap();
         assert_eq!(chip8.v[0], 0x01);
 
         // 0xFx0A - LD Vx, K
         // Wait for a key press, store the value of the key in Vx
         chip8.keyboard[0] = false;
-        chip8.process_opcode(0xF00A);
+        chip8.process_opcode(0xF00A).unwrap();
         assert_eq!(chip8.pc, 0x01FE); // pc should be decremented by 2 as this is a blocking operation
 
         chip8.keyboard[0] = true;
-        chip8.process_opcode(0xF00A);
+        chip8.process_opcode(0xF00A).unwrap();
+        assert_eq!(chip8.pc, 0x01FC); // still blocking until the key is released
+        chip8.keyboard[0] = false;
+        chip8.process_opcode(0xF00A).unwrap();
         assert_eq!(chip8.v[0], 0x00);
+        assert_eq!(chip8.pc, 0x01FC);
 
         // reset pc
         chip8.pc = MEMORY_START as u16;
@@ -809,33 +2960,33 @@ mod tests {
         // 0xFx15 - LD DT, Vx
         // Set delay timer = Vx
         chip8.v[0] = 0x01;
-        chip8.process_opcode(0xF015);
+        chip8.process_opcode(0xF015).unwrap();
         assert_eq!(chip8.dt, 0x01);
 
         // 0xFx18 - LD ST, Vx
         // Set sound timer = Vx
         chip8.v[0] = 0x01;
-        chip8.process_opcode(0xF018);
+        chip8.process_opcode(0xF018).unwrap();
         assert_eq!(chip8.st, 0x01);
 
         // 0xFx1E - ADD I, Vx
         // Set I = I + Vx
         chip8.i = 0x01;
         chip8.v[0] = 0x01;
-        chip8.process_opcode(0xF01E);
+        chip8.process_opcode(0xF01E).unwrap();
         assert_eq!(chip8.i, 0x02);
 
         // 0xFx29 - LD F, Vx
         // Set I = location of sprite for digit Vx
         chip8.v[0] = 0x01;
-        chip8.process_opcode(0xF029);
+        chip8.process_opcode(0xF029).unwrap();
         assert_eq!(chip8.i, 0x05);
 
         // 0xFx33 - LD B, Vx
         // Store Binary-Coded Decimal (BCD) representation of Vx in memory locations I, I+1, and I+2
         chip8.i = 0x00;
         chip8.v[0] = 123;
-        chip8.process_opcode(0xF033);
+        chip8.process_opcode(0xF033).unwrap();
         assert_eq!(chip8.memory[0], 1);
         assert_eq!(chip8.memory[1], 2);
         assert_eq!(chip8.memory[2], 3);
@@ -845,7 +2996,7 @@ mod tests {
         chip8.i = 0x00;
         chip8.v[0] = 0x01;
         chip8.v[1] = 0x02;
-        chip8.process_opcode(0xF155);
+        chip8.process_opcode(0xF155).unwrap();
         assert_eq!(chip8.memory[0], 0x01);
         assert_eq!(chip8.memory[1], 0x02);
 
@@ -856,7 +3007,7 @@ mod tests {
         chip8.memory[1] = 0x02;
         chip8.v[0] = 0x00;
         chip8.v[1] = 0x00;
-        chip8.process_opcode(0xF165);
+        chip8.process_opcode(0xF165).unwrap();
         assert_eq!(chip8.v[0], 0x01);
         assert_eq!(chip8.v[1], 0x02);
     }
@@ -0,0 +1,38 @@
+/*
+System clipboard
+Ctrl+C, Ctrl+R and Ctrl+L copy the frame as text art, the register dump and the disassembly pane
+as text, for pasting into a bug report or a chat. It needs the clipboard feature (arboard), off
+by default since it pulls in the X11 and Wayland libraries on Linux:
+
+    cargo run --features clipboard -- pong.ch8
+
+The clipboard is opened on the first copy and kept open for the session, as on X11 the contents
+are served by the process that copied them.
+*/
+#[derive(Default)]
+pub struct Clipboard {
+    #[cfg(feature = "clipboard")]
+    inner: Option<arboard::Clipboard>,
+}
+
+impl Clipboard {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    #[cfg(feature = "clipboard")]
+    pub fn copy(&mut self, text: &str) -> Result<(), String> {
+        let clipboard = match &mut self.inner {
+            Some(clipboard) => clipboard,
+            None => self
+                .inner
+                .insert(arboard::Clipboard::new().map_err(|e| e.to_string())?),
+        };
+        clipboard.set_text(text).map_err(|e| e.to_string())
+    }
+
+    #[cfg(not(feature = "clipboard"))]
+    pub fn copy(&mut self, _text: &str) -> Result<(), String> {
+        Err("built without the clipboard feature".to_string())
+    }
+}
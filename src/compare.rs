@@ -0,0 +1,264 @@
+use crate::{diagnostic::Diagnostic, read_rom};
+use rustc8::{chip8::MEMORY_START, disasm};
+use std::{collections::HashSet, fmt::Write};
+
+const COMPARE_USAGE: &str = "Usage: cargo run compare <ROM file> <ROM file> [--context N]";
+// Unchanged lines shown around each change
+const DEFAULT_CONTEXT: usize = 2;
+
+// An instruction or a data byte, with its bytes in each ROM, None past the end of a ROM
+struct Line<'a> {
+    addr: usize,
+    code: bool,
+    a: Option<&'a [u8]>,
+    b: Option<&'a [u8]>,
+}
+
+impl Line<'_> {
+    fn changed(&self) -> bool {
+        self.a != self.b
+    }
+}
+
+/*
+ROM comparison, `rustc8 compare <a> <b>`
+Prints the differences between two ROMs as a diff of their disassembly, for studying patched or
+hacked variants of a game:
+
+    --- pong.ch8 (246 bytes)
+    +++ pong-hack.ch8 (246 bytes)
+    @@ 0x206 @@
+      206  6A02  LD VA, 0x02
+    - 208  6B0C  LD VB, 0x0C
+    + 208  6B10  LD VB, 0x10
+      20A  6C3F  LD VC, 0x3F
+    ...
+    1 instruction and 0 data bytes changed
+
+The bytes are compared at the same addresses, so it suits patches that change bytes in place
+rather than ones that move the code around. Code is found by following the jumps, calls and
+skips from the start of each ROM and is compared instruction by instruction, everything else
+as data bytes, shown with their bits to make sprites readable. Code only reached through
+JP V0 can't be followed and is shown as data.
+*/
+pub fn run(args: &[String]) -> Result<(), Diagnostic> {
+    let mut paths = Vec::new();
+    let mut context = DEFAULT_CONTEXT;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--context" => {
+                let value = args.next().ok_or("Missing value for --context")?;
+                context = value
+                    .parse()
+                    .map_err(|_| format!("Invalid number of lines: {}", value))?;
+            }
+            _ => paths.push(arg),
+        }
+    }
+    let [a, b] = paths[..] else {
+        return Err(COMPARE_USAGE.into());
+    };
+    let (rom_a, rom_b) = (read_rom(a)?, read_rom(b)?);
+    print!("{}", report((a, &rom_a), (b, &rom_b), context));
+    Ok(())
+}
+
+fn report(a: (&str, &[u8]), b: (&str, &[u8]), context: usize) -> String {
+    let (name_a, rom_a) = a;
+    let (name_b, rom_b) = b;
+    let mut text = String::new();
+    let _ = writeln!(text, "--- {} ({} bytes)", name_a, rom_a.len());
+    let _ = writeln!(text, "+++ {} ({} bytes)", name_b, rom_b.len());
+
+    let lines = lines(rom_a, rom_b);
+    let changed: Vec<usize> = (0..lines.len())
+        .filter(|index| lines[*index].changed())
+        .collect();
+    if changed.is_empty() {
+        let _ = writeln!(text, "The ROMs are identical");
+        return text;
+    }
+
+    // Changes with overlapping or adjacent context share a hunk
+    let mut hunks: Vec<(usize, usize)> = Vec::new();
+    for &index in &changed {
+        let start = index.saturating_sub(context);
+        let end = (index + context + 1).min(lines.len());
+        match hunks.last_mut() {
+            Some(hunk) if start <= hunk.1 => hunk.1 = end,
+            _ => hunks.push((start, end)),
+        }
+    }
+    for (start, end) in hunks {
+        let _ = writeln!(text, "@@ {:#05X} @@", lines[start].addr);
+        for line in &lines[start..end] {
+            if !line.changed() {
+                let _ = writeln!(text, "  {}", format_line(line.addr, line.code, line.a));
+                continue;
+            }
+            for (marker, bytes) in [('-', line.a), ('+', line.b)] {
+                if bytes.is_some() {
+                    let _ = writeln!(
+                        text,
+                        "{} {}",
+                        marker,
+                        format_line(line.addr, line.code, bytes)
+                    );
+                }
+            }
+        }
+    }
+
+    let instructions = changed.iter().filter(|index| lines[**index].code).count();
+    let bytes = changed.len() - instructions;
+    let _ = writeln!(
+        text,
+        "{} {} and {} data {} changed",
+        instructions,
+        if instructions == 1 {
+            "instruction"
+        } else {
+            "instructions"
+        },
+        bytes,
+        if bytes == 1 { "byte" } else { "bytes" },
+    );
+    text
+}
+
+// The ROMs split into instructions and data bytes, code in either ROM is compared as code
+fn lines<'a>(rom_a: &'a [u8], rom_b: &'a [u8]) -> Vec<Line<'a>> {
+    let code: HashSet<usize> = code_addresses(rom_a, MEMORY_START)
+        .union(&code_addresses(rom_b, MEMORY_START))
+        .copied()
+        .collect();
+    let end = rom_a.len().max(rom_b.len());
+    let mut lines = Vec::new();
+    let mut offset = 0;
+    while offset < end {
+        let addr = MEMORY_START + offset;
+        // An instruction cut off by the end of both ROMs is a data byte
+        let size = match code.contains(&addr) && offset + 2 <= end {
+            true => 2,
+            false => 1,
+        };
+        let bytes = |rom: &'a [u8]| rom.get(offset..(offset + size).min(rom.len()));
+        lines.push(Line {
+            addr,
+            code: size == 2,
+            a: bytes(rom_a).filter(|bytes| !bytes.is_empty()),
+            b: bytes(rom_b).filter(|bytes| !bytes.is_empty()),
+        });
+        offset += size;
+    }
+    lines
+}
+
+// "208  6B0C  LD VB, 0x0C" for an instruction, "2A4  3C    DB 0x3C  ..####.." for a byte, also
+// used by listing
+pub fn format_line(addr: usize, code: bool, bytes: Option<&[u8]>) -> String {
+    let bytes = bytes.unwrap_or_default();
+    match bytes {
+        [high, low] if code => {
+            let opcode = u16::from_be_bytes([*high, *low]);
+            format!(
+                "{:03X}  {:04X}  {}",
+                addr,
+                opcode,
+                disasm::disassemble(opcode)
+            )
+        }
+        _ => {
+            let byte = bytes.first().copied().unwrap_or_default();
+            let bits: String = (0..8)
+                .map(|bit| match byte & (0x80 >> bit) {
+                    0 => '.',
+                    _ => '#',
+                })
+                .collect();
+            format!("{:03X}  {:02X}    DB {:#04X}  {}", addr, byte, byte, bits)
+        }
+    }
+}
+
+// Addresses of the instructions reached from the start of the ROM loaded at start
+pub fn code_addresses(rom: &[u8], start: usize) -> HashSet<usize> {
+    let mut code = HashSet::new();
+    let mut pending = vec![start];
+    while let Some(addr) = pending.pop() {
+        if addr < start || code.contains(&addr) {
+            continue;
+        }
+        let Some(opcode) = disasm::opcode_at(rom, addr - start) else {
+            continue;
+        };
+        if !disasm::is_instruction(opcode) {
+            continue;
+        }
+        code.insert(addr);
+        let nnn = (opcode & 0x0FFF) as usize;
+        match opcode & 0xF000 {
+            0x0000 if opcode == 0x00EE => {}
+            0x1000 => pending.push(nnn),
+            0x2000 => pending.extend([nnn, addr + 2]),
+            0x3000 | 0x4000 | 0x5000 | 0x9000 | 0xE000 => pending.extend([addr + 2, addr + 4]),
+            // The target depends on V0
+            0xB000 => {}
+            _ => pending.push(addr + 2),
+        }
+    }
+    code
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // LD VA, 0x02; LD VB, 0x0C; LD I, 0x20A; DRW VA, VB, 1; JP 0x208; sprite 0x3C
+    const ROM: [u8; 11] = [
+        0x6A, 0x02, 0x6B, 0x0C, 0xA2, 0x0A, 0xDA, 0xB1, 0x12, 0x08, 0x3C,
+    ];
+
+    #[test]
+    fn test_code_addresses() {
+        let code = code_addresses(&ROM, MEMORY_START);
+        let mut code: Vec<usize> = code.into_iter().collect();
+        code.sort();
+        assert_eq!(code, [0x200, 0x202, 0x204, 0x206, 0x208]);
+        // Both sides of a skip and past a call, nothing after a return
+        let code = code_addresses(
+            &[0x3A, 0x01, 0x22, 0x08, 0x00, 0xEE, 0x00, 0xE0],
+            MEMORY_START,
+        );
+        assert!(code.contains(&0x202) && code.contains(&0x204));
+        assert!(!code.contains(&0x206));
+    }
+
+    #[test]
+    fn test_report() {
+        assert!(report(("a", &ROM), ("b", &ROM), 2).ends_with("The ROMs are identical\n"));
+
+        let mut hack = ROM.to_vec();
+        hack[3] = 0x10;
+        hack[10] = 0x7E;
+        hack.push(0xFF);
+        let text = report(("pong.ch8", &ROM), ("hack.ch8", &hack), 1);
+        assert_eq!(
+            text,
+            "--- pong.ch8 (11 bytes)\n\
+             +++ hack.ch8 (12 bytes)\n\
+             @@ 0x200 @@\n  \
+               200  6A02  LD VA, 0x02\n\
+             - 202  6B0C  LD VB, 0x0C\n\
+             + 202  6B10  LD VB, 0x10\n  \
+               204  A20A  LD I, 0x20A\n\
+             @@ 0x208 @@\n  \
+               208  1208  JP 0x208\n\
+             - 20A  3C    DB 0x3C  ..####..\n\
+             + 20A  7E    DB 0x7E  .######.\n\
+             + 20B  FF    DB 0xFF  ########\n\
+             1 instruction and 2 data bytes changed\n"
+        );
+    }
+}
@@ -0,0 +1,114 @@
+use rustc8::storage::{FileStorage, Storage};
+use serde_json::{Map, Value};
+use std::{env, io, path::PathBuf};
+
+pub const CONFIG_KEY: &str = "config.json";
+
+/*
+Files kept between runs (the ROM database, ...) are stored in a per-user directory:
+- $XDG_CONFIG_HOME/rustc8, or ~/.config/rustc8 on Linux and macOS
+- %APPDATA%\rustc8 on Windows
+*/
+pub fn config_dir() -> Option<PathBuf> {
+    // Unset and empty variables are treated the same
+    let var = |name: &str| env::var_os(name).filter(|value| !value.is_empty());
+    let base = var("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| var("APPDATA").map(PathBuf::from))
+        .or_else(|| var("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+    Some(base.join("rustc8"))
+}
+
+// The storage of the files kept between runs, the current directory without a config directory
+pub fn storage() -> FileStorage {
+    FileStorage::new(config_dir().unwrap_or_default())
+}
+
+/*
+Config file
+config.json in the config directory holds the defaults of the options, written by the setup
+wizard on the first run (see setup) and edited by hand, along with the palettes (see palette):
+
+    {"renderer": "braille", "keymap": "azerty", "rom_dir": "/home/me/roms"}
+
+Options given on the command line take precedence. Unknown entries are kept when the file is
+written.
+*/
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Config {
+    pub renderer: Option<String>,
+    // Name of a preset, see input::Keymap
+    pub keymap: Option<String>,
+    // Opened in the browser when no ROM is given
+    pub rom_dir: Option<String>,
+}
+
+// The entries of config.json, none without the file
+pub fn read(storage: &dyn Storage) -> Result<Map<String, Value>, String> {
+    if !storage.exists(CONFIG_KEY) {
+        return Ok(Map::new());
+    }
+    let json = storage.read(CONFIG_KEY).map_err(|e| e.to_string())?;
+    match serde_json::from_slice(&json) {
+        Ok(Value::Object(entries)) => Ok(entries),
+        Ok(_) => Err(format!("{}: not a JSON object", CONFIG_KEY)),
+        Err(e) => Err(format!("{}: {}", CONFIG_KEY, e)),
+    }
+}
+
+pub fn load(storage: &dyn Storage) -> Result<Config, String> {
+    let entries = read(storage)?;
+    let text = |name: &str| entries.get(name)?.as_str().map(str::to_string);
+    Ok(Config {
+        renderer: text("renderer"),
+        keymap: text("keymap"),
+        rom_dir: text("rom_dir").filter(|dir| !dir.is_empty()),
+    })
+}
+
+// Write the settings of the config, creating the file if there is none
+pub fn save(storage: &mut dyn Storage, config: &Config) -> io::Result<()> {
+    let mut entries = read(storage).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    let settings = [
+        ("renderer", &config.renderer),
+        ("keymap", &config.keymap),
+        ("rom_dir", &config.rom_dir),
+    ];
+    for (name, value) in settings {
+        match value {
+            Some(value) => entries.insert(name.to_string(), value.as_str().into()),
+            None => entries.remove(name),
+        };
+    }
+    let json = serde_json::to_string_pretty(&entries).map_err(io::Error::other)?;
+    storage.write(CONFIG_KEY, json.as_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rustc8::storage::MemoryStorage;
+
+    #[test]
+    fn test_save_and_load() {
+        let mut storage = MemoryStorage::new();
+        assert_eq!(load(&storage), Ok(Config::default()));
+
+        storage
+            .write(CONFIG_KEY, br#"{"palettes": {}, "rom_dir": "old"}"#)
+            .unwrap();
+        let config = Config {
+            renderer: Some("braille".to_string()),
+            keymap: Some("azerty".to_string()),
+            rom_dir: None,
+        };
+        save(&mut storage, &config).unwrap();
+        assert_eq!(load(&storage), Ok(config));
+        // Entries that aren't settings are kept
+        assert!(read(&storage).unwrap().contains_key("palettes"));
+
+        storage.write(CONFIG_KEY, b"[]").unwrap();
+        assert!(load(&storage).is_err());
+        assert!(save(&mut storage, &Config::default()).is_err());
+    }
+}
@@ -0,0 +1,158 @@
+use rustc8::{
+    chip8::Chip8,
+    disasm,
+    step::{Step, StepObserver},
+    storage::Storage,
+};
+use std::{io, ops::Range};
+
+const DIR: &str = "coverage";
+
+// Flags of a byte of the ROM
+pub const EXECUTED: u8 = 1;
+pub const READ: u8 = 2;
+
+/*
+Coverage
+What each byte of the ROM has been used for while it ran: executed as an instruction, or read
+through I as data by DRW or LD Vx, [I], e.g. a sprite. It's kept for each ROM as
+coverage/<CRC-32 of the ROM>.cov in the storage, a byte of flags per byte of the ROM, and every
+run adds to what the earlier ones found, so `rustc8 disasm --analyze` can tell the code from the
+data (see listing). It's written when the ROM is left, by dropping the Coverage.
+Only the bytes the ROM was loaded to are followed, not what the program writes elsewhere.
+*/
+pub struct Coverage<S: Storage> {
+    storage: S,
+    rom_crc: u32,
+    rom: Range<usize>,
+    flags: Vec<u8>,
+    // Whether flags has anything the storage doesn't
+    changed: bool,
+}
+
+fn key(rom_crc: u32) -> String {
+    format!("{}/{:08x}.cov", DIR, rom_crc)
+}
+
+// The flags of the earlier runs of a ROM of len bytes, none if it hasn't run
+pub fn load(storage: &dyn Storage, rom_crc: u32, len: usize) -> Option<Vec<u8>> {
+    storage
+        .read(&key(rom_crc))
+        .ok()
+        .filter(|flags| flags.len() == len)
+}
+
+// The bytes an instruction reads or writes through I
+pub enum Access {
+    Read(Range<usize>),
+    Write(Range<usize>),
+}
+
+pub fn data_access(chip8: &Chip8, opcode: u16) -> Option<Access> {
+    let i = chip8.i as usize;
+    let x = ((opcode & 0x0F00) >> 8) as usize;
+    match opcode & 0xF0FF {
+        0xF033 => Some(Access::Write(i..i + 3)),
+        0xF055 => Some(Access::Write(i..i + x + 1)),
+        0xF065 => Some(Access::Read(i..i + x + 1)),
+        _ if opcode & 0xF000 == 0xD000 => Some(Access::Read(i..i + (opcode & 0xF) as usize)),
+        _ => None,
+    }
+}
+
+impl<S: Storage> Coverage<S> {
+    // Follow the ROM loaded at rom, starting from what the earlier runs found
+    pub fn new(storage: S, rom_crc: u32, rom: Range<usize>) -> Self {
+        let flags = load(&storage, rom_crc, rom.len()).unwrap_or_else(|| vec![0; rom.len()]);
+        Coverage {
+            storage,
+            rom_crc,
+            rom,
+            flags,
+            changed: false,
+        }
+    }
+
+    // Called before each cycle with the machine as the instruction finds it
+    pub fn record_step(&mut self, chip8: &Chip8) {
+        let pc = chip8.pc as usize;
+        self.mark(pc..pc + 2, EXECUTED);
+        let Some(opcode) = disasm::opcode_at(&chip8.memory, pc) else {
+            return;
+        };
+        if let Some(Access::Read(range)) = data_access(chip8, opcode) {
+            self.mark(range, READ);
+        }
+    }
+
+    fn mark(&mut self, range: Range<usize>, flag: u8) {
+        let start = range.start.max(self.rom.start);
+        let end = range.end.min(self.rom.end);
+        for addr in start..end {
+            let flags = &mut self.flags[addr - self.rom.start];
+            if *flags & flag == 0 {
+                *flags |= flag;
+                self.changed = true;
+            }
+        }
+    }
+
+    pub fn save(&mut self) -> io::Result<()> {
+        if self.changed {
+            self.storage.write(&key(self.rom_crc), &self.flags)?;
+            self.changed = false;
+        }
+        Ok(())
+    }
+}
+
+impl<S: Storage> StepObserver for Coverage<S> {
+    fn before_step(&mut self, _step: Step, chip8: &Chip8) {
+        self.record_step(chip8);
+    }
+}
+
+// Coverage is a nice to have, it's not worth stopping on an error when leaving
+impl<S: Storage> Drop for Coverage<S> {
+    fn drop(&mut self) {
+        let _ = self.save();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rustc8::storage::MemoryStorage;
+
+    #[test]
+    fn test_coverage() {
+        let mut chip8 = Chip8::new();
+        // LD I, 0x208; DRW V0, V0, 2; JP 0x204; a sprite of 2 rows and an unused byte
+        let rom = [
+            0xA2, 0x08, 0xD0, 0x02, 0x12, 0x04, 0x00, 0x00, 0x3C, 0x7E, 0xFF,
+        ];
+        chip8.load_rom(&rom).unwrap();
+        let mut coverage = Coverage::new(MemoryStorage::new(), 7, 0x200..0x200 + rom.len());
+        for _ in 0..4 {
+            chip8.step_with(&mut coverage).unwrap();
+        }
+        let (x, r) = (EXECUTED, READ);
+        let flags = [x, x, x, x, x, x, 0, 0, r, r, 0];
+        assert_eq!(coverage.flags, flags);
+        coverage.save().unwrap();
+        assert!(!coverage.changed);
+        assert_eq!(load(&coverage.storage, 7, rom.len()), Some(flags.to_vec()));
+        assert_eq!(load(&coverage.storage, 7, 3), None);
+        assert_eq!(load(&coverage.storage, 8, rom.len()), None);
+
+        // Later runs add to it
+        let storage = std::mem::take(&mut coverage.storage);
+        let mut coverage = Coverage::new(storage, 7, 0x200..0x200 + rom.len());
+        chip8.pc = 0x206;
+        coverage.record_step(&chip8);
+        assert_eq!(coverage.flags[..8], [x; 8]);
+        // Outside the ROM is ignored
+        chip8.pc = 0x100;
+        coverage.record_step(&chip8);
+    }
+}
@@ -0,0 +1,179 @@
+use crate::{gzip, platform::Platform};
+use serde_json::Value;
+use std::{collections::HashMap, fmt, path::Path};
+
+/*
+ROM database
+Metadata about known programs, from the index of the chip8Archive
+(https://github.com/JohnEarnest/chip8Archive), a JSON object with one entry per program:
+
+"pong": {
+    "title": "Pong", "authors": ["..."], "desc": "...", "release": "2019-10-30",
+    "platform": "chip8", "options": { "tickrate": 20, "shiftQuirks": false, ... }
+}
+
+Programs are keyed by their file name without the extension, which is how ROMs are matched.
+Only the fields used by the emulator are kept, quirk flags are the boolean options ending in "Quirks".
+*/
+pub const ARCHIVE_INDEX_URL: &str =
+    "https://raw.githubusercontent.com/JohnEarnest/chip8Archive/master/programs.json";
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum DatabaseError {
+    Json(String),
+    InvalidIndex,
+}
+
+impl fmt::Display for DatabaseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DatabaseError::Json(e) => write!(f, "Invalid JSON in ROM database: {}", e),
+            DatabaseError::InvalidIndex => write!(f, "ROM database is not a program index"),
+        }
+    }
+}
+
+impl std::error::Error for DatabaseError {}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ProgramInfo {
+    pub id: String,
+    pub title: String,
+    pub authors: Vec<String>,
+    pub description: String,
+    pub release: Option<String>,
+    pub platform: Option<Platform>,
+    // Instructions per frame the program was made for
+    pub tickrate: Option<u32>,
+    pub quirks: HashMap<String, bool>,
+}
+
+#[derive(Debug, Default)]
+pub struct Database {
+    programs: HashMap<String, ProgramInfo>,
+}
+
+impl Database {
+    pub fn parse(json: &str) -> Result<Self, DatabaseError> {
+        let index: Value =
+            serde_json::from_str(json).map_err(|e| DatabaseError::Json(e.to_string()))?;
+        let entries = index.as_object().ok_or(DatabaseError::InvalidIndex)?;
+        let programs = entries
+            .iter()
+            .map(|(id, entry)| (id.clone(), parse_program(id, entry)))
+            .collect();
+        Ok(Database { programs })
+    }
+
+    pub fn len(&self) -> usize {
+        self.programs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.programs.is_empty()
+    }
+
+    pub fn get(&self, id: &str) -> Option<&ProgramInfo> {
+        self.programs.get(id)
+    }
+
+    // Look up a ROM by its file name, e.g. roms/pong.ch8 and roms/pong.ch8.gz match the "pong" entry
+    pub fn find_rom(&self, path: &Path) -> Option<&ProgramInfo> {
+        self.get(gzip::strip_extension(path).file_stem()?.to_str()?)
+    }
+
+    // Programs sorted by title
+    pub fn programs(&self) -> Vec<&ProgramInfo> {
+        let mut programs: Vec<&ProgramInfo> = self.programs.values().collect();
+        programs.sort_by(|a, b| a.title.cmp(&b.title));
+        programs
+    }
+}
+
+fn parse_program(id: &str, entry: &Value) -> ProgramInfo {
+    let text = |key: &str| entry.get(key).and_then(Value::as_str).map(str::to_string);
+    let options = entry.get("options").and_then(Value::as_object);
+    ProgramInfo {
+        id: id.to_string(),
+        title: text("title").unwrap_or_else(|| id.to_string()),
+        authors: entry
+            .get("authors")
+            .and_then(Value::as_array)
+            .map(|authors| {
+                authors
+                    .iter()
+                    .filter_map(|author| author.as_str().map(str::to_string))
+                    .collect()
+            })
+            .unwrap_or_default(),
+        description: text("desc").unwrap_or_default(),
+        release: text("release"),
+        platform: text("platform").and_then(|name| Platform::from_name(&name)),
+        tickrate: options
+            .and_then(|options| options.get("tickrate"))
+            .and_then(Value::as_u64)
+            .map(|tickrate| tickrate as u32),
+        quirks: options
+            .map(|options| {
+                options
+                    .iter()
+                    .filter(|(key, _)| key.ends_with("Quirks"))
+                    .filter_map(|(key, value)| Some((key.clone(), value.as_bool()?)))
+                    .collect()
+            })
+            .unwrap_or_default(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const INDEX: &str = r##"{
+        "pong": {
+            "title": "Pong",
+            "authors": ["Paul Vervalin"],
+            "desc": "Two player pong",
+            "release": "1990-01-01",
+            "platform": "chip8",
+            "options": { "tickrate": 15, "shiftQuirks": true, "jumpQuirks": false, "fillColor": "#FFFFFF" }
+        },
+        "ant": { "title": "An Ant", "platform": "schip" },
+        "nameless": {}
+    }"##;
+
+    #[test]
+    fn test_parse() {
+        let database = Database::parse(INDEX).unwrap();
+        assert_eq!(database.len(), 3);
+
+        let pong = database.find_rom(Path::new("roms/pong.ch8")).unwrap();
+        assert_eq!(pong.title, "Pong");
+        assert_eq!(pong.authors, ["Paul Vervalin"]);
+        assert_eq!(pong.platform, Some(Platform::Chip8));
+        assert_eq!(pong.tickrate, Some(15));
+        assert_eq!(pong.quirks.len(), 2);
+        assert!(pong.quirks["shiftQuirks"]);
+
+        assert_eq!(
+            database.get("ant").unwrap().platform,
+            Some(Platform::SuperChip)
+        );
+        assert_eq!(database.get("nameless").unwrap().title, "nameless");
+        let titles: Vec<&str> = database
+            .programs()
+            .iter()
+            .map(|p| p.title.as_str())
+            .collect();
+        assert_eq!(titles, ["An Ant", "Pong", "nameless"]);
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert!(matches!(Database::parse("{"), Err(DatabaseError::Json(_))));
+        assert_eq!(
+            Database::parse("[]").unwrap_err(),
+            DatabaseError::InvalidIndex
+        );
+    }
+}
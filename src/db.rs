@@ -0,0 +1,65 @@
+use crate::{config, diagnostic::Diagnostic};
+use rustc8::{
+    database::{self, Database},
+    storage::Storage,
+};
+
+const DB_USAGE: &str = "Usage: cargo run db update|path";
+const INDEX_KEY: &str = "programs.json";
+
+// Load the downloaded ROM database, if there is one
+pub fn load(storage: &dyn Storage) -> Option<Database> {
+    let json = storage.read(INDEX_KEY).ok()?;
+    Database::parse(&String::from_utf8_lossy(&json)).ok()
+}
+
+// The db subcommand, e.g. `rustc8 db update`
+pub fn run(args: &[String]) -> Result<(), Diagnostic> {
+    let mut storage = config::storage();
+    match args.first().map(String::as_str) {
+        Some("update") => {
+            println!("Downloading {}", database::ARCHIVE_INDEX_URL);
+            let json = download().map_err(|e| Diagnostic::new("database", e))?;
+            let database = save(&mut storage, &json).map_err(|e| Diagnostic::new("database", e))?;
+            println!(
+                "Saved {} programs to {}",
+                database.len(),
+                storage.location(INDEX_KEY)
+            );
+            Ok(())
+        }
+        Some("path") => {
+            println!("{}", storage.location(INDEX_KEY));
+            Ok(())
+        }
+        _ => Err(DB_USAGE.into()),
+    }
+}
+
+// Save a downloaded index, checking it before replacing the previous one
+pub fn save(storage: &mut dyn Storage, json: &str) -> Result<Database, String> {
+    let database = Database::parse(json).map_err(|e| e.to_string())?;
+    storage
+        .write(INDEX_KEY, json.as_bytes())
+        .map_err(|e| format!("Failed to write {}: {}", storage.location(INDEX_KEY), e))?;
+    Ok(database)
+}
+
+// Also run from the ROM browser with F5
+#[cfg(feature = "net")]
+pub fn download() -> Result<String, String> {
+    ureq::get(database::ARCHIVE_INDEX_URL)
+        .call()
+        .map_err(|e| format!("Failed to download the ROM database: {}", e))?
+        .into_string()
+        .map_err(|e| format!("Failed to download the ROM database: {}", e))
+}
+
+#[cfg(not(feature = "net"))]
+pub fn download() -> Result<String, String> {
+    Err(format!(
+        "Built without networking (the net feature), download {} to {} instead",
+        database::ARCHIVE_INDEX_URL,
+        config::storage().location(INDEX_KEY)
+    ))
+}
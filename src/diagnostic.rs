@@ -0,0 +1,99 @@
+use rustc8::chip8::Chip8Error;
+use serde_json::json;
+use std::{fmt, process};
+
+/*
+Diagnostics
+The errors that end a command, with a code that stays the same between versions so scripts can
+tell failures apart without reading the messages. They're printed as text, or with --json-errors
+(accepted by every command) as a line of JSON on stderr:
+
+    {"code":"invalid-opcode","message":"Invalid opcode 0xFFFF at 0x200"}
+
+and the exit status is 1. The codes:
+- usage: the command line is invalid, e.g. an unknown value or a missing ROM
+- io: a file, directory, socket or the terminal failed
+- not-found: a directory has no ROMs
+- rom-format: the file can't be loaded as a ROM, e.g. empty, corrupt gzip data or too large for
+  memory
+- patch: the --patch file can't be applied
+- assembly: the source doesn't assemble
+- database: the ROM database couldn't be downloaded, read or saved
+- invalid-opcode, unsupported-sys, stack-overflow, stack-underflow, memory-out-of-bounds,
+  sprite-past-rom: the program stopped on an error of the core, see Chip8Error
+- panic: the emulator crashed running the ROM, from `rustc8 batch`
+*/
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub code: &'static str,
+    pub message: String,
+}
+
+impl Diagnostic {
+    pub fn new(code: &'static str, message: impl Into<String>) -> Self {
+        Diagnostic {
+            code,
+            message: message.into(),
+        }
+    }
+
+    pub fn io(message: impl Into<String>) -> Self {
+        Diagnostic::new("io", message)
+    }
+
+    pub fn to_json(&self) -> String {
+        json!({"code": self.code, "message": self.message}).to_string()
+    }
+
+    // Print the error and exit with status 1, only once the terminal has been restored
+    pub fn exit(&self, json: bool) -> ! {
+        match json {
+            true => eprintln!("{}", self.to_json()),
+            false => println!("{}", self.message),
+        }
+        process::exit(1)
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl From<Chip8Error> for Diagnostic {
+    fn from(error: Chip8Error) -> Self {
+        Diagnostic::new(error.code(), error.to_string())
+    }
+}
+
+// Messages without a code are mistakes on the command line
+impl From<String> for Diagnostic {
+    fn from(message: String) -> Self {
+        Diagnostic::new("usage", message)
+    }
+}
+
+impl From<&str> for Diagnostic {
+    fn from(message: &str) -> Self {
+        Diagnostic::new("usage", message)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_diagnostic() {
+        let error = Chip8Error::InvalidOpcode {
+            addr: 0x200,
+            opcode: 0xFFFF,
+        };
+        assert_eq!(
+            Diagnostic::from(error).to_json(),
+            r#"{"code":"invalid-opcode","message":"Invalid opcode 0xFFFF at 0x200"}"#
+        );
+        assert_eq!(Diagnostic::from("Missing ROM").code, "usage");
+    }
+}
@@ -0,0 +1,262 @@
+use ratatui::{
+    buffer::Buffer,
+    crossterm::event::KeyCode,
+    layout::{Constraint, Flex, Layout, Rect},
+    style::{Modifier, Style},
+    text::{Line, Span},
+    widgets::{Block, Clear, Paragraph, Widget, Wrap},
+};
+
+// Width of the popup, the text is wrapped to fit
+const DIALOG_WIDTH: u16 = 60;
+// Rows of a list shown at once, it scrolls to keep the selected item visible
+const LIST_ROWS: usize = 10;
+
+/*
+Dialog
+A modal popup drawn over the display, with a message and a row of choices, e.g.
+"[C] Ignore and continue  [R] Reset  [Q] Quit". A choice is picked with its key, or selected
+with Left/Right/Tab and confirmed with Enter. The caller decides what each choice does, and
+keeps the keys from reaching the game while the dialog is open.
+Dialogs can also ask for:
+- a line of text, e.g. a file name; typed keys then go to the text and choices are only picked
+  with Enter
+- an item of a list, selected with Up/Down, with an optional preview of the selected item shown
+  below the list, e.g. the thumbnail of a save state
+*/
+pub struct Dialog {
+    title: String,
+    lines: Vec<String>,
+    // Key and label of each choice
+    choices: Vec<(char, String)>,
+    selected: usize,
+    input: Option<String>,
+    items: Vec<String>,
+    item: usize,
+    // Lines shown below the list for each item
+    previews: Vec<String>,
+}
+
+impl Dialog {
+    pub fn new(title: &str) -> Self {
+        Dialog {
+            title: title.to_string(),
+            lines: Vec::new(),
+            choices: Vec::new(),
+            selected: 0,
+            input: None,
+            items: Vec::new(),
+            item: 0,
+            previews: Vec::new(),
+        }
+    }
+
+    // Ask for a line of text, starting with the given value
+    pub fn input(mut self, text: &str) -> Self {
+        self.input = Some(text.to_string());
+        self
+    }
+
+    pub fn items(mut self, items: Vec<String>) -> Self {
+        self.items = items;
+        self
+    }
+
+    // Select an item of the list, the first one by default
+    pub fn select(mut self, item: usize) -> Self {
+        self.item = item.min(self.items.len().saturating_sub(1));
+        self
+    }
+
+    pub fn previews(mut self, previews: Vec<String>) -> Self {
+        self.previews = previews;
+        self
+    }
+
+    pub fn input_text(&self) -> &str {
+        self.input.as_deref().unwrap_or("")
+    }
+
+    // Index of the selected item of the list
+    pub fn selected_item(&self) -> usize {
+        self.item
+    }
+
+    pub fn line(mut self, text: &str) -> Self {
+        self.lines.push(text.to_string());
+        self
+    }
+
+    pub fn choice(mut self, key: char, label: &str) -> Self {
+        self.choices
+            .push((key.to_ascii_lowercase(), label.to_string()));
+        self
+    }
+
+    // Key of the choice that was picked, None while the user is still choosing
+    pub fn handle_key(&mut self, code: KeyCode) -> Option<char> {
+        match code {
+            KeyCode::Left | KeyCode::BackTab => {
+                self.selected = self.selected.saturating_sub(1);
+                None
+            }
+            KeyCode::Right | KeyCode::Tab => {
+                self.selected = (self.selected + 1).min(self.choices.len().saturating_sub(1));
+                None
+            }
+            KeyCode::Up => {
+                self.item = self.item.saturating_sub(1);
+                None
+            }
+            KeyCode::Down => {
+                self.item = (self.item + 1).min(self.items.len().saturating_sub(1));
+                None
+            }
+            KeyCode::Enter => self.choices.get(self.selected).map(|(key, _)| *key),
+            KeyCode::Backspace => {
+                if let Some(input) = self.input.as_mut() {
+                    input.pop();
+                }
+                None
+            }
+            KeyCode::Char(c) => match self.input.as_mut() {
+                Some(input) => {
+                    input.push(c);
+                    None
+                }
+                None => {
+                    let c = c.to_ascii_lowercase();
+                    self.choices.iter().find(|(key, _)| *key == c).map(|_| c)
+                }
+            },
+            _ => None,
+        }
+    }
+}
+
+impl Widget for &Dialog {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        let mut lines: Vec<Line> = self
+            .lines
+            .iter()
+            .map(|text| Line::raw(text.as_str()))
+            .collect();
+        if let Some(input) = &self.input {
+            lines.push(Line::raw(format!("> {}_", input)));
+        }
+        let first = (self.item + 1).saturating_sub(LIST_ROWS);
+        for (index, item) in self.items.iter().enumerate().skip(first).take(LIST_ROWS) {
+            let style = match index == self.item {
+                true => Style::default().add_modifier(Modifier::REVERSED),
+                false => Style::default(),
+            };
+            lines.push(Line::styled(item.as_str(), style));
+        }
+        if let Some(preview) = self.previews.get(self.item) {
+            lines.push(Line::raw(""));
+            lines.extend(preview.lines().map(Line::raw));
+        }
+        lines.push(Line::raw(""));
+        let choices: Vec<Span> = self
+            .choices
+            .iter()
+            .enumerate()
+            .flat_map(|(index, (key, label))| {
+                let text = format!("[{}] {}", key.to_ascii_uppercase(), label);
+                let style = match index == self.selected {
+                    true => Style::default().add_modifier(Modifier::REVERSED),
+                    false => Style::default(),
+                };
+                [Span::styled(text, style), Span::raw("  ")]
+            })
+            .collect();
+        lines.push(Line::from(choices));
+
+        // Rows taken by the wrapped lines, plus the borders
+        let text_width = DIALOG_WIDTH as usize - 2;
+        let rows: usize = lines
+            .iter()
+            .map(|line| line.width().max(1).div_ceil(text_width))
+            .sum();
+        let height = (rows as u16 + 2).min(area.height);
+        let [area] = Layout::horizontal([Constraint::Length(DIALOG_WIDTH)])
+            .flex(Flex::Center)
+            .areas(area);
+        let [area] = Layout::vertical([Constraint::Length(height)])
+            .flex(Flex::Center)
+            .areas(area);
+        Clear.render(area, buf);
+        Paragraph::new(lines)
+            .wrap(Wrap { trim: false })
+            .block(Block::bordered().title(format!(" {} ", self.title)))
+            .render(area, buf);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dialog() -> Dialog {
+        Dialog::new("Error")
+            .line("Something went wrong")
+            .choice('c', "Continue")
+            .choice('q', "Quit")
+    }
+
+    #[test]
+    fn test_handle_key() {
+        let mut dialog = dialog();
+        assert_eq!(dialog.handle_key(KeyCode::Char('Q')), Some('q'));
+        assert_eq!(dialog.handle_key(KeyCode::Char('x')), None);
+        assert_eq!(dialog.handle_key(KeyCode::Enter), Some('c'));
+        dialog.handle_key(KeyCode::Right);
+        dialog.handle_key(KeyCode::Right);
+        assert_eq!(dialog.handle_key(KeyCode::Enter), Some('q'));
+        dialog.handle_key(KeyCode::Left);
+        assert_eq!(dialog.handle_key(KeyCode::Enter), Some('c'));
+    }
+
+    #[test]
+    fn test_input_and_list() {
+        let mut dialog = Dialog::new("Save state as")
+            .input("pong-1")
+            .choice('s', "Save");
+        dialog.handle_key(KeyCode::Backspace);
+        // Typed keys go to the text rather than picking a choice
+        assert_eq!(dialog.handle_key(KeyCode::Char('s')), None);
+        assert_eq!(dialog.input_text(), "pong-s");
+        assert_eq!(dialog.handle_key(KeyCode::Enter), Some('s'));
+
+        let mut dialog = Dialog::new("Load state")
+            .items(vec!["a".to_string(), "b".to_string()])
+            .choice('l', "Load");
+        dialog.handle_key(KeyCode::Down);
+        dialog.handle_key(KeyCode::Down);
+        assert_eq!(dialog.selected_item(), 1);
+        dialog.handle_key(KeyCode::Up);
+        assert_eq!(dialog.selected_item(), 0);
+        assert_eq!(dialog.handle_key(KeyCode::Char('l')), Some('l'));
+        assert_eq!(dialog.select(5).selected_item(), 1);
+    }
+
+    #[test]
+    fn test_render() {
+        let area = Rect::new(0, 0, 80, 20);
+        let mut buf = Buffer::empty(area);
+        dialog().render(area, &mut buf);
+        let text: String = buf.content().iter().map(|cell| cell.symbol()).collect();
+        assert!(text.contains("Something went wrong"));
+        assert!(text.contains("[C] Continue  [Q] Quit"));
+
+        // The preview of the selected item is shown
+        let mut dialog = Dialog::new("Load state")
+            .items(vec!["a".to_string(), "b".to_string()])
+            .previews(vec!["first".to_string(), "second\nrow".to_string()]);
+        dialog.handle_key(KeyCode::Down);
+        let mut buf = Buffer::empty(area);
+        (&dialog).render(area, &mut buf);
+        let text: String = buf.content().iter().map(|cell| cell.symbol()).collect();
+        assert!(text.contains("second") && text.contains("row") && !text.contains("first"));
+    }
+}
@@ -0,0 +1,133 @@
+use alloc::{
+    format,
+    string::{String, ToString},
+};
+
+/*
+Disassembler
+Turns opcodes back into the mnemonics used in Cowgod's Chip-8 technical reference
+(the same ones used in the comments of the interpreter), e.g. 0x6A05 -> "LD VA, 0x05".
+Opcodes that are not valid instructions are shown as data words, e.g. "DW 0xFFFF".
+Decoding follows the interpreter, so e.g. 5xy1 is shown as SE like it is executed.
+*/
+pub fn disassemble(opcode: u16) -> String {
+    let x = (opcode >> 8) & 0x000F;
+    let y = (opcode >> 4) & 0x000F;
+    let kk = opcode & 0x00FF;
+    let nnn = opcode & 0x0FFF;
+    let nibble = opcode & 0x000F;
+
+    match opcode & 0xF000 {
+        0x0000 => match opcode {
+            0x00E0 => "CLS".to_string(),
+            0x00EE => "RET".to_string(),
+            0x00FD => "EXIT".to_string(),
+            _ => format!("SYS {:#05X}", nnn),
+        },
+        0x1000 => format!("JP {:#05X}", nnn),
+        0x2000 => format!("CALL {:#05X}", nnn),
+        0x3000 => format!("SE V{:X}, {:#04X}", x, kk),
+        0x4000 => format!("SNE V{:X}, {:#04X}", x, kk),
+        0x5000 => format!("SE V{:X}, V{:X}", x, y),
+        0x6000 => format!("LD V{:X}, {:#04X}", x, kk),
+        0x7000 => format!("ADD V{:X}, {:#04X}", x, kk),
+        0x8000 => match nibble {
+            0x0 => format!("LD V{:X}, V{:X}", x, y),
+            0x1 => format!("OR V{:X}, V{:X}", x, y),
+            0x2 => format!("AND V{:X}, V{:X}", x, y),
+            0x3 => format!("XOR V{:X}, V{:X}", x, y),
+            0x4 => format!("ADD V{:X}, V{:X}", x, y),
+            0x5 => format!("SUB V{:X}, V{:X}", x, y),
+            0x6 => format!("SHR V{:X}, V{:X}", x, y),
+            0x7 => format!("SUBN V{:X}, V{:X}", x, y),
+            0xE => format!("SHL V{:X}, V{:X}", x, y),
+            _ => data_word(opcode),
+        },
+        0x9000 => format!("SNE V{:X}, V{:X}", x, y),
+        0xA000 => format!("LD I, {:#05X}", nnn),
+        0xB000 => format!("JP V0, {:#05X}", nnn),
+        0xC000 => format!("RND V{:X}, {:#04X}", x, kk),
+        0xD000 => format!("DRW V{:X}, V{:X}, {}", x, y, nibble),
+        0xE000 => match kk {
+            0x9E => format!("SKP V{:X}", x),
+            0xA1 => format!("SKNP V{:X}", x),
+            _ => data_word(opcode),
+        },
+        0xF000 => match kk {
+            0x07 => format!("LD V{:X}, DT", x),
+            0x0A => format!("LD V{:X}, K", x),
+            0x15 => format!("LD DT, V{:X}", x),
+            0x18 => format!("LD ST, V{:X}", x),
+            0x1E => format!("ADD I, V{:X}", x),
+            0x29 => format!("LD F, V{:X}", x),
+            0x33 => format!("LD B, V{:X}", x),
+            0x55 => format!("LD [I], V{:X}", x),
+            0x65 => format!("LD V{:X}, [I]", x),
+            0x75 => format!("LD R, V{:X}", x),
+            0x85 => format!("LD V{:X}, R", x),
+            _ => data_word(opcode),
+        },
+        _ => data_word(opcode),
+    }
+}
+
+fn data_word(opcode: u16) -> String {
+    format!("DW {:#06X}", opcode)
+}
+
+// Whether the opcode is an instruction, rather than shown as a data word
+pub fn is_instruction(opcode: u16) -> bool {
+    !disassemble(opcode).starts_with("DW ")
+}
+
+// Read the big endian opcode at addr, None if it would run past the end of memory
+pub fn opcode_at(memory: &[u8], addr: usize) -> Option<u16> {
+    let high = *memory.get(addr)?;
+    let low = *memory.get(addr + 1)?;
+    Some(((high as u16) << 8) | low as u16)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_disassemble() {
+        assert_eq!(disassemble(0x00E0), "CLS");
+        assert_eq!(disassemble(0x00EE), "RET");
+        assert_eq!(disassemble(0x00FD), "EXIT");
+        assert_eq!(disassemble(0x0123), "SYS 0x123");
+        assert_eq!(disassemble(0x1208), "JP 0x208");
+        assert_eq!(disassemble(0x2ABC), "CALL 0xABC");
+        assert_eq!(disassemble(0x3A05), "SE VA, 0x05");
+        assert_eq!(disassemble(0x5120), "SE V1, V2");
+        assert_eq!(disassemble(0x6AFF), "LD VA, 0xFF");
+        assert_eq!(disassemble(0x8124), "ADD V1, V2");
+        assert_eq!(disassemble(0x812E), "SHL V1, V2");
+        assert_eq!(disassemble(0xA22A), "LD I, 0x22A");
+        assert_eq!(disassemble(0xB300), "JP V0, 0x300");
+        assert_eq!(disassemble(0xD015), "DRW V0, V1, 5");
+        assert_eq!(disassemble(0xE39E), "SKP V3");
+        assert_eq!(disassemble(0xF00A), "LD V0, K");
+        assert_eq!(disassemble(0xF565), "LD V5, [I]");
+        assert_eq!(disassemble(0xF775), "LD R, V7");
+        assert_eq!(disassemble(0xF385), "LD V3, R");
+    }
+
+    #[test]
+    fn test_disassemble_invalid() {
+        assert_eq!(disassemble(0x8128), "DW 0x8128");
+        assert_eq!(disassemble(0xE1FF), "DW 0xE1FF");
+        assert_eq!(disassemble(0xFFFF), "DW 0xFFFF");
+        assert!(is_instruction(0x00E0));
+        assert!(!is_instruction(0xFFFF));
+    }
+
+    #[test]
+    fn test_opcode_at() {
+        let memory = [0x12, 0x34, 0x56];
+        assert_eq!(opcode_at(&memory, 0), Some(0x1234));
+        assert_eq!(opcode_at(&memory, 1), Some(0x3456));
+        assert_eq!(opcode_at(&memory, 2), None);
+    }
+}
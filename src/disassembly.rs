@@ -0,0 +1,195 @@
+use crate::i18n::tr;
+use ratatui::{
+    buffer::Buffer,
+    layout::Rect,
+    style::{Color, Modifier, Style},
+    text::Line,
+    widgets::{Block, Widget},
+};
+use rustc8::{
+    chip8::Chip8,
+    disasm,
+    step::{Step, StepObserver},
+};
+use std::collections::VecDeque;
+
+// Width of the disassembly pane, enough for "*FFE  F065  LD VF, [I]" and the borders
+pub const PANE_WIDTH: u16 = 30;
+
+// Number of taken branches remembered for highlighting
+const BRANCH_HISTORY: usize = 8;
+
+/*
+DisassemblyView lists the instructions around PC.
+- While following, the listing scrolls with PC so that the current instruction stays centred
+- When locked, the listing stays where it is and can be scrolled by hand
+The current instruction is highlighted, as well as the addresses of recently taken branches,
+i.e. instructions after which PC did not simply move on to the next instruction
+(jumps, calls, returns and skips).
+*/
+pub struct DisassemblyView {
+    follow: bool,
+    top: u16,
+    // Instructions shown the last time the pane was drawn, copied by listing
+    rows: u16,
+    branches: VecDeque<u16>,
+}
+
+impl DisassemblyView {
+    pub fn new() -> Self {
+        DisassemblyView {
+            follow: true,
+            top: 0x200,
+            rows: 0,
+            branches: VecDeque::with_capacity(BRANCH_HISTORY),
+        }
+    }
+
+    pub fn toggle_follow(&mut self) {
+        self.follow = !self.follow;
+    }
+
+    // Scroll by a number of instructions, only when not following PC
+    pub fn scroll(&mut self, instructions: i32) {
+        if !self.follow {
+            let top = self.top as i32 + instructions * 2;
+            self.top = top.clamp(0, 4094) as u16;
+        }
+    }
+
+    // Called after each cycle with PC before and after the instruction
+    pub fn record_step(&mut self, from: u16, to: u16) {
+        if to != from.wrapping_add(2) {
+            // Keep a single entry per address so a tight loop doesn't flush the others
+            self.branches.retain(|addr| *addr != from);
+            if self.branches.len() == BRANCH_HISTORY {
+                self.branches.pop_front();
+            }
+            self.branches.push_back(from);
+        }
+    }
+
+    pub fn render(&mut self, chip8: &Chip8, area: Rect, buf: &mut Buffer) {
+        let title = if self.follow {
+            tr(" Disassembly (F4: lock) ")
+        } else {
+            tr(" Disassembly (locked) ")
+        };
+        let block = Block::bordered().title(title);
+        let inner = block.inner(area);
+        block.render(area, buf);
+
+        let rows = inner.height;
+        self.rows = rows;
+        if self.follow {
+            // Keep the parity of PC so the listing stays aligned on the instructions being run
+            self.top = chip8.pc.saturating_sub(rows / 2 * 2);
+            if chip8.pc % 2 != self.top % 2 {
+                self.top += 1;
+            }
+        }
+
+        for row in 0..rows {
+            let addr = self.top + row * 2;
+            let Some(text) = self.line(chip8, addr) else {
+                break;
+            };
+            let mut style = Style::default();
+            if self.branches.contains(&addr) {
+                style = style.fg(Color::Yellow);
+            }
+            if addr == chip8.pc {
+                style = style.add_modifier(Modifier::REVERSED);
+            }
+            Line::styled(text, style).render(
+                Rect {
+                    y: inner.y + row,
+                    height: 1,
+                    ..inner
+                },
+                buf,
+            );
+        }
+    }
+
+    // The instructions shown in the pane as text, one per line, to be copied
+    pub fn listing(&self, chip8: &Chip8) -> String {
+        (0..self.rows)
+            .map_while(|row| self.line(chip8, self.top + row * 2))
+            .map(|line| line + "\n")
+            .collect()
+    }
+
+    // ">202  1200  JP 0x200", marked > at PC and * for a recent branch
+    fn line(&self, chip8: &Chip8, addr: u16) -> Option<String> {
+        let opcode = disasm::opcode_at(&chip8.memory, addr as usize)?;
+        let marker = if addr == chip8.pc {
+            '>'
+        } else if self.branches.contains(&addr) {
+            '*'
+        } else {
+            ' '
+        };
+        Some(format!(
+            "{}{:03X}  {:04X}  {}",
+            marker,
+            addr,
+            opcode,
+            disasm::disassemble(opcode)
+        ))
+    }
+}
+
+impl StepObserver for DisassemblyView {
+    fn after_step(&mut self, step: Step, chip8: &Chip8) {
+        self.record_step(step.pc, chip8.pc);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_record_step() {
+        let mut view = DisassemblyView::new();
+        view.record_step(0x200, 0x202);
+        assert!(view.branches.is_empty());
+
+        view.record_step(0x202, 0x200);
+        view.record_step(0x202, 0x200);
+        assert_eq!(view.branches, [0x202]);
+
+        for addr in 0..BRANCH_HISTORY as u16 * 2 {
+            view.record_step(0x300 + addr * 2, 0x200);
+        }
+        assert_eq!(view.branches.len(), BRANCH_HISTORY);
+    }
+
+    #[test]
+    fn test_follow_pc() {
+        let mut chip8 = Chip8::new();
+        chip8.load_rom(&[0x00, 0xE0, 0x12, 0x00]).unwrap();
+        chip8.pc = 0x202;
+        let mut view = DisassemblyView::new();
+        let area = Rect::new(0, 0, PANE_WIDTH, 7);
+        let mut buf = Buffer::empty(area);
+
+        view.render(&chip8, area, &mut buf);
+        assert_eq!(view.top, 0x1FE);
+        let line: String = (1..PANE_WIDTH - 1).map(|x| buf[(x, 3)].symbol()).collect();
+        assert_eq!(line.trim_end(), ">202  1200  JP 0x200");
+        assert!(buf[(1, 3)].modifier.contains(Modifier::REVERSED));
+        assert_eq!(
+            view.listing(&chip8).lines().nth(2),
+            Some(">202  1200  JP 0x200")
+        );
+
+        // Locked, scrolling moves the listing but PC doesn't
+        view.toggle_follow();
+        view.scroll(1);
+        chip8.pc = 0x300;
+        view.render(&chip8, area, &mut buf);
+        assert_eq!(view.top, 0x200);
+    }
+}
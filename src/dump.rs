@@ -0,0 +1,96 @@
+use rustc8::{chip8::Chip8, disasm};
+use std::fmt::Write;
+
+// Instructions listed before and after PC
+const CONTEXT: u16 = 8;
+
+/*
+Register dump, written with Ctrl+D to attach the state of the machine to a bug report:
+
+    PC 204  I 300  SP 1  DT 00  ST 00
+    V0 0A  V1 00  V2 00  V3 00  V4 00  V5 00  V6 00  V7 00
+    V8 00  V9 00  VA 00  VB 00  VC 00  VD 00  VE 00  VF 00
+    Stack: 20A
+    Keys: 5
+
+      1FC  0000  SYS 0x000
+      ...
+    > 204  D015  DRW V0, V1, 5
+      ...
+*/
+pub fn format(chip8: &Chip8) -> String {
+    let mut text = String::new();
+    let _ = writeln!(
+        text,
+        "PC {:03X}  I {:03X}  SP {:X}  DT {:02X}  ST {:02X}",
+        chip8.pc, chip8.i, chip8.sp, chip8.dt, chip8.st
+    );
+    for (row, registers) in chip8.v.chunks(8).enumerate() {
+        let registers: Vec<String> = registers
+            .iter()
+            .enumerate()
+            .map(|(column, value)| format!("V{:X} {:02X}", row * 8 + column, value))
+            .collect();
+        let _ = writeln!(text, "{}", registers.join("  "));
+    }
+    let stack: Vec<String> = chip8.stack[..(chip8.sp as usize).min(chip8.stack.len())]
+        .iter()
+        .map(|addr| format!("{:03X}", addr))
+        .collect();
+    let _ = writeln!(text, "Stack: {}", list(&stack));
+    let keys: Vec<String> = (0..16)
+        .filter(|key| chip8.keyboard[*key])
+        .map(|key| format!("{:X}", key))
+        .collect();
+    let _ = writeln!(text, "Keys: {}", list(&keys));
+    let _ = writeln!(text);
+
+    // Instructions around PC, keeping its alignment
+    let start = chip8.pc.saturating_sub(CONTEXT * 2);
+    for addr in (start..=chip8.pc + CONTEXT * 2).step_by(2) {
+        let Some(opcode) = disasm::opcode_at(&chip8.memory, addr as usize) else {
+            break;
+        };
+        let marker = if addr == chip8.pc { '>' } else { ' ' };
+        let _ = writeln!(
+            text,
+            "{} {:03X}  {:04X}  {}",
+            marker,
+            addr,
+            opcode,
+            disasm::disassemble(opcode)
+        );
+    }
+    text
+}
+
+fn list(items: &[String]) -> String {
+    match items.is_empty() {
+        true => "none".to_string(),
+        false => items.join(" "),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format() {
+        let mut chip8 = Chip8::new();
+        // LD V0, 10; CALL 0x206; ...; LD I, 0x300
+        chip8
+            .load_rom(&[0x60, 0x0A, 0x22, 0x06, 0x00, 0x00, 0xA3, 0x00])
+            .unwrap();
+        chip8.run_cycle().unwrap();
+        chip8.run_cycle().unwrap();
+        chip8.key_down(5);
+        let text = format(&chip8);
+        assert!(text.starts_with("PC 206  I 000  SP 1  DT 00  ST 00\n"));
+        assert!(text.contains("V0 0A  V1 00"));
+        assert!(text.contains("Stack: 204\nKeys: 5\n"));
+        assert!(format(&Chip8::new()).contains("Stack: none\nKeys: none\n"));
+        assert!(text.contains("> 206  A300  LD I, 0x300\n"));
+        assert!(text.contains("  200  600A  LD V0, 0x0A\n"));
+    }
+}
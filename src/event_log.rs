@@ -0,0 +1,153 @@
+use rustc8::{
+    chip8::Chip8,
+    step::{Step, StepObserver},
+};
+use serde_json::{json, Value};
+use std::{
+    fs::File,
+    io::{self, BufWriter, Write},
+    net::TcpStream,
+};
+
+/*
+Event log, `--event-log <file>` or `--event-log tcp:<address>`
+A line of JSON per frame, for tools outside the emulator such as bots or statistics to follow the
+game without linking against the crate:
+
+    {"clears":0,"draws":3,"frame":120,"hash":"9a3c05e1b2f4d870","keys":[5],"pressed":[5],"released":[],"sound":"start"}
+
+- keys: the keys held at the end of the frame, pressed and released: those that changed since
+  the previous frame
+- draws and clears: the DRW and CLS instructions run in the frame
+- sound: "start" or "stop" when the sound timer started or stopped the beep, null otherwise
+- hash: Chip8::state_hash after the frame, as in --hash-log
+With tcp: the emulator connects to a tool listening on the address. Lines are flushed every frame,
+so the log can be followed while the game runs.
+*/
+pub struct EventLog {
+    out: Box<dyn Write>,
+    // The keys and sound at the end of the last frame
+    keys: [bool; 16],
+    sound: bool,
+    draws: u32,
+    clears: u32,
+}
+
+impl EventLog {
+    pub fn open(target: &str) -> io::Result<Self> {
+        let out: Box<dyn Write> = match target.strip_prefix("tcp:") {
+            Some(addr) => Box::new(BufWriter::new(TcpStream::connect(addr)?)),
+            None => Box::new(BufWriter::new(File::create(target)?)),
+        };
+        Ok(Self::new(out))
+    }
+
+    fn new(out: Box<dyn Write>) -> Self {
+        EventLog {
+            out,
+            keys: [false; 16],
+            sound: false,
+            draws: 0,
+            clears: 0,
+        }
+    }
+
+    // Count the instruction about to run
+    pub fn record_step(&mut self, opcode: u16) {
+        match opcode {
+            0x00E0 => self.clears += 1,
+            _ if opcode & 0xF000 == 0xD000 => self.draws += 1,
+            _ => {}
+        }
+    }
+
+    // Write the event of the frame that ended
+    pub fn end_frame(&mut self, frame: u64, chip8: &Chip8) -> io::Result<()> {
+        let event = self.event(frame, chip8);
+        writeln!(self.out, "{}", event)?;
+        self.out.flush()
+    }
+
+    fn event(&mut self, frame: u64, chip8: &Chip8) -> Value {
+        let keys = |held: &[bool; 16], filter: &dyn Fn(usize) -> bool| -> Vec<usize> {
+            (0..16).filter(|key| held[*key] && filter(*key)).collect()
+        };
+        let held = keys(&chip8.keyboard, &|_| true);
+        let pressed = keys(&chip8.keyboard, &|key| !self.keys[key]);
+        let released = keys(&self.keys, &|key| !chip8.keyboard[key]);
+        let sound = chip8.st > 0;
+        let transition = match (self.sound, sound) {
+            (false, true) => json!("start"),
+            (true, false) => json!("stop"),
+            _ => Value::Null,
+        };
+        let event = json!({
+            "frame": frame,
+            "keys": held,
+            "pressed": pressed,
+            "released": released,
+            "draws": self.draws,
+            "clears": self.clears,
+            "sound": transition,
+            "hash": format!("{:016x}", chip8.state_hash()),
+        });
+        self.keys = chip8.keyboard;
+        self.sound = sound;
+        self.draws = 0;
+        self.clears = 0;
+        event
+    }
+}
+
+impl StepObserver for EventLog {
+    fn before_step(&mut self, step: Step, _chip8: &Chip8) {
+        self.record_step(step.opcode);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{io::BufRead, net::TcpListener};
+
+    #[test]
+    fn test_events() {
+        let mut log = EventLog::new(Box::new(io::sink()));
+        let mut chip8 = Chip8::new();
+        chip8.keyboard[5] = true;
+        chip8.st = 10;
+        log.record_step(0x00E0);
+        log.record_step(0xD015);
+        log.record_step(0xD015);
+        let event = log.event(0, &chip8);
+        assert_eq!(event["keys"], json!([5]));
+        assert_eq!(event["pressed"], json!([5]));
+        assert_eq!(event["draws"], 2);
+        assert_eq!(event["clears"], 1);
+        assert_eq!(event["sound"], "start");
+        assert_eq!(event["hash"], format!("{:016x}", chip8.state_hash()));
+
+        chip8.keyboard[5] = false;
+        chip8.keyboard[1] = true;
+        let event = log.event(1, &chip8);
+        assert_eq!(event["pressed"], json!([1]));
+        assert_eq!(event["released"], json!([5]));
+        assert_eq!(event["draws"], 0);
+        assert_eq!(event["sound"], Value::Null);
+        chip8.st = 0;
+        assert_eq!(log.event(2, &chip8)["sound"], "stop");
+    }
+
+    #[test]
+    fn test_tcp() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let mut log = EventLog::open(&format!("tcp:{}", addr)).unwrap();
+        let (stream, _) = listener.accept().unwrap();
+        log.end_frame(7, &Chip8::new()).unwrap();
+
+        let line = io::BufReader::new(stream).lines().next().unwrap().unwrap();
+        let event: Value = serde_json::from_str(&line).unwrap();
+        assert_eq!(event["frame"], 7);
+    }
+}
@@ -0,0 +1,152 @@
+use crate::i18n::tr;
+use rustc8::{chip8::display_size, gzip, png};
+use std::{
+    io,
+    path::{Path, PathBuf},
+};
+
+// Each Chip-8 pixel is exported as a square of this many pixels, 512x256 for the whole display
+const EXPORT_SCALE: usize = 8;
+
+/*
+Gallery keeps the screenshots captured while playing the current ROM, so they can be browsed
+inside the TUI before deciding which ones to keep. Screenshots stay in memory until exported,
+which writes them as PNG files named after the ROM and the number of the screenshot,
+e.g. pong-003.png.
+*/
+pub struct Screenshot {
+    pub number: usize,
+    pub frame: u64,
+    pub display: Vec<u8>,
+}
+
+pub struct Gallery {
+    rom_name: String,
+    screenshots: Vec<Screenshot>,
+    selected: usize,
+    captured: usize,
+    pub visible: bool,
+}
+
+impl Gallery {
+    pub fn new(rom_path: &str) -> Self {
+        let rom_name = gzip::strip_extension(Path::new(rom_path))
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().into_owned())
+            .unwrap_or_else(|| "screenshot".to_string());
+        Gallery {
+            rom_name,
+            screenshots: Vec::new(),
+            selected: 0,
+            captured: 0,
+            visible: false,
+        }
+    }
+
+    pub fn capture(&mut self, display: &[u8], frame: u64) {
+        self.captured += 1;
+        self.screenshots.push(Screenshot {
+            number: self.captured,
+            frame,
+            display: display.to_vec(),
+        });
+        self.selected = self.screenshots.len() - 1;
+    }
+
+    // File name of the ROM without the extension, used to name exported files
+    pub fn rom_name(&self) -> &str {
+        &self.rom_name
+    }
+
+    pub fn len(&self) -> usize {
+        self.screenshots.len()
+    }
+
+    pub fn selected(&self) -> Option<&Screenshot> {
+        self.screenshots.get(self.selected)
+    }
+
+    pub fn selected_index(&self) -> usize {
+        self.selected
+    }
+
+    pub fn previous(&mut self) {
+        self.selected = self.selected.saturating_sub(1);
+    }
+
+    pub fn next(&mut self) {
+        if self.selected + 1 < self.screenshots.len() {
+            self.selected += 1;
+        }
+    }
+
+    pub fn delete(&mut self) {
+        if self.selected < self.screenshots.len() {
+            self.screenshots.remove(self.selected);
+            self.selected = self.selected.min(self.screenshots.len().saturating_sub(1));
+        }
+    }
+
+    // File the selected screenshot is exported to, to check whether it would be overwritten
+    pub fn export_path(&self, directory: &Path) -> Option<PathBuf> {
+        let screenshot = self.selected()?;
+        Some(directory.join(format!("{}-{:03}.png", self.rom_name, screenshot.number)))
+    }
+
+    // Export the selected screenshot as a PNG file in the given directory, returning its path
+    pub fn export(&self, directory: &Path) -> io::Result<String> {
+        let (Some(screenshot), Some(path)) = (self.selected(), self.export_path(directory)) else {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                tr("No screenshot selected"),
+            ));
+        };
+        // In black and white, MEGA-CHIP colors included
+        let lit: Vec<bool> = screenshot.display.iter().map(|pixel| *pixel != 0).collect();
+        let (width, height) = display_size(lit.len());
+        let data = png::encode(&lit, width, height, EXPORT_SCALE);
+        std::fs::write(&path, data)?;
+        Ok(path.display().to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rustc8::chip8::{DISPLAY_HEIGHT, DISPLAY_WIDTH};
+
+    #[test]
+    fn test_browse_and_delete() {
+        let mut gallery = Gallery::new("roms/pong.ch8");
+        let display = vec![0; DISPLAY_WIDTH * DISPLAY_HEIGHT];
+        gallery.capture(&display, 10);
+        gallery.capture(&display, 20);
+        gallery.capture(&display, 30);
+        assert_eq!(gallery.selected().unwrap().frame, 30);
+
+        gallery.previous();
+        gallery.delete();
+        assert_eq!(gallery.len(), 2);
+        assert_eq!(gallery.selected().unwrap().frame, 30);
+
+        gallery.delete();
+        gallery.next();
+        assert_eq!(gallery.selected().unwrap().number, 1);
+        gallery.delete();
+        assert!(gallery.selected().is_none());
+    }
+
+    #[test]
+    fn test_export() {
+        let mut gallery = Gallery::new("roms/pong.ch8");
+        let directory = std::env::temp_dir();
+        assert!(gallery.export(&directory).is_err());
+
+        gallery.capture(&vec![1; DISPLAY_WIDTH * DISPLAY_HEIGHT], 0);
+        let path = gallery.export(&directory).unwrap();
+        assert!(path.ends_with("pong-001.png"));
+        let data = std::fs::read(&path).unwrap();
+        assert!(data.starts_with(b"\x89PNG"));
+        std::fs::remove_file(path).unwrap();
+    }
+}
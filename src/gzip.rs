@@ -0,0 +1,344 @@
+use crate::patch::crc32;
+use std::{
+    fmt,
+    path::{Path, PathBuf},
+};
+
+/*
+Gzip decompression for ROMs
+Archived ROM collections often ship their files gzipped (pong.ch8.gz). ROMs are decompressed in
+memory when loading, detected by the gzip magic bytes rather than the file name. The decoder
+follows RFC 1952 for the gzip wrapper and RFC 1951 for the deflate data, with no support for
+multiple members since compressors only write one.
+*/
+const MAGIC: [u8; 2] = [0x1F, 0x8B];
+// Compression method 8 is deflate, the only one defined
+const METHOD_DEFLATE: u8 = 8;
+// Header flags
+const FLAG_HCRC: u8 = 0x02;
+const FLAG_EXTRA: u8 = 0x04;
+const FLAG_NAME: u8 = 0x08;
+const FLAG_COMMENT: u8 = 0x10;
+
+// Base lengths and extra bits of the length codes 257 to 285
+const LENGTH_BASE: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131,
+    163, 195, 227, 258,
+];
+const LENGTH_EXTRA: [u8; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+];
+// Base distances and extra bits of the distance codes 0 to 29
+const DISTANCE_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537,
+    2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+const DISTANCE_EXTRA: [u8; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13,
+    13,
+];
+// Order in which the code lengths of the code length alphabet are stored in dynamic blocks
+const CODE_LENGTH_ORDER: [usize; 19] = [
+    16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15,
+];
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum GzipError {
+    NotGzip,
+    UnsupportedMethod(u8),
+    Truncated,
+    InvalidData,
+    ChecksumMismatch,
+}
+
+impl fmt::Display for GzipError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            GzipError::NotGzip => write!(f, "Not a gzip file"),
+            GzipError::UnsupportedMethod(method) => {
+                write!(f, "Unsupported gzip compression method {}", method)
+            }
+            GzipError::Truncated => write!(f, "Gzip file is truncated"),
+            GzipError::InvalidData => write!(f, "Invalid compressed data"),
+            GzipError::ChecksumMismatch => write!(f, "Gzip checksum mismatch, the file is corrupt"),
+        }
+    }
+}
+
+impl std::error::Error for GzipError {}
+
+pub fn is_gzip(data: &[u8]) -> bool {
+    data.starts_with(&MAGIC)
+}
+
+// The path without its .gz extension, e.g. pong.ch8.gz is pong.ch8, to find the ROM's format
+pub fn strip_extension(path: &Path) -> PathBuf {
+    match path.extension() {
+        Some(extension) if extension.eq_ignore_ascii_case("gz") => path.with_extension(""),
+        _ => path.to_path_buf(),
+    }
+}
+
+pub fn decompress(data: &[u8]) -> Result<Vec<u8>, GzipError> {
+    if !is_gzip(data) {
+        return Err(GzipError::NotGzip);
+    }
+    // Magic, method, flags, modification time, extra flags and OS
+    let header = data.get(..10).ok_or(GzipError::Truncated)?;
+    if header[2] != METHOD_DEFLATE {
+        return Err(GzipError::UnsupportedMethod(header[2]));
+    }
+    let flags = header[3];
+    let mut position = 10;
+    if flags & FLAG_EXTRA != 0 {
+        let len = data
+            .get(position..position + 2)
+            .ok_or(GzipError::Truncated)?;
+        position += 2 + u16::from_le_bytes([len[0], len[1]]) as usize;
+    }
+    // File name and comment are zero terminated
+    for flag in [FLAG_NAME, FLAG_COMMENT] {
+        if flag & flags != 0 {
+            let text = data.get(position..).ok_or(GzipError::Truncated)?;
+            let end = text.iter().position(|byte| *byte == 0);
+            position += end.ok_or(GzipError::Truncated)? + 1;
+        }
+    }
+    if flags & FLAG_HCRC != 0 {
+        position += 2;
+    }
+
+    let deflate = data.get(position..).ok_or(GzipError::Truncated)?;
+    let (output, used) = inflate(deflate)?;
+    // The CRC-32 and the length of the uncompressed data follow the deflate data
+    let trailer = deflate.get(used..used + 8).ok_or(GzipError::Truncated)?;
+    let checksum = u32::from_le_bytes([trailer[0], trailer[1], trailer[2], trailer[3]]);
+    let size = u32::from_le_bytes([trailer[4], trailer[5], trailer[6], trailer[7]]);
+    if checksum != crc32(&output) || size != output.len() as u32 {
+        return Err(GzipError::ChecksumMismatch);
+    }
+    Ok(output)
+}
+
+// Decompressed data and the number of bytes of deflate data read
+fn inflate(data: &[u8]) -> Result<(Vec<u8>, usize), GzipError> {
+    let mut bits = BitReader { data, position: 0 };
+    let mut output = Vec::new();
+    loop {
+        let last = bits.read(1)? == 1;
+        match bits.read(2)? {
+            0 => {
+                // Stored block: aligned length, its complement and the bytes as they are
+                bits.align();
+                let len = bits.read(16)?;
+                if bits.read(16)? != !len & 0xFFFF {
+                    return Err(GzipError::InvalidData);
+                }
+                for _ in 0..len {
+                    output.push(bits.read(8)? as u8);
+                }
+            }
+            1 => {
+                let (lengths, distances) = fixed_codes();
+                inflate_block(&mut bits, &lengths, &distances, &mut output)?;
+            }
+            2 => {
+                let (lengths, distances) = dynamic_codes(&mut bits)?;
+                inflate_block(&mut bits, &lengths, &distances, &mut output)?;
+            }
+            _ => return Err(GzipError::InvalidData),
+        }
+        if last {
+            return Ok((output, bits.position.div_ceil(8)));
+        }
+    }
+}
+
+// Literals, and lengths and distances to copy earlier output, until the end of block code
+fn inflate_block(
+    bits: &mut BitReader,
+    lengths: &Huffman,
+    distances: &Huffman,
+    output: &mut Vec<u8>,
+) -> Result<(), GzipError> {
+    loop {
+        let symbol = lengths.decode(bits)?;
+        match symbol {
+            0..=255 => output.push(symbol as u8),
+            256 => return Ok(()),
+            257..=285 => {
+                let index = symbol - 257;
+                let len = LENGTH_BASE[index] as usize + bits.read(LENGTH_EXTRA[index])? as usize;
+                let index = distances.decode(bits)?;
+                if index >= DISTANCE_BASE.len() {
+                    return Err(GzipError::InvalidData);
+                }
+                let distance =
+                    DISTANCE_BASE[index] as usize + bits.read(DISTANCE_EXTRA[index])? as usize;
+                if distance > output.len() {
+                    return Err(GzipError::InvalidData);
+                }
+                // Byte by byte, the copy can overlap the bytes it writes
+                let start = output.len() - distance;
+                for offset in 0..len {
+                    output.push(output[start + offset]);
+                }
+            }
+            _ => return Err(GzipError::InvalidData),
+        }
+    }
+}
+
+// The codes of fixed blocks, defined by the format
+fn fixed_codes() -> (Huffman, Huffman) {
+    let mut lengths = [0u8; 288];
+    lengths[..144].fill(8);
+    lengths[144..256].fill(9);
+    lengths[256..280].fill(7);
+    lengths[280..].fill(8);
+    (Huffman::new(&lengths), Huffman::new(&[5; 30]))
+}
+
+// The codes of dynamic blocks, stored as code lengths themselves Huffman coded
+fn dynamic_codes(bits: &mut BitReader) -> Result<(Huffman, Huffman), GzipError> {
+    let literal_count = bits.read(5)? as usize + 257;
+    let distance_count = bits.read(5)? as usize + 1;
+    let code_length_count = bits.read(4)? as usize + 4;
+    let mut code_lengths = [0u8; 19];
+    for index in CODE_LENGTH_ORDER.iter().take(code_length_count) {
+        code_lengths[*index] = bits.read(3)? as u8;
+    }
+    let code_lengths = Huffman::new(&code_lengths);
+
+    let mut lengths = Vec::with_capacity(literal_count + distance_count);
+    while lengths.len() < literal_count + distance_count {
+        let (value, repeat) = match code_lengths.decode(bits)? {
+            symbol @ 0..=15 => (symbol as u8, 1),
+            // Repeat the previous length 3 to 6 times
+            16 => {
+                let previous = *lengths.last().ok_or(GzipError::InvalidData)?;
+                (previous, 3 + bits.read(2)?)
+            }
+            // Repeat zero 3 to 10 times, or 11 to 138 times
+            17 => (0, 3 + bits.read(3)?),
+            _ => (0, 11 + bits.read(7)?),
+        };
+        lengths.extend(std::iter::repeat_n(value, repeat as usize));
+    }
+    if lengths.len() > literal_count + distance_count || lengths[256] == 0 {
+        return Err(GzipError::InvalidData);
+    }
+    let (literals, distances) = lengths.split_at(literal_count);
+    Ok((Huffman::new(literals), Huffman::new(distances)))
+}
+
+// Canonical Huffman code: the number of codes of each length and the symbols sorted by code
+struct Huffman {
+    counts: [u16; 16],
+    symbols: Vec<u16>,
+}
+
+impl Huffman {
+    fn new(lengths: &[u8]) -> Self {
+        let mut counts = [0u16; 16];
+        for len in lengths {
+            counts[*len as usize] += 1;
+        }
+        counts[0] = 0;
+        let mut symbols = Vec::with_capacity(lengths.len());
+        for len in 1..16 {
+            for (symbol, _) in lengths.iter().enumerate().filter(|(_, l)| **l == len) {
+                symbols.push(symbol as u16);
+            }
+        }
+        Huffman { counts, symbols }
+    }
+
+    // Codes are read one bit at a time, most significant bit first
+    fn decode(&self, bits: &mut BitReader) -> Result<usize, GzipError> {
+        let mut code = 0;
+        // First code and index of the first symbol of the current length
+        let mut first = 0;
+        let mut index = 0;
+        for count in &self.counts[1..] {
+            code |= bits.read(1)? as i32;
+            let count = *count as i32;
+            if code - first < count {
+                return Ok(self.symbols[(index + code - first) as usize] as usize);
+            }
+            index += count;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+        Err(GzipError::InvalidData)
+    }
+}
+
+// Deflate packs values least significant bit first
+struct BitReader<'a> {
+    data: &'a [u8],
+    // In bits
+    position: usize,
+}
+
+impl BitReader<'_> {
+    fn read(&mut self, count: u8) -> Result<u32, GzipError> {
+        let mut value = 0;
+        for offset in 0..count {
+            let byte = self
+                .data
+                .get(self.position / 8)
+                .ok_or(GzipError::Truncated)?;
+            value |= ((*byte >> (self.position % 8)) as u32 & 1) << offset;
+            self.position += 1;
+        }
+        Ok(value)
+    }
+
+    fn align(&mut self) {
+        self.position = self.position.div_ceil(8) * 8;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // "hello hello hello\n" compressed by gzip -9 -n, a fixed block with a back reference
+    const HELLO: &[u8] = &[
+        0x1f, 0x8b, 0x08, 0x00, 0x00, 0x00, 0x00, 0x00, 0x02, 0x03, 0xcb, 0x48, 0xcd, 0xc9, 0xc9,
+        0x57, 0xc8, 0x40, 0x90, 0x5c, 0x00, 0x3b, 0x7c, 0x8a, 0xdf, 0x12, 0x00, 0x00, 0x00,
+    ];
+    // 80 random a, b and c, compressed with a dynamic block
+    const DYNAMIC: &[u8] = &[
+        0x1f, 0x8b, 0x08, 0x00, 0x00, 0x00, 0x00, 0x00, 0x02, 0x03, 0x3d, 0x8b, 0xc1, 0x11, 0x00,
+        0x40, 0x0c, 0x01, 0x6b, 0x5d, 0xfa, 0xef, 0xe1, 0x38, 0x33, 0x79, 0x64, 0xc4, 0x02, 0x01,
+        0x92, 0x5c, 0xf9, 0xa7, 0x3d, 0xe0, 0xc8, 0x70, 0xf2, 0xfa, 0x15, 0x7c, 0xd4, 0x9b, 0x6e,
+        0x53, 0x18, 0xf2, 0x00, 0xb9, 0x54, 0xe9, 0xda, 0x50, 0x00, 0x00, 0x00,
+    ];
+
+    #[test]
+    fn test_decompress() {
+        assert!(is_gzip(HELLO));
+        assert_eq!(decompress(HELLO).unwrap(), b"hello hello hello\n");
+        assert_eq!(decompress(b"\x12\x34"), Err(GzipError::NotGzip));
+        assert_eq!(decompress(&HELLO[..20]), Err(GzipError::Truncated));
+        let mut corrupt = HELLO.to_vec();
+        corrupt[22] ^= 1;
+        assert_eq!(decompress(&corrupt), Err(GzipError::ChecksumMismatch));
+        // The checksum is verified, the data is right if it decompresses
+        assert_eq!(decompress(DYNAMIC).unwrap().len(), 80);
+    }
+
+    #[test]
+    fn test_strip_extension() {
+        assert_eq!(
+            strip_extension(Path::new("roms/pong.ch8.GZ")),
+            Path::new("roms/pong.ch8")
+        );
+        assert_eq!(
+            strip_extension(Path::new("roms/pong.ch8")),
+            Path::new("roms/pong.ch8")
+        );
+    }
+}
@@ -0,0 +1,155 @@
+use crate::{
+    chip8::{Chip8, Chip8Error},
+    timing::{FrameBudget, FRAME_RATE},
+};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+
+/*
+Shared core
+Chip8Handle shares a machine between threads, e.g. one thread running the emulation while
+others pause it, step it or read its state for a debugger, a server or the audio. Clones of a
+handle are the same machine, and every call locks it for its own duration only:
+
+    let handle = Chip8Handle::new(chip8);
+    let emulation = handle.clone();
+    thread::spawn(move || loop {
+        emulation.wait_while_paused();
+        emulation.run_frame(&mut budget)?;
+        thread::sleep(frame_duration);
+    });
+    handle.pause();
+    handle.step()?;
+    let pc = handle.with(|chip8| chip8.pc);
+
+Pausing only stops run_frame, the other calls work whether the machine is paused or not, so
+a paused machine can still be stepped, inspected and changed. Callbacks registered on the
+machine (SYS handler, memory hooks, timer observers) run with the lock held and mustn't use
+the handle.
+*/
+#[derive(Clone)]
+pub struct Chip8Handle {
+    shared: Arc<Shared>,
+}
+
+struct Shared {
+    state: Mutex<State>,
+    // Signalled when the machine is resumed, see wait_while_paused
+    resumed: Condvar,
+}
+
+struct State {
+    chip8: Chip8,
+    paused: bool,
+}
+
+impl Chip8Handle {
+    pub fn new(chip8: Chip8) -> Self {
+        Chip8Handle {
+            shared: Arc::new(Shared {
+                state: Mutex::new(State {
+                    chip8,
+                    paused: false,
+                }),
+                resumed: Condvar::new(),
+            }),
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, State> {
+        self.shared.state.lock().unwrap()
+    }
+
+    pub fn pause(&self) {
+        self.lock().paused = true;
+    }
+
+    pub fn resume(&self) {
+        self.lock().paused = false;
+        self.shared.resumed.notify_all();
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.lock().paused
+    }
+
+    // Block the calling thread until the machine isn't paused, for the thread running it
+    pub fn wait_while_paused(&self) {
+        let state = self.lock();
+        let _state = self
+            .shared
+            .resumed
+            .wait_while(state, |state| state.paused)
+            .unwrap();
+    }
+
+    /*
+    Run a frame at 60Hz, see Chip8::run_frame. Returns whether the frame ran, it doesn't while
+    paused.
+    */
+    pub fn run_frame(&self, budget: &mut FrameBudget) -> Result<bool, Chip8Error> {
+        let mut state = self.lock();
+        if state.paused {
+            return Ok(false);
+        }
+        state.chip8.run_frame(budget, FRAME_RATE)?;
+        Ok(true)
+    }
+
+    // Run one instruction, paused or not
+    pub fn step(&self) -> Result<(), Chip8Error> {
+        self.lock().chip8.run_cycle()
+    }
+
+    // Read the machine, e.g. handle.with(|chip8| chip8.v[0])
+    pub fn with<R>(&self, f: impl FnOnce(&Chip8) -> R) -> R {
+        f(&self.lock().chip8)
+    }
+
+    // Change the machine, e.g. to press keys or load a state
+    pub fn with_mut<R>(&self, f: impl FnOnce(&mut Chip8) -> R) -> R {
+        f(&mut self.lock().chip8)
+    }
+
+    // A copy of the machine as it is now
+    pub fn snapshot(&self) -> Chip8 {
+        self.with(Chip8::clone)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::timing::Timing;
+    use std::{thread, time::Duration};
+
+    #[test]
+    fn test_threads() {
+        let mut chip8 = Chip8::new();
+        // ADD V0, 1; JP 0x200
+        chip8.load_rom(&[0x70, 0x01, 0x12, 0x00]).unwrap();
+        let handle = Chip8Handle::new(chip8);
+        handle.pause();
+
+        let emulation = handle.clone();
+        let runner = thread::spawn(move || {
+            let mut budget = FrameBudget::new(Timing::Fixed, 10);
+            while emulation.with(|chip8| chip8.frames_elapsed()) < 3 {
+                emulation.wait_while_paused();
+                emulation.run_frame(&mut budget).unwrap();
+            }
+        });
+
+        // Paused, nothing runs but the machine can be stepped
+        thread::sleep(Duration::from_millis(20));
+        assert_eq!(handle.with(|chip8| chip8.cycles_executed()), 0);
+        handle.step().unwrap();
+        assert_eq!(handle.with(|chip8| chip8.v[0]), 1);
+        assert!(!handle
+            .run_frame(&mut FrameBudget::new(Timing::Fixed, 10))
+            .unwrap());
+
+        handle.resume();
+        runner.join().unwrap();
+        assert_eq!(handle.snapshot().cycles_executed(), 31);
+    }
+}
@@ -0,0 +1,207 @@
+use crate::{
+    chip8::{is_hires_rom, Chip8, Chip8Error, Quirks, Stop, MAX_STACK_DEPTH},
+    script::InputScript,
+    timing::FRAME_RATE,
+};
+
+// Instructions per frame of the frontend
+const DEFAULT_IPF: u32 = 11;
+
+/*
+Test harness
+Runs a ROM frame by frame the way the frontend does, without a terminal, for end-to-end tests of
+ROMs. Runs are deterministic: RND is seeded (0 unless set with seed) and keys only come from the
+input script, so a test gets the same frames on every run.
+
+    let script = InputScript::parse("frame 10: press 5")?;
+    let mut harness = TestHarness::new(&rom).quirks(Quirks::CHIP8).script(script);
+    harness.assert_register_at_frame(0x3, 20, 0x01);
+    harness.assert_pixel(12, 4, true);
+
+The assertions panic with the frame and what was found, like assert_eq, and also panic if the
+ROM stops on an error before reaching the frame; use run_until to handle errors.
+*/
+pub struct TestHarness {
+    pub chip8: Chip8,
+    script: Option<InputScript>,
+    ipf: u32,
+    // Frames run so far, the next frame to run
+    frame: u64,
+}
+
+impl TestHarness {
+    // Panics if the ROM doesn't fit in memory
+    pub fn new(rom: &[u8]) -> Self {
+        let mut chip8 = Chip8::new();
+        chip8.load_rom(rom).unwrap_or_else(|e| panic!("{}", e));
+        if is_hires_rom(rom) {
+            chip8.enable_hires();
+        }
+        chip8.seed_rng(0);
+        TestHarness {
+            chip8,
+            script: None,
+            ipf: DEFAULT_IPF,
+            frame: 0,
+        }
+    }
+
+    pub fn quirks(mut self, quirks: Quirks) -> Self {
+        self.chip8.quirks = quirks;
+        self
+    }
+
+    // Levels of the stack, up to MAX_STACK_DEPTH, see MemoryLayout
+    pub fn stack_depth(mut self, depth: usize) -> Self {
+        assert!((1..=MAX_STACK_DEPTH).contains(&depth));
+        self.chip8.layout.stack_depth = depth;
+        self
+    }
+
+    pub fn ipf(mut self, ipf: u32) -> Self {
+        self.ipf = ipf;
+        self
+    }
+
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.chip8.seed_rng(seed);
+        self
+    }
+
+    pub fn script(mut self, script: InputScript) -> Self {
+        self.script = Some(script);
+        self
+    }
+
+    pub fn frame(&self) -> u64 {
+        self.frame
+    }
+
+    // Run one frame: the inputs of the frame, the instructions, then the timers and vertical blank
+    pub fn step(&mut self) -> Result<(), Chip8Error> {
+        if let Some(script) = self.script.as_mut() {
+            script.apply(self.frame, &mut self.chip8);
+        }
+        let mut budget = self.ipf;
+        while budget > 0 {
+            let executed = self.chip8.cycles_executed();
+            match self.chip8.run_for(budget)? {
+                Stop::Drawn => budget -= (self.chip8.cycles_executed() - executed) as u32,
+                // Nothing more happens this frame
+                _ => break,
+            }
+        }
+        self.chip8.clock_timers(FRAME_RATE);
+        self.chip8.vblank();
+        self.frame += 1;
+        Ok(())
+    }
+
+    // Run the frames before the given one, nothing if it has already been reached
+    pub fn run_until(&mut self, frame: u64) -> Result<(), Chip8Error> {
+        while self.frame < frame {
+            self.step()?;
+        }
+        Ok(())
+    }
+
+    pub fn pixel(&self, x: usize, y: usize) -> bool {
+        assert!(
+            x < self.chip8.display_width() && y < self.chip8.display_height(),
+            "pixel ({}, {}) is outside of the display",
+            x,
+            y
+        );
+        self.chip8.display[x + y * self.chip8.display_width()] != 0
+    }
+
+    pub fn assert_pixel(&self, x: usize, y: usize, on: bool) {
+        assert_eq!(
+            self.pixel(x, y),
+            on,
+            "pixel ({}, {}) on frame {}",
+            x,
+            y,
+            self.frame
+        );
+    }
+
+    // Run until the start of the frame and check the register, Vx with x from 0x0 to 0xF
+    pub fn assert_register_at_frame(&mut self, register: usize, frame: u64, value: u8) {
+        if let Err(e) = self.run_until(frame) {
+            panic!(
+                "stopped on frame {} before frame {}: {}",
+                self.frame, frame, e
+            );
+        }
+        assert_eq!(
+            self.chip8.v[register], value,
+            "V{:X} on frame {}",
+            register, self.frame
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // LD V1, K; LD F, V1; DRW V2, V2, 5; ADD V0, 1; RND V3, 0xFF; JP 0x206
+    const ROM: [u8; 12] = [
+        0xF1, 0x0A, 0xF1, 0x29, 0xD2, 0x25, 0x70, 0x01, 0xC3, 0xFF, 0x12, 0x06,
+    ];
+
+    #[test]
+    fn test_harness() {
+        let script = InputScript::parse("frame 3: press 7; frame 4: release 7").unwrap();
+        let mut harness = TestHarness::new(&ROM).script(script);
+        // Waiting for a key until it's released on frame 4
+        harness.assert_register_at_frame(0x0, 4, 0);
+        harness.assert_register_at_frame(0x1, 5, 7);
+        assert_eq!(harness.frame(), 5);
+        // The 7 glyph at (0, 0)
+        harness.assert_pixel(0, 0, true);
+        harness.assert_pixel(0, 1, false);
+        harness.assert_pixel(3, 1, true);
+
+        // The same seed gives the same numbers
+        let run = |seed| {
+            let script = InputScript::parse("frame 0: press 1; frame 1: release 1").unwrap();
+            let mut harness = TestHarness::new(&ROM).seed(seed).script(script);
+            harness.run_until(10).unwrap();
+            harness.chip8.v[3]
+        };
+        assert_eq!(run(1), run(1));
+        assert!((2..5).any(|seed| run(seed) != run(1)));
+    }
+
+    #[test]
+    #[should_panic(expected = "V0 on frame 2")]
+    fn test_assert_register_at_frame() {
+        TestHarness::new(&ROM).assert_register_at_frame(0x0, 2, 1);
+    }
+
+    #[test]
+    fn test_timers_ignore_ipf() {
+        // LD V0, 60; LD DT, V0; LD V1, DT; SE V1, 0; JP 0x204; JP 0x20A
+        let rom = [
+            0x60, 0x3C, 0xF0, 0x15, 0xF1, 0x07, 0x31, 0x00, 0x12, 0x04, 0x12, 0x0A,
+        ];
+        // A second of delay takes 60 frames at any speed
+        for ipf in [11, 100, 1000] {
+            let mut harness = TestHarness::new(&rom).ipf(ipf);
+            harness.run_until(59).unwrap();
+            assert_eq!(harness.chip8.dt, 1, "{} IPF", ipf);
+            harness.run_until(61).unwrap();
+            assert_eq!(harness.chip8.dt, 0, "{} IPF", ipf);
+            assert_eq!(harness.chip8.pc, 0x20A, "{} IPF", ipf);
+        }
+    }
+
+    #[test]
+    fn test_run_until_error() {
+        let mut harness = TestHarness::new(&[0xFF, 0xFF]);
+        assert!(harness.run_until(1).is_err());
+        assert_eq!(harness.frame(), 0);
+    }
+}
@@ -0,0 +1,648 @@
+use rustc8::chip8::Chip8Error;
+use std::{env, fmt::Display, sync::OnceLock};
+
+static LANG: OnceLock<Lang> = OnceLock::new();
+
+/*
+Translations, `--lang <language>`
+The text of the interface (dialogs, status messages, pane titles) is written in English in the
+code and looked up in the table of the language when shown, falling back to English for text
+without a translation. Text with values has {} where each value goes, in the same order in
+every language:
+
+    Dialog::new(tr("Paused")).line(&trf("Quirks: {}", &[&name]))
+
+The language is picked once at startup, with --lang or from the locale of the environment
+($LC_ALL, $LC_MESSAGES, then $LANG), English when it has no translation. Command line usage and
+errors printed before the interface starts stay in English.
+To add a language, add it to Lang and give it a table of the same entries as SPANISH.
+*/
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Lang {
+    #[default]
+    English,
+    Spanish,
+}
+
+impl Lang {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "en" => Some(Lang::English),
+            "es" => Some(Lang::Spanish),
+            _ => None,
+        }
+    }
+
+    // The language of a locale such as es_ES.UTF-8
+    pub fn from_locale(locale: &str) -> Option<Self> {
+        Lang::from_name(locale.split(['_', '.', '-']).next()?)
+    }
+
+    // The first locale variable that is set, like the C library
+    pub fn from_env() -> Option<Self> {
+        ["LC_ALL", "LC_MESSAGES", "LANG"]
+            .iter()
+            .find_map(|name| env::var(name).ok().filter(|value| !value.is_empty()))
+            .and_then(|locale| Lang::from_locale(&locale))
+    }
+
+    fn table(self) -> &'static [(&'static str, &'static str)] {
+        match self {
+            Lang::English => &[],
+            Lang::Spanish => SPANISH,
+        }
+    }
+}
+
+// Only the first call has an effect, the language doesn't change while running
+pub fn set_lang(lang: Lang) {
+    let _ = LANG.set(lang);
+}
+
+pub fn lang() -> Lang {
+    LANG.get().copied().unwrap_or_default()
+}
+
+pub fn translate(lang: Lang, text: &'static str) -> &'static str {
+    lang.table()
+        .iter()
+        .find(|(english, _)| *english == text)
+        .map_or(text, |(_, translation)| translation)
+}
+
+// The text in the language of the interface
+pub fn tr(text: &'static str) -> &'static str {
+    translate(lang(), text)
+}
+
+// The text in the language of the interface with the values in place of the {}
+pub fn trf(text: &'static str, values: &[&dyn Display]) -> String {
+    fill(tr(text), values)
+}
+
+fn fill(text: &str, values: &[&dyn Display]) -> String {
+    let mut parts = text.split("{}");
+    let mut filled = parts.next().unwrap_or_default().to_string();
+    for (index, part) in parts.enumerate() {
+        if let Some(value) = values.get(index) {
+            filled += &value.to_string();
+        }
+        filled += part;
+    }
+    filled
+}
+
+// Errors of the core as shown in the interface, the same as their Display in English
+pub fn chip8_error(error: &Chip8Error) -> String {
+    let addr = |addr: &u16| format!("{:#05X}", addr);
+    match error {
+        Chip8Error::InvalidOpcode { addr: at, opcode } => trf(
+            "Invalid opcode {} at {}",
+            &[&format!("{:#06X}", opcode), &addr(at)],
+        ),
+        Chip8Error::UnsupportedSys { addr: at, nnn } => {
+            trf("Unsupported SYS call {} at {}", &[&addr(nnn), &addr(at)])
+        }
+        Chip8Error::StackOverflow { addr: at } => {
+            trf("Stack overflow, CALL at {} with a full stack", &[&addr(at)])
+        }
+        Chip8Error::StackUnderflow { addr: at } => trf(
+            "Stack underflow, RET at {} with an empty stack",
+            &[&addr(at)],
+        ),
+        Chip8Error::MemoryOutOfBounds { addr: at, target } => trf(
+            "Memory access out of bounds at {}, address {}",
+            &[&addr(at), &format!("{:#05X}", target)],
+        ),
+        Chip8Error::SpriteOutOfBounds {
+            addr: at,
+            i,
+            height,
+        } => trf(
+            "Sprite of {} rows at I {}, drawn at {}, reads past the end of memory",
+            &[height, &addr(i), &addr(at)],
+        ),
+        Chip8Error::SpritePastRom {
+            addr: at,
+            i,
+            height,
+            target,
+        } => trf(
+            "Sprite of {} rows at I {}, drawn at {}, reads {} past the end of the ROM, never written by the program",
+            &[height, &addr(i), &addr(at), &addr(target)],
+        ),
+    }
+}
+
+const SPANISH: &[(&str, &str)] = &[
+    // Emulator
+    (
+        "============= CHIP-8 Emulator (Press ESC to Exit) ===============",
+        "=========== Emulador CHIP-8 (Pulsa ESC para salir) ============",
+    ),
+    ("Error", "Error"),
+    ("Invalid opcode {} at {}", "Código de operación {} no válido en {}"),
+    ("Unsupported SYS call {} at {}", "Llamada SYS {} no admitida en {}"),
+    (
+        "Stack overflow, CALL at {} with a full stack",
+        "Desbordamiento de pila, CALL en {} con la pila llena",
+    ),
+    (
+        "Stack underflow, RET at {} with an empty stack",
+        "Pila vacía, RET en {} sin llamada de la que volver",
+    ),
+    (
+        "Memory access out of bounds at {}, address {}",
+        "Acceso fuera de la memoria en {}, dirección {}",
+    ),
+    (
+        "Sprite of {} rows at I {}, drawn at {}, reads past the end of memory",
+        "El sprite de {} filas en I {}, dibujado en {}, se lee más allá del final de la memoria",
+    ),
+    (
+        "Sprite of {} rows at I {}, drawn at {}, reads {} past the end of the ROM, never written by the program",
+        "El sprite de {} filas en I {}, dibujado en {}, lee {} más allá del final de la ROM, que el programa nunca escribió",
+    ),
+    (
+        "This is usually data executed by mistake, e.g. after a jump to the wrong address.",
+        "Suelen ser datos ejecutados por error, p. ej. tras un salto a una dirección equivocada.",
+    ),
+    (
+        "SYS called machine code of the original computers, use --sys ignore to skip these calls.",
+        "SYS llamaba a código máquina de los ordenadores originales, usa --sys ignore para saltar estas llamadas.",
+    ),
+    (" Ignored SYS {} at {} ", " SYS {} ignorado en {} "),
+    (
+        " Program finished, press R to reset ",
+        " Programa terminado, pulsa R para reiniciar ",
+    ),
+    (
+        "Subroutine calls and returns don't match, or the program jumped to the wrong address.",
+        "Las llamadas y los retornos de subrutinas no coinciden, o el programa saltó a una dirección equivocada.",
+    ),
+    (
+        "I or the program counter points past the end of memory, usually after a jump to the wrong address. ROMs written for interpreters that wrap I run with --memory-bounds wrap.",
+        "I o el contador de programa apuntan más allá del final de la memoria, normalmente tras un salto a una dirección equivocada. Las ROMs escritas para intérpretes que dan la vuelta a I funcionan con --memory-bounds wrap.",
+    ),
+    (
+        "I was set near the end of memory, check the LD I or ADD I before this DRW. ROMs written for interpreters that wrap I run with --memory-bounds wrap.",
+        "I apunta cerca del final de la memoria, revisa el LD I o ADD I antes de este DRW. Las ROMs escritas para intérpretes que dan la vuelta a I funcionan con --memory-bounds wrap.",
+    ),
+    (
+        "The sprite is read from memory the ROM doesn't fill, check the LD I or ADD I before this DRW. Run without --strict to draw it anyway.",
+        "El sprite se lee de memoria que la ROM no ocupa, revisa el LD I o ADD I antes de este DRW. Ejecuta sin --strict para dibujarlo de todos modos.",
+    ),
+    ("Ignore and continue", "Ignorar y continuar"),
+    ("Reset", "Reiniciar"),
+    ("Quit", "Salir"),
+    ("Breakpoint", "Punto de interrupción"),
+    ("Stopped at {}: {}", "Detenido en {}: {}"),
+    ("Continue", "Continuar"),
+    ("Step", "Paso a paso"),
+    ("Paused", "En pausa"),
+    (
+        "Speed: {} instructions per frame",
+        "Velocidad: {} instrucciones por fotograma",
+    ),
+    ("Quirks: {}", "Peculiaridades: {}"),
+    ("Renderer: {}", "Dibujo: {}"),
+    ("Palette: {}", "Paleta: {}"),
+    ("Key repeat: {}", "Repetición de teclas: {}"),
+    (" Palette: {} ", " Paleta: {} "),
+    ("Resume", "Reanudar"),
+    ("Speed", "Velocidad"),
+    ("Quirks", "Peculiaridades"),
+    ("Renderer", "Dibujo"),
+    ("Palette", "Paleta"),
+    ("Key repeat", "Repetición de teclas"),
+    ("Save for this ROM", "Guardar para esta ROM"),
+    (" Saved the settings of {} ", " Guardados los ajustes de {} "),
+    (
+        " Failed to save the settings: {} ",
+        " No se pudieron guardar los ajustes: {} ",
+    ),
+    ("Saved {}", "Guardado {}"),
+    ("Export failed: {}", "No se pudo exportar: {}"),
+    ("No screenshot selected", "Ninguna captura seleccionada"),
+    (
+        " {} is not supported yet, running as CHIP-8 ",
+        " {} aún no es compatible, se ejecuta como CHIP-8 ",
+    ),
+    (" Reloaded {} ", " Recargado {} "),
+    (" Spectators: {} ", " Espectadores: {} "),
+    (
+        " Netplay: desync at frame {} ",
+        " Juego en red: desincronizado en el fotograma {} ",
+    ),
+    (
+        " Netplay: frame {}, {} rollbacks ",
+        " Juego en red: fotograma {}, {} retrocesos ",
+    ),
+    (" Frame {}, state {} ", " Fotograma {}, estado {} "),
+    (
+        " Screenshot {}/{}, frame {} (Left/Right, Del: delete, Enter: export PNG) {}",
+        " Captura {}/{}, fotograma {} (Izquierda/Derecha, Supr: borrar, Intro: exportar PNG) {}",
+    ),
+    (
+        " No screenshots, press F6 while playing to capture one ",
+        " No hay capturas, pulsa F6 mientras juegas para hacer una ",
+    ),
+    (" Screenshot {} captured (F7: gallery) ", " Captura {} hecha (F7: galería) "),
+    ("Overwrite save state?", "¿Sobrescribir el estado guardado?"),
+    ("Overwrite screenshot?", "¿Sobrescribir la captura?"),
+    ("{} already exists.", "{} ya existe."),
+    ("Overwrite", "Sobrescribir"),
+    ("Cancel", "Cancelar"),
+    (" Invalid name ", " Nombre no válido "),
+    (" Saved {} ", " Guardado {} "),
+    (" Failed to autosave: {} ", " No se pudo guardar automáticamente: {} "),
+    (" Event log stopped: {} ", " Registro de eventos detenido: {} "),
+    (" Video recording stopped: {} ", " Grabación de vídeo detenida: {} "),
+    (" Reset ", " Reiniciado "),
+    (" Reset is not available during netplay ", " El reinicio no está disponible en juego en red "),
+    ("The emulation is stalled", "La emulación está atascada"),
+    (
+        "A frame has been running for over {} seconds, {} instructions so far.",
+        "Un fotograma lleva más de {} segundos ejecutándose, {} instrucciones hasta ahora.",
+    ),
+    ("Pause", "Pausar"),
+    ("Lower speed", "Bajar la velocidad"),
+    (" Speed: {} instructions per frame ", " Velocidad: {} instrucciones por fotograma "),
+    (" Copied the frame ", " Pantalla copiada "),
+    (" Copied the registers ", " Registros copiados "),
+    (" Copied the disassembly ", " Desensamblado copiado "),
+    (" Failed to copy: {} ", " No se pudo copiar: {} "),
+    (
+        " Press F3 to show the disassembly to copy ",
+        " Pulsa F3 para mostrar el desensamblado a copiar ",
+    ),
+    (" Failed to save {}: {} ", " No se pudo guardar {}: {} "),
+    (
+        " Failed to save the RPL flags: {} ",
+        " No se pudieron guardar los indicadores RPL: {} ",
+    ),
+    (" Loaded {} ", " Cargado {} "),
+    (" Failed to load {}: {} ", " No se pudo cargar {}: {} "),
+    (
+        " Save states are not available during netplay ",
+        " Los estados guardados no están disponibles en el juego en red ",
+    ),
+    ("Save state as", "Guardar el estado como"),
+    ("Saved in {}", "Se guarda en {}"),
+    ("Save", "Guardar"),
+    (
+        " No save states for this ROM, press Ctrl+S to save one ",
+        " No hay estados guardados de esta ROM, pulsa Ctrl+S para guardar uno ",
+    ),
+    ("Load which state?", "¿Qué estado cargar?"),
+    ("Load", "Cargar"),
+    ("No preview", "Sin vista previa"),
+    (
+        " Quick save is not available during netplay ",
+        " El guardado rápido no está disponible en el juego en red ",
+    ),
+    (" Quick saved ", " Guardado rápido hecho "),
+    (" Quick loaded ", " Carga rápida hecha "),
+    (" Rewind is not available during netplay ", " Rebobinar no está disponible en juego en red "),
+    (" Nothing to rewind ", " Nada que rebobinar "),
+    (" Rewind: {}s of history left ", " Rebobinar: quedan {}s de historial "),
+    (" Failed to rewind: {} ", " No se pudo rebobinar: {} "),
+    (
+        " Nothing to load, press F9 to quick save first ",
+        " Nada que cargar, pulsa F9 para guardar primero ",
+    ),
+    // Panes
+    (
+        " Memory map (click or Left/Right to select, wheel to scroll) {} ",
+        " Mapa de memoria (clic o Izquierda/Derecha para elegir, rueda para desplazar) {} ",
+    ),
+    ("free", "libre"),
+    ("font", "fuente"),
+    ("program", "programa"),
+    ("data", "datos"),
+    ("written", "escrito"),
+    ("code", "código"),
+    (" Disassembly (F4: lock) ", " Desensamblado (F4: fijar) "),
+    (" Disassembly (locked) ", " Desensamblado (fijado) "),
+    (" Draw timeline (live) ", " Historial de dibujo (en directo) "),
+    (
+        " Draw timeline (PgDn/Home) ",
+        " Historial de dibujo (AvPág/Inicio) ",
+    ),
+    (" Sprite ", " Sprite "),
+    (" Time ", " Tiempo "),
+    (" Ready ", " Preparado "),
+    ("Frame time: {} ms", "Tiempo por fotograma: {} ms"),
+    ("Cycles per frame: {}", "Ciclos por fotograma: {}"),
+    // ROM browser
+    ("Looking for ROMs", "Buscando ROMs"),
+    (
+        "Downloading the ROM database",
+        "Descargando la base de datos de ROMs",
+    ),
+    ("(Esc to cancel)", "(Esc para cancelar)"),
+    ("Cancelled", "Cancelado"),
+    ("Cancelled the download", "Descarga cancelada"),
+    (
+        "Updated the ROM database, {} programs",
+        "Base de datos de ROMs actualizada, {} programas",
+    ),
+    (
+        "F5: download the ROM database for the titles",
+        "F5: descargar la base de datos de ROMs para ver los títulos",
+    ),
+    (
+        " Search (type to filter, Enter to play, Esc to quit) ",
+        " Buscar (escribe para filtrar, Intro para jugar, Esc para salir) ",
+    ),
+    (" {} of {} ROMs in {} ", " {} de {} ROMs en {} "),
+    // Assembler REPL
+    (" Registers ", " Registros "),
+    (
+        " History (:reset to start again, :save and :source <file> for scripts) ",
+        " Historial (:reset para empezar de nuevo, :save y :source <archivo> para scripts) ",
+    ),
+    (" Display ", " Pantalla "),
+    (
+        " Instruction (Enter to execute, Esc to quit) ",
+        " Instrucción (Intro para ejecutar, Esc para salir) ",
+    ),
+    ("{} is not an instruction", "{} no es una instrucción"),
+    (
+        "PC {} is past the end of memory",
+        "PC {} está más allá del final de la memoria",
+    ),
+    ("Saved {} lines to {}", "{} líneas guardadas en {}"),
+    (
+        "Scripts can't source other scripts",
+        "Los scripts no pueden cargar otros scripts",
+    ),
+    // Teaching mode
+    (" Teaching mode (F11: hide) ", " Modo de aprendizaje (F11: ocultar) "),
+    ("Next: {}", "Siguiente: {}"),
+    ("VF is reset to 0", "VF se pone a 0"),
+    ("I moves past the last register", "I avanza tras el último registro"),
+    (
+        "Not an instruction, the interpreter stops with an error",
+        "No es una instrucción, el intérprete se detiene con un error",
+    ),
+    ("Clears the screen", "Borra la pantalla"),
+    (
+        "Returns from the subroutine to {}",
+        "Vuelve de la subrutina a {}",
+    ),
+    (
+        "Returns from a subroutine, but the stack is empty",
+        "Vuelve de una subrutina, pero la pila está vacía",
+    ),
+    (
+        "Calls machine code at {} on the original computer, ignored by modern interpreters",
+        "Llama al código máquina en {} del ordenador original, los intérpretes modernos lo ignoran",
+    ),
+    (
+        "Ends the program, nothing else runs after it",
+        "Termina el programa, no se ejecuta nada más después",
+    ),
+    (
+        "Jumps to itself forever, the usual way to end a program",
+        "Salta a sí misma para siempre, la forma habitual de terminar un programa",
+    ),
+    ("Jumps to {}", "Salta a {}"),
+    (
+        "Calls the subroutine at {}, saving the return address {} on the stack",
+        "Llama a la subrutina en {}, guardando la dirección de retorno {} en la pila",
+    ),
+    (
+        "Skips the next instruction if {} ({}) equals {}",
+        "Salta la siguiente instrucción si {} ({}) es igual a {}",
+    ),
+    (
+        "Skips the next instruction if {} ({}) is not {}",
+        "Salta la siguiente instrucción si {} ({}) no es {}",
+    ),
+    (
+        "Skips the next instruction if {} ({}) equals {} ({})",
+        "Salta la siguiente instrucción si {} ({}) es igual a {} ({})",
+    ),
+    ("Sets {} to {}", "Pone {} a {}"),
+    (
+        "Adds {} to {} ({}), VF is left unchanged",
+        "Suma {} a {} ({}), VF no cambia",
+    ),
+    ("Copies {} ({}) into {}", "Copia {} ({}) en {}"),
+    (
+        "Sets {} to the bitwise {} of {} ({}) and {} ({})",
+        "Pone en {} el {} bit a bit de {} ({}) y {} ({})",
+    ),
+    (
+        "Adds {} ({}) to {} ({}), VF is set to 1 on a carry",
+        "Suma {} ({}) a {} ({}), VF se pone a 1 si hay acarreo",
+    ),
+    (
+        "Subtracts {} ({}) from {} ({}), VF is set to 0 on a borrow",
+        "Resta {} ({}) de {} ({}), VF se pone a 0 si hay préstamo",
+    ),
+    (
+        "Sets {} to {} ({}) minus {} ({}), VF is set to 0 on a borrow",
+        "Pone en {} {} ({}) menos {} ({}), VF se pone a 0 si hay préstamo",
+    ),
+    (
+        "Shifts {} ({}) right by one into {}, VF gets the bit shifted out",
+        "Desplaza {} ({}) un bit a la derecha en {}, VF recibe el bit que sale",
+    ),
+    (
+        "Shifts {} ({}) left by one into {}, VF gets the bit shifted out",
+        "Desplaza {} ({}) un bit a la izquierda en {}, VF recibe el bit que sale",
+    ),
+    (
+        "Skips the next instruction if {} ({}) is not {} ({})",
+        "Salta la siguiente instrucción si {} ({}) no es {} ({})",
+    ),
+    ("Sets I to {}", "Pone I a {}"),
+    ("Jumps to {} plus {} ({})", "Salta a {} más {} ({})"),
+    (
+        "Sets {} to a random number ANDed with {}",
+        "Pone en {} un número aleatorio AND {}",
+    ),
+    (
+        "Draws an 8x{} sprite from I ({}) at {}, {} ({}, {}); VF is set to 1 if it erases a pixel",
+        "Dibuja un sprite de 8x{} desde I ({}) en {}, {} ({}, {}); VF se pone a 1 si borra un píxel",
+    ),
+    (
+        "Skips the next instruction if key {} ({}) is pressed",
+        "Salta la siguiente instrucción si la tecla {} ({}) está pulsada",
+    ),
+    (
+        "Skips the next instruction if key {} ({}) is not pressed",
+        "Salta la siguiente instrucción si la tecla {} ({}) no está pulsada",
+    ),
+    (
+        "Sets {} to the delay timer ({})",
+        "Pone en {} el temporizador de retardo ({})",
+    ),
+    (
+        "Waits for a key press and stores the key in {}",
+        "Espera a que se pulse una tecla y la guarda en {}",
+    ),
+    (
+        "Sets the delay timer to {} ({}), it counts down at 60Hz",
+        "Pone el temporizador de retardo a {} ({}), cuenta hacia atrás a 60Hz",
+    ),
+    (
+        "Sets the sound timer to {} ({}), the buzzer sounds until it reaches 0",
+        "Pone el temporizador de sonido a {} ({}), el zumbador suena hasta que llega a 0",
+    ),
+    ("Adds {} ({}) to I ({})", "Suma {} ({}) a I ({})"),
+    (
+        "Points I to the font sprite of digit {} ({})",
+        "Apunta I al sprite de la fuente del dígito {} ({})",
+    ),
+    (
+        "Stores the hundreds, tens and ones digits of {} ({}) at I ({}), I+1 and I+2",
+        "Guarda las centenas, decenas y unidades de {} ({}) en I ({}), I+1 e I+2",
+    ),
+    (
+        "Stores V0 to {} in memory starting at I ({})",
+        "Guarda de V0 a {} en la memoria desde I ({})",
+    ),
+    (
+        "Loads V0 to {} from memory starting at I ({})",
+        "Carga de V0 a {} desde la memoria en I ({})",
+    ),
+    (
+        "Saves V0 to {} in the RPL user flags, which are kept after the game is closed",
+        "Guarda de V0 a {} en los flags de usuario RPL, que se conservan al cerrar el juego",
+    ),
+    (
+        "Loads V0 to {} from the RPL user flags",
+        "Carga de V0 a {} desde los flags de usuario RPL",
+    ),
+    // Setup wizard
+    ("Welcome to rustc8", "Bienvenido a rustc8"),
+    (
+        "A few questions to set up the emulator, Esc skips them. Run rustc8 setup to answer them again.",
+        "Unas preguntas para configurar el emulador, Esc las salta. Ejecuta rustc8 setup para responderlas de nuevo.",
+    ),
+    ("yes", "sí"),
+    ("no", "no"),
+    ("Unicode: {}", "Unicode: {}"),
+    ("Braille: {}", "Braille: {}"),
+    ("Colors: {}", "Colores: {}"),
+    ("How should the display be drawn?", "¿Cómo se dibuja la pantalla?"),
+    ("a block per pixel, the sharpest", "un bloque por píxel, el más nítido"),
+    (
+        "two pixels per character, for small windows",
+        "dos píxeles por carácter, para ventanas pequeñas",
+    ),
+    (
+        "eight dots per character, the smallest",
+        "ocho puntos por carácter, el más pequeño",
+    ),
+    ("# and . for any terminal", "# y . para cualquier terminal"),
+    ("Next", "Siguiente"),
+    ("Back", "Atrás"),
+    ("Keyboard", "Teclado"),
+    (
+        "Which layout is your keyboard? The keypad is played on the keys shown.",
+        "¿Qué distribución tiene tu teclado? El teclado hexadecimal se juega con las teclas mostradas.",
+    ),
+    ("ROM folder", "Carpeta de ROMs"),
+    (
+        "Which folder are your ROMs in? It's opened when rustc8 is run without a ROM. Leave it empty to give a ROM every time.",
+        "¿En qué carpeta están tus ROMs? Se abre al ejecutar rustc8 sin una ROM. Déjala vacía para indicar una ROM cada vez.",
+    ),
+    ("Finish", "Terminar"),
+    ("{} is not a folder", "{} no es una carpeta"),
+    ("All set", "Listo"),
+    ("Saved the settings in {}", "Ajustes guardados en {}"),
+    ("Setup", "Configuración"),
+    (
+        "Failed to save the settings: {}",
+        "No se pudieron guardar los ajustes: {}",
+    ),
+    ("OK", "Aceptar"),
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The text given to tr and trf in a source file
+    fn keys(source: &str) -> Vec<String> {
+        let mut keys = Vec::new();
+        for (start, _) in source.match_indices("tr") {
+            let before = source[..start].chars().next_back();
+            if before.is_some_and(|c| c.is_alphanumeric() || c == '_') {
+                continue;
+            }
+            let rest = source[start + 2..]
+                .strip_prefix('f')
+                .unwrap_or(&source[start + 2..]);
+            let Some(rest) = rest.strip_prefix('(') else {
+                continue;
+            };
+            let Some(rest) = rest.trim_start().strip_prefix('"') else {
+                continue;
+            };
+            keys.push(rest[..rest.find('"').unwrap()].to_string());
+        }
+        keys
+    }
+
+    #[test]
+    fn test_translations() {
+        let sources = [
+            include_str!("main.rs"),
+            include_str!("browser.rs"),
+            include_str!("disassembly.rs"),
+            include_str!("gallery.rs"),
+            include_str!("i18n.rs"),
+            include_str!("memory_map.rs"),
+            include_str!("repl.rs"),
+            include_str!("setup.rs"),
+            include_str!("speedrun.rs"),
+            include_str!("states.rs"),
+            include_str!("task.rs"),
+            include_str!("teach.rs"),
+            include_str!("telemetry.rs"),
+            include_str!("timeline.rs"),
+        ];
+        let mut used: Vec<String> = sources.iter().flat_map(|source| keys(source)).collect();
+        used.push(crate::TITLE.to_string());
+        assert!(used.contains(&"Paused".to_string()));
+        for key in &used {
+            assert!(
+                SPANISH.iter().any(|(english, _)| english == key),
+                "no translation for {:?}",
+                key
+            );
+        }
+        for (english, spanish) in SPANISH {
+            assert!(used.contains(&english.to_string()), "unused {:?}", english);
+            assert_eq!(
+                english.matches("{}").count(),
+                spanish.matches("{}").count(),
+                "{:?}",
+                english
+            );
+        }
+    }
+
+    #[test]
+    fn test_translate() {
+        assert_eq!(translate(Lang::Spanish, "Paused"), "En pausa");
+        assert_eq!(translate(Lang::English, "Paused"), "Paused");
+        assert_eq!(translate(Lang::Spanish, "Not translated"), "Not translated");
+        assert_eq!(fill("{} of {} ROMs", &[&3, &"10"]), "3 of 10 ROMs");
+        assert_eq!(fill("{} of {}", &[&3]), "3 of ");
+        assert_eq!(Lang::from_locale("es_ES.UTF-8"), Some(Lang::Spanish));
+        assert_eq!(Lang::from_locale("C"), None);
+
+        // The same text as Display in English
+        let error = Chip8Error::MemoryOutOfBounds {
+            addr: 0x200,
+            target: 0x1000,
+        };
+        assert_eq!(chip8_error(&error), error.to_string());
+    }
+}
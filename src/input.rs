@@ -0,0 +1,287 @@
+use ratatui::crossterm::event::KeyCode;
+use rustc8::chip8::{Chip8, TAP_FRAMES};
+use std::{
+    collections::VecDeque,
+    time::{Duration, Instant},
+};
+
+/*
+InputQueue buffers the Chip-8 key events received from the terminal between emulation steps.
+Terminals often deliver several key events at once (e.g. rapid taps or a burst after a redraw),
+and the cycle rate may be lower than the rate at which keys arrive.
+Instead of only applying the latest poll result, every event is queued and fed to the core one
+per emulation step, in the order it was received, so that no tap is lost.
+Terminals with the kitty keyboard protocol report releases, so their keys are held from Down to
+Up. The others only send presses and key repeats, which are taps held for TAP_FRAMES.
+A key released is held to the end of the frame, so a quick tap whose press and release arrive
+together is still seen by a program that reads the keys once a frame.
+A Repeat is a press made by the auto-repeat of the host, see KeyRepeat.
+*/
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum KeyInput {
+    Down(u8),
+    Up(u8),
+    Tap(u8),
+    Repeat(u8),
+}
+
+pub struct InputQueue {
+    keys: VecDeque<KeyInput>,
+}
+
+impl InputQueue {
+    pub fn new() -> Self {
+        InputQueue {
+            keys: VecDeque::new(),
+        }
+    }
+
+    pub fn push(&mut self, key: KeyInput) {
+        self.keys.push_back(key);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.keys.is_empty()
+    }
+
+    // Take all queued key presses at once as a mask with one bit per key, releases are dropped
+    pub fn take_mask(&mut self) -> u16 {
+        self.keys.drain(..).fold(0, |mask, key| match key {
+            KeyInput::Down(key) | KeyInput::Tap(key) => mask | (1 << key),
+            KeyInput::Up(_) | KeyInput::Repeat(_) => mask,
+        })
+    }
+
+    // Apply the oldest queued key event to the core, called once before each emulation step
+    pub fn apply_next(&mut self, chip8: &mut Chip8) {
+        match self.keys.pop_front() {
+            Some(KeyInput::Down(key)) => chip8.key_down(key),
+            Some(KeyInput::Up(key)) if chip8.keyboard[key as usize] => chip8.tap_key(key, 1),
+            Some(KeyInput::Up(_)) => {}
+            Some(KeyInput::Tap(key)) => chip8.tap_key(key, TAP_FRAMES),
+            // Keeps a tapped key held, but doesn't press it again once released
+            Some(KeyInput::Repeat(key)) if chip8.keyboard[key as usize] => {
+                chip8.tap_key(key, TAP_FRAMES)
+            }
+            Some(KeyInput::Repeat(_)) => {}
+            None => {}
+        }
+    }
+}
+
+/*
+Key repeat
+Holding a key makes the host repeat its press, after a delay and then many times a second. As
+presses, the repeats release and press again a key the program has seen released, which completes
+an Fx0A twice and makes movement stutter across the delay. Per game, they can be:
+- Keep: repeats are presses, as typed
+- Filter: repeats keep the key held rather than pressing it again. With the kitty keyboard
+  protocol the terminal tells them apart, the key is held from its press to its release. Others
+  send repeats as presses, so those closer than REPEAT_DELAY to the previous press of the key are
+  taken as repeats, and a tap is held for TAP_FRAMES whatever the host repeats
+*/
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum KeyRepeat {
+    #[default]
+    Keep,
+    Filter,
+}
+
+pub const KEY_REPEATS: [KeyRepeat; 2] = [KeyRepeat::Keep, KeyRepeat::Filter];
+
+// The usual delay before the host starts repeating a key, and well over the time between repeats
+const REPEAT_DELAY: Duration = Duration::from_millis(500);
+
+impl KeyRepeat {
+    pub fn from_name(name: &str) -> Option<Self> {
+        KEY_REPEATS.into_iter().find(|repeat| repeat.name() == name)
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            KeyRepeat::Keep => "keep",
+            KeyRepeat::Filter => "filter",
+        }
+    }
+}
+
+// Tells the repeats of the host from presses in terminals that send both as presses
+pub struct RepeatDetector {
+    // The last press or repeat of each key
+    last: [Option<Instant>; 16],
+}
+
+impl RepeatDetector {
+    pub fn new() -> Self {
+        RepeatDetector { last: [None; 16] }
+    }
+
+    // Whether a press of the key at the time is a repeat, a key held down repeats until released
+    pub fn is_repeat(&mut self, key: u8, time: Instant) -> bool {
+        let last = self.last[key as usize].replace(time);
+        last.is_some_and(|last| time.duration_since(last) < REPEAT_DELAY)
+    }
+}
+
+/*
+Keymaps
+The 16 keys of the keypad are mapped onto the 4x4 block at the left of the keyboard, keeping
+their layout on the COSMAC VIP:
+
+    1 2 3 C        1 2 3 4
+    4 5 6 D   ->   Q W E R
+    7 8 9 E        A S D F
+    A 0 B F        Z X C V
+
+The presets put the block on the same keys of other layouts, where the letters differ. The digit
+row also answers to the digits on every layout, as AZERTY keyboards type them with Shift.
+*/
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Keymap {
+    #[default]
+    Qwerty,
+    Azerty,
+    Qwertz,
+}
+
+pub const KEYMAPS: [Keymap; 3] = [Keymap::Qwerty, Keymap::Azerty, Keymap::Qwertz];
+
+// Keypad keys of the block, row by row
+const KEYPAD: [[u8; 4]; 4] = [
+    [0x1, 0x2, 0x3, 0xC],
+    [0x4, 0x5, 0x6, 0xD],
+    [0x7, 0x8, 0x9, 0xE],
+    [0xA, 0x0, 0xB, 0xF],
+];
+
+impl Keymap {
+    pub fn from_name(name: &str) -> Option<Self> {
+        KEYMAPS.into_iter().find(|keymap| keymap.name() == name)
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Keymap::Qwerty => "qwerty",
+            Keymap::Azerty => "azerty",
+            Keymap::Qwertz => "qwertz",
+        }
+    }
+
+    // Keys of the block, row by row
+    pub fn rows(&self) -> [&'static str; 4] {
+        match self {
+            Keymap::Qwerty => ["1234", "qwer", "asdf", "zxcv"],
+            Keymap::Azerty => ["&é\"'", "azer", "qsdf", "wxcv"],
+            Keymap::Qwertz => ["1234", "qwer", "asdf", "yxcv"],
+        }
+    }
+
+    pub fn key(&self, key: KeyCode) -> Option<u8> {
+        let KeyCode::Char(c) = key else {
+            return None;
+        };
+        let rows = self.rows();
+        let digits = ["1234", "", "", ""];
+        [rows, digits].iter().find_map(|rows| {
+            rows.iter().zip(KEYPAD).find_map(|(row, keys)| {
+                let column = row.chars().position(|key| key == c)?;
+                Some(keys[column])
+            })
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_apply_in_order() {
+        let mut chip8 = Chip8::new();
+        let mut input = InputQueue::new();
+        input.push(KeyInput::Down(0x1));
+        input.push(KeyInput::Tap(0x2));
+        input.push(KeyInput::Up(0x1));
+
+        input.apply_next(&mut chip8);
+        assert!(chip8.keyboard[0x1]);
+        assert!(!chip8.keyboard[0x2]);
+
+        input.apply_next(&mut chip8);
+        input.apply_next(&mut chip8);
+        assert!(chip8.keyboard[0x1]);
+        assert!(chip8.keyboard[0x2]);
+        chip8.vblank();
+        assert!(!chip8.keyboard[0x1]);
+        assert!(chip8.keyboard[0x2]);
+
+        // Taps are released by themselves
+        for _ in 1..TAP_FRAMES {
+            chip8.vblank();
+        }
+        assert!(!chip8.keyboard[0x2]);
+        input.apply_next(&mut chip8);
+        assert!(!chip8.keyboard[0x2]);
+    }
+
+    #[test]
+    fn test_repeats() {
+        let start = Instant::now();
+        let mut detector = RepeatDetector::new();
+        assert!(!detector.is_repeat(0x5, start));
+        assert!(detector.is_repeat(0x5, start + Duration::from_millis(450)));
+        assert!(detector.is_repeat(0x5, start + Duration::from_millis(480)));
+        assert!(!detector.is_repeat(0x6, start + Duration::from_millis(480)));
+        assert!(!detector.is_repeat(0x5, start + Duration::from_millis(1000)));
+
+        // A repeat keeps a tap held, but doesn't press the key again
+        let mut chip8 = Chip8::new();
+        let mut input = InputQueue::new();
+        input.push(KeyInput::Tap(0x5));
+        input.push(KeyInput::Repeat(0x5));
+        input.apply_next(&mut chip8);
+        for _ in 1..TAP_FRAMES {
+            chip8.vblank();
+        }
+        input.apply_next(&mut chip8);
+        chip8.vblank();
+        assert!(chip8.keyboard[0x5]);
+        for _ in 1..TAP_FRAMES {
+            chip8.vblank();
+        }
+        assert!(!chip8.keyboard[0x5]);
+        input.push(KeyInput::Repeat(0x5));
+        input.apply_next(&mut chip8);
+        assert!(!chip8.keyboard[0x5]);
+        for repeat in KEY_REPEATS {
+            assert_eq!(KeyRepeat::from_name(repeat.name()), Some(repeat));
+        }
+    }
+
+    #[test]
+    fn test_keymaps() {
+        assert_eq!(Keymap::Qwerty.key(KeyCode::Char('4')), Some(0xC));
+        assert_eq!(Keymap::Qwerty.key(KeyCode::Char('x')), Some(0x0));
+        assert_eq!(Keymap::Qwerty.key(KeyCode::Char('y')), None);
+        assert_eq!(Keymap::Azerty.key(KeyCode::Char('a')), Some(0x4));
+        assert_eq!(Keymap::Azerty.key(KeyCode::Char('é')), Some(0x2));
+        assert_eq!(Keymap::Azerty.key(KeyCode::Char('2')), Some(0x2));
+        assert_eq!(Keymap::Qwertz.key(KeyCode::Char('y')), Some(0xA));
+        assert_eq!(Keymap::Qwertz.key(KeyCode::Enter), None);
+        for keymap in KEYMAPS {
+            assert_eq!(Keymap::from_name(keymap.name()), Some(keymap));
+        }
+    }
+
+    #[test]
+    fn test_take_mask() {
+        let mut input = InputQueue::new();
+        input.push(KeyInput::Tap(0x1));
+        input.push(KeyInput::Down(0xF));
+        input.push(KeyInput::Up(0x2));
+        input.push(KeyInput::Tap(0x1));
+        input.push(KeyInput::Repeat(0x4));
+        assert_eq!(input.take_mask(), 0x8002);
+        assert_eq!(input.take_mask(), 0);
+    }
+}
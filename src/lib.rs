@@ -0,0 +1,49 @@
+#![cfg_attr(not(feature = "std"), no_std)]
+
+extern crate alloc;
+
+#[cfg(feature = "std")]
+pub mod asm;
+#[cfg(feature = "std")]
+pub mod audio;
+pub mod bus;
+pub mod chip8;
+#[cfg(feature = "std")]
+pub mod database;
+pub mod disasm;
+#[cfg(feature = "std")]
+pub mod gzip;
+#[cfg(feature = "std")]
+pub mod handle;
+#[cfg(feature = "std")]
+pub mod harness;
+#[cfg(feature = "megachip")]
+pub mod megachip;
+#[cfg(feature = "std")]
+pub mod netplay;
+#[cfg(feature = "std")]
+pub mod octocart;
+#[cfg(feature = "std")]
+pub mod patch;
+#[cfg(feature = "std")]
+pub mod platform;
+#[cfg(feature = "std")]
+pub mod png;
+#[cfg(feature = "std")]
+pub mod rewind;
+#[cfg(feature = "std")]
+pub mod savestate;
+#[cfg(feature = "std")]
+pub mod script;
+#[cfg(feature = "serde")]
+mod serialize;
+pub mod step;
+#[cfg(any(feature = "std", feature = "web"))]
+pub mod storage;
+pub mod timing;
+#[cfg(feature = "std")]
+pub mod trace;
+#[cfg(feature = "web")]
+pub mod web;
+#[cfg(feature = "tui")]
+pub mod widget;
@@ -0,0 +1,194 @@
+use crate::{
+    compare::{code_addresses, format_line},
+    config,
+    coverage::{self, EXECUTED, READ},
+    diagnostic::Diagnostic,
+    patch, read_rom, trace,
+};
+use rustc8::{
+    chip8::{Chip8, MEMORY_START},
+    disasm,
+};
+use std::{collections::HashSet, fmt::Write, fs};
+
+const DISASM_USAGE: &str =
+    "Usage: cargo run disasm <ROM file> [--analyze] [-o <file>] [--load-addr <address>]";
+
+/*
+Disassembly listing, `rustc8 disasm <rom>`
+Writes the disassembly of a ROM to the terminal, or to a file with -o. By default every pair of
+bytes is shown as an instruction. With --analyze the code is told apart from the data, and each
+line says how it was found:
+
+    ; pong.ch8, 246 bytes at 0x200
+    ; 168 bytes executed and 36 read as data by earlier runs
+    200  6A02  LD VA, 0x02          ; executed
+    ...
+    2EA  80    DB 0x80  #.......    ; read
+    2EB  80    DB 0x80  #.......    ; read
+
+- executed: the instruction ran in an earlier run of the ROM, see coverage
+- read: the byte was read through I by DRW or LD Vx, [I], e.g. a sprite
+- code: the instruction is reached by following the jumps, calls and skips from the start but
+  never ran, e.g. the end of the game nobody has reached yet
+Bytes without a note are neither, usually padding or data the runs didn't touch. The coverage
+is far more accurate than following the jumps alone, which misses code only reached through
+JP V0 and mistakes data after a skip for code, so the more the ROM is played before, the better
+the listing.
+*/
+pub fn run(args: &[String]) -> Result<(), Diagnostic> {
+    let mut path = None;
+    let mut analyze = false;
+    let mut output = None;
+    let mut load_addr = MEMORY_START;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--analyze" => analyze = true,
+            "-o" => output = Some(args.next().ok_or("Missing value for -o")?),
+            "--load-addr" => {
+                let value = args.next().ok_or("Missing value for --load-addr")?;
+                load_addr = trace::parse_address(value)
+                    .ok()
+                    .map(|addr| addr as usize)
+                    .filter(|addr| (MEMORY_START..Chip8::new().memory.len()).contains(addr))
+                    .ok_or_else(|| format!("Invalid load address: {}", value))?;
+            }
+            _ if path.is_none() => path = Some(arg),
+            _ => return Err(DISASM_USAGE.into()),
+        }
+    }
+    let path = path.ok_or(DISASM_USAGE)?;
+    let rom = read_rom(path)?;
+    let text = match analyze {
+        true => {
+            let flags = coverage::load(&config::storage(), patch::crc32(&rom), rom.len());
+            analyzed(path, &rom, load_addr, flags.as_deref())
+        }
+        false => linear(&rom, load_addr),
+    };
+    match output {
+        Some(output) => fs::write(output, text)
+            .map_err(|e| format!("Failed to write the listing to {}: {}", output, e))?,
+        None => print!("{}", text),
+    }
+    Ok(())
+}
+
+// Every pair of bytes as an instruction, an odd byte at the end as data
+fn linear(rom: &[u8], load_addr: usize) -> String {
+    let mut text = String::new();
+    for (index, bytes) in rom.chunks(2).enumerate() {
+        let code = bytes.len() == 2;
+        let _ = writeln!(
+            text,
+            "{}",
+            format_line(load_addr + index * 2, code, Some(bytes))
+        );
+    }
+    text
+}
+
+fn analyzed(name: &str, rom: &[u8], load_addr: usize, flags: Option<&[u8]>) -> String {
+    let mut text = String::new();
+    let _ = writeln!(
+        text,
+        "; {}, {} bytes at {:#05X}",
+        name,
+        rom.len(),
+        load_addr
+    );
+    match flags {
+        Some(flags) => {
+            let count = |flag| flags.iter().filter(|flags| *flags & flag != 0).count();
+            let _ = writeln!(
+                text,
+                "; {} bytes executed and {} read as data by earlier runs",
+                count(EXECUTED),
+                count(READ)
+            );
+        }
+        None => {
+            let _ = writeln!(
+                text,
+                "; The ROM hasn't run yet, code is only found by following the jumps: run it \
+                 for a more accurate listing"
+            );
+        }
+    }
+
+    let reached: HashSet<usize> = code_addresses(rom, load_addr);
+    let flag = |offset: usize, flag: u8| flags.is_some_and(|flags| flags[offset] & flag != 0);
+    let mut offset = 0;
+    while offset < rom.len() {
+        let addr = load_addr + offset;
+        let instruction =
+            disasm::opcode_at(rom, offset).filter(|opcode| disasm::is_instruction(*opcode));
+        // What ran is code even if it was also read, e.g. a sprite drawn from the code
+        let note = match instruction {
+            Some(_) if flag(offset, EXECUTED) => Some("executed"),
+            Some(_) if reached.contains(&addr) && !flag(offset, READ) => Some("code"),
+            _ => None,
+        };
+        let (len, note) = match note {
+            Some(note) => (2, note),
+            None if flag(offset, READ) => (1, "read"),
+            None => (1, ""),
+        };
+        let line = format_line(addr, len == 2, Some(&rom[offset..offset + len]));
+        let _ = match note {
+            "" => writeln!(text, "{}", line),
+            note => writeln!(text, "{:<30}; {}", line, note),
+        };
+        offset += len;
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // LD I, 0x20A; DRW V0, V0, 2; JP 0x204; JP V0; CLS only reached through JP V0; a sprite
+    const ROM: [u8; 12] = [
+        0xA2, 0x0A, 0xD0, 0x02, 0x12, 0x04, 0xB0, 0x00, 0x00, 0xE0, 0x3C, 0x7E,
+    ];
+
+    #[test]
+    fn test_linear() {
+        let text = linear(&ROM[..3], MEMORY_START);
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(lines[0], "200  A20A  LD I, 0x20A");
+        assert!(lines[1].starts_with("202  D0    DB 0xD0"));
+        assert_eq!(linear(&ROM[..2], 0x300), "300  A20A  LD I, 0x20A\n");
+    }
+
+    #[test]
+    fn test_analyzed() {
+        // Without coverage only what the jumps reach is code
+        let text = analyzed("rom.ch8", &ROM, MEMORY_START, None);
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(lines.len(), 2 + 3 + 6);
+        assert!(lines[1].starts_with("; The ROM hasn't run yet"));
+        assert!(lines[2].starts_with("200  A20A  LD I, 0x20A") && lines[2].ends_with("; code"));
+        assert!(lines[4].ends_with("; code"));
+        assert_eq!(lines[5], format_line(0x206, false, Some(&[0xB0])));
+
+        // Coverage finds what JP V0 reached, and the sprite
+        let (x, r) = (EXECUTED, READ);
+        let flags = [x, x, x, x, x, x, 0, 0, x, x, r, r];
+        let text = analyzed("rom.ch8", &ROM, MEMORY_START, Some(&flags));
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(
+            lines[1],
+            "; 8 bytes executed and 2 read as data by earlier runs"
+        );
+        assert!(lines[2].ends_with("; executed"));
+        assert!(lines[5].starts_with("206  B0") && !lines[5].contains(';'));
+        assert!(lines[7].starts_with("208  00E0  CLS") && lines[7].ends_with("; executed"));
+        assert!(
+            lines[8].starts_with("20A  3C    DB 0x3C  ..####..") && lines[8].ends_with("; read")
+        );
+        assert_eq!(lines.len(), 10);
+    }
+}
@@ -1,117 +1,2240 @@
+use clipboard::Clipboard;
+use coverage::Coverage;
+use diagnostic::Diagnostic;
+use dialog::Dialog;
+use disassembly::DisassemblyView;
+use event_log::EventLog;
+use gallery::Gallery;
+use i18n::{tr, trf, Lang};
+use input::{InputQueue, KeyInput, KeyRepeat, Keymap, RepeatDetector, KEY_REPEATS};
+use memory_map::MemoryMap;
+use palette::Palette;
 use ratatui::{
-    crossterm::event::{self, Event, KeyCode, KeyEventKind},
-    widgets::{Block, Paragraph},
+    crossterm::{
+        event::{
+            self, DisableMouseCapture, EnableMouseCapture, Event, KeyCode, KeyEventKind,
+            KeyModifiers, KeyboardEnhancementFlags, MouseButton, MouseEventKind,
+            PopKeyboardEnhancementFlags, PushKeyboardEnhancementFlags,
+        },
+        execute,
+        terminal::supports_keyboard_enhancement,
+    },
+    layout::{Constraint, Layout},
+    style::Color,
+    widgets::Block,
     DefaultTerminal,
 };
+use rom_settings::RomSettings;
+use rustc8::{
+    asm::{self, Program},
+    audio::{self, AudioSink, Buzzer, NullSink, TerminalBell},
+    chip8::{
+        is_hires_rom, Chip8, Chip8Error, MemoryBounds, MemoryLayout, Quirks, SysHandler,
+        DEFAULT_TIMER_HZ, FONT_SIZE, MAX_STACK_DEPTH, MEMORY_START, STACK_DEPTH,
+    },
+    database::ProgramInfo,
+    disasm, gzip,
+    netplay::{NetplayError, NetplaySession, UdpTransport},
+    octocart, patch,
+    platform::Platform,
+    rewind::RewindBuffer,
+    script::InputScript,
+    step::StepObserver,
+    storage::Storage,
+    timing::{FrameBudget, Timing, FRAME_RATE},
+    trace::{self, Tracepoint, Tracer},
+    widget::{Chip8DisplayWidget, Renderer, Rotation},
+};
+use spectate::SpectatorServer;
+use speedrun::SpeedrunTimer;
 use std::{
+    collections::{HashMap, HashSet},
     env::args,
-    io, path,
-    time::{Duration, Instant},
+    fs::{self, File},
+    io::{self, BufWriter, Write},
+    net::SocketAddr,
+    path::Path,
+    time::{Duration, Instant, SystemTime},
 };
+use teach::TeachingPane;
+use telemetry::Telemetry;
+use terminal::Capabilities;
+use timeline::DrawTimeline;
+use video::VideoRecorder;
+
+mod batch;
+mod browser;
+mod clipboard;
+mod compare;
+mod config;
+mod coverage;
+mod db;
+mod diagnostic;
+mod dialog;
+mod disassembly;
+mod dump;
+mod event_log;
+mod gallery;
+mod i18n;
+mod input;
+mod listing;
+mod memory_map;
+mod palette;
+mod quirks_check;
+mod repl;
+mod rom_settings;
+mod rpl;
+mod serve;
+mod setup;
+mod spectate;
+mod speedrun;
+mod states;
+mod task;
+mod teach;
+mod telemetry;
+mod terminal;
+mod timeline;
+mod video;
+mod websocket;
+
+const TITLE: &str = "============= CHIP-8 Emulator (Press ESC to Exit) ===============";
+const USAGE: &str = "Usage: cargo run <ROM file or directory> [--patch <IPS/BPS file>] [--platform chip8|schip|xochip|megachip] [--renderer auto|text|braille|halfblock|ascii] [--rotate 0|90|270] [--pixel-width N] [--palette <name>] [--keymap qwerty|azerty|qwertz] [--key-repeat keep|filter] [--ipf N] [--timing fixed|vip] [--timer-hz <hz>] [--seed N] [--autosave <seconds>] [--fps N] [--remote] [--audio speaker|bell|none] [--min-beep <frames>] [--sys ignore|warn|error] [--memory-bounds error|wrap|saturate] [--strict] [--lang en|es] [--quirks chip8|schip|xochip] [--wrap xy|x|y|none] [--shift vx|vy] [--load-store increment|keep] [--vf-reset on|off] [--font-addr <address>] [--load-addr <address>] [--stack-depth N] [--telemetry] [--teach] [--netplay <bind address> <peer address>] [--spectate <address>] [--trace <address> <message>]... [--trace-log <file>] [--speedrun] [--speedrun-start <address>] [--record-text <file>] [--record-video <file>] [--input-script <file>] [--state-hash] [--hash-log <file>] [--event-log <file or tcp:address>] [--break <address or label>]... [--json-errors]
+
+       cargo run dev <source file> [options]";
+
+// Instructions per frame, 11 IPF at 60 FPS is roughly the speed of the original interpreter
+const DEFAULT_IPF: u32 = 11;
+// Terminal columns per pixel, character cells are about twice as tall as wide so 2 gives square pixels
+const DEFAULT_PIXEL_WIDTH: usize = 2;
+// Shortest beep in frames, 3 frames (50ms) is long enough to be heard
+const DEFAULT_MIN_BEEP_FRAMES: u32 = 3;
+// Confirmations such as "Quick saved" are shown for this long
+const NOTICE_DURATION: Duration = Duration::from_millis(1500);
+// Tracepoint messages are written to a file, as the terminal is taken by the display
+const DEFAULT_TRACE_LOG: &str = "trace.log";
+// How often `rustc8 dev` checks whether the source file has changed
+const SOURCE_CHECK_INTERVAL: Duration = Duration::from_millis(250);
+// How often the counters of the status bar (netplay frames, state hashes) are refreshed
+const STATUS_INTERVAL: Duration = Duration::from_secs(1);
+// A frame still running after this long is stopped with the watchdog dialog, see Prompt::Stalled
+const FRAME_DEADLINE: Duration = Duration::from_secs(2);
+// Instructions run between two looks at the clock of the watchdog
+const WATCHDOG_INTERVAL: u64 = 1024;
+// Bytes of rewind history, minutes of most games, and the frames each press of Backspace goes back
+const REWIND_BUDGET: usize = 4 << 20;
+const REWIND_STEP: usize = 4;
+// With --remote, frames drawn per second unless set with --fps, and how often the panes follow
+const REMOTE_FPS: u32 = 20;
+const REMOTE_PANE_INTERVAL: Duration = Duration::from_millis(250);
+// Speeds offered by the pause menu, in instructions per frame
+const SPEEDS: [u32; 7] = [5, 8, 11, 15, 20, 30, 50];
+// Quirks offered by the pause menu
+const QUIRKS_CHOICES: [&str; 4] = ["default", "chip8", "schip", "xochip"];
+// Options setting the quirks, which take precedence over the quirks saved for the ROM
+const QUIRK_OPTIONS: [&str; 5] = [
+    "--quirks",
+    "--wrap",
+    "--shift",
+    "--load-store",
+    "--vf-reset",
+];
+const RENDERERS: [Renderer; 4] = [
+    Renderer::Text,
+    Renderer::Braille,
+    Renderer::HalfBlock,
+    Renderer::Ascii,
+];
+
+// Optional panes drawn around the display, and overlays drawn over it
+struct Panes<'a> {
+    telemetry: Option<&'a Telemetry>,
+    disassembly: Option<&'a mut DisassemblyView>,
+    timeline: Option<&'a DrawTimeline>,
+    teaching: Option<&'a TeachingPane>,
+    memory_map: Option<&'a mut MemoryMap>,
+    speedrun: Option<&'a SpeedrunTimer>,
+    dialog: Option<&'a Dialog>,
+}
+
+// What the open dialog asks for, to act on the choice picked
+enum Prompt {
+    // The error the game stopped on, reported when quitting from the dialog
+    Error(Chip8Error),
+    SaveState,
+    // Keys of the states in the storage
+    OverwriteState(String),
+    LoadState(Vec<String>),
+    OverwriteScreenshot,
+    Breakpoint,
+    Pause,
+    // A frame ran past FRAME_DEADLINE, e.g. at a speed the computer can't keep up with
+    Stalled,
+}
+
+struct Options {
+    path: String,
+    patch: Option<String>,
+    // Given with --platform or by the file extension, see platform()
+    platform: Option<Platform>,
+    // Entry of the ROM in the ROM database, if it has been downloaded
+    program: Option<ProgramInfo>,
+    renderer: Renderer,
+    rotation: Rotation,
+    pixel_width: usize,
+    // The built-in palettes and those of config.json, and the one the display is drawn in
+    palettes: Vec<Palette>,
+    palette: Palette,
+    keymap: Keymap,
+    // Whether the auto-repeat of held keys presses them again, see KeyRepeat
+    key_repeat: KeyRepeat,
+    ipf: u32,
+    // How many instructions a frame runs, ipf of them unless --timing vip
+    timing: Timing,
+    timer_hz: u32,
+    // Seconds between autosaves, see states::autosave
+    autosave: Option<Duration>,
+    // Seed of RND, random unless given with --seed, see Chip8::seed_rng
+    seed: Option<u64>,
+    // Frames drawn per second, the others are skipped, see run
+    fps: u32,
+    // Drawing tuned for slow connections, see --remote in the README
+    remote: bool,
+    audio: Audio,
+    min_beep_frames: u32,
+    sys_handler: SysHandler,
+    memory_bounds: MemoryBounds,
+    // Stop on sprites read from memory past the ROM, see Chip8::strict_sprites
+    strict: bool,
+    quirks: Quirks,
+    // Language of the interface, see i18n
+    lang: Lang,
+    font_addr: u16,
+    // Where the ROM is loaded and starts, MEMORY_START unless given with --load-addr
+    load_addr: usize,
+    // Levels of the stack, STACK_DEPTH unless given with --stack-depth
+    stack_depth: usize,
+    telemetry: bool,
+    // Show the teaching pane from the start, see teach
+    teach: bool,
+    netplay: Option<(SocketAddr, SocketAddr)>,
+    // Address viewers connect to, see spectate
+    spectate: Option<SocketAddr>,
+    tracepoints: Vec<Tracepoint>,
+    trace_log: String,
+    speedrun: Option<speedrun::Start>,
+    record_text: Option<String>,
+    // Video file encoded with ffmpeg, see video
+    record_video: Option<String>,
+    input_script: Option<InputScript>,
+    state_hash: bool,
+    hash_log: Option<String>,
+    // File or tcp:<address> the frame events are written to, see event_log
+    event_log: Option<String>,
+    // Addresses or labels of the source, resolved when the ROM is loaded
+    breakpoints: Vec<String>,
+    // Set by `rustc8 dev`, the path is an assembly source to assemble and reload when it changes
+    dev: bool,
+    // Options given on the command line, e.g. "--ipf", which the settings saved for the ROM don't override
+    given: HashSet<String>,
+}
+
+impl Options {
+    // Memory of the platform, with the program and font where the options put them
+    fn layout(&self) -> MemoryLayout {
+        MemoryLayout {
+            program_start: self.load_addr,
+            font_addr: self.font_addr,
+            stack_depth: self.stack_depth,
+            ..self.platform().memory_layout()
+        }
+    }
+
+    // --platform and the file extension take precedence over the ROM database
+    fn platform(&self) -> Platform {
+        self.platform
+            .or_else(|| self.program.as_ref()?.platform)
+            .unwrap_or_default()
+    }
+
+    fn display_widget<'a>(&self, display_data: &'a [u8]) -> Chip8DisplayWidget<'a> {
+        Chip8DisplayWidget::new(display_data)
+            .renderer(self.renderer)
+            .rotation(self.rotation)
+            .pixel_width(self.pixel_width)
+            .colors(self.palette.foreground(), self.palette.background())
+    }
+}
 
-mod chip8;
+#[derive(Clone, Copy)]
+enum Audio {
+    // The sound card, the default with the audio feature
+    #[cfg(feature = "audio")]
+    Speaker,
+    Bell,
+    None,
+}
+
+impl Default for Audio {
+    fn default() -> Self {
+        #[cfg(feature = "audio")]
+        return Audio::Speaker;
+        #[cfg(not(feature = "audio"))]
+        return Audio::Bell;
+    }
+}
+
+impl Audio {
+    fn sink(&self) -> Box<dyn AudioSink> {
+        match self {
+            // The bell when there is no sound card to play on
+            #[cfg(feature = "audio")]
+            Audio::Speaker => match audio::CpalSink::new() {
+                Ok(sink) => Box::new(sink),
+                Err(_) => Box::new(TerminalBell),
+            },
+            Audio::Bell => Box::new(TerminalBell),
+            Audio::None => Box::new(NullSink),
+        }
+    }
+}
 
 fn main() -> io::Result<()> {
-    let args: Vec<String> = args().collect();
-    if args.len() < 2 {
-        println!("Usage: cargo run <ROM file>");
+    let mut args: Vec<String> = args().collect();
+    // Accepted by every command, see diagnostic
+    let json_errors = args.iter().any(|arg| arg == "--json-errors");
+    args.retain(|arg| arg != "--json-errors");
+    if args.get(1).map(String::as_str) == Some("db") {
+        if let Err(diagnostic) = db::run(&args[2..]) {
+            diagnostic.exit(json_errors);
+        }
+        return Ok(());
+    }
+    if args.get(1).map(String::as_str) == Some("batch") {
+        if let Err(diagnostic) = batch::run(&args[2..], json_errors) {
+            diagnostic.exit(json_errors);
+        }
+        return Ok(());
+    }
+    if args.get(1).map(String::as_str) == Some("compare") {
+        if let Err(diagnostic) = compare::run(&args[2..]) {
+            diagnostic.exit(json_errors);
+        }
+        return Ok(());
+    }
+    if args.get(1).map(String::as_str) == Some("disasm") {
+        if let Err(diagnostic) = listing::run(&args[2..]) {
+            diagnostic.exit(json_errors);
+        }
+        return Ok(());
+    }
+    if args.get(1).map(String::as_str) == Some("quirks-check") {
+        if let Err(diagnostic) = quirks_check::run(&args[2..]) {
+            diagnostic.exit(json_errors);
+        }
+        return Ok(());
+    }
+    if args.get(1).map(String::as_str) == Some("serve") {
+        if let Err(diagnostic) = serve::run(&args[2..]) {
+            diagnostic.exit(json_errors);
+        }
         return Ok(());
     }
-    let path = &args[1];
+    if args.get(1).map(String::as_str) == Some("repl") {
+        i18n::set_lang(Lang::from_env().unwrap_or_default());
+        let mut terminal = ratatui::init();
+        let result = repl::run(&mut terminal);
+        ratatui::restore();
+        return result;
+    }
+    if args.get(1).map(String::as_str) == Some("terminal") {
+        println!("{}", Capabilities::detect());
+        return Ok(());
+    }
+    // `rustc8 setup` runs the setup wizard again, it runs by itself when there is no config
+    let setup = args.get(1).map(String::as_str) == Some("setup");
+    if setup || setup::is_first_run(&config::storage()) {
+        i18n::set_lang(Lang::from_env().unwrap_or_default());
+        let mut terminal = ratatui::init();
+        let result = setup::run(&mut terminal, &mut config::storage());
+        ratatui::restore();
+        result?;
+        if setup {
+            return Ok(());
+        }
+    }
+    // `rustc8 dev <source>` takes the same options as running a ROM
+    let dev = args.get(1).map(String::as_str) == Some("dev");
+    let mut options = match parse_args(&args[if dev { 2 } else { 1 }..]) {
+        Ok(options) => options,
+        Err(message) if json_errors => Diagnostic::from(message).exit(true),
+        Err(message) => Diagnostic::from(format!("{}\n{}", message, USAGE)).exit(false),
+    };
+    options.dev = dev;
+    i18n::set_lang(options.lang);
+    // Labels are assembled for programs loaded at MEMORY_START
+    if dev && options.load_addr != MEMORY_START {
+        Diagnostic::from("--load-addr can't be used with dev").exit(json_errors);
+    }
+
+    // Given a directory, pick the ROM to play from the browser
+    if Path::new(&options.path).is_dir() {
+        let mut terminal = ratatui::init();
+        let selected = browser::run(&mut terminal, &options.path);
+        ratatui::restore();
+        let selected = selected.unwrap_or_else(|e| Diagnostic::io(e.to_string()).exit(json_errors));
+        match selected {
+            Some(path) => {
+                options.platform = options.platform.or_else(|| Platform::from_extension(&path));
+                options.path = path.to_string_lossy().into_owned();
+            }
+            None => return Ok(()),
+        }
+    }
+    // Opened after the browser, which can update the database
+    let (rom, breakpoints) =
+        open_rom(&mut options).unwrap_or_else(|diagnostic| diagnostic.exit(json_errors));
+
+    // Started before the terminal is taken over, so a missing ffmpeg is reported as any error
+    let mut video = options.record_video.as_ref().map(|path| {
+        VideoRecorder::start(path, FRAME_RATE).unwrap_or_else(|e| {
+            Diagnostic::io(format!("Failed to record {}: {}", path, e)).exit(json_errors)
+        })
+    });
+
+    // Opened before as well, as the sound libraries may print their errors to the terminal
+    let mut sink = options.audio.sink();
 
     let mut terminal = ratatui::init();
     terminal.clear()?;
-    let app_result = run(terminal, path);
+    // Terminals with the kitty keyboard protocol report key releases, see InputQueue. Others
+    // don't answer the query, which is skipped where it would only wait for nothing
+    let key_releases = !Capabilities::detect().dumb
+        && supports_keyboard_enhancement().unwrap_or(false)
+        && execute!(
+            io::stdout(),
+            PushKeyboardEnhancementFlags(KeyboardEnhancementFlags::REPORT_EVENT_TYPES)
+        )
+        .is_ok();
+    let app_result = run(
+        terminal,
+        &mut options,
+        rom,
+        breakpoints,
+        key_releases,
+        sink.as_mut(),
+        &mut video,
+    );
+    if key_releases {
+        execute!(io::stdout(), PopKeyboardEnhancementFlags)?;
+    }
     ratatui::restore();
-    app_result
+    // The memory map captures the mouse, which restoring the terminal doesn't release
+    execute!(io::stdout(), DisableMouseCapture)?;
+    if let Some(Err(e)) = video.map(VideoRecorder::finish) {
+        let path = options.record_video.unwrap_or_default();
+        Diagnostic::io(format!("Failed to record {}: {}", path, e)).exit(json_errors);
+    }
+    // Quitting from the dialog of an error of the core reports it
+    match app_result {
+        Ok(None) => Ok(()),
+        Ok(Some(error)) => Diagnostic::from(error).exit(json_errors),
+        Err(e) => Diagnostic::io(e.to_string()).exit(json_errors),
+    }
+}
+
+fn parse_args(args: &[String]) -> Result<Options, String> {
+    let mut path = None;
+    let mut patch = None;
+    let mut platform = None;
+    // None picks the renderer from the terminal capabilities
+    let mut renderer = None;
+    let mut rotation = Rotation::None;
+    let mut palette = None;
+    let mut keymap = None;
+    let mut key_repeat = KeyRepeat::Keep;
+    let mut pixel_width = DEFAULT_PIXEL_WIDTH;
+    let mut ipf = DEFAULT_IPF;
+    let mut timing = Timing::Fixed;
+    let mut timer_hz = DEFAULT_TIMER_HZ;
+    let mut seed = None;
+    let mut autosave = None;
+    let mut fps = FRAME_RATE;
+    let mut remote = false;
+    let mut audio = Audio::default();
+    let mut min_beep_frames = DEFAULT_MIN_BEEP_FRAMES;
+    let mut sys_handler = SysHandler::Ignore;
+    let mut memory_bounds = MemoryBounds::default();
+    let mut strict = false;
+    let mut lang = Lang::from_env().unwrap_or_default();
+    let mut quirks = Quirks::default();
+    let mut wrap = None;
+    let mut shift_vy = None;
+    let mut increment_i = None;
+    let mut vf_reset = None;
+    let mut font_addr = 0;
+    let mut load_addr = MEMORY_START;
+    let mut stack_depth = STACK_DEPTH;
+    let mut telemetry = false;
+    let mut teach = false;
+    let mut netplay = None;
+    let mut spectate = None;
+    let mut tracepoints = Vec::new();
+    let mut trace_log = DEFAULT_TRACE_LOG.to_string();
+    let mut speedrun = None;
+    let mut record_text = None;
+    let mut record_video = None;
+    let mut input_script = None;
+    let mut state_hash = false;
+    let mut hash_log = None;
+    let mut event_log = None;
+    let mut breakpoints = Vec::new();
+
+    let mut args = args.iter();
+    let mut given = HashSet::new();
+    while let Some(arg) = args.next() {
+        if arg.starts_with("--") {
+            given.insert(arg.clone());
+        }
+        match arg.as_str() {
+            "--patch" => {
+                let value = args.next().ok_or("Missing value for --patch")?;
+                patch = Some(value.clone());
+            }
+            "--platform" => {
+                let name = args.next().ok_or("Missing value for --platform")?;
+                platform = Some(
+                    Platform::from_name(name)
+                        .ok_or_else(|| format!("Unknown platform: {}", name))?,
+                );
+            }
+            "--renderer" => {
+                let name = args.next().ok_or("Missing value for --renderer")?;
+                renderer = match name.as_str() {
+                    "auto" => None,
+                    _ => Some(
+                        Renderer::from_name(name)
+                            .ok_or_else(|| format!("Unknown renderer: {}", name))?,
+                    ),
+                };
+            }
+            "--palette" => {
+                palette = Some(args.next().ok_or("Missing value for --palette")?.clone());
+            }
+            "--keymap" => {
+                let name = args.next().ok_or("Missing value for --keymap")?;
+                keymap = Some(
+                    Keymap::from_name(name).ok_or_else(|| format!("Unknown keymap: {}", name))?,
+                );
+            }
+            "--key-repeat" => {
+                let value = args.next().ok_or("Missing value for --key-repeat")?;
+                key_repeat = KeyRepeat::from_name(value)
+                    .ok_or_else(|| format!("Invalid key repeat, keep or filter: {}", value))?;
+            }
+            "--rotate" => {
+                let value = args.next().ok_or("Missing value for --rotate")?;
+                rotation = value
+                    .parse()
+                    .ok()
+                    .and_then(Rotation::from_degrees)
+                    .ok_or_else(|| format!("Invalid rotation: {}", value))?;
+            }
+            "--pixel-width" => {
+                let value = args.next().ok_or("Missing value for --pixel-width")?;
+                pixel_width = value
+                    .parse()
+                    .ok()
+                    .filter(|width| *width > 0)
+                    .ok_or_else(|| format!("Invalid pixel width: {}", value))?;
+            }
+            "--ipf" => {
+                let value = args.next().ok_or("Missing value for --ipf")?;
+                // A frame of no instructions would never run the program
+                ipf = value
+                    .parse()
+                    .ok()
+                    .filter(|ipf| *ipf > 0)
+                    .ok_or_else(|| format!("Invalid instructions per frame: {}", value))?;
+            }
+            "--timing" => {
+                let value = args.next().ok_or("Missing value for --timing")?;
+                timing = Timing::from_name(value)
+                    .ok_or_else(|| format!("Invalid timing, fixed or vip: {}", value))?;
+            }
+            "--timer-hz" => {
+                let value = args.next().ok_or("Missing value for --timer-hz")?;
+                timer_hz = value
+                    .parse()
+                    .ok()
+                    .filter(|hz| *hz > 0)
+                    .ok_or_else(|| format!("Invalid timer frequency: {}", value))?;
+            }
+            "--autosave" => {
+                let value = args.next().ok_or("Missing value for --autosave")?;
+                let seconds = value
+                    .parse()
+                    .ok()
+                    .filter(|seconds| *seconds > 0)
+                    .ok_or_else(|| format!("Invalid number of seconds: {}", value))?;
+                autosave = Some(Duration::from_secs(seconds));
+            }
+            "--seed" => {
+                let value = args.next().ok_or("Missing value for --seed")?;
+                seed = Some(
+                    value
+                        .parse()
+                        .map_err(|_| format!("Invalid seed: {}", value))?,
+                );
+            }
+            "--fps" => {
+                let value = args.next().ok_or("Missing value for --fps")?;
+                fps = value
+                    .parse()
+                    .ok()
+                    .filter(|fps| (1..=FRAME_RATE).contains(fps))
+                    .ok_or_else(|| {
+                        format!("Invalid refresh rate, 1 to {}: {}", FRAME_RATE, value)
+                    })?;
+            }
+            "--audio" => {
+                let name = args.next().ok_or("Missing value for --audio")?;
+                audio = match name.as_str() {
+                    #[cfg(feature = "audio")]
+                    "speaker" => Audio::Speaker,
+                    "bell" => Audio::Bell,
+                    "none" => Audio::None,
+                    _ => return Err(format!("Unknown audio output: {}", name)),
+                };
+            }
+            "--min-beep" => {
+                let value = args.next().ok_or("Missing value for --min-beep")?;
+                min_beep_frames = value
+                    .parse()
+                    .map_err(|_| format!("Invalid minimum beep duration: {}", value))?;
+            }
+            "--sys" => {
+                let name = args.next().ok_or("Missing value for --sys")?;
+                sys_handler = match name.as_str() {
+                    "ignore" => SysHandler::Ignore,
+                    "warn" => SysHandler::Warn,
+                    "error" => SysHandler::Error,
+                    _ => return Err(format!("Unknown SYS handler: {}", name)),
+                };
+            }
+            "--lang" => {
+                let name = args.next().ok_or("Missing value for --lang")?;
+                lang =
+                    Lang::from_name(name).ok_or_else(|| format!("Unknown language: {}", name))?;
+            }
+            "--memory-bounds" => {
+                let name = args.next().ok_or("Missing value for --memory-bounds")?;
+                memory_bounds = MemoryBounds::from_name(name)
+                    .ok_or_else(|| format!("Unknown memory bounds policy: {}", name))?;
+            }
+            "--strict" => strict = true,
+            "--quirks" => {
+                let name = args.next().ok_or("Missing value for --quirks")?;
+                quirks = Quirks::from_preset(name)
+                    .ok_or_else(|| format!("Unknown quirks preset: {}", name))?;
+            }
+            "--wrap" => {
+                let value = args.next().ok_or("Missing value for --wrap")?;
+                wrap = match value.as_str() {
+                    "xy" => Some((true, true)),
+                    "x" => Some((true, false)),
+                    "y" => Some((false, true)),
+                    "none" => Some((false, false)),
+                    _ => return Err(format!("Invalid sprite wrapping: {}", value)),
+                };
+            }
+            "--shift" => {
+                let value = args.next().ok_or("Missing value for --shift")?;
+                shift_vy = match value.as_str() {
+                    "vx" => Some(false),
+                    "vy" => Some(true),
+                    _ => return Err(format!("Invalid shift register: {}", value)),
+                };
+            }
+            "--load-store" => {
+                let value = args.next().ok_or("Missing value for --load-store")?;
+                increment_i = match value.as_str() {
+                    "increment" => Some(true),
+                    "keep" => Some(false),
+                    _ => return Err(format!("Invalid load/store behaviour: {}", value)),
+                };
+            }
+            "--vf-reset" => {
+                let value = args.next().ok_or("Missing value for --vf-reset")?;
+                vf_reset = match value.as_str() {
+                    "on" => Some(true),
+                    "off" => Some(false),
+                    _ => return Err(format!("Invalid VF reset: {}", value)),
+                };
+            }
+            "--font-addr" => {
+                let value = args.next().ok_or("Missing value for --font-addr")?;
+                font_addr = trace::parse_address(value)
+                    .ok()
+                    .filter(|addr| *addr as usize + FONT_SIZE <= MEMORY_START)
+                    .ok_or_else(|| format!("Invalid font address: {}", value))?;
+            }
+            "--load-addr" => {
+                let value = args.next().ok_or("Missing value for --load-addr")?;
+                // Checked against the memory of the platform by check_rom, once it's known
+                load_addr = trace::parse_memory_address(value)
+                    .map(|addr| addr as usize)
+                    .filter(|addr| *addr >= MEMORY_START)
+                    .ok_or_else(|| format!("Invalid load address: {}", value))?;
+            }
+            "--stack-depth" => {
+                let value = args.next().ok_or("Missing value for --stack-depth")?;
+                stack_depth = value
+                    .parse()
+                    .ok()
+                    .filter(|depth| (1..=MAX_STACK_DEPTH).contains(depth))
+                    .ok_or_else(|| {
+                        format!("Invalid stack depth, 1 to {}: {}", MAX_STACK_DEPTH, value)
+                    })?;
+            }
+            "--remote" => remote = true,
+            "--telemetry" => telemetry = true,
+            "--teach" => teach = true,
+            "--netplay" => {
+                let mut address = || -> Result<SocketAddr, String> {
+                    let value = args.next().ok_or("Missing address for --netplay")?;
+                    value
+                        .parse()
+                        .map_err(|_| format!("Invalid address: {}", value))
+                };
+                netplay = Some((address()?, address()?));
+            }
+            "--spectate" => {
+                let value = args.next().ok_or("Missing address for --spectate")?;
+                spectate = Some(
+                    value
+                        .parse()
+                        .map_err(|_| format!("Invalid address: {}", value))?,
+                );
+            }
+            "--trace" => {
+                let addr = args.next().ok_or("Missing address for --trace")?;
+                let message = args.next().ok_or("Missing message for --trace")?;
+                let addr = trace::parse_address(addr).map_err(|e| e.to_string())?;
+                tracepoints.push(Tracepoint::new(addr, message).map_err(|e| e.to_string())?);
+            }
+            "--trace-log" => {
+                let value = args.next().ok_or("Missing value for --trace-log")?;
+                trace_log = value.clone();
+            }
+            "--speedrun" => speedrun = Some(speedrun::Start::FirstInput),
+            "--speedrun-start" => {
+                let value = args.next().ok_or("Missing address for --speedrun-start")?;
+                let addr = trace::parse_address(value).map_err(|e| e.to_string())?;
+                speedrun = Some(speedrun::Start::Address(addr));
+            }
+            "--record-text" => {
+                let value = args.next().ok_or("Missing value for --record-text")?;
+                record_text = Some(value.clone());
+            }
+            "--record-video" => {
+                let value = args.next().ok_or("Missing value for --record-video")?;
+                record_video = Some(value.clone());
+            }
+            "--input-script" => {
+                let value = args.next().ok_or("Missing value for --input-script")?;
+                let text = std::fs::read_to_string(value)
+                    .map_err(|e| format!("Failed to read {}: {}", value, e))?;
+                input_script = Some(InputScript::parse(&text)?);
+            }
+            "--break" => {
+                let value = args.next().ok_or("Missing address for --break")?;
+                breakpoints.push(value.clone());
+            }
+            "--state-hash" => state_hash = true,
+            "--hash-log" => {
+                let value = args.next().ok_or("Missing value for --hash-log")?;
+                hash_log = Some(value.clone());
+            }
+            "--event-log" => {
+                let value = args.next().ok_or("Missing value for --event-log")?;
+                event_log = Some(value.clone());
+            }
+            _ => path = Some(arg.clone()),
+        }
+    }
+
+    // config.json has the defaults of the options not given, see config
+    let config = config::load(&config::storage())?;
+    let path = path.or(config.rom_dir).ok_or("Missing ROM file")?;
+    let keymap = match (keymap, config.keymap) {
+        (Some(keymap), _) => keymap,
+        (None, Some(name)) => Keymap::from_name(&name)
+            .ok_or_else(|| format!("{}: unknown keymap: {}", config::CONFIG_KEY, name))?,
+        (None, None) => Keymap::default(),
+    };
+    let config_renderer = match config.renderer {
+        Some(name) => Some(
+            Renderer::from_name(&name)
+                .ok_or_else(|| format!("{}: unknown renderer: {}", config::CONFIG_KEY, name))?,
+        ),
+        None => None,
+    };
+    // Netplay frames are driven by both peers, a script would only play on one side
+    if autosave.is_some() && netplay.is_some() {
+        return Err("--autosave can't be used with --netplay".to_string());
+    }
+    if timing != Timing::Fixed && netplay.is_some() {
+        return Err("--timing vip can't be used with --netplay".to_string());
+    }
+    if input_script.is_some() && netplay.is_some() {
+        return Err("--input-script can't be used with --netplay".to_string());
+    }
+    // --wrap overrides the sprite wrapping of the --quirks preset
+    if let Some((wrap_x, wrap_y)) = wrap {
+        (quirks.wrap_x, quirks.wrap_y) = (wrap_x, wrap_y);
+    }
+    // and --shift the register 8xy6 and 8xyE shift
+    if let Some(shift_vy) = shift_vy {
+        quirks.shift_vy = shift_vy;
+    }
+    // and --load-store whether Fx55 and Fx65 move I
+    if let Some(increment_i) = increment_i {
+        quirks.increment_i = increment_i;
+    }
+    // and --vf-reset whether 8xy1, 8xy2 and 8xy3 reset VF
+    if let Some(vf_reset) = vf_reset {
+        quirks.vf_reset = vf_reset;
+    }
+    // --platform overrides the platform given by the file extension
+    let platform = platform.or_else(|| Platform::from_extension(Path::new(&path)));
+    // --remote changes the defaults of --renderer and --fps
+    if remote && !given.contains("--fps") {
+        fps = REMOTE_FPS;
+    }
+    let capabilities = Capabilities::detect();
+    // The renderer of the config is picked for the terminal, not for a remote connection
+    let renderer = renderer
+        .or(config_renderer.filter(|_| !remote))
+        .unwrap_or(match remote {
+            true => capabilities.remote_renderer(),
+            false => capabilities.renderer(),
+        });
+    let palettes = palette::load(&config::storage())?;
+    let palette = match palette {
+        Some(name) => palette::find(&palettes, &name)
+            .ok_or_else(|| format!("Unknown palette: {}", name))?
+            .clone(),
+        None => Palette::default(),
+    };
+    Ok(Options {
+        path,
+        patch,
+        platform,
+        program: None,
+        renderer,
+        rotation,
+        pixel_width,
+        palettes,
+        palette,
+        keymap,
+        key_repeat,
+        ipf,
+        timing,
+        autosave,
+        timer_hz,
+        // Both players of netplay need the same numbers
+        seed: seed.or(netplay.map(|_| 0)),
+        fps,
+        remote,
+        audio,
+        min_beep_frames,
+        sys_handler,
+        memory_bounds,
+        strict,
+        quirks,
+        lang,
+        font_addr,
+        load_addr,
+        stack_depth,
+        telemetry,
+        teach,
+        netplay,
+        spectate,
+        tracepoints,
+        trace_log,
+        speedrun,
+        record_text,
+        record_video,
+        input_script,
+        state_hash,
+        hash_log,
+        event_log,
+        breakpoints,
+        dev: false,
+        given,
+    })
+}
+
+/*
+The ROM of the options with its breakpoints, ready for new_core: the options are completed from
+the ROM database and the settings saved for the ROM, and the ROM is loaded, patched or built from
+its cartridge or source, and checked. Shared with serve, which sets up its machine the same way.
+*/
+fn open_rom(options: &mut Options) -> Result<(Vec<u8>, Vec<u16>), Diagnostic> {
+    let database = db::load(&config::storage());
+    options.program =
+        database.and_then(|database| database.find_rom(Path::new(&options.path)).cloned());
+    let clip = options
+        .program
+        .as_ref()
+        .and_then(|program| program.quirks.get("clipQuirks").copied());
+    apply_clip_quirk(options, clip);
+
+    let program = match is_cartridge(&options.path) {
+        true => load_cartridge(options)?,
+        false => load_program(options)?,
+    };
+    check_rom(options, &program.bytes)?;
+    let breakpoints = resolve_breakpoints(&options.breakpoints, &program.labels)?;
+
+    let settings = rom_settings::load(&config::storage(), patch::crc32(&program.bytes));
+    apply_rom_settings(options, &settings);
+    Ok((program.bytes, breakpoints))
+}
+
+// Whether the ROM can be loaded by new_core: it starts in the memory of the platform, it isn't empty
+// and fits
+fn check_rom(options: &Options, rom: &[u8]) -> Result<(), Diagnostic> {
+    let layout = options.layout();
+    if options.load_addr >= layout.size {
+        return Err(Diagnostic::from(format!(
+            "Invalid load address, {:#05X} to {:#05X}: {:#05X}",
+            MEMORY_START,
+            layout.size - 1,
+            options.load_addr
+        )));
+    }
+    Chip8::with_layout(layout)
+        .load_rom(rom)
+        .map_err(|e| Diagnostic::new("rom-format", format!("{}: {}", options.path, e)))
+}
+
+// A machine with the ROM loaded and configured from the options, also used to reset
+// The ROM has been through check_rom
+fn new_core(options: &Options, rom: &[u8]) -> Chip8 {
+    let mut chip8 = Chip8::with_layout(options.layout());
+    chip8.sys_handler = options.sys_handler.clone();
+    chip8.memory_bounds = options.memory_bounds;
+    chip8.strict_sprites = options.strict;
+    chip8.quirks = options.quirks;
+    chip8.timer_hz = options.timer_hz;
+    if let Some(seed) = options.seed {
+        chip8.seed_rng(seed);
+    }
+    chip8
+        .load_rom(rom)
+        .expect("The ROM was checked when it was read");
+    // HiRes programs are CHIP-8 programs loaded where the VIP put them
+    if options.platform() == Platform::Chip8
+        && options.load_addr == MEMORY_START
+        && is_hires_rom(rom)
+    {
+        chip8.enable_hires();
+    }
+    #[cfg(feature = "megachip")]
+    if options.platform() == Platform::MegaChip {
+        chip8.enable_megachip();
+    }
+    chip8
+}
+
+// The colors of the MEGA-CHIP display, the others are drawn in the palette of the options
+#[cfg(feature = "megachip")]
+fn pixel_colors(chip8: &Chip8) -> Vec<Color> {
+    match &chip8.megachip {
+        Some(megachip) if chip8.is_megachip_mode() => megachip.colors(),
+        _ => Vec::new(),
+    }
+}
+
+#[cfg(not(feature = "megachip"))]
+fn pixel_colors(_chip8: &Chip8) -> Vec<Color> {
+    Vec::new()
+}
+
+// Reset is left out during netplay, resetting one side only would desync the peers
+fn error_dialog(error: &Chip8Error, reset: bool) -> Dialog {
+    let hint = match error {
+        Chip8Error::InvalidOpcode { .. } => {
+            tr("This is usually data executed by mistake, e.g. after a jump to the wrong address.")
+        }
+        Chip8Error::UnsupportedSys { .. } => {
+            tr("SYS called machine code of the original computers, use --sys ignore to skip these calls.")
+        }
+        Chip8Error::StackOverflow { .. } | Chip8Error::StackUnderflow { .. } => {
+            tr("Subroutine calls and returns don't match, or the program jumped to the wrong address.")
+        }
+        Chip8Error::MemoryOutOfBounds { .. } => {
+            tr("I or the program counter points past the end of memory, usually after a jump to the wrong address. ROMs written for interpreters that wrap I run with --memory-bounds wrap.")
+        }
+        Chip8Error::SpriteOutOfBounds { .. } => {
+            tr("I was set near the end of memory, check the LD I or ADD I before this DRW. ROMs written for interpreters that wrap I run with --memory-bounds wrap.")
+        }
+        Chip8Error::SpritePastRom { .. } => {
+            tr("The sprite is read from memory the ROM doesn't fill, check the LD I or ADD I before this DRW. Run without --strict to draw it anyway.")
+        }
+    };
+    let dialog = Dialog::new(tr("Error"))
+        .line(&i18n::chip8_error(error))
+        .line(hint)
+        .choice('c', tr("Ignore and continue"));
+    match reset {
+        true => dialog.choice('r', tr("Reset")),
+        false => dialog,
+    }
+    .choice('q', tr("Quit"))
+}
+
+fn breakpoint_dialog(chip8: &Chip8) -> Dialog {
+    let opcode = disasm::opcode_at(&chip8.memory, chip8.pc as usize).unwrap_or_default();
+    Dialog::new(tr("Breakpoint"))
+        .line(&trf(
+            "Stopped at {}: {}",
+            &[&format!("{:03X}", chip8.pc), &disasm::disassemble(opcode)],
+        ))
+        .choice('c', tr("Continue"))
+        .choice('s', tr("Step"))
+        .choice('q', tr("Quit"))
+}
+
+// The pause menu, with the settings that can be changed and saved for the ROM outside netplay
+fn pause_dialog(options: &Options, netplay: bool) -> Dialog {
+    let mut dialog = Dialog::new(tr("Paused"))
+        .line(&trf("Speed: {} instructions per frame", &[&options.ipf]))
+        .line(&trf("Quirks: {}", &[&quirks_name(&options.quirks)]))
+        .line(&trf("Renderer: {}", &[&options.renderer.name()]))
+        .line(&trf("Palette: {}", &[&options.palette.name]))
+        .line(&trf("Key repeat: {}", &[&options.key_repeat.name()]))
+        .choice('p', tr("Resume"));
+    if !netplay {
+        dialog = dialog
+            .choice('i', tr("Speed"))
+            .choice('k', tr("Quirks"))
+            .choice('d', tr("Renderer"))
+            .choice('c', tr("Palette"))
+            .choice('r', tr("Key repeat"))
+            .choice('s', tr("Save for this ROM"));
+    }
+    dialog
+}
+
+// Shown by the watchdog when a frame has run for FRAME_DEADLINE, after steps instructions
+fn stalled_dialog(options: &Options, steps: u64) -> Dialog {
+    Dialog::new(tr("The emulation is stalled"))
+        .line(&trf(
+            "A frame has been running for over {} seconds, {} instructions so far.",
+            &[&FRAME_DEADLINE.as_secs(), &steps],
+        ))
+        .line(&trf("Speed: {} instructions per frame", &[&options.ipf]))
+        .choice('c', tr("Continue"))
+        .choice('p', tr("Pause"))
+        .choice('r', tr("Reset"))
+        .choice('l', tr("Lower speed"))
+}
+
+// "default", the name of a preset, or "custom" for other combinations (e.g. with --wrap)
+fn quirks_name(quirks: &Quirks) -> &'static str {
+    QUIRKS_CHOICES
+        .iter()
+        .find(|name| quirks_from_name(name) == Some(*quirks))
+        .copied()
+        .unwrap_or("custom")
+}
+
+fn quirks_from_name(name: &str) -> Option<Quirks> {
+    match name {
+        "default" => Some(Quirks::default()),
+        _ => Quirks::from_preset(name),
+    }
+}
+
+// The value after the current one, going back to the first after the last
+fn next_choice<T: PartialEq + Copy>(choices: &[T], current: T) -> T {
+    let index = choices.iter().position(|choice| *choice == current);
+    choices[index.map_or(0, |index| (index + 1) % choices.len())]
 }
 
-fn run(mut terminal: DefaultTerminal, path: &str) -> io::Result<()> {
-    let mut chip8 = chip8::Chip8::new();
+// Save the settings of the pause menu for the ROM, returning the status message
+fn save_rom_settings(storage: &mut dyn Storage, options: &Options, rom_crc: u32) -> String {
+    let quirks = Some(quirks_name(&options.quirks)).filter(|name| *name != "custom");
+    let settings = RomSettings {
+        ipf: Some(options.ipf),
+        quirks: quirks.map(str::to_string),
+        renderer: Some(options.renderer.name().to_string()),
+        palette: Some(options.palette.name.clone()),
+        key_repeat: Some(options.key_repeat.name().to_string()),
+    };
+    let name = Path::new(&options.path)
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    match rom_settings::save(storage, rom_crc, &name, &settings) {
+        Ok(()) => trf(" Saved the settings of {} ", &[&name]),
+        Err(e) => trf(" Failed to save the settings: {} ", &[&e]),
+    }
+}
 
-    let rom = read_rom(path);
-    chip8.load_rom(&rom);
+// Saved settings are used unless the option is given on the command line
+fn apply_rom_settings(options: &mut Options, settings: &RomSettings) {
+    if let Some(ipf) = settings.ipf.filter(|_| !options.given.contains("--ipf")) {
+        options.ipf = ipf;
+    }
+    let quirks = settings.quirks.as_deref().and_then(quirks_from_name);
+    let quirks_given = QUIRK_OPTIONS
+        .iter()
+        .any(|option| options.given.contains(*option));
+    if let Some(quirks) = quirks.filter(|_| !quirks_given) {
+        options.quirks = quirks;
+    }
+    let renderer = settings.renderer.as_deref().and_then(Renderer::from_name);
+    if let Some(renderer) = renderer.filter(|_| !options.given.contains("--renderer")) {
+        options.renderer = renderer;
+    }
+    // A palette removed from config.json since is ignored
+    let palette = settings
+        .palette
+        .as_deref()
+        .and_then(|name| palette::find(&options.palettes, name));
+    if let Some(palette) = palette.filter(|_| !options.given.contains("--palette")) {
+        options.palette = palette.clone();
+    }
+    let key_repeat = settings
+        .key_repeat
+        .as_deref()
+        .and_then(KeyRepeat::from_name);
+    if let Some(key_repeat) = key_repeat.filter(|_| !options.given.contains("--key-repeat")) {
+        options.key_repeat = key_repeat;
+    }
+}
 
-    let cycle_rate = Duration::from_micros(2000);
-    let mut last_cycle = Instant::now();
+// Export the selected screenshot to the current directory, returning the status message
+fn export_screenshot(gallery: &Gallery) -> String {
+    match gallery.export(Path::new(".")) {
+        Ok(path) => trf("Saved {}", &[&path]),
+        Err(e) => trf("Export failed: {}", &[&e]),
+    }
+}
+
+fn run(
+    mut terminal: DefaultTerminal,
+    options: &mut Options,
+    mut rom: Vec<u8>,
+    mut breakpoints: Vec<u16>,
+    key_releases: bool,
+    sink: &mut dyn AudioSink,
+    video: &mut Option<VideoRecorder>,
+) -> io::Result<Option<Chip8Error>> {
+    let mut chip8 = new_core(options, &rom);
+    // Save states are tied to the ROM they were saved with
+    let mut rom_crc = patch::crc32(&rom);
+    let mut storage = config::storage();
+    // The RPL flags are kept between sessions, except in netplay where both peers start from 0
+    let keep_flags = options.netplay.is_none();
+    if keep_flags {
+        chip8.rpl_flags = rpl::load(&storage, rom_crc);
+    }
+    let mut saved_flags = chip8.rpl_flags;
+    let mut next_autosave = Instant::now() + options.autosave.unwrap_or_default();
+
+    let frame_duration = Duration::from_secs(1) / FRAME_RATE;
+    let mut next_frame = Instant::now();
+
+    let mut buzzer = Buzzer::new().min_frames(options.min_beep_frames);
+    let samples_per_frame = (audio::SAMPLE_RATE / FRAME_RATE) as usize;
+
+    let mut input = InputQueue::new();
+    let mut repeats = RepeatDetector::new();
+    let mut telemetry = Telemetry::new();
+    let mut show_telemetry = options.telemetry;
+    let mut disassembly = DisassemblyView::new();
+    let mut show_disassembly = false;
+    let mut clipboard = Clipboard::new();
+    let mut timeline = DrawTimeline::new();
+    let mut show_timeline = false;
+    let mut teaching = TeachingPane::new();
+    let mut show_teaching = options.teach;
+    let mut memory_map = MemoryMap::new(options.load_addr..options.load_addr + rom.len(), &chip8);
+    // What the ROM executed and read, for `rustc8 disasm --analyze`, see coverage
+    let mut coverage = Coverage::new(
+        config::storage(),
+        rom_crc,
+        options.load_addr..options.load_addr + rom.len(),
+    );
+    let mut show_memory_map = false;
+    let mut gallery = Gallery::new(&options.path);
+    let mut frames: u64 = 0;
+    let platform = options.platform();
+    let mut message = (!platform.is_supported()).then(|| {
+        trf(
+            " {} is not supported yet, running as CHIP-8 ",
+            &[&platform.name()],
+        )
+    });
+    // Messages that go away by themselves rather than on the next key press
+    let mut message_expires: Option<Instant> = None;
+    let mut speedrun = options.speedrun.map(SpeedrunTimer::new);
+    let mut quick_save: Option<Chip8> = None;
+    let mut rewind = RewindBuffer::new(REWIND_BUDGET);
+    let mut text_recording = match &options.record_text {
+        Some(path) => Some(BufWriter::new(File::create(path)?)),
+        None => None,
+    };
+    let mut input_script = options.input_script.clone();
+    /*
+    Hash of the machine state after each frame, shown with --state-hash and written to the log
+    given with --hash-log, one "<frame> <hash>" line per frame. Two runs of the same ROM with the
+    same inputs, e.g. from an input script, log the same hashes until they diverge, so comparing
+    the logs gives the first frame that differs between versions or quirk settings.
+    */
+    // Frame number and hash of the last frame
+    let mut hash: Option<(u64, u64)> = None;
+    let mut hash_log = match &options.hash_log {
+        Some(path) => Some(BufWriter::new(File::create(path)?)),
+        None => None,
+    };
+    let mut event_log = match &options.event_log {
+        Some(target) => Some(EventLog::open(target)?),
+        None => None,
+    };
+    // Errors from the core and file operations open a dialog, the emulation is paused while it's open
+    let mut dialog: Option<(Prompt, Dialog)> = None;
+    let mut redraw = false;
+    // The display as it was last drawn, frames that leave it the same aren't drawn
+    let mut drawn_display = chip8.display.clone();
+    // Set when the core has a new frame, see Chip8::take_frame, until it's drawn
+    let mut frame_pending = false;
+    /*
+    The display is drawn at options.fps, on the frames that have built up enough credit: each
+    frame adds fps and a frame is drawn once it reaches FRAME_RATE, so 30 draws every other frame.
+    The core still runs every frame, skipped frames are only not drawn. Draws happen between
+    frames, never with a frame half run, apart from breakpoints and errors. Changes from the user,
+    e.g. a dialog, are drawn straight away.
+    */
+    let mut render_credit = 0;
+    let mut render_due = true;
+    // Set when a frame changed a pane, drawn with the next frame due
+    let mut frame_changed = false;
+    let mut next_pane_update = Instant::now();
+    // Counters shown in the status bar, refreshed every STATUS_INTERVAL rather than every frame
+    let mut live_status: Option<String> = None;
+    let mut next_status_update = Instant::now();
+    // Set while the ROM is waiting for a key that hasn't been pressed, no cycles are run until then
+    let mut idle = false;
+    // Set when continuing from a breakpoint so it doesn't stop again straight away, and to stop
+    // after the next instruction when stepping
+    let mut resuming = false;
+    let mut stepping = false;
+    /*
+    What the current frame has run, see FrameBudget. Frames are only cut short by a breakpoint or an error, and
+    are finished when the emulation resumes, so the timers and everything recorded per frame
+    (hashes, scripts, timelines) stay aligned as if the game had never stopped. Pausing, saving
+    states and taking screenshots happen between frames, as the dialog of a stopped frame takes
+    the keys until it's closed.
+    */
+    let mut budget = FrameBudget::new(options.timing, options.ipf);
+    // `rustc8 dev` reloads the source when its modification time changes
+    let mut source_modified = modified(&options.path);
+    let mut next_source_check = Instant::now() + SOURCE_CHECK_INTERVAL;
+
+    let mut tracer = match options.tracepoints.is_empty() {
+        true => None,
+        false => Some(Tracer::new(
+            options.tracepoints.clone(),
+            BufWriter::new(File::create(&options.trace_log)?),
+        )),
+    };
+
+    let mut netplay = match options.netplay {
+        Some((bind, peer)) => Some(NetplaySession::new(
+            UdpTransport::new(bind, peer)?,
+            options.ipf,
+        )),
+        None => None,
+    };
+    let mut spectators = options.spectate.map(SpectatorServer::bind).transpose()?;
 
     // main loop
     loop {
-        if last_cycle.elapsed() >= cycle_rate {
-            chip8.run_cycle();
-            last_cycle = Instant::now();
+        // Emulation is paused while browsing the gallery or answering a dialog
+        if !gallery.visible && dialog.is_none() && Instant::now() >= next_frame {
+            // Scripts press keys on given frames, so the frames are always run with one
+            idle = netplay.is_none()
+                && input_script.is_none()
+                && input.is_empty()
+                && (chip8.is_waiting_for_key() || chip8.is_halted());
+            // Set when the frame was stopped by a breakpoint or an error, it's finished on resume
+            let mut frame_finished = true;
+            match netplay.as_mut() {
+                // Netplay runs whole frames with the keys pressed since the last frame
+                // The peers stop on the same error, the program runs the same on both
+                Some(session) => {
+                    let result = match session.poll(&mut chip8) {
+                        Ok(true) => session.advance_frame(&mut chip8, input.take_mask()),
+                        result => result.map(|_| ()),
+                    };
+                    match result {
+                        Ok(()) => {}
+                        Err(NetplayError::Io(e)) => return Err(e),
+                        Err(NetplayError::Chip8(e)) => {
+                            dialog = Some((Prompt::Error(e), error_dialog(&e, false)));
+                            redraw = true;
+                        }
+                    }
+                }
+                None => {
+                    // A ROM waiting for a key would only run the same wait again, see idle below
+                    // The speed may have been changed in the pause menu
+                    budget.ipf = options.ipf;
+                    let frame_start = Instant::now();
+                    let mut steps: u64 = 0;
+                    while !idle && !budget.is_spent() {
+                        // Stop a frame that takes too long rather than leave the screen frozen
+                        steps += 1;
+                        if steps.is_multiple_of(WATCHDOG_INTERVAL)
+                            && frame_start.elapsed() > FRAME_DEADLINE
+                        {
+                            dialog = Some((Prompt::Stalled, stalled_dialog(options, steps)));
+                            redraw = true;
+                            break;
+                        }
+                        if let Some(script) = input_script.as_mut() {
+                            script.apply(frames, &mut chip8);
+                        }
+                        input.apply_next(&mut chip8);
+                        // DRW waits for the next frame with the display_wait quirk
+                        if chip8.is_waiting_for_vblank() {
+                            budget.end_frame();
+                            break;
+                        }
+                        let pc = chip8.pc;
+                        if !std::mem::take(&mut resuming)
+                            && (std::mem::take(&mut stepping) || breakpoints.contains(&pc))
+                        {
+                            dialog = Some((Prompt::Breakpoint, breakpoint_dialog(&chip8)));
+                            redraw = true;
+                            break;
+                        }
+                        let opcode = disasm::opcode_at(&chip8.memory, pc as usize).unwrap_or(0);
+                        // The tools following the instructions, see StepObserver
+                        let mut observers: [&mut dyn StepObserver; 9] = [
+                            &mut tracer,
+                            &mut speedrun,
+                            &mut show_teaching.then_some(&mut teaching),
+                            &mut memory_map,
+                            &mut coverage,
+                            &mut event_log,
+                            &mut timeline,
+                            &mut disassembly,
+                            &mut telemetry,
+                        ];
+                        let result = chip8.step_with(&mut observers);
+                        budget.spend(opcode);
+                        if let Err(e) = result {
+                            dialog = Some((Prompt::Error(e), error_dialog(&e, true)));
+                            redraw = true;
+                            break;
+                        }
+                        buzzer.observe(chip8.st);
+                    }
+                    frame_finished = idle || budget.is_spent();
+                    if frame_finished {
+                        budget.next_frame();
+                        // The timers run on their own clock, driven by the frames
+                        chip8.clock_timers(FRAME_RATE);
+                        chip8.vblank();
+                    }
+                }
+            }
+            if frame_finished {
+                if let Some(tracer) = tracer.as_mut() {
+                    // Flushed every frame so the log can be followed while the game is running
+                    tracer.flush()?;
+                }
+                if options.state_hash || hash_log.is_some() {
+                    let frame_hash = chip8.state_hash();
+                    if let Some(log) = hash_log.as_mut() {
+                        writeln!(log, "{} {:016x}", frames, frame_hash)?;
+                        log.flush()?;
+                    }
+                    hash = Some((frames, frame_hash));
+                }
+                // A tool going away stops the log, not the game
+                if let Some(Err(e)) = event_log.as_mut().map(|log| log.end_frame(frames, &chip8)) {
+                    event_log = None;
+                    message = Some(trf(" Event log stopped: {} ", &[&e]));
+                    message_expires = Some(Instant::now() + NOTICE_DURATION);
+                }
+                // Going back on one side only would desync the peers
+                if netplay.is_none() {
+                    rewind.push(&chip8);
+                }
+                if keep_flags && chip8.rpl_flags != saved_flags {
+                    // Not retried every frame if it fails, only when the flags change again
+                    saved_flags = chip8.rpl_flags;
+                    if let Err(e) = rpl::save(&mut storage, rom_crc, &saved_flags) {
+                        message = Some(trf(" Failed to save the RPL flags: {} ", &[&e]));
+                        message_expires = None;
+                    }
+                }
+                if let Some(interval) = options.autosave {
+                    if Instant::now() >= next_autosave {
+                        next_autosave = Instant::now() + interval;
+                        let rom_name = gallery.rom_name();
+                        if let Err(e) = states::autosave(&mut storage, rom_name, &chip8, rom_crc) {
+                            message = Some(trf(" Failed to autosave: {} ", &[&e]));
+                            message_expires = Some(Instant::now() + NOTICE_DURATION);
+                        }
+                    }
+                }
+                buzzer.update(
+                    chip8.st,
+                    samples_per_frame,
+                    &mut (&mut *sink, video.as_mut()),
+                );
+                if let Some(recorder) = video.as_mut() {
+                    let colors = video::colors(
+                        &pixel_colors(&chip8),
+                        options.palette.foreground(),
+                        options.palette.background(),
+                    );
+                    let size = (chip8.display_width(), chip8.display_height());
+                    let recorded =
+                        recorder.end_frame(&chip8.display, size, &colors, samples_per_frame);
+                    if let Err(e) = recorded {
+                        *video = None;
+                        message = Some(trf(" Video recording stopped: {} ", &[&e]));
+                        message_expires = None;
+                        redraw = true;
+                    }
+                }
+                telemetry.record_frame();
+                timeline.next_frame();
+                frames += 1;
+                if let Some(timer) = speedrun.as_mut() {
+                    timer.next_frame();
+                }
+                render_credit += options.fps;
+                if render_credit >= FRAME_RATE {
+                    render_credit -= FRAME_RATE;
+                    render_due = true;
+                }
+                // The panes follow the instructions run, so they change every frame even if the
+                // screen doesn't, but not while waiting for a key
+                // With --remote they only follow a few times a second
+                if Instant::now() >= next_pane_update {
+                    frame_changed |= !idle
+                        && (show_disassembly || show_timeline || show_memory_map || show_teaching);
+                    frame_changed |= speedrun.as_ref().is_some_and(SpeedrunTimer::is_running);
+                    if options.remote && frame_changed {
+                        next_pane_update = Instant::now() + REMOTE_PANE_INTERVAL;
+                    }
+                }
+
+                // Schedule frames on a fixed grid to avoid drift, but don't try to catch up after a stall
+                next_frame += frame_duration;
+                if next_frame < Instant::now() {
+                    next_frame = Instant::now() + frame_duration;
+                }
+            }
+        }
+
+        if options.dev && Instant::now() >= next_source_check {
+            next_source_check = Instant::now() + SOURCE_CHECK_INTERVAL;
+            let source_modified_now = modified(&options.path);
+            if source_modified_now != source_modified {
+                source_modified = source_modified_now;
+                let reloaded = load_program(options).and_then(|program| {
+                    check_rom(options, &program.bytes)?;
+                    let breakpoints = resolve_breakpoints(&options.breakpoints, &program.labels)?;
+                    Ok((program.bytes, breakpoints))
+                });
+                match reloaded {
+                    // Start again with the new program, keeping the breakpoints on their labels
+                    Ok((new_rom, new_breakpoints)) => {
+                        rom = new_rom;
+                        breakpoints = new_breakpoints;
+                        chip8 = new_core(options, &rom);
+                        chip8.redraw_all();
+                        rom_crc = patch::crc32(&rom);
+                        if keep_flags {
+                            saved_flags = rpl::load(&storage, rom_crc);
+                        }
+                        chip8.rpl_flags = saved_flags;
+                        memory_map = MemoryMap::new(
+                            options.load_addr..options.load_addr + rom.len(),
+                            &chip8,
+                        );
+                        // Saved first, for the new one to start from it when the ROM is the same
+                        let _ = coverage.save();
+                        coverage = Coverage::new(
+                            config::storage(),
+                            rom_crc,
+                            options.load_addr..options.load_addr + rom.len(),
+                        );
+                        if matches!(dialog, Some((Prompt::Error(_) | Prompt::Breakpoint, _))) {
+                            dialog = None;
+                        }
+                        message = Some(trf(" Reloaded {} ", &[&options.path]));
+                        message_expires = Some(Instant::now() + NOTICE_DURATION);
+                    }
+                    // The old program keeps running, the error is shown until the source is fixed
+                    Err(e) => {
+                        message = Some(format!(" {} ", e));
+                        message_expires = None;
+                    }
+                }
+                redraw = true;
+            }
+        }
+
+        if message_expires.is_some_and(|expires| Instant::now() >= expires) {
+            message = None;
+            message_expires = None;
+            redraw = true;
+        }
+
+        if let Some(display) = chip8.take_frame() {
+            frame_pending = true;
+            if let Some(recording) = text_recording.as_mut() {
+                let text = options.display_widget(display).to_text();
+                writeln!(recording, "Frame {}\n{}", frames, text)?;
+                recording.flush()?;
+            }
+        }
+
+        // SYS calls are shown as they're ignored with --sys warn
+        if let Some((addr, nnn)) = chip8.take_sys_call() {
+            let (addr, nnn) = (format!("{:#05X}", addr), format!("{:#05X}", nnn));
+            message = Some(trf(" Ignored SYS {} at {} ", &[&nnn, &addr]));
+            message_expires = Some(Instant::now() + NOTICE_DURATION);
+            redraw = true;
         }
 
-        if chip8.is_drawing {
-            let display_data = chip8.get_display_data();
-            update_display(&mut terminal, &display_data).unwrap();
-            chip8.is_drawing = false;
+        // Shown whenever there is nothing else to show once the program has ended, see R below
+        if netplay.is_none() && message.is_none() && chip8.is_halted() {
+            message = Some(tr(" Program finished, press R to reset ").to_string());
+            redraw = true;
         }
 
-        if event::poll(Duration::from_millis(1))? {
-            if let Event::Key(key) = event::read()? {
-                match key.kind {
-                    KeyEventKind::Press => {
+        let damage = chip8.take_damage();
+        if let Some(spectators) = spectators.as_mut() {
+            let viewers = spectators.viewers();
+            spectators.update(&chip8.display, damage.is_some());
+            if spectators.viewers() != viewers {
+                message = Some(trf(" Spectators: {} ", &[&spectators.viewers()]));
+                message_expires = Some(Instant::now() + NOTICE_DURATION);
+                redraw = true;
+            }
+        }
+
+        if Instant::now() >= next_status_update {
+            next_status_update = Instant::now() + STATUS_INTERVAL;
+            let netplay_status = netplay.as_ref().map(|session| match session.desync() {
+                Some(frame) => trf(" Netplay: desync at frame {} ", &[&frame]),
+                None => trf(
+                    " Netplay: frame {}, {} rollbacks ",
+                    &[&session.frame(), &session.rollbacks()],
+                ),
+            });
+            let hash_status = hash.filter(|_| options.state_hash).map(|(frame, hash)| {
+                trf(" Frame {}, state {} ", &[&frame, &format!("{:016x}", hash)])
+            });
+            let status = netplay_status.or(hash_status);
+            if status != live_status {
+                live_status = status;
+                redraw = true;
+            }
+        }
+
+        // Nothing is drawn when the frame left the display as it was, e.g. a sprite erased and
+        // drawn again in the same place
+        if frame_pending && !redraw && !frame_changed && chip8.display == drawn_display {
+            frame_pending = false;
+        }
+        if redraw || (render_due && (frame_pending || frame_changed)) {
+            let mut display_data = &chip8.display;
+            let mut status = message.clone().or(live_status.clone());
+            if gallery.visible {
+                status = Some(match gallery.selected() {
+                    Some(screenshot) => {
+                        display_data = &screenshot.display;
+                        trf(
+                            " Screenshot {}/{}, frame {} (Left/Right, Del: delete, Enter: export PNG) {}",
+                            &[
+                                &(gallery.selected_index() + 1),
+                                &gallery.len(),
+                                &screenshot.frame,
+                                &message.as_deref().unwrap_or(""),
+                            ],
+                        )
+                    }
+                    None => {
+                        tr(" No screenshots, press F6 while playing to capture one ").to_string()
+                    }
+                });
+            }
+            let panes = Panes {
+                telemetry: show_telemetry.then_some(&telemetry),
+                disassembly: show_disassembly.then_some(&mut disassembly),
+                timeline: show_timeline.then_some(&timeline),
+                teaching: show_teaching.then_some(&teaching),
+                memory_map: show_memory_map.then_some(&mut memory_map),
+                speedrun: speedrun.as_ref(),
+                dialog: dialog.as_ref().map(|(_, dialog)| dialog),
+            };
+            update_display(&mut terminal, options, &chip8, display_data, panes, status)?;
+            frame_pending = false;
+            drawn_display.clone_from(&chip8.display);
+            redraw = false;
+            render_due = false;
+            frame_changed = false;
+        }
+
+        // Wait briefly for the first event, then drain everything the terminal has already delivered
+        // When idle there is nothing to do before the next frame, so sleep until a key or the frame
+        let mut timeout = Duration::from_millis(1);
+        if idle {
+            timeout = timeout.max(next_frame.saturating_duration_since(Instant::now()));
+        }
+        while event::poll(timeout)? {
+            timeout = Duration::ZERO;
+            let event = event::read()?;
+            if let Event::Resize(..) = event {
+                redraw = true;
+            }
+            // The mouse is only captured while the memory map is shown
+            if let Event::Mouse(mouse) = event {
+                match mouse.kind {
+                    MouseEventKind::Down(MouseButton::Left) => {
+                        memory_map.click(mouse.column, mouse.row);
+                    }
+                    MouseEventKind::ScrollUp => memory_map.scroll(-1),
+                    MouseEventKind::ScrollDown => memory_map.scroll(1),
+                    _ => {}
+                }
+                redraw = true;
+            }
+            if let Event::Key(key) = event {
+                // Releases only come with key_releases, key repeats are handled as presses
+                if key.kind == KeyEventKind::Release {
+                    if let Some(key) = options.keymap.key(key.code) {
+                        input.push(KeyInput::Up(key));
+                    }
+                } else {
+                    if let Some((prompt, mut open)) = dialog.take() {
+                        redraw = true;
+                        // Esc closes the dialog, apart from errors where it quits as usual
                         if key.code == KeyCode::Esc {
-                            return Ok(());
+                            if let Prompt::Error(error) = prompt {
+                                return Ok(Some(error));
+                            }
+                            resuming = matches!(prompt, Prompt::Breakpoint);
+                            continue;
                         }
-                        if let Some(key) = key_map(key.code) {
-                            chip8.set_key(key);
+                        let choice = open.handle_key(key.code);
+                        let notice = match (prompt, choice) {
+                            (prompt, None) => {
+                                dialog = Some((prompt, open));
+                                None
+                            }
+                            (Prompt::Error(_), Some('r')) => {
+                                chip8 = new_core(options, &rom);
+                                chip8.rpl_flags = saved_flags;
+                                chip8.redraw_all();
+                                None
+                            }
+                            (Prompt::Error(error), Some('q')) => return Ok(Some(error)),
+                            (Prompt::Breakpoint, Some('q')) => return Ok(None),
+                            (Prompt::Breakpoint, Some('c')) => {
+                                resuming = true;
+                                None
+                            }
+                            (Prompt::Breakpoint, Some('s')) => {
+                                resuming = true;
+                                stepping = true;
+                                None
+                            }
+                            (Prompt::SaveState, Some('s')) => {
+                                let name = open.input_text().trim().to_string();
+                                let key = states::key(&name);
+                                if !states::is_valid_name(&name) {
+                                    dialog = Some((Prompt::SaveState, open));
+                                    Some(tr(" Invalid name ").to_string())
+                                } else if storage.exists(&key) {
+                                    let confirm = Dialog::new(tr("Overwrite save state?"))
+                                        .line(&trf("{} already exists.", &[&name]))
+                                        .choice('y', tr("Overwrite"))
+                                        .choice('n', tr("Cancel"));
+                                    dialog = Some((Prompt::OverwriteState(key), confirm));
+                                    None
+                                } else {
+                                    Some(match states::save(&mut storage, &key, &chip8, rom_crc) {
+                                        Ok(()) => trf(" Saved {} ", &[&name]),
+                                        Err(e) => trf(" Failed to save {}: {} ", &[&name, &e]),
+                                    })
+                                }
+                            }
+                            (Prompt::OverwriteState(key), Some('y')) => {
+                                let name = states::name(&key);
+                                Some(match states::save(&mut storage, &key, &chip8, rom_crc) {
+                                    Ok(()) => trf(" Saved {} ", &[&name]),
+                                    Err(e) => trf(" Failed to save {}: {} ", &[&name, &e]),
+                                })
+                            }
+                            (Prompt::LoadState(keys), Some('l')) => {
+                                let key = &keys[open.selected_item()];
+                                let name = states::name(key);
+                                Some(match states::load(&storage, key, rom_crc, &mut chip8) {
+                                    Ok(()) => trf(" Loaded {} ", &[&name]),
+                                    Err(e) => trf(" Failed to load {}: {} ", &[&name, &e]),
+                                })
+                            }
+                            (Prompt::OverwriteScreenshot, Some('y')) => {
+                                Some(export_screenshot(&gallery))
+                            }
+                            (Prompt::Stalled, Some('p')) => {
+                                let pause = pause_dialog(options, netplay.is_some());
+                                dialog = Some((Prompt::Pause, pause));
+                                None
+                            }
+                            (Prompt::Stalled, Some('r')) => {
+                                chip8.reset();
+                                Some(tr(" Reset ").to_string())
+                            }
+                            (Prompt::Stalled, Some('l')) => {
+                                options.ipf = (options.ipf / 2).max(1);
+                                Some(trf(" Speed: {} instructions per frame ", &[&options.ipf]))
+                            }
+                            (Prompt::Pause, Some(choice @ ('i' | 'k' | 'd' | 'c' | 'r' | 's'))) => {
+                                let notice = match choice {
+                                    'i' => {
+                                        options.ipf = next_choice(&SPEEDS, options.ipf);
+                                        None
+                                    }
+                                    'k' => {
+                                        let name = quirks_name(&options.quirks);
+                                        let next = next_choice(&QUIRKS_CHOICES, name);
+                                        options.quirks = quirks_from_name(next).unwrap_or_default();
+                                        chip8.quirks = options.quirks;
+                                        None
+                                    }
+                                    'd' => {
+                                        options.renderer =
+                                            next_choice(&RENDERERS, options.renderer);
+                                        None
+                                    }
+                                    'c' => {
+                                        options.palette =
+                                            palette::next(&options.palettes, &options.palette)
+                                                .clone();
+                                        None
+                                    }
+                                    'r' => {
+                                        options.key_repeat =
+                                            next_choice(&KEY_REPEATS, options.key_repeat);
+                                        None
+                                    }
+                                    _ => Some(save_rom_settings(&mut storage, options, rom_crc)),
+                                };
+                                let pause = pause_dialog(options, netplay.is_some());
+                                dialog = Some((Prompt::Pause, pause));
+                                notice
+                            }
+                            // Continue after an error, or cancel
+                            _ => None,
+                        };
+                        if notice.is_some() {
+                            message = notice;
+                            message_expires = Some(Instant::now() + NOTICE_DURATION);
+                        }
+                        // Keys are not sent to the paused game
+                        continue;
+                    }
+                    if key.code == KeyCode::Esc {
+                        return Ok(None);
+                    }
+                    // Messages are shown until the next key press
+                    if message.take().is_some() {
+                        message_expires = None;
+                        redraw = true;
+                    }
+                    // Ctrl+S and Ctrl+O save and load states to files, Ctrl+D dumps the registers
+                    // Ctrl+C, Ctrl+R and Ctrl+L copy the frame, registers and disassembly
+                    // Ctrl+P switches to the next palette, Ctrl+T restarts the game
+                    // Ctrl combinations aren't game keys
+                    if key.modifiers.contains(KeyModifiers::CONTROL) {
+                        let notice = match key.code {
+                            KeyCode::Char('s' | 'o') if netplay.is_some() => Some(
+                                tr(" Save states are not available during netplay ").to_string(),
+                            ),
+                            KeyCode::Char('d') => {
+                                let time = SystemTime::now()
+                                    .duration_since(SystemTime::UNIX_EPOCH)
+                                    .unwrap_or_default();
+                                let path =
+                                    format!("{}-dump-{}.txt", gallery.rom_name(), time.as_secs());
+                                Some(match fs::write(&path, dump::format(&chip8)) {
+                                    Ok(()) => trf(" Saved {} ", &[&path]),
+                                    Err(e) => trf(" Failed to save {}: {} ", &[&path, &e]),
+                                })
+                            }
+                            // Resetting one side only would desync the peers
+                            KeyCode::Char('t') if netplay.is_some() => {
+                                Some(tr(" Reset is not available during netplay ").to_string())
+                            }
+                            KeyCode::Char('t') => {
+                                chip8.reset();
+                                Some(tr(" Reset ").to_string())
+                            }
+                            KeyCode::Char('p') => {
+                                options.palette =
+                                    palette::next(&options.palettes, &options.palette).clone();
+                                Some(trf(" Palette: {} ", &[&options.palette.name]))
+                            }
+                            KeyCode::Char('l') if !show_disassembly => {
+                                Some(tr(" Press F3 to show the disassembly to copy ").to_string())
+                            }
+                            KeyCode::Char(c @ ('c' | 'r' | 'l')) => {
+                                let (text, copied) = match c {
+                                    'c' => (
+                                        options.display_widget(&chip8.display).to_text(),
+                                        tr(" Copied the frame "),
+                                    ),
+                                    'r' => (dump::format(&chip8), tr(" Copied the registers ")),
+                                    _ => (
+                                        disassembly.listing(&chip8),
+                                        tr(" Copied the disassembly "),
+                                    ),
+                                };
+                                Some(match clipboard.copy(&text) {
+                                    Ok(()) => copied.to_string(),
+                                    Err(e) => trf(" Failed to copy: {} ", &[&e]),
+                                })
+                            }
+                            KeyCode::Char('s') => {
+                                let name = states::next_name(&storage, gallery.rom_name());
+                                let save = Dialog::new(tr("Save state as"))
+                                    .line(&trf("Saved in {}", &[&storage.location(states::DIR)]))
+                                    .input(&name)
+                                    .choice('s', tr("Save"))
+                                    .choice('n', tr("Cancel"));
+                                dialog = Some((Prompt::SaveState, save));
+                                None
+                            }
+                            KeyCode::Char('o') => {
+                                let keys = states::list(&storage, rom_crc);
+                                if keys.is_empty() {
+                                    Some(
+                                        tr(" No save states for this ROM, press Ctrl+S to save one ")
+                                            .to_string(),
+                                    )
+                                } else {
+                                    let load = Dialog::new(tr("Load which state?"))
+                                        .items(keys.iter().map(|key| states::name(key)).collect())
+                                        .previews(
+                                            keys.iter()
+                                                .map(|key| states::preview(&storage, key))
+                                                .collect(),
+                                        )
+                                        .choice('l', tr("Load"))
+                                        .choice('n', tr("Cancel"));
+                                    dialog = Some((Prompt::LoadState(keys), load));
+                                    None
+                                }
+                            }
+                            _ => None,
+                        };
+                        if notice.is_some() {
+                            message = notice;
+                            message_expires = Some(Instant::now() + NOTICE_DURATION);
+                        }
+                        redraw = true;
+                        continue;
+                    }
+                    // Once the program has ended R restarts it, rather than pressing keypad D
+                    if matches!(key.code, KeyCode::Char('r' | 'R'))
+                        && netplay.is_none()
+                        && chip8.is_halted()
+                    {
+                        chip8 = new_core(options, &rom);
+                        chip8.rpl_flags = saved_flags;
+                        chip8.redraw_all();
+                        redraw = true;
+                        continue;
+                    }
+                    // The frame in progress is finished before pausing, see budget
+                    if key.code == KeyCode::Char('p') {
+                        let pause = pause_dialog(options, netplay.is_some());
+                        dialog = Some((Prompt::Pause, pause));
+                        redraw = true;
+                        continue;
+                    }
+                    if key.code == KeyCode::F(6) {
+                        gallery.capture(&chip8.display, frames);
+                        message = Some(trf(
+                            " Screenshot {} captured (F7: gallery) ",
+                            &[&gallery.len()],
+                        ));
+                        redraw = true;
+                    }
+                    if key.code == KeyCode::F(9) || key.code == KeyCode::F(10) {
+                        // Loading a state on one side only would desync the peers
+                        let text = if netplay.is_some() {
+                            tr(" Quick save is not available during netplay ")
+                        } else if key.code == KeyCode::F(9) {
+                            quick_save = Some(chip8.clone());
+                            tr(" Quick saved ")
+                        } else if let Some(state) = &quick_save {
+                            chip8 = state.clone();
+                            chip8.redraw_all();
+                            tr(" Quick loaded ")
+                        } else {
+                            tr(" Nothing to load, press F9 to quick save first ")
+                        };
+                        message = Some(text.to_string());
+                        message_expires = Some(Instant::now() + NOTICE_DURATION);
+                        redraw = true;
+                    }
+                    // Held down, the key repeat scrubs back through the history
+                    if key.code == KeyCode::Backspace {
+                        let text = if netplay.is_some() {
+                            tr(" Rewind is not available during netplay ").to_string()
+                        } else if rewind.is_empty() {
+                            tr(" Nothing to rewind ").to_string()
+                        } else {
+                            match rewind.rewind(REWIND_STEP, &mut chip8) {
+                                Ok(_) => {
+                                    let seconds = rewind.len() as f64 / FRAME_RATE as f64;
+                                    trf(
+                                        " Rewind: {}s of history left ",
+                                        &[&format!("{:.1}", seconds)],
+                                    )
+                                }
+                                Err(e) => trf(" Failed to rewind: {} ", &[&e]),
+                            }
+                        };
+                        message = Some(text);
+                        message_expires = Some(Instant::now() + NOTICE_DURATION);
+                        redraw = true;
+                    }
+                    if key.code == KeyCode::F(12) {
+                        let path = format!("{}-{}.txt", gallery.rom_name(), frames);
+                        let text = options.display_widget(&chip8.display).to_text();
+                        let text = match std::fs::write(&path, text) {
+                            Ok(()) => trf(" Saved {} ", &[&path]),
+                            Err(e) => trf(" Failed to save {}: {} ", &[&path, &e]),
+                        };
+                        message = Some(text);
+                        message_expires = Some(Instant::now() + NOTICE_DURATION);
+                        redraw = true;
+                    }
+                    if key.code == KeyCode::F(7) {
+                        gallery.visible = !gallery.visible;
+                        redraw = true;
+                    }
+                    if gallery.visible {
+                        match key.code {
+                            KeyCode::Left => gallery.previous(),
+                            KeyCode::Right => gallery.next(),
+                            KeyCode::Delete => gallery.delete(),
+                            KeyCode::Enter => match gallery.export_path(Path::new(".")) {
+                                Some(path) if path.exists() => {
+                                    let confirm = Dialog::new(tr("Overwrite screenshot?"))
+                                        .line(&trf("{} already exists.", &[&path.display()]))
+                                        .choice('y', tr("Overwrite"))
+                                        .choice('n', tr("Cancel"));
+                                    dialog = Some((Prompt::OverwriteScreenshot, confirm));
+                                }
+                                _ => message = Some(export_screenshot(&gallery)),
+                            },
+                            _ => {}
+                        }
+                        redraw = true;
+                        // Keys are not sent to the paused game
+                        continue;
+                    }
+                    if key.code == KeyCode::F(2) {
+                        show_telemetry = !show_telemetry;
+                        redraw = true;
+                    }
+                    if key.code == KeyCode::F(3) {
+                        show_disassembly = !show_disassembly;
+                        redraw = true;
+                    }
+                    if key.code == KeyCode::F(4) {
+                        disassembly.toggle_follow();
+                        redraw = true;
+                    }
+                    if key.code == KeyCode::Up {
+                        disassembly.scroll(-1);
+                        redraw = true;
+                    }
+                    if key.code == KeyCode::Down {
+                        disassembly.scroll(1);
+                        redraw = true;
+                    }
+                    if key.code == KeyCode::F(1) {
+                        show_memory_map = !show_memory_map;
+                        if show_memory_map {
+                            execute!(io::stdout(), EnableMouseCapture)?;
+                        } else {
+                            execute!(io::stdout(), DisableMouseCapture)?;
+                        }
+                        redraw = true;
+                    }
+                    if show_memory_map && key.code == KeyCode::Left {
+                        memory_map.step(-1);
+                        redraw = true;
+                    }
+                    if show_memory_map && key.code == KeyCode::Right {
+                        memory_map.step(1);
+                        redraw = true;
+                    }
+                    if key.code == KeyCode::F(5) {
+                        show_timeline = !show_timeline;
+                        redraw = true;
+                    }
+                    if key.code == KeyCode::PageUp {
+                        timeline.step(-1);
+                        redraw = true;
+                    }
+                    if key.code == KeyCode::PageDown {
+                        timeline.step(1);
+                        redraw = true;
+                    }
+                    if key.code == KeyCode::Home {
+                        timeline.select_newest();
+                        redraw = true;
+                    }
+                    if key.code == KeyCode::F(11) {
+                        show_teaching = !show_teaching;
+                        redraw = true;
+                    }
+                    if key.code == KeyCode::F(8) {
+                        if let Some(timer) = speedrun.as_mut() {
+                            timer.split();
+                        }
+                    }
+                    let repeat = key.kind == KeyEventKind::Repeat;
+                    if let Some(key) = options.keymap.key(key.code) {
+                        if let Some(timer) = speedrun.as_mut() {
+                            timer.input();
+                        }
+                        // Without key releases, repeats are told from presses by their timing
+                        let repeat = match key_releases {
+                            true => repeat,
+                            false => repeats.is_repeat(key, Instant::now()),
+                        };
+                        match (
+                            repeat && options.key_repeat == KeyRepeat::Filter,
+                            key_releases,
+                        ) {
+                            // Held since it was pressed
+                            (true, true) => {}
+                            (true, false) => input.push(KeyInput::Repeat(key)),
+                            (false, true) => input.push(KeyInput::Down(key)),
+                            (false, false) => input.push(KeyInput::Tap(key)),
                         }
                     }
-                    _ => {}
                 }
             }
         }
     }
 }
 
-fn read_rom(path: &str) -> Vec<u8> {
-    let path = path::Path::new(path);
-    let rom = std::fs::read(path).expect("Failed to read ROM file");
-    rom
+// Gzipped ROMs are decompressed, whatever their file name
+fn read_rom(path: &str) -> Result<Vec<u8>, Diagnostic> {
+    let data = std::fs::read(path)
+        .map_err(|e| Diagnostic::io(format!("Failed to read ROM file {}: {}", path, e)))?;
+    match gzip::is_gzip(&data) {
+        true => gzip::decompress(&data)
+            .map_err(|e| Diagnostic::new("rom-format", format!("{}: {}", path, e))),
+        false => Ok(data),
+    }
 }
 
-fn update_display(terminal: &mut DefaultTerminal, display_data: &[bool]) -> io::Result<()> {
-    terminal.draw(|frame| {
-        let width = chip8::DISPLAY_WIDTH;
-        let height = chip8::DISPLAY_HEIGHT;
-        let mut text = String::new();
-        for y in 0..height {
-            for x in 0..width {
-                let index = y * width + x;
-                let pixel = display_data[index];
-                text.push_str(if pixel { "█" } else { " " });
-            }
-            text.push_str("\n");
-        }
-        let block = Paragraph::new(text).block(
-            Block::default()
-                .title("============= CHIP-8 Emulator (Press ESC to Exit) ==============="),
+// Read the ROM and apply the patch given with --patch, if any
+fn load_rom(options: &Options) -> Result<Vec<u8>, Diagnostic> {
+    let rom = read_rom(&options.path)?;
+    match &options.patch {
+        Some(path) => {
+            let patch = std::fs::read(path).map_err(|e| {
+                Diagnostic::io(format!("Failed to read patch file {}: {}", path, e))
+            })?;
+            patch::apply_patch(&rom, &patch).map_err(|e| {
+                Diagnostic::new("patch", format!("Failed to apply patch {}: {}", path, e))
+            })
+        }
+        None => Ok(rom),
+    }
+}
+
+// The ROM to run, assembled from the source with `rustc8 dev`
+fn load_program(options: &Options) -> Result<Program, Diagnostic> {
+    if !options.dev {
+        return Ok(Program {
+            bytes: load_rom(options)?,
+            labels: HashMap::new(),
+        });
+    }
+    let source = fs::read_to_string(&options.path)
+        .map_err(|e| Diagnostic::io(format!("Failed to read {}: {}", options.path, e)))?;
+    asm::assemble_program(&source)
+        .map_err(|e| Diagnostic::new("assembly", format!("{}: {}", options.path, e)))
+}
+
+fn is_cartridge(path: &str) -> bool {
+    Path::new(path)
+        .extension()
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("gif"))
+}
+
+// Octo cartridges carry the settings the program was written for, used unless set on the command line
+fn load_cartridge(options: &mut Options) -> Result<Program, Diagnostic> {
+    let data = fs::read(&options.path)
+        .map_err(|e| Diagnostic::io(format!("Failed to read {}: {}", options.path, e)))?;
+    let cartridge = octocart::parse(&data)
+        .map_err(|e| Diagnostic::new("rom-format", format!("{}: {}", options.path, e)))?;
+    if options.ipf == DEFAULT_IPF {
+        options.ipf = cartridge.tickrate().unwrap_or(options.ipf);
+    }
+    if options.rotation == Rotation::None {
+        let rotation = cartridge.screen_rotation().and_then(Rotation::from_degrees);
+        options.rotation = rotation.unwrap_or(options.rotation);
+    }
+    apply_clip_quirk(options, cartridge.quirk("clipQuirks"));
+    asm::assemble_program(&cartridge.program).map_err(|e| {
+        let message = format!(
+            "{}: the program is Octo source, which can't be assembled yet ({})",
+            options.path, e
         );
-        frame.render_widget(block, frame.area());
-    })?;
-    Ok(())
+        Diagnostic::new("assembly", message)
+    })
 }
 
-fn key_map(key: KeyCode) -> Option<u8> {
-    match key {
-        KeyCode::Char('1') => Some(0x1),
-        KeyCode::Char('2') => Some(0x2),
-        KeyCode::Char('3') => Some(0x3),
-        KeyCode::Char('4') => Some(0xC),
-        KeyCode::Char('q') => Some(0x4),
-        KeyCode::Char('w') => Some(0x5),
-        KeyCode::Char('e') => Some(0x6),
-        KeyCode::Char('r') => Some(0xD),
-        KeyCode::Char('a') => Some(0x7),
-        KeyCode::Char('s') => Some(0x8),
-        KeyCode::Char('d') => Some(0x9),
-        KeyCode::Char('f') => Some(0xE),
-        KeyCode::Char('z') => Some(0xA),
-        KeyCode::Char('x') => Some(0x0),
-        KeyCode::Char('c') => Some(0xB),
-        KeyCode::Char('v') => Some(0xF),
-        _ => None,
+/*
+Octo clips sprites at the edges of the screen with clipQuirks, set for the ROMs written for it
+in the ROM database and in cartridges. Sprites are clipped for these ROMs unless the quirks are
+set on the command line with --quirks or --wrap.
+*/
+fn apply_clip_quirk(options: &mut Options, clip: Option<bool>) {
+    let wrap_given = options.given.contains("--quirks") || options.given.contains("--wrap");
+    if !wrap_given && clip == Some(true) {
+        options.quirks.wrap_x = false;
+        options.quirks.wrap_y = false;
     }
 }
+
+// Labels of the source take precedence over addresses, e.g. "add" is a label before 0xADD
+fn resolve_breakpoints(
+    breakpoints: &[String],
+    labels: &HashMap<String, u16>,
+) -> Result<Vec<u16>, String> {
+    breakpoints
+        .iter()
+        .map(|breakpoint| match labels.get(breakpoint) {
+            Some(addr) => Ok(*addr),
+            None => trace::parse_address(breakpoint).map_err(|e| e.to_string()),
+        })
+        .collect()
+}
+
+fn modified(path: &str) -> Option<SystemTime> {
+    fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+}
+
+fn update_display(
+    terminal: &mut DefaultTerminal,
+    options: &Options,
+    chip8: &Chip8,
+    display_data: &[u8],
+    panes: Panes,
+    status: Option<String>,
+) -> io::Result<()> {
+    terminal.draw(|frame| {
+        let mut display_area = frame.area();
+        if let Some(telemetry) = panes.telemetry {
+            let [top, bottom] = Layout::vertical([
+                Constraint::Fill(1),
+                Constraint::Length(telemetry::PANE_HEIGHT),
+            ])
+            .areas(frame.area());
+            frame.render_widget(telemetry, bottom);
+            display_area = top;
+        }
+        if let Some(memory_map) = panes.memory_map {
+            let [top, bottom] = Layout::vertical([
+                Constraint::Fill(1),
+                Constraint::Length(memory_map::PANE_HEIGHT),
+            ])
+            .areas(display_area);
+            memory_map.render(chip8, bottom, frame.buffer_mut());
+            display_area = top;
+        }
+        if let Some(disassembly) = panes.disassembly {
+            let [left, right] = Layout::horizontal([
+                Constraint::Fill(1),
+                Constraint::Length(disassembly::PANE_WIDTH),
+            ])
+            .areas(display_area);
+            disassembly.render(chip8, right, frame.buffer_mut());
+            display_area = left;
+        }
+        if let Some(timeline) = panes.timeline {
+            let [left, right] = Layout::horizontal([
+                Constraint::Fill(1),
+                Constraint::Length(timeline::PANE_WIDTH),
+            ])
+            .areas(display_area);
+            frame.render_widget(timeline, right);
+            display_area = left;
+        }
+        if let Some(teaching) = panes.teaching {
+            let [left, right] =
+                Layout::horizontal([Constraint::Fill(1), Constraint::Length(teach::PANE_WIDTH)])
+                    .areas(display_area);
+            teaching.render(chip8, right, frame.buffer_mut());
+            display_area = left;
+        }
+
+        let mut block = Block::default().title(tr(TITLE));
+        if let Some(program) = &options.program {
+            block = block.title(format!(" {} ", program.title));
+        }
+        let platform = options.platform();
+        if platform != Platform::Chip8 {
+            block = block.title(format!(" {} ", platform.name()));
+        }
+        if let Some(status) = status {
+            block = block.title_bottom(status);
+        }
+        let colors = pixel_colors(chip8);
+        let widget = options
+            .display_widget(display_data)
+            .pixel_colors(&colors)
+            .block(block);
+        frame.render_widget(widget, display_area);
+        if let Some(timer) = panes.speedrun {
+            frame.render_widget(timer, timer.area(display_area));
+        }
+        if let Some(dialog) = panes.dialog {
+            frame.render_widget(dialog, frame.area());
+        }
+        if options.remote {
+            terminal::strip_colors(frame.buffer_mut());
+        }
+    })?;
+    Ok(())
+}
@@ -0,0 +1,246 @@
+use crate::chip8::{
+    Chip8, Chip8Error, DISPLAY_SIZE, MEGACHIP_DISPLAY_HEIGHT, MEGACHIP_DISPLAY_SIZE,
+    MEGACHIP_DISPLAY_WIDTH,
+};
+use alloc::vec;
+#[cfg(feature = "tui")]
+use alloc::vec::Vec;
+#[cfg(feature = "tui")]
+use ratatui::style::Color;
+
+/*
+MEGA-CHIP
+The extension of Revival Studios with a 256x192 display in 256 colors, built with the megachip
+feature and turned on for a program with Chip8::enable_megachip. The instructions it adds:
+- 0010: MEGAOFF, back to the 64x32 display
+- 0011: MEGAON, the 256x192 display, cleared
+- 01nn nnnn: LDHI I, nnnnnn, a 4-byte instruction loading a 24-bit address into I
+- 02nn: LDPAL nn, load nn colors from I into the palette from index 1, 4 bytes each (ARGB)
+- 03nn: SPRW nn, the width of the sprites, 0 for 256
+- 04nn: SPRH nn, the height of the sprites, 0 for 256
+- 09nn: CCOL nn, the color index DRW collides with
+- 05nn (alpha), 060n and 0700 (digitised sound), 080n (blend mode) are accepted and do nothing
+On the 256x192 display, DRW draws a sprite of the set size from I, a byte per pixel holding the
+index of its color in the palette, 0 for transparent. Drawn pixels replace those under them
+rather than being XORed, and VF is set when one of them covers a pixel of the collision color.
+Sprites are clipped at the edges, and the font is drawn like any other sprite.
+Memory is the 64k of XO-CHIP, so LDHI past it is a MemoryOutOfBounds and programs with more data
+than that can't run. The palette, sprite size and collision color aren't in save states.
+*/
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MegaChip {
+    // ARGB colors of the pixel values, 0 is the background
+    pub palette: [u32; 256],
+    pub sprite_width: usize,
+    pub sprite_height: usize,
+    pub collision_color: u8,
+}
+
+impl Default for MegaChip {
+    // White pixels until the program loads its palette
+    fn default() -> Self {
+        let mut palette = [0xFFFFFFFF; 256];
+        palette[0] = 0xFF000000;
+        MegaChip {
+            palette,
+            sprite_width: 1,
+            sprite_height: 1,
+            collision_color: 0,
+        }
+    }
+}
+
+impl MegaChip {
+    // The palette as terminal colors, see Chip8DisplayWidget::pixel_colors
+    #[cfg(feature = "tui")]
+    pub fn colors(&self) -> Vec<Color> {
+        self.palette
+            .iter()
+            .map(|argb| Color::from_u32(argb & 0xFFFFFF))
+            .collect()
+    }
+}
+
+impl Chip8 {
+    // Decode the MEGA-CHIP instructions from now on, call it after loading a MEGA-CHIP program
+    pub fn enable_megachip(&mut self) {
+        self.megachip = Some(MegaChip::default());
+    }
+
+    // Whether the 256x192 display is on, between MEGAON and MEGAOFF
+    pub fn is_megachip_mode(&self) -> bool {
+        self.megachip.is_some() && self.display.len() == MEGACHIP_DISPLAY_SIZE
+    }
+
+    fn set_display_size(&mut self, size: usize) {
+        self.display = vec![0; size];
+        self.redraw_all();
+    }
+
+    // Run the instruction if it's one of MEGA-CHIP, returns whether it was
+    pub(crate) fn megachip_opcode(&mut self, opcode: u16) -> Result<bool, Chip8Error> {
+        let Some(megachip) = &mut self.megachip else {
+            return Ok(false);
+        };
+        let nn = opcode & 0x00FF;
+        match opcode & 0xFF00 {
+            0x0000 if opcode == 0x0010 => self.set_display_size(DISPLAY_SIZE),
+            0x0000 if opcode == 0x0011 => self.set_display_size(MEGACHIP_DISPLAY_SIZE),
+            0x0100 => {
+                // 01nn nnnn - LDHI I, nnnnnn
+                let addr = self.pc.wrapping_sub(2);
+                let pc = self.pc as usize;
+                let low = match self.memory.get(pc..pc + 2) {
+                    Some(bytes) => u16::from_be_bytes([bytes[0], bytes[1]]),
+                    None => {
+                        return Err(Chip8Error::MemoryOutOfBounds {
+                            addr,
+                            target: self.memory.len(),
+                        })
+                    }
+                };
+                self.pc = self.pc.wrapping_add(2);
+                let target = (nn as usize) << 16 | low as usize;
+                if target >= self.memory.len() {
+                    return Err(Chip8Error::MemoryOutOfBounds { addr, target });
+                }
+                self.i = low;
+            }
+            0x0200 => {
+                // 02nn - LDPAL nn
+                let start = self.i as usize;
+                self.check_memory(start, nn as usize * 4, false)?;
+                for color in 0..nn as usize {
+                    let argb = (0..4).fold(0, |argb, byte| {
+                        let addr = self.bounded(start + color * 4 + byte, false);
+                        argb << 8 | self.read_memory(addr) as u32
+                    });
+                    if let Some(megachip) = &mut self.megachip {
+                        megachip.palette[color + 1] = argb;
+                    }
+                }
+                self.redraw_all();
+            }
+            0x0300 => megachip.sprite_width = if nn == 0 { 256 } else { nn as usize },
+            0x0400 => megachip.sprite_height = if nn == 0 { 256 } else { nn as usize },
+            0x0900 => megachip.collision_color = nn as u8,
+            0x0500 | 0x0800 => {}
+            0x0600 | 0x0700 => {}
+            _ if opcode & 0xF000 == 0xD000 && self.is_megachip_mode() => self.draw_megachip(
+                self.v[(opcode >> 8 & 0xF) as usize] as usize,
+                self.v[(opcode >> 4 & 0xF) as usize] as usize,
+            )?,
+            _ => return Ok(false),
+        }
+        Ok(true)
+    }
+
+    // DRW on the 256x192 display
+    fn draw_megachip(&mut self, x: usize, y: usize) -> Result<(), Chip8Error> {
+        let Some(megachip) = &self.megachip else {
+            return Ok(());
+        };
+        let (width, height, collision_color) = (
+            megachip.sprite_width,
+            megachip.sprite_height,
+            megachip.collision_color,
+        );
+        let start = self.i as usize;
+        self.check_memory(start, width * height, false)?;
+        self.v[0xF] = 0;
+        for row in 0..height.min(MEGACHIP_DISPLAY_HEIGHT.saturating_sub(y)) {
+            for column in 0..width.min(MEGACHIP_DISPLAY_WIDTH.saturating_sub(x)) {
+                let addr = self.bounded(start + row * width + column, false);
+                let color = self.read_memory(addr);
+                if color == 0 {
+                    continue;
+                }
+                let (pixel_x, pixel_y) = (x + column, y + row);
+                let pixel = &mut self.display[pixel_x + pixel_y * MEGACHIP_DISPLAY_WIDTH];
+                if *pixel == collision_color {
+                    self.v[0xF] = 1;
+                }
+                *pixel = color;
+                self.damage_pixel(pixel_x, pixel_y);
+            }
+        }
+        self.display_changed();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chip8::{MemoryLayout, DISPLAY_WIDTH};
+
+    #[test]
+    fn test_megachip() {
+        let mut chip8 = Chip8::with_layout(MemoryLayout::XO_CHIP);
+        #[rustfmt::skip]
+        let rom = [
+            0x00, 0x11,             // MEGAON
+            0x01, 0x00, 0x02, 0x1C, // LDHI I, 0x21C
+            0x02, 0x02,             // LDPAL 2
+            0x01, 0x00, 0x02, 0x24, // LDHI I, 0x224
+            0x03, 0x02,             // SPRW 2
+            0x04, 0x02,             // SPRH 2
+            0x09, 0x02,             // CCOL 2
+            0x60, 0xFE,             // LD V0, 254
+            0xD0, 0x10,             // DRW V0, V1, 0
+            0xD0, 0x10,             // DRW V0, V1, 0
+            0x00, 0x10,             // MEGAOFF
+            0x00, 0x00,
+            0xFF, 0xFF, 0x00, 0x00, // Red
+            0xFF, 0x00, 0x00, 0xFF, // Blue
+            0x01, 0x02,             // The sprite
+            0x00, 0x01,
+        ];
+        chip8.load_rom(&rom).unwrap();
+        // 0011 is a SYS call until MEGA-CHIP is enabled
+        let mut plain = chip8.clone();
+        let _ = plain.run_cycle();
+        assert!(!plain.is_megachip_mode());
+        chip8.enable_megachip();
+
+        chip8.run_cycle().unwrap();
+        assert!(chip8.is_megachip_mode());
+        assert_eq!(chip8.full_damage().right, MEGACHIP_DISPLAY_WIDTH);
+        for _ in 0..3 {
+            chip8.run_cycle().unwrap();
+        }
+        let megachip = chip8.megachip.as_ref().unwrap();
+        assert_eq!(&megachip.palette[1..3], &[0xFFFF0000, 0xFF0000FF]);
+        #[cfg(feature = "tui")]
+        assert_eq!(megachip.colors()[1], Color::Rgb(255, 0, 0));
+        assert_eq!(chip8.i, 0x224);
+        for _ in 0..5 {
+            chip8.run_cycle().unwrap();
+        }
+        // Transparent pixels are left alone
+        assert_eq!(&chip8.display[254..256], &[1, 2]);
+        assert_eq!(chip8.display[MEGACHIP_DISPLAY_WIDTH + 255], 1);
+        assert_eq!(chip8.display[MEGACHIP_DISPLAY_WIDTH + 254], 0);
+        assert_eq!(chip8.v[0xF], 0);
+        // Drawn over the pixel of the collision color
+        chip8.run_cycle().unwrap();
+        assert_eq!(chip8.v[0xF], 1);
+        assert_eq!(&chip8.display[254..256], &[1, 2]);
+
+        chip8.run_cycle().unwrap();
+        assert!(!chip8.is_megachip_mode());
+        assert_eq!(chip8.display_width(), DISPLAY_WIDTH);
+
+        // Past the 64k of memory
+        let mut chip8 = Chip8::with_layout(MemoryLayout::XO_CHIP);
+        chip8.load_rom(&[0x01, 0x01, 0x00, 0x00]).unwrap();
+        chip8.enable_megachip();
+        assert_eq!(
+            chip8.run_cycle(),
+            Err(Chip8Error::MemoryOutOfBounds {
+                addr: 0x200,
+                target: 0x10000
+            })
+        );
+    }
+}
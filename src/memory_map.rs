@@ -0,0 +1,316 @@
+use crate::{
+    coverage::{self, Access},
+    i18n::{tr, trf},
+};
+use ratatui::{
+    buffer::Buffer,
+    layout::{Constraint, Layout, Rect},
+    style::{Color, Modifier, Style},
+    text::{Line, Span},
+    widgets::{Block, Widget},
+};
+use rustc8::{
+    chip8::{Chip8, FONT_SIZE},
+    disasm,
+    step::{Step, StepObserver},
+};
+use std::ops::Range;
+
+// Height of the memory map pane: the bar, the legend, the hex view and the borders
+pub const PANE_HEIGHT: u16 = 12;
+
+// Rows of the bar, the whole memory is spread over them
+const BAR_ROWS: u16 = 3;
+// Bytes shown on each row of the hex view
+const ROW_BYTES: usize = 16;
+
+// What a byte of memory has been used for, the later ones take precedence
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Region {
+    Free,
+    Font,
+    Program,
+    Data,
+    Written,
+    Executed,
+}
+
+impl Region {
+    fn color(self) -> Color {
+        match self {
+            Region::Free => Color::DarkGray,
+            Region::Font => Color::Magenta,
+            Region::Program => Color::Blue,
+            Region::Data => Color::Cyan,
+            Region::Written => Color::Yellow,
+            Region::Executed => Color::Green,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Region::Free => tr("free"),
+            Region::Font => tr("font"),
+            Region::Program => tr("program"),
+            Region::Data => tr("data"),
+            Region::Written => tr("written"),
+            Region::Executed => tr("code"),
+        }
+    }
+}
+
+/*
+MemoryMap shows the whole 4K of memory as a coloured bar, to see at a glance where the code,
+the data and the free space are, and a hex view of the part of memory selected in the bar.
+Each byte is coloured by what it has been used for since the ROM started:
+- code: executed as an instruction
+- written: stored to by LD [I], Vx or LD B, Vx
+- data: read through I by DRW or LD Vx, [I], e.g. sprites
+- program: loaded from the ROM but not used yet, and the font
+Each cell of the bar covers several bytes and takes the colour of the most significant one,
+the cells holding PC and I are marked with P and I. A cell is selected by clicking it or with
+Left and Right, the mouse wheel scrolls the hex view.
+*/
+pub struct MemoryMap {
+    // Addresses the ROM was loaded at
+    rom: Range<usize>,
+    font_addr: usize,
+    executed: Vec<bool>,
+    data: Vec<bool>,
+    written: Vec<bool>,
+    // First address of the hex view
+    selected: usize,
+    // Where the bar was drawn and how many bytes each cell covers, to map clicks to addresses
+    bar: Rect,
+    cell_bytes: usize,
+}
+
+impl MemoryMap {
+    pub fn new(rom: Range<usize>, chip8: &Chip8) -> Self {
+        let memory_size = chip8.memory.len();
+        MemoryMap {
+            selected: rom.start,
+            rom,
+            font_addr: chip8.font_addr as usize,
+            executed: vec![false; memory_size],
+            data: vec![false; memory_size],
+            written: vec![false; memory_size],
+            bar: Rect::default(),
+            cell_bytes: 1,
+        }
+    }
+
+    // Called before each cycle, marks the bytes the next instruction executes, reads and writes
+    pub fn record_step(&mut self, chip8: &Chip8) {
+        let pc = chip8.pc as usize;
+        mark(&mut self.executed, pc, 2);
+        let Some(opcode) = disasm::opcode_at(&chip8.memory, pc) else {
+            return;
+        };
+        match coverage::data_access(chip8, opcode) {
+            Some(Access::Read(range)) => mark(&mut self.data, range.start, range.len()),
+            Some(Access::Write(range)) => mark(&mut self.written, range.start, range.len()),
+            None => {}
+        }
+    }
+
+    fn region(&self, addr: usize) -> Region {
+        if self.executed[addr] {
+            Region::Executed
+        } else if self.written[addr] {
+            Region::Written
+        } else if self.data[addr] {
+            Region::Data
+        } else if self.rom.contains(&addr) {
+            Region::Program
+        } else if (self.font_addr..self.font_addr + FONT_SIZE).contains(&addr) {
+            Region::Font
+        } else {
+            Region::Free
+        }
+    }
+
+    // Move the selection by a number of cells of the bar
+    pub fn step(&mut self, cells: i32) {
+        let addr = self.selected as i64 + cells as i64 * self.cell_bytes as i64;
+        self.select(addr);
+    }
+
+    // Scroll the hex view by a number of rows
+    pub fn scroll(&mut self, rows: i32) {
+        self.select(self.selected as i64 + rows as i64 * ROW_BYTES as i64);
+    }
+
+    // Select the cell under the mouse, returns false if the click was outside the bar
+    pub fn click(&mut self, column: u16, row: u16) -> bool {
+        let bar = self.bar;
+        if column < bar.x || column >= bar.right() || row < bar.y || row >= bar.bottom() {
+            return false;
+        }
+        let cell = (row - bar.y) as usize * bar.width as usize + (column - bar.x) as usize;
+        self.select((cell * self.cell_bytes) as i64);
+        true
+    }
+
+    fn select(&mut self, addr: i64) {
+        let last_row = self.executed.len().saturating_sub(ROW_BYTES);
+        self.selected = (addr.max(0) as usize).min(last_row) / ROW_BYTES * ROW_BYTES;
+    }
+
+    pub fn render(&mut self, chip8: &Chip8, area: Rect, buf: &mut Buffer) {
+        let block = Block::bordered().title(trf(
+            " Memory map (click or Left/Right to select, wheel to scroll) {} ",
+            &[&format!("{:03X}", self.selected)],
+        ));
+        let inner = block.inner(area);
+        block.render(area, buf);
+        let [bar, legend, hex] = Layout::vertical([
+            Constraint::Length(BAR_ROWS),
+            Constraint::Length(1),
+            Constraint::Fill(1),
+        ])
+        .areas(inner);
+
+        let size = self.executed.len();
+        let cells = (bar.width as usize * bar.height as usize).max(1);
+        self.bar = bar;
+        self.cell_bytes = size.div_ceil(cells);
+        let shown = self.selected..self.selected + hex.height as usize * ROW_BYTES;
+        for cell in 0..cells {
+            let start = cell * self.cell_bytes;
+            if start >= size {
+                break;
+            }
+            let range = start..(start + self.cell_bytes).min(size);
+            let region = range.clone().map(|addr| self.region(addr)).max();
+            let style = Style::default().bg(region.unwrap_or(Region::Free).color());
+            let symbol = if range.contains(&(chip8.pc as usize)) {
+                "P"
+            } else if range.contains(&(chip8.i as usize)) {
+                "I"
+            } else if range.start < shown.end && shown.start < range.end {
+                // Underline the cells shown in the hex view
+                "▁"
+            } else {
+                " "
+            };
+            let x = bar.x + (cell % bar.width as usize) as u16;
+            let y = bar.y + (cell / bar.width as usize) as u16;
+            buf[(x, y)]
+                .set_symbol(symbol)
+                .set_style(style.fg(Color::Black));
+        }
+
+        let mut spans: Vec<Span> = [
+            Region::Executed,
+            Region::Written,
+            Region::Data,
+            Region::Program,
+            Region::Font,
+            Region::Free,
+        ]
+        .iter()
+        .flat_map(|region| {
+            [
+                Span::styled("  ", Style::default().bg(region.color())),
+                Span::raw(format!(" {}  ", region.name())),
+            ]
+        })
+        .collect();
+        spans.push(Span::raw(format!("PC {:03X}  I {:03X}", chip8.pc, chip8.i)));
+        Line::from(spans).render(legend, buf);
+
+        for row in 0..hex.height {
+            let start = self.selected + row as usize * ROW_BYTES;
+            if start >= size {
+                break;
+            }
+            let mut spans = vec![Span::raw(format!("{:03X} ", start))];
+            for addr in start..(start + ROW_BYTES).min(size) {
+                let mut style = Style::default().fg(self.region(addr).color());
+                if addr == chip8.pc as usize || addr == chip8.pc as usize + 1 {
+                    style = style.add_modifier(Modifier::REVERSED);
+                }
+                if addr == chip8.i as usize {
+                    style = style.add_modifier(Modifier::UNDERLINED);
+                }
+                spans.push(Span::raw(" "));
+                spans.push(Span::styled(format!("{:02X}", chip8.memory[addr]), style));
+            }
+            Line::from(spans).render(
+                Rect {
+                    y: hex.y + row,
+                    height: 1,
+                    ..hex
+                },
+                buf,
+            );
+        }
+    }
+}
+
+// Mark a run of bytes, wrapping around the end of memory like the core does
+fn mark(bytes: &mut [bool], start: usize, len: usize) {
+    let size = bytes.len();
+    for offset in 0..len {
+        bytes[(start + offset) % size] = true;
+    }
+}
+
+impl StepObserver for MemoryMap {
+    fn before_step(&mut self, _step: Step, chip8: &Chip8) {
+        self.record_step(chip8);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_record_step() {
+        let mut chip8 = Chip8::new();
+        // LD I, 0x300; DRW V0, V0, 5; LD [I], V2
+        chip8
+            .load_rom(&[0xA3, 0x00, 0xD0, 0x05, 0xF2, 0x55])
+            .unwrap();
+        let mut map = MemoryMap::new(0x200..0x206, &chip8);
+        assert_eq!(map.region(0x000), Region::Font);
+        assert_eq!(map.region(0x200), Region::Program);
+        assert_eq!(map.region(0x206), Region::Free);
+
+        map.record_step(&chip8);
+        chip8.run_cycle().unwrap();
+        map.record_step(&chip8);
+        chip8.run_cycle().unwrap();
+        assert_eq!(map.region(0x200), Region::Executed);
+        assert_eq!(map.region(0x304), Region::Data);
+        assert_eq!(map.region(0x305), Region::Free);
+
+        chip8.i = 0x304;
+        map.record_step(&chip8);
+        assert_eq!(map.region(0x304), Region::Written);
+        assert_eq!(map.region(0x306), Region::Written);
+        assert_eq!(map.region(0x307), Region::Free);
+    }
+
+    #[test]
+    fn test_select() {
+        let chip8 = Chip8::new();
+        let mut map = MemoryMap::new(0x200..0x200, &chip8);
+        let area = Rect::new(0, 0, 66, PANE_HEIGHT);
+        let mut buf = Buffer::empty(area);
+        map.render(&chip8, area, &mut buf);
+        // 64 cells per row over 3 rows, 22 bytes each
+        assert_eq!(map.cell_bytes, 22);
+        assert!(!map.click(0, 0));
+        assert!(map.click(2, 2));
+        assert_eq!(map.selected, 0x590);
+        map.step(-1);
+        assert_eq!(map.selected, 0x570);
+        map.scroll(-1000);
+        assert_eq!(map.selected, 0);
+        map.scroll(1000);
+        assert_eq!(map.selected, 0xFF0);
+    }
+}
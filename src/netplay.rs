@@ -0,0 +1,628 @@
+use std::{
+    collections::HashMap,
+    fmt, io,
+    net::{SocketAddr, UdpSocket},
+};
+
+// Netplay frames are frames of the front end, which runs at FRAME_RATE
+use crate::{
+    chip8::{Chip8, Chip8Error, TAP_FRAMES},
+    timing::{FrameBudget, Timing, FRAME_RATE},
+};
+
+/*
+Rollback netplay for two-player ROMs (experimental)
+
+Both peers run the same ROM and exchange their key presses for every frame.
+Inputs are a 16-bit mask, one bit per Chip-8 key, and the keys of both players are
+combined before being applied to the core, as two-player ROMs use separate keys per player.
+
+- Each peer simulates a frame as soon as its own input is known, predicting that the
+  remote player pressed nothing when the remote input has not arrived yet
+- The machine state at the start of every unconfirmed frame is kept as a snapshot
+- When a remote input arrives for a frame that was already simulated with a wrong
+  prediction, the state is rolled back to that frame and re-simulated up to the present
+- A peer never runs more than MAX_ROLLBACK_FRAMES ahead of the last confirmed frame,
+  if the remote falls further behind the session stalls (lockstep) until it catches up
+- Once all inputs before a frame are known its state is final, both peers exchange the
+  hash of that state and a mismatch is reported as a desync, as is a rollback to a frame
+  whose state is no longer kept
+- Frames run as the frontend runs them (see Chip8::run_frame), honoring the display_wait quirk.
+  An error stops the frame where it happened on both peers alike, and is returned to the host
+  once the frames to simulate have all run
+
+Packet layout (big endian):
+- ack: u32, the number of leading frames of the receiver's inputs the sender has
+- start: u32, the frame of the first input in the packet
+- count: u8, the number of inputs in the packet
+- inputs: count * u16, the sender's inputs from frame start
+- hash_frame: u32, the frame of the state hash
+- hash: u64, the hash of the state at the start of hash_frame
+Inputs are resent until acknowledged, so lost UDP packets are recovered.
+*/
+pub const MAX_ROLLBACK_FRAMES: u32 = 8;
+// Most inputs sent in a single packet
+const MAX_PACKET_INPUTS: usize = 64;
+const HEADER_SIZE: usize = 9;
+const FOOTER_SIZE: usize = 12;
+// Hashes are kept for a while so late packets can still be compared
+const HASH_HISTORY: u32 = 120;
+
+#[derive(Debug)]
+pub enum NetplayError {
+    // The transport failed
+    Io(io::Error),
+    // The program stopped on an error, the session can carry on from where it stopped
+    Chip8(Chip8Error),
+}
+
+impl fmt::Display for NetplayError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            NetplayError::Io(e) => write!(f, "{}", e),
+            NetplayError::Chip8(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for NetplayError {}
+
+impl From<io::Error> for NetplayError {
+    fn from(error: io::Error) -> Self {
+        NetplayError::Io(error)
+    }
+}
+
+impl From<Chip8Error> for NetplayError {
+    fn from(error: Chip8Error) -> Self {
+        NetplayError::Chip8(error)
+    }
+}
+
+pub trait Transport {
+    fn send(&mut self, packet: &[u8]) -> io::Result<()>;
+    // Non-blocking receive, Ok(None) when no packet is pending
+    fn recv(&mut self, buf: &mut [u8]) -> io::Result<Option<usize>>;
+}
+
+pub struct UdpTransport {
+    socket: UdpSocket,
+}
+
+impl UdpTransport {
+    pub fn new(bind: SocketAddr, peer: SocketAddr) -> io::Result<Self> {
+        let socket = UdpSocket::bind(bind)?;
+        socket.connect(peer)?;
+        socket.set_nonblocking(true)?;
+        Ok(UdpTransport { socket })
+    }
+}
+
+impl Transport for UdpTransport {
+    fn send(&mut self, packet: &[u8]) -> io::Result<()> {
+        match self.socket.send(packet) {
+            Ok(_) => Ok(()),
+            // The peer may not be listening yet, the packet will be resent
+            Err(e) if e.kind() == io::ErrorKind::ConnectionRefused => Ok(()),
+            Err(e) => Err(e),
+        }
+    }
+
+    fn recv(&mut self, buf: &mut [u8]) -> io::Result<Option<usize>> {
+        match self.socket.recv(buf) {
+            Ok(len) => Ok(Some(len)),
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => Ok(None),
+            Err(e) if e.kind() == io::ErrorKind::ConnectionRefused => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+}
+
+pub struct NetplaySession<T: Transport> {
+    transport: T,
+    // Instructions executed per frame, must be the same on both peers
+    ipf: u32,
+    // Next frame to simulate
+    frame: u32,
+    // Number of leading frames for which the remote input is known
+    confirmed: u32,
+    // Number of leading frames of our inputs the remote has acknowledged
+    remote_ack: u32,
+    local_inputs: HashMap<u32, u16>,
+    remote_inputs: HashMap<u32, u16>,
+    // Remote input used when each unconfirmed frame was simulated
+    predictions: HashMap<u32, u16>,
+    // Machine state at the start of each unconfirmed frame
+    snapshots: HashMap<u32, Chip8>,
+    local_hashes: HashMap<u32, u64>,
+    remote_hashes: HashMap<u32, u64>,
+    desync: Option<u32>,
+    rollbacks: u32,
+}
+
+impl<T: Transport> NetplaySession<T> {
+    pub fn new(transport: T, ipf: u32) -> Self {
+        NetplaySession {
+            transport,
+            ipf,
+            frame: 0,
+            confirmed: 0,
+            remote_ack: 0,
+            local_inputs: HashMap::new(),
+            remote_inputs: HashMap::new(),
+            predictions: HashMap::new(),
+            snapshots: HashMap::new(),
+            local_hashes: HashMap::new(),
+            remote_hashes: HashMap::new(),
+            desync: None,
+            rollbacks: 0,
+        }
+    }
+
+    pub fn frame(&self) -> u32 {
+        self.frame
+    }
+
+    // First frame at which the state hashes of the two peers differed
+    pub fn desync(&self) -> Option<u32> {
+        self.desync
+    }
+
+    // Number of rollbacks performed, a measure of how often predictions were wrong
+    pub fn rollbacks(&self) -> u32 {
+        self.rollbacks
+    }
+
+    /*
+    Processes the packets received from the remote, rolling back if needed.
+    Returns whether the next frame can be simulated with advance_frame, or false if the
+    session is stalled waiting for the remote. An error of the program in the frames simulated
+    again is returned once they have all run.
+    */
+    pub fn poll(&mut self, chip8: &mut Chip8) -> Result<bool, NetplayError> {
+        let mut result = Ok(());
+        if let Some(frame) = self.receive()? {
+            result = self.rollback(chip8, frame);
+        }
+        self.check_hashes(chip8);
+        self.prune();
+
+        let ready = self.frame.saturating_sub(self.confirmed) < MAX_ROLLBACK_FRAMES;
+        if !ready {
+            // Keep the remote fed while stalled, it may be waiting for our inputs too
+            self.send(chip8)?;
+        }
+        result?;
+        Ok(ready)
+    }
+
+    // Simulates the next frame with the given local input and sends it to the remote
+    pub fn advance_frame(&mut self, chip8: &mut Chip8, keys: u16) -> Result<(), NetplayError> {
+        self.local_inputs.insert(self.frame, keys);
+        let result = self.simulate(chip8, self.frame);
+        self.frame += 1;
+        self.send(chip8)?;
+        Ok(result?)
+    }
+
+    fn simulate(&mut self, chip8: &mut Chip8, frame: u32) -> Result<(), Chip8Error> {
+        let remote = self.remote_inputs.get(&frame).copied().unwrap_or(0);
+        let local = self.local_inputs.get(&frame).copied().unwrap_or(0);
+        self.snapshots.insert(frame, chip8.clone());
+        self.predictions.insert(frame, remote);
+        step(chip8, local | remote, self.ipf)
+    }
+
+    // Returns the earliest simulated frame whose prediction turned out to be wrong
+    fn receive(&mut self) -> io::Result<Option<u32>> {
+        let mut rollback: Option<u32> = None;
+        let mut buf = [0; HEADER_SIZE + MAX_PACKET_INPUTS * 2 + FOOTER_SIZE];
+
+        while let Some(len) = self.transport.recv(&mut buf)? {
+            let Some(packet) = Packet::decode(&buf[..len]) else {
+                continue;
+            };
+            // The remote can't acknowledge frames we haven't sent, nor send inputs past the ones
+            // we're missing, ours start from our acknowledgement
+            if packet.ack > self.frame || packet.start > self.confirmed {
+                continue;
+            }
+
+            self.remote_ack = self.remote_ack.max(packet.ack);
+            self.remote_hashes.insert(packet.hash_frame, packet.hash);
+
+            for (offset, keys) in packet.inputs.iter().enumerate() {
+                let Some(frame) = packet.start.checked_add(offset as u32) else {
+                    break;
+                };
+                if frame < self.confirmed || self.remote_inputs.contains_key(&frame) {
+                    continue;
+                }
+                self.remote_inputs.insert(frame, *keys);
+                if frame < self.frame && self.predictions.get(&frame) != Some(keys) {
+                    rollback = Some(rollback.map_or(frame, |earliest| earliest.min(frame)));
+                }
+            }
+
+            while self.remote_inputs.contains_key(&self.confirmed) {
+                self.confirmed += 1;
+            }
+        }
+
+        Ok(rollback)
+    }
+
+    // The first error of the frames simulated again, which all run whatever happens
+    fn rollback(&mut self, chip8: &mut Chip8, frame: u32) -> Result<(), Chip8Error> {
+        // The peers can't agree anymore without the state of the frame
+        let Some(snapshot) = self.snapshots.get(&frame) else {
+            self.desync = self.desync.or(Some(frame));
+            return Ok(());
+        };
+        *chip8 = snapshot.clone();
+        let mut result = Ok(());
+        for frame in frame..self.frame {
+            let simulated = self.simulate(chip8, frame);
+            result = result.and(simulated);
+        }
+        self.rollbacks += 1;
+        result
+    }
+
+    // Frame whose starting state is final, all inputs before it are known and have been simulated
+    fn settled(&self) -> u32 {
+        self.confirmed.min(self.frame)
+    }
+
+    fn settled_hash(&self, chip8: &Chip8) -> (u32, u64) {
+        let settled = self.settled();
+        let hash = match self.snapshots.get(&settled) {
+            Some(snapshot) => snapshot.state_hash(),
+            // No snapshot means the settled frame is the next one to simulate
+            None => chip8.state_hash(),
+        };
+        (settled, hash)
+    }
+
+    fn check_hashes(&mut self, chip8: &Chip8) {
+        let (frame, hash) = self.settled_hash(chip8);
+        self.local_hashes.insert(frame, hash);
+
+        if self.desync.is_none() {
+            self.desync = self
+                .local_hashes
+                .iter()
+                .filter(|(frame, hash)| {
+                    self.remote_hashes
+                        .get(frame)
+                        .is_some_and(|remote| remote != *hash)
+                })
+                .map(|(frame, _)| *frame)
+                .min();
+        }
+    }
+
+    fn prune(&mut self) {
+        let settled = self.settled();
+        let oldest_local = settled.min(self.remote_ack);
+        let oldest_hash = settled.saturating_sub(HASH_HISTORY);
+        self.snapshots.retain(|frame, _| *frame >= settled);
+        self.predictions.retain(|frame, _| *frame >= settled);
+        self.remote_inputs.retain(|frame, _| *frame >= settled);
+        self.local_inputs.retain(|frame, _| *frame >= oldest_local);
+        self.local_hashes.retain(|frame, _| *frame >= oldest_hash);
+        self.remote_hashes.retain(|frame, _| *frame >= oldest_hash);
+    }
+
+    fn send(&mut self, chip8: &Chip8) -> io::Result<()> {
+        let start = self.remote_ack;
+        let end = self
+            .frame
+            .min(start.saturating_add(MAX_PACKET_INPUTS as u32));
+        let inputs = (start..end)
+            .map(|frame| self.local_inputs.get(&frame).copied().unwrap_or(0))
+            .collect();
+        let (hash_frame, hash) = self.settled_hash(chip8);
+        let packet = Packet {
+            ack: self.confirmed,
+            start,
+            inputs,
+            hash_frame,
+            hash,
+        };
+        self.transport.send(&packet.encode())
+    }
+}
+
+// Run a single frame with the keys of the mask tapped, releases aren't exchanged
+// The budget starts afresh every frame, the same on both peers whatever was rolled back
+fn step(chip8: &mut Chip8, keys: u16, ipf: u32) -> Result<(), Chip8Error> {
+    for key in 0..16 {
+        if keys & (1 << key) != 0 {
+            chip8.tap_key(key, TAP_FRAMES);
+        }
+    }
+    chip8.run_frame(&mut FrameBudget::new(Timing::Fixed, ipf), FRAME_RATE)
+}
+
+struct Packet {
+    ack: u32,
+    start: u32,
+    inputs: Vec<u16>,
+    hash_frame: u32,
+    hash: u64,
+}
+
+impl Packet {
+    fn encode(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(HEADER_SIZE + self.inputs.len() * 2 + FOOTER_SIZE);
+        bytes.extend_from_slice(&self.ack.to_be_bytes());
+        bytes.extend_from_slice(&self.start.to_be_bytes());
+        bytes.push(self.inputs.len() as u8);
+        for keys in &self.inputs {
+            bytes.extend_from_slice(&keys.to_be_bytes());
+        }
+        bytes.extend_from_slice(&self.hash_frame.to_be_bytes());
+        bytes.extend_from_slice(&self.hash.to_be_bytes());
+        bytes
+    }
+
+    fn decode(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() < HEADER_SIZE + FOOTER_SIZE {
+            return None;
+        }
+        let count = bytes[8] as usize;
+        if bytes.len() != HEADER_SIZE + count * 2 + FOOTER_SIZE {
+            return None;
+        }
+
+        let u32_at = |at: usize| u32::from_be_bytes(bytes[at..at + 4].try_into().unwrap());
+        // The frames of the inputs must all be numbered
+        u32_at(4).checked_add(count as u32)?;
+        let inputs = bytes[HEADER_SIZE..HEADER_SIZE + count * 2]
+            .chunks(2)
+            .map(|chunk| u16::from_be_bytes([chunk[0], chunk[1]]))
+            .collect();
+        let footer = HEADER_SIZE + count * 2;
+        Some(Packet {
+            ack: u32_at(0),
+            start: u32_at(4),
+            inputs,
+            hash_frame: u32_at(footer),
+            hash: u64::from_be_bytes(bytes[footer + 4..footer + 12].try_into().unwrap()),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{cell::RefCell, collections::VecDeque, rc::Rc};
+
+    // In-memory link, packets are only delivered when the test releases them
+    #[derive(Clone, Default)]
+    struct Link {
+        queue: Rc<RefCell<VecDeque<Vec<u8>>>>,
+        delivered: Rc<RefCell<VecDeque<Vec<u8>>>>,
+    }
+
+    impl Link {
+        fn deliver(&self) {
+            let mut queue = self.queue.borrow_mut();
+            self.delivered.borrow_mut().extend(queue.drain(..));
+        }
+    }
+
+    struct TestTransport {
+        outgoing: Link,
+        incoming: Link,
+    }
+
+    impl Transport for TestTransport {
+        fn send(&mut self, packet: &[u8]) -> io::Result<()> {
+            self.outgoing.queue.borrow_mut().push_back(packet.to_vec());
+            Ok(())
+        }
+
+        fn recv(&mut self, buf: &mut [u8]) -> io::Result<Option<usize>> {
+            Ok(self.incoming.delivered.borrow_mut().pop_front().map(|p| {
+                buf[..p.len()].copy_from_slice(&p);
+                p.len()
+            }))
+        }
+    }
+
+    type TestSession = NetplaySession<TestTransport>;
+
+    fn session_pair() -> (TestSession, TestSession, Link, Link) {
+        let a_to_b = Link::default();
+        let b_to_a = Link::default();
+        let a = NetplaySession::new(
+            TestTransport {
+                outgoing: a_to_b.clone(),
+                incoming: b_to_a.clone(),
+            },
+            4,
+        );
+        let b = NetplaySession::new(
+            TestTransport {
+                outgoing: b_to_a.clone(),
+                incoming: a_to_b.clone(),
+            },
+            4,
+        );
+        (a, b, a_to_b, b_to_a)
+    }
+
+    // ROM that waits for a key into V1 (Fx0A) and accumulates it into V2, forever
+    fn chip8_with_rom() -> Chip8 {
+        let mut chip8 = Chip8::new();
+        chip8
+            .load_rom(&[0xF1, 0x0A, 0x82, 0x14, 0x12, 0x00])
+            .unwrap();
+        chip8
+    }
+
+    #[test]
+    fn test_packet_roundtrip() {
+        let packet = Packet {
+            ack: 3,
+            start: 7,
+            inputs: vec![0x0001, 0x8000],
+            hash_frame: 5,
+            hash: 0x0123456789ABCDEF,
+        };
+        let decoded = Packet::decode(&packet.encode()).unwrap();
+        assert_eq!(decoded.ack, 3);
+        assert_eq!(decoded.start, 7);
+        assert_eq!(decoded.inputs, vec![0x0001, 0x8000]);
+        assert_eq!(decoded.hash_frame, 5);
+        assert_eq!(decoded.hash, 0x0123456789ABCDEF);
+        assert!(Packet::decode(&[0; 4]).is_none());
+    }
+
+    #[test]
+    fn test_implausible_packets() {
+        let overflowing = Packet {
+            ack: 0,
+            start: u32::MAX,
+            inputs: vec![0x0001, 0x0002],
+            hash_frame: 0,
+            hash: 0,
+        };
+        assert!(Packet::decode(&overflowing.encode()).is_none());
+
+        let (mut a, _b, _a_to_b, b_to_a) = session_pair();
+        let mut chip8 = chip8_with_rom();
+        a.advance_frame(&mut chip8, 0).unwrap();
+        // Acknowledging frames never sent, and inputs from past the ones missing
+        for (ack, start) in [(2, 0), (0, 1), (0, u32::MAX - 1)] {
+            let packet = Packet {
+                ack,
+                start,
+                inputs: vec![0x0001],
+                hash_frame: 0,
+                hash: 0,
+            };
+            b_to_a.queue.borrow_mut().push_back(packet.encode());
+        }
+        b_to_a.queue.borrow_mut().push_back(overflowing.encode());
+        b_to_a.deliver();
+
+        assert!(a.poll(&mut chip8).unwrap());
+        assert_eq!(a.remote_ack, 0);
+        assert_eq!(a.confirmed, 0);
+        assert!(a.remote_inputs.is_empty());
+    }
+
+    #[test]
+    fn test_rollback_converges() {
+        let (mut a, mut b, a_to_b, b_to_a) = session_pair();
+        let mut chip8_a = chip8_with_rom();
+        let mut chip8_b = chip8_with_rom();
+
+        // Both peers run ahead on predictions before any packet is delivered
+        for frame in 0..6u16 {
+            assert!(a.poll(&mut chip8_a).unwrap());
+            a.advance_frame(&mut chip8_a, 1 << (frame % 3)).unwrap();
+            assert!(b.poll(&mut chip8_b).unwrap());
+            b.advance_frame(&mut chip8_b, 1 << (4 + frame % 2)).unwrap();
+        }
+
+        // Late inputs arrive and both peers roll back to the same state
+        a_to_b.deliver();
+        b_to_a.deliver();
+        a.poll(&mut chip8_a).unwrap();
+        b.poll(&mut chip8_b).unwrap();
+
+        assert!(a.rollbacks() > 0);
+        assert!(b.rollbacks() > 0);
+        assert_eq!(chip8_a.state_hash(), chip8_b.state_hash());
+
+        // Keep exchanging hashes, no desync should be detected
+        for _ in 0..4 {
+            a.advance_frame(&mut chip8_a, 0).unwrap();
+            b.advance_frame(&mut chip8_b, 0).unwrap();
+            a_to_b.deliver();
+            b_to_a.deliver();
+            a.poll(&mut chip8_a).unwrap();
+            b.poll(&mut chip8_b).unwrap();
+        }
+        assert_eq!(a.desync(), None);
+        assert_eq!(b.desync(), None);
+    }
+
+    #[test]
+    fn test_stall_when_remote_is_silent() {
+        let (mut a, _b, _a_to_b, _b_to_a) = session_pair();
+        let mut chip8 = chip8_with_rom();
+
+        for _ in 0..MAX_ROLLBACK_FRAMES {
+            assert!(a.poll(&mut chip8).unwrap());
+            a.advance_frame(&mut chip8, 0).unwrap();
+        }
+        assert!(!a.poll(&mut chip8).unwrap());
+        assert_eq!(a.frame(), MAX_ROLLBACK_FRAMES);
+    }
+
+    #[test]
+    fn test_desync_detected() {
+        let (mut a, mut b, a_to_b, b_to_a) = session_pair();
+        let mut chip8_a = chip8_with_rom();
+        let mut chip8_b = chip8_with_rom();
+        chip8_b.memory[0xF00] = 1;
+
+        for _ in 0..3 {
+            a.poll(&mut chip8_a).unwrap();
+            a.advance_frame(&mut chip8_a, 0).unwrap();
+            b.poll(&mut chip8_b).unwrap();
+            b.advance_frame(&mut chip8_b, 0).unwrap();
+            a_to_b.deliver();
+            b_to_a.deliver();
+        }
+        a.poll(&mut chip8_a).unwrap();
+        b.poll(&mut chip8_b).unwrap();
+
+        assert!(a.desync().is_some());
+        assert!(b.desync().is_some());
+    }
+
+    #[test]
+    fn test_machine_error() {
+        let (mut a, _b, _a_to_b, _b_to_a) = session_pair();
+        let mut chip8 = Chip8::new();
+        chip8.load_rom(&[0xFF, 0xFF]).unwrap();
+
+        assert!(a.poll(&mut chip8).unwrap());
+        let result = a.advance_frame(&mut chip8, 0);
+        assert!(matches!(result, Err(NetplayError::Chip8(_))));
+        // The frame was still sent and counted, the remote runs into the same error
+        assert_eq!(a.frame(), 1);
+    }
+
+    #[test]
+    fn test_display_wait() {
+        let (mut a, _b, _a_to_b, _b_to_a) = session_pair();
+        let mut chip8 = Chip8::new();
+        chip8.quirks.display_wait = true;
+        chip8.load_rom(&[0xD0, 0x01, 0x12, 0x00]).unwrap();
+
+        // DRW and the jump back, then the next DRW waits for the vertical blank
+        for frame in 1..=3 {
+            a.advance_frame(&mut chip8, 0).unwrap();
+            assert_eq!(chip8.cycles_executed(), frame * 2);
+        }
+    }
+
+    #[test]
+    fn test_pruned_snapshot() {
+        let (mut a, _b, _a_to_b, _b_to_a) = session_pair();
+        let mut chip8 = chip8_with_rom();
+        a.advance_frame(&mut chip8, 0).unwrap();
+        a.snapshots.clear();
+
+        a.rollback(&mut chip8, 0).unwrap();
+        assert_eq!(a.desync(), Some(0));
+        assert_eq!(a.rollbacks(), 0);
+    }
+}
@@ -0,0 +1,300 @@
+use serde_json::{Map, Value};
+use std::fmt;
+
+/*
+Octo cartridges
+Octo shares programs as GIF images, a picture of a cartridge with the program hidden in it:
+- The palette indices of the pixels of every frame, in order, carry 2 bits each in their low
+  bits, 4 pixels per byte with the high bits first
+- The bytes are a 32 bit big endian length followed by that many bytes of JSON:
+  {"program": "<Octo source>", "options": {"tickrate": 20, "shiftQuirks": false, ...}}
+The program is the source code rather than the assembled ROM, and the options are the settings
+it was written for (speed, quirks, colours, rotation).
+Only what's needed to get the payload out of the GIF is decoded: no interlacing, disposal or
+colours.
+*/
+#[derive(Debug, PartialEq, Eq)]
+pub enum CartError {
+    NotAGif,
+    Truncated,
+    InvalidImage,
+    InvalidPayload(String),
+}
+
+impl fmt::Display for CartError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CartError::NotAGif => write!(f, "Not a GIF image"),
+            CartError::Truncated => write!(f, "GIF image is truncated"),
+            CartError::InvalidImage => write!(f, "Invalid GIF image data"),
+            CartError::InvalidPayload(reason) => {
+                write!(f, "No Octo cartridge in the image: {}", reason)
+            }
+        }
+    }
+}
+
+impl std::error::Error for CartError {}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Cartridge {
+    pub program: String,
+    pub options: Map<String, Value>,
+}
+
+impl Cartridge {
+    // Instructions per frame
+    pub fn tickrate(&self) -> Option<u32> {
+        self.options.get("tickrate")?.as_u64()?.try_into().ok()
+    }
+
+    // Rotation of the screen in degrees, clockwise
+    pub fn screen_rotation(&self) -> Option<u32> {
+        self.options
+            .get("screenRotation")?
+            .as_u64()?
+            .try_into()
+            .ok()
+    }
+
+    // Quirk setting by its Octo name, e.g. "clipQuirks"
+    pub fn quirk(&self, name: &str) -> Option<bool> {
+        self.options.get(name)?.as_bool()
+    }
+}
+
+pub fn parse(data: &[u8]) -> Result<Cartridge, CartError> {
+    let pixels = decode_gif(data)?;
+    let bytes: Vec<u8> = pixels
+        .chunks_exact(4)
+        .map(|pixels| pixels.iter().fold(0, |byte, pixel| byte << 2 | (pixel & 3)))
+        .collect();
+    let invalid = |reason: &str| CartError::InvalidPayload(reason.to_string());
+    let (length, payload) = bytes
+        .split_first_chunk::<4>()
+        .ok_or_else(|| invalid("image is too small"))?;
+    let payload = payload
+        .get(..u32::from_be_bytes(*length) as usize)
+        .ok_or_else(|| invalid("length is past the end of the image"))?;
+    let text = std::str::from_utf8(payload).map_err(|_| invalid("payload is not text"))?;
+    let json: Value = serde_json::from_str(text).map_err(|e| invalid(&e.to_string()))?;
+    let program = json["program"]
+        .as_str()
+        .ok_or_else(|| invalid("no program"))?;
+    let options = match &json["options"] {
+        Value::Object(options) => options.clone(),
+        _ => Map::new(),
+    };
+    Ok(Cartridge {
+        program: program.to_string(),
+        options,
+    })
+}
+
+// Palette indices of the pixels of all the frames, in order
+fn decode_gif(data: &[u8]) -> Result<Vec<u8>, CartError> {
+    let mut reader = Reader { data };
+    let signature = reader.take(6).map_err(|_| CartError::NotAGif)?;
+    if signature != b"GIF87a" && signature != b"GIF89a" {
+        return Err(CartError::NotAGif);
+    }
+    // Logical screen descriptor: width, height, flags, background colour and aspect ratio
+    let screen = reader.take(7)?;
+    skip_color_table(&mut reader, screen[4])?;
+
+    let mut pixels = Vec::new();
+    loop {
+        match reader.u8()? {
+            // Extension: label and sub-blocks
+            0x21 => {
+                reader.u8()?;
+                reader.sub_blocks()?;
+            }
+            // Image: position, size and flags, then the LZW data
+            0x2C => {
+                let descriptor = reader.take(9)?;
+                skip_color_table(&mut reader, descriptor[8])?;
+                let min_code_size = reader.u8()?;
+                let data = reader.sub_blocks()?;
+                lzw_decode(min_code_size, &data, &mut pixels)?;
+            }
+            0x3B => return Ok(pixels),
+            _ => return Err(CartError::InvalidImage),
+        }
+    }
+}
+
+// Colour tables follow a descriptor when bit 7 of its flags is set, with 2^(n+1) entries
+fn skip_color_table(reader: &mut Reader, flags: u8) -> Result<(), CartError> {
+    if flags & 0x80 != 0 {
+        reader.take(3 << ((flags & 0x07) + 1))?;
+    }
+    Ok(())
+}
+
+// Variable length LZW with codes of up to 12 bits, read least significant bit first
+fn lzw_decode(min_code_size: u8, data: &[u8], pixels: &mut Vec<u8>) -> Result<(), CartError> {
+    if !(1..=11).contains(&min_code_size) {
+        return Err(CartError::InvalidImage);
+    }
+    let clear = 1usize << min_code_size;
+    let end = clear + 1;
+    let roots = || -> Vec<Vec<u8>> { (0..clear + 2).map(|code| vec![code as u8]).collect() };
+    let mut table = roots();
+    let mut code_size = min_code_size as usize + 1;
+    let mut previous: Option<usize> = None;
+    let mut bit = 0;
+    while bit + code_size <= data.len() * 8 {
+        let code = (0..code_size).fold(0, |code, offset| {
+            let position = bit + offset;
+            code | (((data[position / 8] >> (position % 8)) & 1) as usize) << offset
+        });
+        bit += code_size;
+        if code == clear {
+            table = roots();
+            code_size = min_code_size as usize + 1;
+            previous = None;
+            continue;
+        }
+        if code == end {
+            break;
+        }
+        let entry = match (table.get(code), previous) {
+            (Some(entry), _) => entry.clone(),
+            // A code being defined by this very step: the previous entry and its first byte
+            (None, Some(previous)) if code == table.len() => {
+                let mut entry = table[previous].clone();
+                entry.push(entry[0]);
+                entry
+            }
+            _ => return Err(CartError::InvalidImage),
+        };
+        pixels.extend_from_slice(&entry);
+        if let Some(previous) = previous {
+            if table.len() < 4096 {
+                let mut new = table[previous].clone();
+                new.push(entry[0]);
+                table.push(new);
+                if table.len() == 1 << code_size && code_size < 12 {
+                    code_size += 1;
+                }
+            }
+        }
+        previous = Some(code);
+    }
+    Ok(())
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], CartError> {
+        if self.data.len() < len {
+            return Err(CartError::Truncated);
+        }
+        let (bytes, rest) = self.data.split_at(len);
+        self.data = rest;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> Result<u8, CartError> {
+        Ok(self.take(1)?[0])
+    }
+
+    // Data split in blocks of up to 255 bytes, each preceded by its length, ending with 0
+    fn sub_blocks(&mut self) -> Result<Vec<u8>, CartError> {
+        let mut data = Vec::new();
+        loop {
+            let len = self.u8()? as usize;
+            if len == 0 {
+                return Ok(data);
+            }
+            data.extend_from_slice(self.take(len)?);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A GIF with a single frame of the given 8-bit pixels, LZW compressed without using the
+    // string table: a clear code every 254 pixels keeps the codes 9 bits wide
+    fn gif(pixels: &[u8]) -> Vec<u8> {
+        let mut codes = Vec::new();
+        for chunk in pixels.chunks(254) {
+            codes.push(0x100);
+            codes.extend(chunk.iter().map(|pixel| *pixel as usize));
+        }
+        codes.push(0x101);
+        let mut lzw = vec![0u8; (codes.len() * 9).div_ceil(8)];
+        for (index, code) in codes.iter().enumerate() {
+            for offset in 0..9 {
+                let position = index * 9 + offset;
+                lzw[position / 8] |= (((code >> offset) & 1) as u8) << (position % 8);
+            }
+        }
+
+        let mut gif = b"GIF89a".to_vec();
+        let width = pixels.len() as u16;
+        gif.extend_from_slice(&width.to_le_bytes());
+        gif.extend_from_slice(&[1, 0, 0x80, 0, 0]);
+        gif.extend_from_slice(&[0; 6]);
+        // Graphic control extension, as written by most encoders
+        gif.extend_from_slice(&[0x21, 0xF9, 4, 0, 0, 0, 0, 0]);
+        gif.push(0x2C);
+        gif.extend_from_slice(&[0, 0, 0, 0]);
+        gif.extend_from_slice(&width.to_le_bytes());
+        gif.extend_from_slice(&[1, 0, 0]);
+        gif.push(8);
+        for block in lzw.chunks(255) {
+            gif.push(block.len() as u8);
+            gif.extend_from_slice(block);
+        }
+        gif.extend_from_slice(&[0, 0x3B]);
+        gif
+    }
+
+    // Hide the payload in the low bits of pixels of a grey picture
+    fn cartridge(json: &str) -> Vec<u8> {
+        let mut payload = (json.len() as u32).to_be_bytes().to_vec();
+        payload.extend_from_slice(json.as_bytes());
+        let pixels: Vec<u8> = payload
+            .iter()
+            .flat_map(|byte| [6, 4, 2, 0].map(|shift| 0x80 | (byte >> shift) & 3))
+            .collect();
+        gif(&pixels)
+    }
+
+    #[test]
+    fn test_parse() {
+        let json = r#"{"key": "", "program": ": main\n  loop again", "options": {"tickrate": 20, "clipQuirks": true, "screenRotation": 90}}"#;
+        let cartridge = parse(&cartridge(json)).unwrap();
+        assert_eq!(cartridge.program, ": main\n  loop again");
+        assert_eq!(cartridge.tickrate(), Some(20));
+        assert_eq!(cartridge.screen_rotation(), Some(90));
+        assert_eq!(cartridge.quirk("clipQuirks"), Some(true));
+        assert_eq!(cartridge.quirk("shiftQuirks"), None);
+    }
+
+    #[test]
+    fn test_lzw_string_table() {
+        // Codes of 3 bits: clear (4), 0, then 6 which is the entry this step defines, i.e. the
+        // previous entry and its first byte, then end (5)
+        let mut pixels = Vec::new();
+        lzw_decode(2, &[0b1000_0100, 0b0000_1011], &mut pixels).unwrap();
+        assert_eq!(pixels, [0, 0, 0]);
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert_eq!(parse(b"\x89PNG"), Err(CartError::NotAGif));
+        assert_eq!(parse(b"GIF89a\x01\x00"), Err(CartError::Truncated));
+        assert!(matches!(
+            parse(&gif(&[0x80; 40])),
+            Err(CartError::InvalidPayload(_))
+        ));
+    }
+}
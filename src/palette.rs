@@ -0,0 +1,166 @@
+use crate::config::{self, CONFIG_KEY};
+use ratatui::style::Color;
+use rustc8::storage::Storage;
+use serde_json::Value;
+use std::str::FromStr;
+
+/*
+Palettes
+The colors the display is drawn in. A palette has up to four colors, as in Octo: the background,
+the pixels of the first plane, of the second plane and of both planes. Only the first two are
+used until XO-CHIP planes are emulated, the others are kept so palettes don't have to be
+rewritten then.
+
+Besides the built-in palettes, more can be defined in config.json in the config directory, with
+the colors as names ("green", "lightyellow") or hex codes:
+
+    {"palettes": {"gameboy": ["#0f380f", "#9bbc0f", "#306230", "#8bac0f"], "paper": ["white", "black"]}}
+
+A palette with the name of a built-in one replaces it. Missing colors of the planes are those of
+the first plane.
+*/
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Palette {
+    pub name: String,
+    pub colors: [Color; 4],
+}
+
+impl Palette {
+    fn new(name: &str, colors: [Color; 4]) -> Self {
+        Palette {
+            name: name.to_string(),
+            colors,
+        }
+    }
+
+    pub fn background(&self) -> Color {
+        self.colors[0]
+    }
+
+    pub fn foreground(&self) -> Color {
+        self.colors[1]
+    }
+}
+
+impl Default for Palette {
+    // The colors of the terminal
+    fn default() -> Self {
+        Palette::new("default", [Color::Reset; 4])
+    }
+}
+
+fn builtin() -> Vec<Palette> {
+    let rgb = |rgb: u32| Color::from_u32(rgb);
+    vec![
+        Palette::default(),
+        Palette::new(
+            "green",
+            [Color::Black, Color::Green, Color::Green, Color::Green],
+        ),
+        Palette::new(
+            "amber",
+            [Color::Black, rgb(0xFFB000), rgb(0xFFB000), rgb(0xFFB000)],
+        ),
+        Palette::new(
+            "octo",
+            [rgb(0x996600), rgb(0xFFCC00), rgb(0xFF6600), rgb(0x662200)],
+        ),
+    ]
+}
+
+// The built-in palettes followed by those of config.json
+pub fn load(storage: &dyn Storage) -> Result<Vec<Palette>, String> {
+    let mut palettes = builtin();
+    let config = config::read(storage)?;
+    let Some(entries) = config.get("palettes") else {
+        return Ok(palettes);
+    };
+    let entries = entries
+        .as_object()
+        .ok_or_else(|| format!("{}: palettes must be an object", CONFIG_KEY))?;
+    for (name, colors) in entries {
+        let palette = parse(name, colors).map_err(|e| format!("{}: {}", CONFIG_KEY, e))?;
+        match palettes.iter_mut().find(|palette| palette.name == *name) {
+            Some(builtin) => *builtin = palette,
+            None => palettes.push(palette),
+        }
+    }
+    Ok(palettes)
+}
+
+fn parse(name: &str, colors: &Value) -> Result<Palette, String> {
+    let colors = colors
+        .as_array()
+        .filter(|colors| (2..=4).contains(&colors.len()))
+        .ok_or_else(|| format!("palette {} must be a list of 2 to 4 colors", name))?;
+    let mut parsed = Vec::new();
+    for color in colors {
+        let color = color
+            .as_str()
+            .and_then(|color| Color::from_str(color).ok())
+            .ok_or_else(|| format!("palette {} has an invalid color: {}", name, color))?;
+        parsed.push(color);
+    }
+    let plane = |index: usize| parsed.get(index).copied().unwrap_or(parsed[1]);
+    Ok(Palette::new(
+        name,
+        [parsed[0], parsed[1], plane(2), plane(3)],
+    ))
+}
+
+pub fn find<'a>(palettes: &'a [Palette], name: &str) -> Option<&'a Palette> {
+    palettes.iter().find(|palette| palette.name == name)
+}
+
+// The palette after the current one, going back to the first after the last
+pub fn next<'a>(palettes: &'a [Palette], current: &Palette) -> &'a Palette {
+    let index = palettes
+        .iter()
+        .position(|palette| palette.name == current.name);
+    &palettes[index.map_or(0, |index| (index + 1) % palettes.len())]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rustc8::storage::MemoryStorage;
+
+    #[test]
+    fn test_load() {
+        let mut storage = MemoryStorage::new();
+        let palettes = load(&storage).unwrap();
+        assert_eq!(palettes, builtin());
+        assert_eq!(next(&palettes, &Palette::default()).name, "green");
+        assert_eq!(
+            next(&palettes, find(&palettes, "octo").unwrap()).name,
+            "default"
+        );
+
+        let config = r##"{"palettes": {"paper": ["white", "#000000"], "green": ["black", "lightgreen", "red", "blue"]}}"##;
+        storage.write(CONFIG_KEY, config.as_bytes()).unwrap();
+        let palettes = load(&storage).unwrap();
+        assert_eq!(palettes.len(), 5);
+        let paper = find(&palettes, "paper").unwrap();
+        assert_eq!(
+            paper.colors,
+            [
+                Color::White,
+                Color::Rgb(0, 0, 0),
+                Color::Rgb(0, 0, 0),
+                Color::Rgb(0, 0, 0)
+            ]
+        );
+        // Built-in palettes are replaced in place
+        assert_eq!(palettes[1].name, "green");
+        assert_eq!(palettes[1].foreground(), Color::LightGreen);
+
+        storage
+            .write(CONFIG_KEY, br#"{"palettes": {"bad": ["black", "nope"]}}"#)
+            .unwrap();
+        assert!(load(&storage).unwrap_err().contains("invalid color"));
+        storage
+            .write(CONFIG_KEY, br#"{"palettes": {"short": ["black"]}}"#)
+            .unwrap();
+        assert!(load(&storage).is_err());
+    }
+}
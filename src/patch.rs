@@ -0,0 +1,320 @@
+use std::fmt;
+
+/*
+ROM patches
+Bugfixed or translated variants of ROMs are usually distributed as patch files rather than
+modified ROMs. Two common formats are supported, detected from their header:
+
+IPS ("PATCH"):
+- A list of records until "EOF": 3-byte offset, 2-byte size, then size bytes of data
+- A record with size 0 is run-length encoded: 2-byte run length, then 1 byte value
+- An optional 3-byte length after "EOF" truncates the output
+All values are big endian.
+
+BPS ("BPS1"):
+- Variable length integers for the source size, target size and metadata size, then metadata
+- A list of actions, each a variable length integer holding the command and the length:
+  - SourceRead: copy bytes from the source at the current output offset
+  - TargetRead: copy bytes stored in the patch
+  - SourceCopy: copy bytes from a relative offset in the source
+  - TargetCopy: copy bytes from a relative offset in the output written so far
+- CRC32 of the source, the target and the patch itself (little endian)
+*/
+const IPS_HEADER: &[u8] = b"PATCH";
+const IPS_EOF: &[u8] = b"EOF";
+const BPS_HEADER: &[u8] = b"BPS1";
+const BPS_FOOTER_SIZE: usize = 12;
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum PatchError {
+    UnknownFormat,
+    Truncated,
+    SourceChecksum,
+    TargetChecksum,
+    PatchChecksum,
+    SizeMismatch,
+}
+
+impl fmt::Display for PatchError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PatchError::UnknownFormat => write!(f, "Unknown patch format, expected IPS or BPS"),
+            PatchError::Truncated => write!(f, "Patch file is truncated"),
+            PatchError::SourceChecksum => write!(f, "Patch was made for a different ROM"),
+            PatchError::TargetChecksum => write!(f, "Patched ROM checksum mismatch"),
+            PatchError::PatchChecksum => write!(f, "Patch file is corrupted"),
+            PatchError::SizeMismatch => write!(f, "Patched ROM size mismatch"),
+        }
+    }
+}
+
+impl std::error::Error for PatchError {}
+
+// Apply an IPS or BPS patch to a ROM, returning the patched ROM
+pub fn apply_patch(rom: &[u8], patch: &[u8]) -> Result<Vec<u8>, PatchError> {
+    if patch.starts_with(IPS_HEADER) {
+        apply_ips(rom, patch)
+    } else if patch.starts_with(BPS_HEADER) {
+        apply_bps(rom, patch)
+    } else {
+        Err(PatchError::UnknownFormat)
+    }
+}
+
+pub fn apply_ips(rom: &[u8], patch: &[u8]) -> Result<Vec<u8>, PatchError> {
+    let mut reader = Reader::new(&patch[IPS_HEADER.len()..]);
+    let mut output = rom.to_vec();
+
+    loop {
+        let offset = reader.bytes(3)?;
+        if offset == IPS_EOF {
+            break;
+        }
+        let offset = u24(offset);
+        let size = reader.u16()? as usize;
+        let data = if size > 0 {
+            reader.bytes(size)?.to_vec()
+        } else {
+            let run = reader.u16()? as usize;
+            vec![reader.u8()?; run]
+        };
+
+        if output.len() < offset + data.len() {
+            output.resize(offset + data.len(), 0);
+        }
+        output[offset..offset + data.len()].copy_from_slice(&data);
+    }
+
+    // Truncation extension
+    if let Ok(length) = reader.bytes(3) {
+        output.truncate(u24(length));
+    }
+
+    Ok(output)
+}
+
+pub fn apply_bps(rom: &[u8], patch: &[u8]) -> Result<Vec<u8>, PatchError> {
+    if patch.len() < BPS_HEADER.len() + BPS_FOOTER_SIZE {
+        return Err(PatchError::Truncated);
+    }
+    let footer = &patch[patch.len() - BPS_FOOTER_SIZE..];
+    let checksum = |at: usize| u32::from_le_bytes(footer[at..at + 4].try_into().unwrap());
+    if crc32(&patch[..patch.len() - 4]) != checksum(8) {
+        return Err(PatchError::PatchChecksum);
+    }
+    if crc32(rom) != checksum(0) {
+        return Err(PatchError::SourceChecksum);
+    }
+
+    let mut reader = Reader::new(&patch[BPS_HEADER.len()..patch.len() - BPS_FOOTER_SIZE]);
+    let source_size = reader.varint()?;
+    let target_size = reader.varint()?;
+    let metadata_size = reader.varint()?;
+    reader.bytes(metadata_size)?;
+    if source_size != rom.len() {
+        return Err(PatchError::SizeMismatch);
+    }
+
+    let mut output = Vec::with_capacity(target_size);
+    let mut source_offset: usize = 0;
+    let mut target_offset: usize = 0;
+    while !reader.is_empty() {
+        let data = reader.varint()?;
+        let command = data & 3;
+        let length = (data >> 2) + 1;
+        match command {
+            0 => {
+                // SourceRead
+                let start = output.len();
+                let bytes = rom
+                    .get(start..start + length)
+                    .ok_or(PatchError::Truncated)?;
+                output.extend_from_slice(bytes);
+            }
+            1 => {
+                // TargetRead
+                output.extend_from_slice(reader.bytes(length)?);
+            }
+            2 => {
+                // SourceCopy
+                source_offset = relative_offset(source_offset, reader.varint()?)?;
+                let bytes = rom
+                    .get(source_offset..source_offset + length)
+                    .ok_or(PatchError::Truncated)?;
+                output.extend_from_slice(bytes);
+                source_offset += length;
+            }
+            _ => {
+                // TargetCopy
+                // Copied byte by byte as the range may overlap the bytes being written
+                target_offset = relative_offset(target_offset, reader.varint()?)?;
+                for _ in 0..length {
+                    let byte = *output.get(target_offset).ok_or(PatchError::Truncated)?;
+                    output.push(byte);
+                    target_offset += 1;
+                }
+            }
+        }
+    }
+
+    if output.len() != target_size {
+        return Err(PatchError::SizeMismatch);
+    }
+    if crc32(&output) != checksum(4) {
+        return Err(PatchError::TargetChecksum);
+    }
+    Ok(output)
+}
+
+// Offsets in BPS copies are relative, the lowest bit is the sign and the rest the distance
+fn relative_offset(offset: usize, data: usize) -> Result<usize, PatchError> {
+    let distance = data >> 1;
+    if data & 1 == 1 {
+        offset.checked_sub(distance).ok_or(PatchError::Truncated)
+    } else {
+        Ok(offset + distance)
+    }
+}
+
+fn u24(bytes: &[u8]) -> usize {
+    ((bytes[0] as usize) << 16) | ((bytes[1] as usize) << 8) | bytes[2] as usize
+}
+
+// CRC-32 (IEEE 802.3), as used by BPS, zip and png
+pub fn crc32(data: &[u8]) -> u32 {
+    let mut crc = 0xFFFFFFFF_u32;
+    for byte in data {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            let mask = (crc & 1).wrapping_neg();
+            crc = (crc >> 1) ^ (0xEDB88320 & mask);
+        }
+    }
+    !crc
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Reader { data, position: 0 }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.position >= self.data.len()
+    }
+
+    fn bytes(&mut self, count: usize) -> Result<&'a [u8], PatchError> {
+        let bytes = self
+            .data
+            .get(self.position..self.position + count)
+            .ok_or(PatchError::Truncated)?;
+        self.position += count;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> Result<u8, PatchError> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, PatchError> {
+        let bytes = self.bytes(2)?;
+        Ok(u16::from_be_bytes([bytes[0], bytes[1]]))
+    }
+
+    // BPS variable length integer, 7 bits per byte with the high bit marking the last byte
+    fn varint(&mut self) -> Result<usize, PatchError> {
+        let mut data: usize = 0;
+        let mut shift: usize = 1;
+        loop {
+            let byte = self.u8()?;
+            data += (byte & 0x7F) as usize * shift;
+            if byte & 0x80 != 0 {
+                return Ok(data);
+            }
+            shift <<= 7;
+            data += shift;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bps_patch(source: &[u8], target: &[u8], actions: &[u8]) -> Vec<u8> {
+        let mut patch = BPS_HEADER.to_vec();
+        patch.push(0x80 | source.len() as u8);
+        patch.push(0x80 | target.len() as u8);
+        patch.push(0x80); // no metadata
+        patch.extend_from_slice(actions);
+        patch.extend_from_slice(&crc32(source).to_le_bytes());
+        patch.extend_from_slice(&crc32(target).to_le_bytes());
+        let checksum = crc32(&patch);
+        patch.extend_from_slice(&checksum.to_le_bytes());
+        patch
+    }
+
+    #[test]
+    fn test_crc32() {
+        assert_eq!(crc32(b"123456789"), 0xCBF43926);
+    }
+
+    #[test]
+    fn test_apply_ips() {
+        let rom = [0x00, 0xE0, 0x12, 0x00];
+        let mut patch = IPS_HEADER.to_vec();
+        // Replace 0x12 with 0x13 at offset 2
+        patch.extend_from_slice(&[0x00, 0x00, 0x02, 0x00, 0x01, 0x13]);
+        // Run of 3 bytes 0xFF at offset 5, past the end of the ROM
+        patch.extend_from_slice(&[0x00, 0x00, 0x05, 0x00, 0x00, 0x00, 0x03, 0xFF]);
+        patch.extend_from_slice(IPS_EOF);
+
+        let patched = apply_patch(&rom, &patch).unwrap();
+        assert_eq!(
+            patched,
+            vec![0x00, 0xE0, 0x13, 0x00, 0x00, 0xFF, 0xFF, 0xFF]
+        );
+
+        // Truncated to 2 bytes
+        patch.extend_from_slice(&[0x00, 0x00, 0x02]);
+        assert_eq!(apply_patch(&rom, &patch).unwrap(), vec![0x00, 0xE0]);
+
+        assert_eq!(apply_patch(&rom, IPS_HEADER), Err(PatchError::Truncated));
+    }
+
+    #[test]
+    fn test_apply_bps() {
+        // SourceRead 2, TargetRead 1 "X", SourceRead 1
+        let patch = bps_patch(b"ABCD", b"ABXD", &[0x84, 0x81, b'X', 0x80]);
+        assert_eq!(apply_patch(b"ABCD", &patch).unwrap(), b"ABXD");
+
+        // SourceRead 2, TargetCopy 4 from offset 0
+        let patch = bps_patch(b"AB", b"ABABAB", &[0x84, 0x8F, 0x80]);
+        assert_eq!(apply_patch(b"AB", &patch).unwrap(), b"ABABAB");
+
+        // SourceCopy 2 from offset 2
+        let patch = bps_patch(b"ABCD", b"CD", &[0x86, 0x84]);
+        assert_eq!(apply_patch(b"ABCD", &patch).unwrap(), b"CD");
+    }
+
+    #[test]
+    fn test_apply_bps_checksums() {
+        let mut patch = bps_patch(b"ABCD", b"ABXD", &[0x84, 0x81, b'X', 0x80]);
+        assert_eq!(
+            apply_patch(b"ABCE", &patch),
+            Err(PatchError::SourceChecksum)
+        );
+
+        patch[9] = b'Y';
+        assert_eq!(apply_patch(b"ABCD", &patch), Err(PatchError::PatchChecksum));
+
+        assert_eq!(
+            apply_patch(b"ABCD", b"NOPE"),
+            Err(PatchError::UnknownFormat)
+        );
+    }
+}
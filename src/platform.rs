@@ -0,0 +1,95 @@
+use crate::{chip8::MemoryLayout, gzip};
+use std::path::Path;
+
+/*
+Platforms
+Several CHIP-8 variants exist, and programs written for one often misbehave on another.
+The community convention is to name the files after the platform they were written for:
+- .ch8: CHIP-8, the original interpreter of the COSMAC VIP
+- .sc8: SUPER-CHIP, the HP48 extension with a 128x64 mode and scrolling
+- .xo8: XO-CHIP, Octo's extension with more memory, colors and sound
+- .mc8: MEGA-CHIP, the extension with a 256x192 mode and colored sprites
+The platform is picked from the extension when loading a ROM, and can be overridden when the
+file doesn't follow the convention.
+*/
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Platform {
+    #[default]
+    Chip8,
+    SuperChip,
+    XoChip,
+    MegaChip,
+}
+
+impl Platform {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "chip8" | "chip-8" => Some(Platform::Chip8),
+            "schip" | "superchip" | "super-chip" => Some(Platform::SuperChip),
+            "xochip" | "xo-chip" => Some(Platform::XoChip),
+            "megachip" | "mega-chip" => Some(Platform::MegaChip),
+            _ => None,
+        }
+    }
+
+    // The extension under .gz for gzipped ROMs, e.g. pong.sc8.gz is SUPER-CHIP
+    pub fn from_extension(path: &Path) -> Option<Self> {
+        let extension = gzip::strip_extension(path)
+            .extension()?
+            .to_str()?
+            .to_ascii_lowercase();
+        match extension.as_str() {
+            "ch8" => Some(Platform::Chip8),
+            "sc8" => Some(Platform::SuperChip),
+            "xo8" => Some(Platform::XoChip),
+            "mc8" => Some(Platform::MegaChip),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Platform::Chip8 => "CHIP-8",
+            Platform::SuperChip => "SUPER-CHIP",
+            Platform::XoChip => "XO-CHIP",
+            Platform::MegaChip => "MEGA-CHIP",
+        }
+    }
+
+    // XO-CHIP and MEGA-CHIP programs have 64k of memory, the others 4k
+    pub fn memory_layout(&self) -> MemoryLayout {
+        match self {
+            Platform::XoChip | Platform::MegaChip => MemoryLayout::XO_CHIP,
+            _ => MemoryLayout::CHIP8,
+        }
+    }
+
+    // Whether the core runs the platform's programs as intended, the extensions are not emulated
+    // yet but MEGA-CHIP, with the megachip feature
+    pub fn is_supported(&self) -> bool {
+        *self == Platform::Chip8 || (cfg!(feature = "megachip") && *self == Platform::MegaChip)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_extension() {
+        let platform = |path: &str| Platform::from_extension(Path::new(path));
+        assert_eq!(platform("roms/pong.ch8"), Some(Platform::Chip8));
+        assert_eq!(platform("roms/ant.SC8"), Some(Platform::SuperChip));
+        assert_eq!(platform("roms/t8nks.xo8"), Some(Platform::XoChip));
+        assert_eq!(platform("roms/demo.mc8"), Some(Platform::MegaChip));
+        assert_eq!(platform("roms/pong.rom"), None);
+        assert_eq!(platform("roms/pong"), None);
+    }
+
+    #[test]
+    fn test_from_name() {
+        assert_eq!(Platform::from_name("schip"), Some(Platform::SuperChip));
+        assert_eq!(Platform::from_name("XO-CHIP"), Some(Platform::XoChip));
+        assert_eq!(Platform::from_name("chip48"), None);
+    }
+}
@@ -0,0 +1,114 @@
+use crate::patch::crc32;
+
+/*
+Minimal PNG encoder for screenshots
+The display is written as an 8-bit grayscale image, each Chip-8 pixel scaled up to a square
+of scale x scale pixels. The image data is zlib wrapped but not compressed (stored deflate
+blocks), which keeps the encoder small and is still a valid PNG that any viewer can open.
+*/
+const SIGNATURE: &[u8] = &[0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1A, b'\n'];
+// Largest length of a stored deflate block
+const MAX_BLOCK_SIZE: usize = 0xFFFF;
+
+pub fn encode(display: &[bool], width: usize, height: usize, scale: usize) -> Vec<u8> {
+    let image_width = width * scale;
+    let image_height = height * scale;
+
+    // Each row starts with its filter type, 0 for none
+    let mut raw = Vec::with_capacity((image_width + 1) * image_height);
+    for y in 0..image_height {
+        raw.push(0);
+        for x in 0..image_width {
+            let pixel = display[(y / scale) * width + x / scale];
+            raw.push(if pixel { 0xFF } else { 0x00 });
+        }
+    }
+
+    let mut header = Vec::with_capacity(13);
+    header.extend_from_slice(&(image_width as u32).to_be_bytes());
+    header.extend_from_slice(&(image_height as u32).to_be_bytes());
+    // Bit depth 8, grayscale, default compression, filtering and no interlace
+    header.extend_from_slice(&[8, 0, 0, 0, 0]);
+
+    let mut png = SIGNATURE.to_vec();
+    write_chunk(&mut png, b"IHDR", &header);
+    write_chunk(&mut png, b"IDAT", &zlib_stored(&raw));
+    write_chunk(&mut png, b"IEND", &[]);
+    png
+}
+
+// Chunks are the length, the type, the data and the CRC of the type and the data
+fn write_chunk(png: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    png.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    let start = png.len();
+    png.extend_from_slice(kind);
+    png.extend_from_slice(data);
+    let crc = crc32(&png[start..]);
+    png.extend_from_slice(&crc.to_be_bytes());
+}
+
+fn zlib_stored(data: &[u8]) -> Vec<u8> {
+    // Deflate with a 32K window, no preset dictionary
+    let mut output = vec![0x78, 0x01];
+    let mut blocks = data.chunks(MAX_BLOCK_SIZE).peekable();
+    if blocks.peek().is_none() {
+        output.extend_from_slice(&[0x01, 0x00, 0x00, 0xFF, 0xFF]);
+    }
+    while let Some(block) = blocks.next() {
+        let last = blocks.peek().is_none();
+        output.push(last as u8);
+        let length = block.len() as u16;
+        output.extend_from_slice(&length.to_le_bytes());
+        output.extend_from_slice(&(!length).to_le_bytes());
+        output.extend_from_slice(block);
+    }
+    output.extend_from_slice(&adler32(data).to_be_bytes());
+    output
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    let (mut a, mut b) = (1_u32, 0_u32);
+    for byte in data {
+        a = (a + *byte as u32) % 65521;
+        b = (b + a) % 65521;
+    }
+    (b << 16) | a
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_adler32() {
+        assert_eq!(adler32(b"Wikipedia"), 0x11E60398);
+    }
+
+    #[test]
+    fn test_encode() {
+        let display = [true, false, false, true];
+        let png = encode(&display, 2, 2, 2);
+        assert!(png.starts_with(SIGNATURE));
+        // IHDR: 4x4, 8-bit grayscale
+        assert_eq!(&png[12..16], b"IHDR");
+        assert_eq!(&png[16..29], &[0, 0, 0, 4, 0, 0, 0, 4, 8, 0, 0, 0, 0]);
+        assert!(png.ends_with(&[0, 0, 0, 0, b'I', b'E', b'N', b'D', 0xAE, 0x42, 0x60, 0x82]));
+
+        // Single stored block holding 4 rows of a filter byte and 4 pixels
+        let idat = &png[37..];
+        assert_eq!(&idat[..4], b"IDAT");
+        assert_eq!(&idat[4..11], &[0x78, 0x01, 0x01, 20, 0, !20, 0xFF]);
+        assert_eq!(&idat[11..16], &[0, 0xFF, 0xFF, 0x00, 0x00]);
+        assert_eq!(&idat[21..26], &[0, 0x00, 0x00, 0xFF, 0xFF]);
+    }
+
+    #[test]
+    fn test_zlib_stored_blocks() {
+        let data = vec![0xAB; MAX_BLOCK_SIZE + 1];
+        let output = zlib_stored(&data);
+        // Header, 2 blocks with 5 bytes of header each and the checksum
+        assert_eq!(output.len(), 2 + 5 + MAX_BLOCK_SIZE + 5 + 1 + 4);
+        assert_eq!(output[2], 0);
+        assert_eq!(output[2 + 5 + MAX_BLOCK_SIZE], 1);
+    }
+}
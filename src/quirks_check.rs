@@ -0,0 +1,144 @@
+use crate::diagnostic::Diagnostic;
+use rustc8::{
+    chip8::{Chip8Error, Quirks, DISPLAY_HEIGHT, DISPLAY_WIDTH},
+    harness::TestHarness,
+    widget::{Chip8DisplayWidget, Renderer},
+};
+
+const QUIRKS_CHECK_USAGE: &str = "Usage: cargo run quirks-check <quirks test ROM> [--frames N]";
+
+// Instructions per frame, the speed the quirks test expects
+const IPF: u32 = 11;
+// Frames run before reading the screen, the test takes a few seconds to get through all quirks
+const DEFAULT_FRAMES: u32 = 600;
+// The test suite reads the platform to test at 0x1FF, 1 is CHIP-8, which skips its menu
+const PLATFORM_ADDR: usize = 0x1FF;
+const PLATFORM_CHIP8: u8 = 1;
+
+// The default quirks and the presets of the platforms, as they can be picked with --quirks
+fn profiles() -> Vec<(&'static str, Quirks)> {
+    let presets = Quirks::PRESETS
+        .iter()
+        .filter_map(|name| Some((*name, Quirks::from_preset(name)?)));
+    std::iter::once(("default", Quirks::default()))
+        .chain(presets)
+        .collect()
+}
+
+/*
+Quirks check, `rustc8 quirks-check <ROM>`
+Runs the quirks test of the community test suite (Timendus' chip8-test-suite, 5-quirks.ch8)
+headlessly with the default quirks and each preset, and prints the result screen of each. The results are
+compared line by line: a line of the report that changes with the quirks is a quirk the
+interpreter actually implements, and one that never changes is either not implemented or gets
+the same result with every preset.
+The glyphs of the report aren't decoded, read the screens for which quirks pass or fail.
+*/
+pub fn run(args: &[String]) -> Result<(), Diagnostic> {
+    let mut path = None;
+    let mut frames = DEFAULT_FRAMES;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--frames" => {
+                let value = args.next().ok_or("Missing value for --frames")?;
+                frames = value
+                    .parse()
+                    .map_err(|_| format!("Invalid number of frames: {}", value))?;
+            }
+            _ => path = Some(arg),
+        }
+    }
+    let path = path.ok_or(QUIRKS_CHECK_USAGE)?;
+    let rom = std::fs::read(path)
+        .map_err(|e| Diagnostic::io(format!("Failed to read {}: {}", path, e)))?;
+
+    let profiles = profiles();
+    let mut screens = Vec::new();
+    for (name, quirks) in &profiles {
+        let display = run_headless(&rom, *quirks, frames).map_err(|e| {
+            let message = format!("{} stopped with the {} quirks: {}", path, name, e);
+            Diagnostic::new(e.code(), message)
+        })?;
+        let text = Chip8DisplayWidget::new(&display)
+            .renderer(Renderer::HalfBlock)
+            .pixel_width(1)
+            .to_text();
+        println!("{}:\n{}", name, text);
+        screens.push(display);
+    }
+
+    let lines = text_lines(&screens[0]);
+    if lines.is_empty() {
+        println!("The screen is empty, is {} the quirks test?", path);
+        return Ok(());
+    }
+    for (index, rows) in lines.iter().enumerate() {
+        let changed: Vec<&str> = profiles
+            .iter()
+            .zip(&screens)
+            .skip(1)
+            .filter(|(_, screen)| rows.clone().any(|y| row(screen, y) != row(&screens[0], y)))
+            .map(|((name, _), _)| *name)
+            .collect();
+        let result = match changed.is_empty() {
+            true => "same result with every preset".to_string(),
+            false => format!("changes with: {}", changed.join(", ")),
+        };
+        println!(
+            "Line {} (rows {}-{}): {}",
+            index + 1,
+            rows.start,
+            rows.end - 1,
+            result
+        );
+    }
+    Ok(())
+}
+
+// The display after running the ROM for a number of frames
+pub fn run_headless(rom: &[u8], quirks: Quirks, frames: u32) -> Result<Vec<u8>, Chip8Error> {
+    let mut harness = TestHarness::new(rom).quirks(quirks).ipf(IPF);
+    harness.chip8.memory[PLATFORM_ADDR] = PLATFORM_CHIP8;
+    harness.run_until(frames as u64)?;
+    Ok(harness.chip8.get_display_data())
+}
+
+fn row(display: &[u8], y: usize) -> &[u8] {
+    &display[y * DISPLAY_WIDTH..(y + 1) * DISPLAY_WIDTH]
+}
+
+// Lines of text on the screen, runs of rows with pixels separated by empty rows
+fn text_lines(display: &[u8]) -> Vec<std::ops::Range<usize>> {
+    let mut lines = Vec::new();
+    let mut start = None;
+    for y in 0..=DISPLAY_HEIGHT {
+        let empty = y == DISPLAY_HEIGHT || row(display, y).iter().all(|pixel| *pixel == 0);
+        match (start, empty) {
+            (None, false) => start = Some(y),
+            (Some(first), true) => {
+                lines.push(first..y);
+                start = None;
+            }
+            _ => {}
+        }
+    }
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_run_headless() {
+        // Draw the 0 glyph at x = 62, which wraps or is clipped on the right
+        let rom = [0x60, 0x3E, 0xF1, 0x29, 0xD0, 0x15, 0x12, 0x06];
+        let wrapped = run_headless(&rom, Quirks::default(), 1).unwrap();
+        let clipped = run_headless(&rom, Quirks::CHIP8, 1).unwrap();
+        assert_eq!(wrapped[0], 1);
+        assert_eq!(clipped[0], 0);
+        assert_eq!(text_lines(&wrapped), vec![0..5]);
+        assert!(run_headless(&[0xFF, 0xFF], Quirks::default(), 1).is_err());
+    }
+}
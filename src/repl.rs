@@ -0,0 +1,340 @@
+use crate::{
+    i18n::{self, tr, trf},
+    terminal::Capabilities,
+};
+use ratatui::{
+    buffer::Buffer,
+    crossterm::event::{self, Event, KeyCode, KeyEventKind},
+    layout::{Constraint, Layout, Rect},
+    text::Line,
+    widgets::{Block, Paragraph, Widget},
+    DefaultTerminal,
+};
+use rustc8::{
+    asm,
+    chip8::{Chip8, DISPLAY_HEIGHT},
+    disasm::disassemble,
+    timing::FRAME_RATE,
+    widget::Chip8DisplayWidget,
+};
+use std::{
+    io,
+    time::{Duration, Instant},
+};
+
+// Width of the registers pane, 4 registers per row
+const PANE_WIDTH: u16 = 30;
+
+/*
+Assembler REPL, started with `rustc8 repl`
+Each line typed is assembled, written to memory at PC and executed straight away on a live core,
+with the display and registers updated after every instruction. Jumps and calls move PC like they
+would in a program, so the next line is written where execution continues.
+- ":reset" starts again with a fresh core
+- ":save <file>" writes every line typed in the session, instructions and commands, to a script
+- ":source <file>" runs the lines of a script as if they were typed, e.g. one saved by :save in
+  an earlier session, to get back to the same state. Empty lines and lines starting with # are
+  skipped, and scripts can't source other scripts
+- Esc quits
+There is no keypad, typed keys go to the prompt, so SKP and LD Vx, K see every key released.
+*/
+enum Entry {
+    Executed { addr: u16, opcode: u16 },
+    Error { line: String, message: String },
+    Note(String),
+}
+
+struct Repl {
+    chip8: Chip8,
+    input: String,
+    history: Vec<Entry>,
+    // Lines run in the session, written by :save
+    session: Vec<String>,
+}
+
+impl Repl {
+    fn new() -> Self {
+        Repl {
+            chip8: Chip8::new(),
+            input: String::new(),
+            history: Vec::new(),
+            session: Vec::new(),
+        }
+    }
+
+    // Run the line in the prompt and record the outcome in the history
+    fn submit(&mut self) {
+        let line = std::mem::take(&mut self.input);
+        self.run_line(line, false);
+    }
+
+    fn run_line(&mut self, line: String, sourced: bool) {
+        let command = line.trim();
+        if command.is_empty() {
+            return;
+        }
+        if let Some(path) = command.strip_prefix(":save") {
+            let path = path.trim();
+            let mut script = self.session.join("\n");
+            script.push('\n');
+            let entry = match std::fs::write(path, script) {
+                Ok(()) => Entry::Note(trf("Saved {} lines to {}", &[&self.session.len(), &path])),
+                Err(e) => Entry::Error {
+                    message: e.to_string(),
+                    line,
+                },
+            };
+            self.history.push(entry);
+            return;
+        }
+        if let Some(path) = command.strip_prefix(":source") {
+            let result = match sourced {
+                true => Err(tr("Scripts can't source other scripts").to_string()),
+                false => std::fs::read_to_string(path.trim()).map_err(|e| e.to_string()),
+            };
+            match result {
+                Ok(script) => {
+                    let lines = script.lines().filter(|line| !line.trim().starts_with('#'));
+                    for line in lines {
+                        self.run_line(line.to_string(), true);
+                    }
+                }
+                Err(message) => self.history.push(Entry::Error { line, message }),
+            }
+            return;
+        }
+        self.session.push(command.to_string());
+        if command == ":reset" {
+            self.chip8 = Chip8::new();
+            self.history.clear();
+            return;
+        }
+        let entry = match self.execute(&line) {
+            Ok((addr, opcode)) => Entry::Executed { addr, opcode },
+            Err(message) => Entry::Error { line, message },
+        };
+        self.history.push(entry);
+    }
+
+    fn execute(&mut self, line: &str) -> Result<(u16, u16), String> {
+        let opcode = asm::assemble(line).map_err(|e| e.to_string())?;
+        // The interpreter stops on opcodes that aren't instructions
+        if disassemble(opcode).starts_with("DW") {
+            return Err(trf(
+                "{} is not an instruction",
+                &[&format!("{:#06X}", opcode)],
+            ));
+        }
+        let addr = self.chip8.pc;
+        if addr as usize + 1 >= self.chip8.memory.len() {
+            return Err(trf(
+                "PC {} is past the end of memory",
+                &[&format!("{:#05X}", addr)],
+            ));
+        }
+        self.chip8.memory[addr as usize] = (opcode >> 8) as u8;
+        self.chip8.memory[addr as usize + 1] = opcode as u8;
+        self.chip8.run_cycle().map_err(|e| i18n::chip8_error(&e))?;
+        Ok((addr, opcode))
+    }
+}
+
+// Registers pane, refreshed after every instruction
+struct Registers<'a>(&'a Chip8);
+
+impl Widget for Registers<'_> {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        let chip8 = self.0;
+        let mut lines: Vec<Line> = chip8
+            .v
+            .chunks(4)
+            .enumerate()
+            .map(|(row, registers)| {
+                let registers: Vec<String> = registers
+                    .iter()
+                    .enumerate()
+                    .map(|(column, value)| format!("V{:X} {:02X}", row * 4 + column, value))
+                    .collect();
+                Line::raw(registers.join("  "))
+            })
+            .collect();
+        lines.push(Line::raw(""));
+        lines.push(Line::raw(format!(
+            "I  {:03X}  PC {:03X}  SP {:X}",
+            chip8.i, chip8.pc, chip8.sp
+        )));
+        lines.push(Line::raw(format!(
+            "DT {:02X}   ST {:02X}",
+            chip8.dt, chip8.st
+        )));
+        Paragraph::new(lines)
+            .block(Block::bordered().title(tr(" Registers ")))
+            .render(area, buf);
+    }
+}
+
+// Assembled instructions and errors, newest at the bottom
+struct History<'a>(&'a [Entry]);
+
+impl Widget for History<'_> {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        let rows = area.height.saturating_sub(2) as usize;
+        let lines: Vec<Line> = self.0[self.0.len().saturating_sub(rows)..]
+            .iter()
+            .map(|entry| match entry {
+                Entry::Executed { addr, opcode } => Line::raw(format!(
+                    "{:03X}  {:04X}  {}",
+                    addr,
+                    opcode,
+                    disassemble(*opcode)
+                )),
+                Entry::Error { line, message } => Line::raw(format!("{}  <- {}", line, message)),
+                Entry::Note(note) => Line::raw(note.as_str()),
+            })
+            .collect();
+        Paragraph::new(lines)
+            .block(Block::bordered().title(tr(
+                " History (:reset to start again, :save and :source <file> for scripts) ",
+            )))
+            .render(area, buf);
+    }
+}
+
+pub fn run(terminal: &mut DefaultTerminal) -> io::Result<()> {
+    let renderer = Capabilities::detect().renderer();
+    let frame_duration = Duration::from_secs(1) / FRAME_RATE;
+    let mut next_frame = Instant::now() + frame_duration;
+    let mut repl = Repl::new();
+
+    loop {
+        terminal.draw(|frame| {
+            let [top, history_area, input_area] = Layout::vertical([
+                Constraint::Length(DISPLAY_HEIGHT as u16 + 2),
+                Constraint::Fill(1),
+                Constraint::Length(3),
+            ])
+            .areas(frame.area());
+            let [display_area, registers_area] =
+                Layout::horizontal([Constraint::Fill(1), Constraint::Length(PANE_WIDTH)])
+                    .areas(top);
+
+            frame.render_widget(
+                Chip8DisplayWidget::new(&repl.chip8.display)
+                    .renderer(renderer)
+                    .pixel_width(2)
+                    .block(Block::bordered().title(tr(" Display "))),
+                display_area,
+            );
+            frame.render_widget(Registers(&repl.chip8), registers_area);
+            frame.render_widget(History(&repl.history), history_area);
+            frame.render_widget(
+                Paragraph::new(format!("> {}_", repl.input)).block(
+                    Block::bordered().title(tr(" Instruction (Enter to execute, Esc to quit) ")),
+                ),
+                input_area,
+            );
+        })?;
+
+        // The timers keep running between instructions
+        let timeout = next_frame.saturating_duration_since(Instant::now());
+        if event::poll(timeout)? {
+            if let Event::Key(key) = event::read()? {
+                if key.kind != KeyEventKind::Press {
+                    continue;
+                }
+                match key.code {
+                    KeyCode::Esc => return Ok(()),
+                    KeyCode::Enter => repl.submit(),
+                    KeyCode::Backspace => {
+                        repl.input.pop();
+                    }
+                    KeyCode::Char(c) => repl.input.push(c),
+                    _ => {}
+                }
+            }
+        }
+        if Instant::now() >= next_frame {
+            repl.chip8.clock_timers(FRAME_RATE);
+            next_frame += frame_duration;
+            if next_frame < Instant::now() {
+                next_frame = Instant::now() + frame_duration;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn submit(repl: &mut Repl, line: &str) {
+        repl.input = line.to_string();
+        repl.submit();
+    }
+
+    #[test]
+    fn test_execute() {
+        let mut repl = Repl::new();
+        submit(&mut repl, "LD V0, 10");
+        submit(&mut repl, "ADD V0, 5");
+        assert_eq!(repl.chip8.v[0], 15);
+        assert_eq!(repl.chip8.pc, 0x204);
+        assert_eq!(repl.chip8.memory[0x202..0x204], [0x70, 0x05]);
+
+        // Jumps move where the next instruction is written
+        submit(&mut repl, "JP 0x300");
+        submit(&mut repl, "LD I, 0x123");
+        assert_eq!(repl.chip8.i, 0x123);
+        assert_eq!(repl.chip8.pc, 0x302);
+        assert!(matches!(
+            repl.history.last(),
+            Some(Entry::Executed {
+                addr: 0x300,
+                opcode: 0xA123
+            })
+        ));
+    }
+
+    #[test]
+    fn test_errors() {
+        let mut repl = Repl::new();
+        submit(&mut repl, "LD V0");
+        submit(&mut repl, "DW 0xFFFF");
+        assert_eq!(repl.history.len(), 2);
+        assert!(matches!(repl.history[1], Entry::Error { .. }));
+        // Nothing was written or executed
+        assert_eq!(repl.chip8.pc, 0x200);
+
+        submit(&mut repl, "LD V1, 1");
+        submit(&mut repl, ":reset");
+        assert!(repl.history.is_empty());
+        assert_eq!(repl.chip8.v[1], 0);
+    }
+
+    #[test]
+    fn test_session_script() {
+        let path = std::env::temp_dir().join(format!("rustc8-session-{}.rc", std::process::id()));
+        let path = path.to_string_lossy();
+        let mut repl = Repl::new();
+        submit(&mut repl, "LD V0, 10");
+        submit(&mut repl, ":reset");
+        submit(&mut repl, "ADD V0, 5");
+        submit(&mut repl, &format!(":save {}", path));
+        assert!(matches!(repl.history.last(), Some(Entry::Note(_))));
+        assert_eq!(
+            std::fs::read_to_string(&*path).unwrap(),
+            "LD V0, 10\n:reset\nADD V0, 5\n"
+        );
+
+        // Sourcing the script in another session gets back to the same state
+        let mut replay = Repl::new();
+        submit(&mut replay, &format!(":source {}", path));
+        assert_eq!(replay.chip8.v[0], 5);
+        assert_eq!(replay.session, repl.session);
+
+        std::fs::write(&*path, format!("# Nested\n:source {}\n", path)).unwrap();
+        submit(&mut replay, &format!(":source {}", path));
+        assert!(matches!(replay.history.last(), Some(Entry::Error { .. })));
+        std::fs::remove_file(&*path).unwrap();
+    }
+}
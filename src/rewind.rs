@@ -0,0 +1,240 @@
+use crate::{
+    chip8::Chip8,
+    savestate::{self, SaveStateError},
+};
+use std::collections::VecDeque;
+
+/*
+Rewind
+The states of the last frames, to go back in time. Keeping every frame as a save state would be
+over 6k a frame (64k on XO-CHIP), a few seconds in a megabyte, but from one frame to the next only
+the registers, the timers and a few bytes of memory and display change. So only the newest state
+is kept whole, and each older one as its difference with the state after it:
+- the two states XORed, which leaves zeros wherever they are the same
+- run-length encoded as pairs of runs: the number of equal bytes to skip and the number of changed
+  bytes that follow them, both LEB128, then the changed bytes XORed
+A state of another size, e.g. after the display switched to HiRes, is kept whole instead.
+Going back a frame XORs the newest state with one difference, so scrubbing through the history
+costs a frame of work per frame, not a replay from a keyframe. The oldest frames are dropped to
+keep the buffer under its budget.
+Only what a save state holds is rewound, see savestate.
+*/
+pub struct RewindBuffer {
+    // The newest state, savestate::encode
+    newest: Vec<u8>,
+    // How to get each state from the one after it, oldest first
+    deltas: VecDeque<Delta>,
+    // Bytes held by the deltas, see Delta::size
+    size: usize,
+    budget: usize,
+}
+
+enum Delta {
+    Xor(Vec<u8>),
+    Whole(Vec<u8>),
+}
+
+impl Delta {
+    // Bytes held, the bookkeeping included as it outweighs the data of most frames
+    fn size(&self) -> usize {
+        let data = match self {
+            Delta::Xor(data) | Delta::Whole(data) => data.len(),
+        };
+        data + std::mem::size_of::<Delta>()
+    }
+}
+
+impl RewindBuffer {
+    // A buffer keeping the frames that fit in budget bytes
+    pub fn new(budget: usize) -> Self {
+        RewindBuffer {
+            newest: Vec::new(),
+            deltas: VecDeque::new(),
+            size: 0,
+            budget,
+        }
+    }
+
+    // Record the state at the end of a frame
+    pub fn push(&mut self, chip8: &Chip8) {
+        let state = savestate::encode(chip8, 0);
+        if !self.newest.is_empty() {
+            let delta = match self.newest.len() == state.len() {
+                true => Delta::Xor(compress(&self.newest, &state)),
+                false => Delta::Whole(std::mem::take(&mut self.newest)),
+            };
+            self.size += delta.size();
+            self.deltas.push_back(delta);
+        }
+        self.newest = state;
+        while self.size + self.newest.len() > self.budget {
+            let Some(oldest) = self.deltas.pop_front() else {
+                break;
+            };
+            self.size -= oldest.size();
+        }
+    }
+
+    // Go back up to frames frames before the newest state and load that state, returns how many
+    // frames it went back. The frames after it are forgotten
+    pub fn rewind(&mut self, frames: usize, chip8: &mut Chip8) -> Result<usize, SaveStateError> {
+        let frames = frames.min(self.deltas.len());
+        for _ in 0..frames {
+            let Some(delta) = self.deltas.pop_back() else {
+                break;
+            };
+            self.size -= delta.size();
+            match delta {
+                Delta::Xor(data) => decompress(&data, &mut self.newest),
+                Delta::Whole(state) => self.newest = state,
+            }
+        }
+        if !self.newest.is_empty() {
+            savestate::decode(&self.newest, 0, chip8)?;
+        }
+        Ok(frames)
+    }
+
+    // Frames that can be rewound
+    pub fn len(&self) -> usize {
+        self.deltas.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.deltas.is_empty()
+    }
+
+    // Bytes held, the newest state included
+    pub fn size(&self) -> usize {
+        self.size + self.newest.len()
+    }
+
+    pub fn clear(&mut self) {
+        self.newest.clear();
+        self.deltas.clear();
+        self.size = 0;
+    }
+}
+
+// The runs of bytes that differ between two states of the same size, see RewindBuffer
+fn compress(old: &[u8], new: &[u8]) -> Vec<u8> {
+    let mut data = Vec::new();
+    let mut pos = 0;
+    while pos < old.len() {
+        let skip = (pos..old.len())
+            .find(|i| old[*i] != new[*i])
+            .unwrap_or(old.len());
+        if skip == old.len() {
+            break;
+        }
+        let end = (skip..old.len())
+            .find(|i| old[*i] == new[*i])
+            .unwrap_or(old.len());
+        write_leb128(&mut data, skip - pos);
+        write_leb128(&mut data, end - skip);
+        data.extend((skip..end).map(|i| old[i] ^ new[i]));
+        pos = end;
+    }
+    data
+}
+
+// XOR the runs back into the state
+fn decompress(mut data: &[u8], state: &mut [u8]) {
+    let mut pos = 0;
+    while !data.is_empty() {
+        pos += read_leb128(&mut data);
+        let len = read_leb128(&mut data);
+        for (byte, xor) in state[pos..pos + len].iter_mut().zip(&data[..len]) {
+            *byte ^= xor;
+        }
+        data = &data[len..];
+        pos += len;
+    }
+}
+
+fn write_leb128(data: &mut Vec<u8>, mut value: usize) {
+    while value >= 0x80 {
+        data.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    data.push(value as u8);
+}
+
+fn read_leb128(data: &mut &[u8]) -> usize {
+    let mut value = 0;
+    let mut shift = 0;
+    while let Some((byte, rest)) = data.split_first() {
+        *data = rest;
+        value |= ((byte & 0x7F) as usize) << shift;
+        shift += 7;
+        if byte & 0x80 == 0 {
+            break;
+        }
+    }
+    value
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rewind() {
+        let mut chip8 = Chip8::new();
+        // LD V0, 1; ADD V0, 1; LD [I], V0 at I = 0x300; JP 0x202
+        chip8
+            .load_rom(&[0x60, 0x01, 0xA3, 0x00, 0x70, 0x01, 0xF0, 0x55, 0x12, 0x04])
+            .unwrap();
+        let mut buffer = RewindBuffer::new(1 << 20);
+        let mut states = Vec::new();
+        for _ in 0..100 {
+            chip8.run_cycle().unwrap();
+            buffer.push(&chip8);
+            states.push(chip8.clone());
+        }
+        assert_eq!(buffer.len(), 99);
+        // The deltas are a few bytes each rather than whole states
+        assert!(buffer.size() < savestate::encode(&chip8, 0).len() + 99 * 64);
+
+        assert_eq!(buffer.rewind(10, &mut chip8), Ok(10));
+        assert_eq!(chip8.state_hash(), states[89].state_hash());
+        assert_eq!(buffer.rewind(1000, &mut chip8), Ok(89));
+        assert_eq!(chip8.state_hash(), states[0].state_hash());
+        assert!(buffer.is_empty());
+
+        // A change of display size is kept whole
+        chip8.enable_hires();
+        buffer.push(&chip8);
+        assert_eq!(buffer.rewind(1, &mut chip8), Ok(1));
+        assert_eq!(chip8.display_height(), 32);
+    }
+
+    #[test]
+    fn test_budget() {
+        let mut chip8 = Chip8::new();
+        let state_size = savestate::encode(&chip8, 0).len();
+        let mut buffer = RewindBuffer::new(state_size + 1024);
+        for dt in 0..100 {
+            chip8.dt = dt;
+            buffer.push(&chip8);
+        }
+        assert!(buffer.size() <= state_size + 1024);
+        assert!(buffer.len() > 10);
+        buffer.clear();
+        assert_eq!(buffer.size(), 0);
+    }
+
+    #[test]
+    fn test_compress() {
+        let old = vec![0u8; 300];
+        let mut new = old.clone();
+        new[0] = 1;
+        new[200..203].copy_from_slice(&[1, 2, 3]);
+        let data = compress(&old, &new);
+        assert_eq!(data.len(), 2 + 1 + 3 + 3);
+        let mut state = old.clone();
+        decompress(&data, &mut state);
+        assert_eq!(state, new);
+        assert!(compress(&old, &old).is_empty());
+    }
+}
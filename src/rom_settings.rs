@@ -0,0 +1,130 @@
+use rustc8::storage::Storage;
+use serde_json::{Map, Value};
+use std::io;
+
+const SETTINGS_KEY: &str = "roms.json";
+
+/*
+Per-ROM settings
+Settings changed from the pause menu can be saved for the ROM, and are used the next time it's
+loaded. They are kept in roms.json in the storage (the config directory), keyed by the CRC-32 of the ROM so
+that they follow it when the file is renamed or moved:
+
+    {"1a2b3c4d": {"name": "pong.ch8", "ipf": 15, "quirks": "chip8", "renderer": "braille",
+     "palette": "amber", "key_repeat": "filter"}}
+
+The name is only there to make the file readable. Settings that aren't saved are left as they
+are, e.g. a ROM saved with only a speed keeps the renderer picked for the terminal.
+*/
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct RomSettings {
+    pub ipf: Option<u32>,
+    // Name of a quirks preset, or "default"
+    pub quirks: Option<String>,
+    pub renderer: Option<String>,
+    // Name of a palette, see palette
+    pub palette: Option<String>,
+    // "keep" or "filter", see input::KeyRepeat
+    pub key_repeat: Option<String>,
+}
+
+fn key(rom_crc: u32) -> String {
+    format!("{:08x}", rom_crc)
+}
+
+// The settings saved for the ROM, none if there are none or the file can't be read
+pub fn load(storage: &dyn Storage, rom_crc: u32) -> RomSettings {
+    let entries = read(storage).unwrap_or_default();
+    let Some(entry) = entries.get(&key(rom_crc)) else {
+        return RomSettings::default();
+    };
+    let text = |name: &str| entry.get(name)?.as_str().map(str::to_string);
+    RomSettings {
+        ipf: entry
+            .get("ipf")
+            .and_then(Value::as_u64)
+            .and_then(|ipf| ipf.try_into().ok())
+            .filter(|ipf| *ipf > 0),
+        quirks: text("quirks"),
+        renderer: text("renderer"),
+        palette: text("palette"),
+        key_repeat: text("key_repeat"),
+    }
+}
+
+// Replace the settings of the ROM, keeping those of the other ROMs
+pub fn save(
+    storage: &mut dyn Storage,
+    rom_crc: u32,
+    name: &str,
+    settings: &RomSettings,
+) -> io::Result<()> {
+    let mut entries = match storage.exists(SETTINGS_KEY) {
+        true => read(storage).ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidData, "the file is not valid JSON")
+        })?,
+        false => Map::new(),
+    };
+    let mut entry = Map::new();
+    entry.insert("name".to_string(), name.into());
+    if let Some(ipf) = settings.ipf {
+        entry.insert("ipf".to_string(), ipf.into());
+    }
+    if let Some(quirks) = &settings.quirks {
+        entry.insert("quirks".to_string(), quirks.as_str().into());
+    }
+    if let Some(renderer) = &settings.renderer {
+        entry.insert("renderer".to_string(), renderer.as_str().into());
+    }
+    if let Some(palette) = &settings.palette {
+        entry.insert("palette".to_string(), palette.as_str().into());
+    }
+    if let Some(key_repeat) = &settings.key_repeat {
+        entry.insert("key_repeat".to_string(), key_repeat.as_str().into());
+    }
+    entries.insert(key(rom_crc), Value::Object(entry));
+
+    let json = serde_json::to_string_pretty(&entries).map_err(io::Error::other)?;
+    storage.write(SETTINGS_KEY, json.as_bytes())
+}
+
+fn read(storage: &dyn Storage) -> Option<Map<String, Value>> {
+    let json = storage.read(SETTINGS_KEY).ok()?;
+    match serde_json::from_slice(&json).ok()? {
+        Value::Object(entries) => Some(entries),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rustc8::storage::MemoryStorage;
+
+    #[test]
+    fn test_save_and_load() {
+        let mut storage = MemoryStorage::new();
+        assert_eq!(load(&storage, 1), RomSettings::default());
+
+        let pong = RomSettings {
+            ipf: Some(15),
+            quirks: Some("chip8".to_string()),
+            renderer: None,
+            palette: Some("amber".to_string()),
+            key_repeat: Some("filter".to_string()),
+        };
+        save(&mut storage, 1, "pong.ch8", &pong).unwrap();
+        let other = RomSettings {
+            renderer: Some("braille".to_string()),
+            ..RomSettings::default()
+        };
+        save(&mut storage, 2, "other.ch8", &other).unwrap();
+        assert_eq!(load(&storage, 1), pong);
+        assert_eq!(load(&storage, 2), other);
+        assert_eq!(load(&storage, 3), RomSettings::default());
+
+        // Files that aren't settings are kept rather than overwritten
+        storage.write(SETTINGS_KEY, b"not json").unwrap();
+        assert!(save(&mut storage, 1, "pong.ch8", &pong).is_err());
+    }
+}
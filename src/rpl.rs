@@ -0,0 +1,51 @@
+use rustc8::{chip8::RPL_FLAGS_SIZE, storage::Storage};
+use std::io;
+
+const DIR: &str = "flags";
+
+/*
+RPL user flags
+The flags saved by Fx75 are kept for each ROM as flags/<CRC-32 of the ROM>.rpl in the storage,
+the config directory of the frontend, so high scores saved by SCHIP games are there the next time
+the ROM is played. The file holds the 16 flags as bytes. They're loaded when the ROM starts and
+written after each frame that changed them.
+*/
+fn key(rom_crc: u32) -> String {
+    format!("{}/{:08x}.rpl", DIR, rom_crc)
+}
+
+// The flags saved for the ROM, all 0 if there are none or the file isn't valid
+pub fn load(storage: &dyn Storage, rom_crc: u32) -> [u8; RPL_FLAGS_SIZE] {
+    storage
+        .read(&key(rom_crc))
+        .ok()
+        .and_then(|data| data.try_into().ok())
+        .unwrap_or([0; RPL_FLAGS_SIZE])
+}
+
+pub fn save(
+    storage: &mut dyn Storage,
+    rom_crc: u32,
+    flags: &[u8; RPL_FLAGS_SIZE],
+) -> io::Result<()> {
+    storage.write(&key(rom_crc), flags)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rustc8::storage::MemoryStorage;
+
+    #[test]
+    fn test_flags() {
+        let mut storage = MemoryStorage::new();
+        assert_eq!(load(&storage, 0x1234), [0; RPL_FLAGS_SIZE]);
+        let mut flags = [0; RPL_FLAGS_SIZE];
+        flags[..3].copy_from_slice(&[9, 9, 7]);
+        save(&mut storage, 0x1234, &flags).unwrap();
+        assert_eq!(load(&storage, 0x1234), flags);
+        assert_eq!(storage.list("flags").unwrap(), ["flags/00001234.rpl"]);
+        // Flags are kept per ROM
+        assert_eq!(load(&storage, 0x5678), [0; RPL_FLAGS_SIZE]);
+    }
+}
@@ -0,0 +1,388 @@
+use crate::chip8::{
+    display_size, Chip8, DISPLAY_HEIGHT, DISPLAY_WIDTH, HIRES_DISPLAY_HEIGHT,
+    MEGACHIP_DISPLAY_HEIGHT, MEGACHIP_DISPLAY_WIDTH, STACK_DEPTH,
+};
+use std::fmt;
+
+/*
+Save states
+A snapshot of the machine as bytes, to be written to a file and loaded later (big endian):
+- magic: "RC8S"
+- version: u8, FORMAT_VERSION
+- rom_crc: u32, CRC-32 of the ROM it was saved with, so a state isn't loaded over another game
+- thumbnail: the display squeezed to 32x16 pixels (half its size on the 64x32 display),
+  1 bit per pixel row by row, most significant bit first, so states can be told apart without
+  decoding them (since version 2)
+- i, pc: u16, v: 16 * u8
+- stack_depth: u16, the levels of the stack of the layout (since version 5, 16 before)
+- stack: stack_depth * u16, sp: u16, dt, st: u8
+- timer_accumulator: u64, progress of the timer clock
+- memory_size: u32, the size of the memory layout (since version 3, 4096 before)
+- memory: memory_size bytes, keyboard: 16 bytes
+- display_height: u8, 32, 64 for HiRes CHIP-8 or 192 for MEGA-CHIP (since version 4, 32 before)
+- display: 64 * display_height bytes, 256 * 192 for MEGA-CHIP, one per pixel, see Chip8::display
+Callbacks (SYS handler, memory hooks) and settings (quirks, timer clock) are not saved,
+they are set up by the host and kept as they are when a state is loaded. A state only loads
+into a machine with the same memory size and stack depth.
+*/
+const MAGIC: &[u8; 4] = b"RC8S";
+const FORMAT_VERSION: u8 = 5;
+// Version 1 had no thumbnail, it is still loaded
+const FIRST_THUMBNAIL_VERSION: u8 = 2;
+// Versions 1 and 2 always had 4096 bytes of memory
+const FIRST_MEMORY_SIZE_VERSION: u8 = 3;
+const OLD_MEMORY_SIZE: usize = 4096;
+// Versions 1 to 3 always had a 64x32 display
+const FIRST_DISPLAY_HEIGHT_VERSION: u8 = 4;
+// Versions 1 to 4 always had 16 levels of stack
+const FIRST_STACK_DEPTH_VERSION: u8 = 5;
+pub const THUMBNAIL_WIDTH: usize = DISPLAY_WIDTH / 2;
+pub const THUMBNAIL_HEIGHT: usize = DISPLAY_HEIGHT / 2;
+const THUMBNAIL_SIZE: usize = THUMBNAIL_WIDTH * THUMBNAIL_HEIGHT / 8;
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum SaveStateError {
+    NotASaveState,
+    UnsupportedVersion(u8),
+    WrongRom,
+    // The state has this many bytes of memory, not the size of the machine's
+    WrongMemorySize(usize),
+    // The state has this many levels of stack, not the depth of the machine's
+    WrongStackDepth(usize),
+    Truncated,
+}
+
+impl fmt::Display for SaveStateError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SaveStateError::NotASaveState => write!(f, "Not a save state"),
+            SaveStateError::UnsupportedVersion(version) => {
+                write!(f, "Unsupported save state version: {}", version)
+            }
+            SaveStateError::WrongRom => write!(f, "Save state is for another ROM"),
+            SaveStateError::WrongMemorySize(size) => {
+                write!(
+                    f,
+                    "Save state is for a machine with {} bytes of memory",
+                    size
+                )
+            }
+            SaveStateError::WrongStackDepth(depth) => {
+                write!(
+                    f,
+                    "Save state is for a machine with {} levels of stack",
+                    depth
+                )
+            }
+            SaveStateError::Truncated => write!(f, "Save state is truncated"),
+        }
+    }
+}
+
+impl std::error::Error for SaveStateError {}
+
+pub fn encode(chip8: &Chip8, rom_crc: u32) -> Vec<u8> {
+    let mut data = Vec::new();
+    data.extend_from_slice(MAGIC);
+    data.push(FORMAT_VERSION);
+    data.extend_from_slice(&rom_crc.to_be_bytes());
+    for byte in thumbnail_of(&chip8.display).chunks(8) {
+        data.push(byte.iter().fold(0, |bits, pixel| bits << 1 | *pixel as u8));
+    }
+    data.extend_from_slice(&chip8.i.to_be_bytes());
+    data.extend_from_slice(&chip8.pc.to_be_bytes());
+    data.extend_from_slice(&chip8.v);
+    let stack_depth = chip8.layout.stack_depth;
+    data.extend_from_slice(&(stack_depth as u16).to_be_bytes());
+    for addr in &chip8.stack[..stack_depth] {
+        data.extend_from_slice(&addr.to_be_bytes());
+    }
+    data.extend_from_slice(&chip8.sp.to_be_bytes());
+    data.push(chip8.dt);
+    data.push(chip8.st);
+    data.extend_from_slice(&chip8.timer_accumulator.to_be_bytes());
+    data.extend_from_slice(&(chip8.memory.len() as u32).to_be_bytes());
+    data.extend_from_slice(&chip8.memory);
+    data.extend(chip8.keyboard.iter().map(|key| *key as u8));
+    data.push(chip8.display_height() as u8);
+    data.extend_from_slice(&chip8.display);
+    data
+}
+
+// CRC-32 of the ROM a state was saved with, from its header, None if it isn't a save state
+pub fn rom_crc(data: &[u8]) -> Option<u32> {
+    let mut reader = Reader { data };
+    if reader.take(MAGIC.len()).ok()? != MAGIC {
+        return None;
+    }
+    reader.u8().ok()?;
+    reader.u32().ok()
+}
+
+/*
+The display at half its size, THUMBNAIL_WIDTH x THUMBNAIL_HEIGHT pixels row by row.
+A pixel is lit when any of the 2x2 pixels it stands for is, so thin lines and small sprites
+don't disappear. Larger displays are squeezed to the same size, 2x4 pixels each for the 64x64
+display of HiRes CHIP-8 and 8x12 for MEGA-CHIP.
+*/
+pub fn thumbnail_of(display: &[u8]) -> Vec<bool> {
+    let (width, height) = display_size(display.len());
+    let columns = (width / THUMBNAIL_WIDTH).max(1);
+    let rows = (height / THUMBNAIL_HEIGHT).max(1);
+    let mut thumbnail = Vec::with_capacity(THUMBNAIL_WIDTH * THUMBNAIL_HEIGHT);
+    for y in 0..THUMBNAIL_HEIGHT {
+        for x in 0..THUMBNAIL_WIDTH {
+            let lit = (0..rows).any(|dy| {
+                let row = (y * rows + dy) * width + x * columns;
+                display[row..row + columns].iter().any(|pixel| *pixel != 0)
+            });
+            thumbnail.push(lit);
+        }
+    }
+    thumbnail
+}
+
+// The thumbnail of a state, from after its header, None for states saved before thumbnails
+pub fn thumbnail(data: &[u8]) -> Option<Vec<bool>> {
+    let mut reader = Reader { data };
+    if reader.take(MAGIC.len()).ok()? != MAGIC {
+        return None;
+    }
+    let version = reader.u8().ok()?;
+    if !(FIRST_THUMBNAIL_VERSION..=FORMAT_VERSION).contains(&version) {
+        return None;
+    }
+    reader.u32().ok()?;
+    let bytes = reader.take(THUMBNAIL_SIZE).ok()?;
+    Some(
+        bytes
+            .iter()
+            .flat_map(|byte| (0..8).map(move |bit| byte & (0x80 >> bit) != 0))
+            .collect(),
+    )
+}
+
+// Restore a state into the machine, which is left untouched if the state can't be loaded
+pub fn decode(data: &[u8], rom_crc: u32, chip8: &mut Chip8) -> Result<(), SaveStateError> {
+    let mut reader = Reader { data };
+    if reader.take(MAGIC.len())? != MAGIC {
+        return Err(SaveStateError::NotASaveState);
+    }
+    let version = reader.u8()?;
+    if version == 0 || version > FORMAT_VERSION {
+        return Err(SaveStateError::UnsupportedVersion(version));
+    }
+    if reader.u32()? != rom_crc {
+        return Err(SaveStateError::WrongRom);
+    }
+    if version >= FIRST_THUMBNAIL_VERSION {
+        reader.take(THUMBNAIL_SIZE)?;
+    }
+
+    let mut state = chip8.clone();
+    state.i = reader.u16()?;
+    state.pc = reader.u16()?;
+    state.v = reader.array()?;
+    let stack_depth = match version >= FIRST_STACK_DEPTH_VERSION {
+        true => reader.u16()? as usize,
+        false => STACK_DEPTH,
+    };
+    if stack_depth != chip8.layout.stack_depth {
+        return Err(SaveStateError::WrongStackDepth(stack_depth));
+    }
+    for addr in state.stack[..stack_depth].iter_mut() {
+        *addr = reader.u16()?;
+    }
+    state.sp = reader.u16()?;
+    state.dt = reader.u8()?;
+    state.st = reader.u8()?;
+    state.timer_accumulator = u64::from_be_bytes(reader.array()?);
+    let memory_size = match version >= FIRST_MEMORY_SIZE_VERSION {
+        true => reader.u32()? as usize,
+        false => OLD_MEMORY_SIZE,
+    };
+    if memory_size != chip8.memory.len() {
+        return Err(SaveStateError::WrongMemorySize(memory_size));
+    }
+    state.memory = reader.take(memory_size)?.to_vec();
+    // Which bytes the program wrote isn't saved, any byte holding data may have been
+    for addr in 0..state.memory.len() {
+        if state.memory[addr] != 0 {
+            state.mark_written(addr);
+        }
+    }
+    for (key, byte) in state
+        .keyboard
+        .iter_mut()
+        .zip(reader.take(chip8.keyboard.len())?)
+    {
+        *key = *byte != 0;
+    }
+    let display_height = match version >= FIRST_DISPLAY_HEIGHT_VERSION {
+        true => reader.u8()? as usize,
+        false => DISPLAY_HEIGHT,
+    };
+    // No machine has another display
+    let display_width = match display_height {
+        DISPLAY_HEIGHT | HIRES_DISPLAY_HEIGHT => DISPLAY_WIDTH,
+        MEGACHIP_DISPLAY_HEIGHT => MEGACHIP_DISPLAY_WIDTH,
+        _ => return Err(SaveStateError::NotASaveState),
+    };
+    state.display = reader.take(display_width * display_height)?.to_vec();
+    state.redraw_all();
+    *chip8 = state;
+    Ok(())
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], SaveStateError> {
+        if self.data.len() < len {
+            return Err(SaveStateError::Truncated);
+        }
+        let (bytes, rest) = self.data.split_at(len);
+        self.data = rest;
+        Ok(bytes)
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], SaveStateError> {
+        let mut array = [0; N];
+        array.copy_from_slice(self.take(N)?);
+        Ok(array)
+    }
+
+    fn u8(&mut self) -> Result<u8, SaveStateError> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, SaveStateError> {
+        Ok(u16::from_be_bytes(self.array()?))
+    }
+
+    fn u32(&mut self) -> Result<u32, SaveStateError> {
+        Ok(u32::from_be_bytes(self.array()?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chip8::MemoryLayout;
+
+    #[test]
+    fn test_round_trip() {
+        let mut chip8 = Chip8::new();
+        chip8
+            .load_rom(&[0x60, 0x05, 0xA2, 0x0A, 0xD0, 0x05])
+            .unwrap();
+        for _ in 0..3 {
+            chip8.run_cycle().unwrap();
+        }
+        chip8.dt = 30;
+        chip8.stack[0] = 0x0345;
+        chip8.key_down(0xA);
+        let data = encode(&chip8, 0x1234);
+
+        let mut loaded = Chip8::new();
+        decode(&data, 0x1234, &mut loaded).unwrap();
+        assert_eq!(loaded.state_hash(), chip8.state_hash());
+        assert_eq!(loaded.memory, chip8.memory);
+        assert_eq!(loaded.i, 0x20A);
+        assert_eq!(rom_crc(&data), Some(0x1234));
+        assert_eq!(rom_crc(b"RC8"), None);
+
+        // Version 1 states have no thumbnail, stack depth, memory size nor display height and are
+        // still loaded
+        let registers = 9 + THUMBNAIL_SIZE..9 + THUMBNAIL_SIZE + 66;
+        let display = data.len() - DISPLAY_WIDTH * DISPLAY_HEIGHT;
+        let mut old = data[..9].to_vec();
+        old[4] = 1;
+        old.extend_from_slice(&data[registers.start..registers.start + 20]);
+        old.extend_from_slice(&data[registers.start + 22..registers.end]);
+        old.extend_from_slice(&data[registers.end + 4..display - 1]);
+        old.extend_from_slice(&data[display..]);
+        let mut loaded = Chip8::new();
+        decode(&old, 0x1234, &mut loaded).unwrap();
+        assert_eq!(loaded.state_hash(), chip8.state_hash());
+        assert_eq!(thumbnail(&old), None);
+    }
+
+    #[test]
+    fn test_hires() {
+        let mut chip8 = Chip8::new();
+        chip8.enable_hires();
+        chip8.display[63 * DISPLAY_WIDTH] = 1;
+        let data = encode(&chip8, 1);
+
+        // The state brings the taller display along
+        let mut loaded = Chip8::new();
+        decode(&data, 1, &mut loaded).unwrap();
+        assert!(loaded.is_hires());
+        assert_eq!(loaded.display, chip8.display);
+        let thumbnail = thumbnail(&data).unwrap();
+        assert!(thumbnail[15 * THUMBNAIL_WIDTH]);
+
+        // And the colors of the MEGA-CHIP display
+        chip8.display = vec![0; MEGACHIP_DISPLAY_WIDTH * MEGACHIP_DISPLAY_HEIGHT];
+        chip8.display[MEGACHIP_DISPLAY_WIDTH - 1] = 7;
+        let data = encode(&chip8, 1);
+        decode(&data, 1, &mut loaded).unwrap();
+        assert_eq!(loaded.display, chip8.display);
+        assert!(thumbnail_of(&loaded.display)[THUMBNAIL_WIDTH - 1]);
+    }
+
+    #[test]
+    fn test_thumbnail() {
+        let mut chip8 = Chip8::new();
+        // A pixel lights the thumbnail pixel covering it
+        chip8.display[3 + 5 * DISPLAY_WIDTH] = 1;
+        chip8.display[63 + 31 * DISPLAY_WIDTH] = 1;
+        let thumbnail = thumbnail(&encode(&chip8, 1)).unwrap();
+        assert_eq!(thumbnail, thumbnail_of(&chip8.display));
+        let lit: Vec<usize> = (0..thumbnail.len()).filter(|i| thumbnail[*i]).collect();
+        assert_eq!(lit, [1 + 2 * THUMBNAIL_WIDTH, 31 + 15 * THUMBNAIL_WIDTH]);
+    }
+
+    #[test]
+    fn test_decode_errors() {
+        let chip8 = Chip8::new();
+        let data = encode(&chip8, 1);
+        let mut loaded = Chip8::new();
+        loaded.v[0] = 7;
+
+        assert_eq!(
+            decode(b"PNG!", 1, &mut loaded),
+            Err(SaveStateError::NotASaveState)
+        );
+        assert_eq!(decode(&data, 2, &mut loaded), Err(SaveStateError::WrongRom));
+        assert_eq!(
+            decode(&data[..100], 1, &mut loaded),
+            Err(SaveStateError::Truncated)
+        );
+        let mut newer = data.clone();
+        newer[4] = FORMAT_VERSION + 1;
+        assert_eq!(
+            decode(&newer, 1, &mut loaded),
+            Err(SaveStateError::UnsupportedVersion(FORMAT_VERSION + 1))
+        );
+        let mut xo_chip = Chip8::with_layout(MemoryLayout::XO_CHIP);
+        assert_eq!(
+            decode(&data, 1, &mut xo_chip),
+            Err(SaveStateError::WrongMemorySize(4096))
+        );
+        let mut deep = Chip8::with_layout(MemoryLayout {
+            stack_depth: 32,
+            ..MemoryLayout::CHIP8
+        });
+        assert_eq!(
+            decode(&data, 1, &mut deep),
+            Err(SaveStateError::WrongStackDepth(16))
+        );
+        assert_eq!(decode(&encode(&deep, 1), 1, &mut deep.clone()), Ok(()));
+        // Failed loads leave the machine as it was
+        assert_eq!(loaded.v[0], 7);
+    }
+}
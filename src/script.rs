@@ -0,0 +1,122 @@
+use crate::chip8::Chip8;
+
+/*
+Input scripts, given with --input-script
+A readable list of key presses and releases by frame number, to drive a game the same way on
+every run for automated tests and demos:
+
+    # Start the game, then hold 5 for a quarter of a second
+    frame 60: press 5; frame 75: release 5
+    frame 120: press a
+
+- Statements are separated by ';' or new lines, '#' starts a comment
+- Keys are the Chip-8 key values 0-F
+- A pressed key is held until it's released, see Chip8::key_down
+- Frames are counted from 0 when the ROM starts, the events of a frame apply before it runs
+*/
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct ScriptEvent {
+    frame: u64,
+    key: u8,
+    pressed: bool,
+}
+
+#[derive(Clone, Debug)]
+pub struct InputScript {
+    // Sorted by frame, events of the same frame stay in the order they were written
+    events: Vec<ScriptEvent>,
+    next: usize,
+}
+
+impl InputScript {
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut events = Vec::new();
+        for (number, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or("");
+            for statement in line.split(';').map(str::trim).filter(|s| !s.is_empty()) {
+                let event = parse_event(statement)
+                    .ok_or_else(|| format!("Line {}: invalid input: {}", number + 1, statement))?;
+                events.push(event);
+            }
+        }
+        events.sort_by_key(|event| event.frame);
+        Ok(InputScript { events, next: 0 })
+    }
+
+    // Apply the events up to the frame, called before every emulation step
+    pub fn apply(&mut self, frame: u64, chip8: &mut Chip8) {
+        while let Some(event) = self.events.get(self.next).filter(|e| e.frame <= frame) {
+            match event.pressed {
+                true => chip8.key_down(event.key),
+                false => chip8.key_up(event.key),
+            }
+            self.next += 1;
+        }
+    }
+}
+
+// "frame <n>: press|release <key>"
+fn parse_event(statement: &str) -> Option<ScriptEvent> {
+    let (frame, action) = statement.split_once(':')?;
+    let frame = frame.trim().strip_prefix("frame")?.trim().parse().ok()?;
+    let (action, key) = action.trim().split_once(char::is_whitespace)?;
+    let pressed = match action {
+        "press" => true,
+        "release" => false,
+        _ => return None,
+    };
+    let key = u8::from_str_radix(key.trim(), 16)
+        .ok()
+        .filter(|key| *key < 16)?;
+    Some(ScriptEvent {
+        frame,
+        key,
+        pressed,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let script =
+            InputScript::parse("frame 75: release 5 # done\nframe 60: press 5; frame 60: press F")
+                .unwrap();
+        let frames: Vec<(u64, u8, bool)> = script
+            .events
+            .iter()
+            .map(|e| (e.frame, e.key, e.pressed))
+            .collect();
+        assert_eq!(frames, [(60, 5, true), (60, 0xF, true), (75, 5, false)]);
+
+        assert_eq!(
+            InputScript::parse("\nframe 1: press 16").unwrap_err(),
+            "Line 2: invalid input: frame 1: press 16"
+        );
+        assert!(InputScript::parse("frame x: press 1").is_err());
+        assert!(InputScript::parse("frame 1: tap 1").is_err());
+    }
+
+    #[test]
+    fn test_apply() {
+        let mut chip8 = Chip8::new();
+        let mut script = InputScript::parse("frame 2: press 5; frame 4: release 5").unwrap();
+
+        script.apply(0, &mut chip8);
+        assert!(!chip8.keyboard[5]);
+        script.apply(2, &mut chip8);
+        assert!(chip8.keyboard[5]);
+
+        // Held until released, whatever the program does
+        chip8.load_rom(&[0x60, 0x05, 0xE0, 0xA1]).unwrap();
+        chip8.run_cycle().unwrap();
+        chip8.run_cycle().unwrap();
+        script.apply(3, &mut chip8);
+        assert!(chip8.keyboard[5]);
+
+        script.apply(4, &mut chip8);
+        assert!(!chip8.keyboard[5]);
+    }
+}
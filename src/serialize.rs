@@ -0,0 +1,214 @@
+use crate::chip8::{
+    Chip8, MemoryLayout, DISPLAY_SIZE, DISPLAY_WIDTH, FONT_SIZE, HIRES_DISPLAY_HEIGHT,
+    KEYBOARD_SIZE, MAX_MEMORY_SIZE, MAX_STACK_DEPTH, MEGACHIP_DISPLAY_SIZE, MEMORY_START,
+    REGISTERS_SIZE, RPL_FLAGS_SIZE,
+};
+use alloc::vec::Vec;
+use serde::{de::Error, Deserialize, Deserializer, Serialize, Serializer};
+
+/*
+Serde
+With the serde feature Chip8 implements Serialize and Deserialize, for hosts to persist, diff or
+send the state of the machine in any format serde supports, e.g. as JSON:
+
+    {"program_start":512,"font_addr":0,"i":522,"pc":516,"v":[0,...],"stack":[0,...],"sp":0,
+     "dt":0,"st":0,"timer_accumulator":0,"rng":null,"rpl_flags":[0,...],"memory":[240,...],
+     "keyboard":[false,...],"display":[0,...]}
+
+- program_start and font_addr: the memory layout, along with the size of memory and the levels
+  of stack, which are the lengths of memory and stack
+- i, pc, v, stack, sp, dt, st: the registers, the stack and the timers
+- timer_accumulator: progress of the timer clock, see clock_timers
+- rng: the state of the generator of RND once seeded, see seed_rng
+- rpl_flags, memory, keyboard: as in Chip8
+- display: a pixel per byte row by row, its length gives the size of the display
+As with save states, callbacks (SYS handler, hooks, observers, bus) and settings (quirks, timer
+clock, memory bounds) are not part of the state: a deserialized machine has the defaults, and
+the host sets it up again. Keys tapped with tap_key are held until key_up, and the MEGA-CHIP
+mode isn't kept.
+*/
+#[derive(Serialize)]
+struct StateRef<'a> {
+    program_start: usize,
+    font_addr: u16,
+    i: u16,
+    pc: u16,
+    v: &'a [u8; REGISTERS_SIZE],
+    stack: &'a [u16],
+    sp: u16,
+    dt: u8,
+    st: u8,
+    timer_accumulator: u64,
+    rng: Option<u64>,
+    rpl_flags: &'a [u8; RPL_FLAGS_SIZE],
+    memory: &'a [u8],
+    keyboard: &'a [bool; KEYBOARD_SIZE],
+    display: &'a [u8],
+}
+
+// StateRef as read back
+#[derive(Deserialize)]
+struct State {
+    program_start: usize,
+    font_addr: u16,
+    i: u16,
+    pc: u16,
+    v: [u8; REGISTERS_SIZE],
+    stack: Vec<u16>,
+    sp: u16,
+    dt: u8,
+    st: u8,
+    timer_accumulator: u64,
+    rng: Option<u64>,
+    rpl_flags: [u8; RPL_FLAGS_SIZE],
+    memory: Vec<u8>,
+    keyboard: [bool; KEYBOARD_SIZE],
+    display: Vec<u8>,
+}
+
+impl Serialize for Chip8 {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        StateRef {
+            program_start: self.layout.program_start,
+            font_addr: self.font_addr,
+            i: self.i,
+            pc: self.pc,
+            v: &self.v,
+            stack: &self.stack[..self.layout.stack_depth],
+            sp: self.sp,
+            dt: self.dt,
+            st: self.st,
+            timer_accumulator: self.timer_accumulator,
+            rng: self.rng,
+            rpl_flags: &self.rpl_flags,
+            memory: &self.memory,
+            keyboard: &self.keyboard,
+            display: &self.display,
+        }
+        .serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Chip8 {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let state = State::deserialize(deserializer)?;
+        // Checked here as with_layout panics on a layout it can't hold
+        let size = state.memory.len();
+        if !(MEMORY_START..=MAX_MEMORY_SIZE).contains(&size) {
+            return Err(D::Error::custom(format_args!(
+                "Invalid memory size: {}",
+                size
+            )));
+        }
+        if state.program_start > size {
+            return Err(D::Error::custom(format_args!(
+                "Invalid program start: {:#05X}",
+                state.program_start
+            )));
+        }
+        if state.font_addr as usize + FONT_SIZE > state.program_start {
+            return Err(D::Error::custom(format_args!(
+                "Invalid font address: {:#05X}",
+                state.font_addr
+            )));
+        }
+        let stack_depth = state.stack.len();
+        if !(1..=MAX_STACK_DEPTH).contains(&stack_depth) {
+            return Err(D::Error::custom(format_args!(
+                "Invalid stack depth: {}",
+                stack_depth
+            )));
+        }
+        if state.sp as usize > stack_depth {
+            return Err(D::Error::custom(format_args!(
+                "Invalid stack pointer: {}",
+                state.sp
+            )));
+        }
+        // No machine has another display
+        let display = state.display.len();
+        if ![
+            DISPLAY_SIZE,
+            DISPLAY_WIDTH * HIRES_DISPLAY_HEIGHT,
+            MEGACHIP_DISPLAY_SIZE,
+        ]
+        .contains(&display)
+        {
+            return Err(D::Error::custom(format_args!(
+                "Invalid display size: {}",
+                display
+            )));
+        }
+
+        // The font is in the memory of the state, it's only moved into place here
+        let mut chip8 = Chip8::with_layout(MemoryLayout {
+            size,
+            program_start: state.program_start,
+            font_addr: state.font_addr,
+            stack_depth,
+        });
+        chip8.i = state.i;
+        chip8.pc = state.pc;
+        chip8.v = state.v;
+        chip8.stack[..stack_depth].copy_from_slice(&state.stack);
+        chip8.sp = state.sp;
+        chip8.dt = state.dt;
+        chip8.st = state.st;
+        chip8.timer_accumulator = state.timer_accumulator;
+        chip8.rng = state.rng;
+        chip8.rpl_flags = state.rpl_flags;
+        chip8.memory = state.memory;
+        chip8.keyboard = state.keyboard;
+        chip8.display = state.display;
+        chip8.redraw_all();
+        Ok(chip8)
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        let mut chip8 = Chip8::with_layout(MemoryLayout {
+            stack_depth: 32,
+            ..MemoryLayout::XO_CHIP
+        });
+        chip8.seed_rng(7);
+        // CALL 0x204; skipped; LD I, 0x000; DRW V0, V0, 5 drawing the 0 of the font
+        chip8
+            .load_rom(&[0x22, 0x04, 0x00, 0x00, 0xA0, 0x00, 0xD0, 0x05])
+            .unwrap();
+        for _ in 0..3 {
+            chip8.run_cycle().unwrap();
+        }
+        chip8.keyboard[3] = true;
+        chip8.dt = 30;
+        chip8.rpl_flags[15] = 99;
+
+        let json = serde_json::to_string(&chip8).unwrap();
+        let state: Chip8 = serde_json::from_str(&json).unwrap();
+        assert_eq!(state.state_hash(), chip8.state_hash());
+        assert_eq!(state.layout, chip8.layout);
+        assert_eq!(state.display, chip8.display);
+        assert_eq!(state.rng, chip8.rng);
+        assert_eq!(state.rpl_flags, chip8.rpl_flags);
+        assert_eq!(serde_json::to_string(&state).unwrap(), json);
+
+        let mut value: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(value["stack"].as_array().unwrap().len(), 32);
+        assert_eq!(value["sp"], 1);
+        value["sp"] = 33.into();
+        let Err(error) = serde_json::from_value::<Chip8>(value.clone()) else {
+            panic!("A stack pointer past the stack was read");
+        };
+        assert_eq!(error.to_string(), "Invalid stack pointer: 33");
+        value["sp"] = 1.into();
+        value["display"] = serde_json::json!([0, 0]);
+        assert!(serde_json::from_value::<Chip8>(value.clone()).is_err());
+        value["display"] = serde_json::to_value(&chip8.display).unwrap();
+        value["memory"] = serde_json::to_value([0u8; 16]).unwrap();
+        assert!(serde_json::from_value::<Chip8>(value).is_err());
+    }
+}
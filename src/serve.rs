@@ -0,0 +1,933 @@
+use crate::{
+    diagnostic::Diagnostic,
+    new_core, open_rom, parse_args,
+    spectate::SpectatorServer,
+    websocket::{Message, WebSocket},
+    Options,
+};
+use rustc8::{
+    chip8::{Chip8, TAP_FRAMES},
+    timing::{FrameBudget, FRAME_RATE},
+};
+use serde_json::{json, Value};
+use std::{
+    fmt::Write as _,
+    io::{ErrorKind, Read, Write},
+    net::{TcpListener, TcpStream},
+    thread,
+    time::{Duration, Instant},
+};
+
+const SERVE_USAGE: &str = "Usage: cargo run serve <ROM file> [--bind <address>] [options of running a ROM, e.g. --platform, --ipf, --quirks, --seed or --spectate <address>]";
+const DEFAULT_BIND: &str = "127.0.0.1:8080";
+// HTTP requests larger than this are refused and those not complete in REQUEST_TIMEOUT dropped
+const MAX_REQUEST_SIZE: usize = 64 * 1024;
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+// HTTP responses the client hasn't taken in WRITE_TIMEOUT are dropped
+const WRITE_TIMEOUT: Duration = Duration::from_secs(5);
+// Statuses of the requests refused before they're handled
+const BAD_REQUEST: &str = "400 Bad Request";
+const PAYLOAD_TOO_LARGE: &str = "413 Payload Too Large";
+// The page watching and controlling the machine
+const PAGE: &str = include_str!("../web/serve.html");
+
+// JSON-RPC error codes
+const PARSE_ERROR: i64 = -32700;
+const INVALID_REQUEST: i64 = -32600;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+
+// Who sent a request, for the keys each holds
+#[derive(Clone, Copy)]
+enum Client {
+    // POST /rpc, keys are held until key_up
+    Http,
+    Watcher(usize),
+}
+
+// An HTTP connection still sending its request
+struct Pending {
+    stream: TcpStream,
+    data: Vec<u8>,
+    since: Instant,
+}
+
+// An HTTP response still being written, the connection is closed once it's all written
+struct Response {
+    stream: TcpStream,
+    output: Vec<u8>,
+    since: Instant,
+}
+
+// A browser connected to /ws
+struct Watcher {
+    socket: WebSocket,
+    keys: [bool; 16],
+    // Set when a frame couldn't be sent, or it hasn't had one yet
+    needs_frame: bool,
+}
+
+// Counted since the server started, see metrics
+#[derive(Default)]
+struct Counters {
+    frames: u64,
+    cycles: u64,
+    rpc_requests: u64,
+    frames_sent: u64,
+    frames_skipped: u64,
+    resets: u64,
+    errors: u64,
+}
+
+/*
+Server, `rustc8 serve <rom>`
+Runs a ROM headlessly for as long as the process lives and shares it over HTTP, for hosting a
+machine that several people watch and play at once, e.g. a terminal arcade. Everything is on
+one address (--bind, 127.0.0.1:8080 by default):
+- GET /: a page showing the display and sending the keys, see web/serve.html
+- GET /ws: a WebSocket streaming the display, a binary message per frame (the width and height
+  as big endian u16, then a byte per pixel, 0 when unlit), and taking JSON-RPC 2.0 requests as
+  text messages. The server notifies watchers with a "status" notification when the status
+  changes, e.g. the buzzer starting
+- POST /rpc: a JSON-RPC 2.0 request, for scripts
+- GET /metrics: counters and gauges in the Prometheus text format
+The machine is set up from the options of running a ROM, e.g. --platform, --ipf or --quirks, and
+the settings saved for the ROM, as it would be in the terminal; the options of the terminal
+itself are ignored.
+The methods are key_down and key_up {"key": 0-15}, tap {"key"} holding a key for a moment,
+pause, resume, reset restarting the ROM, status, and state giving the registers. A key is held
+while any client holds it, and the keys of a watcher are released when it leaves. Terminals
+can watch as well with --spectate, see spectate.
+Sockets don't block and the server polls them once a frame, on the thread running the machine:
+watchers that can't keep up skip frames. There is no authentication, anyone who can reach the
+address can control the machine.
+*/
+pub fn run(args: &[String]) -> Result<(), Diagnostic> {
+    let mut bind = DEFAULT_BIND.to_string();
+    let mut rest = Vec::new();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--bind" => bind = args.next().ok_or("Missing value for --bind")?.clone(),
+            _ => rest.push(arg.clone()),
+        }
+    }
+    if rest.is_empty() {
+        return Err(SERVE_USAGE.into());
+    }
+    let mut options =
+        parse_args(&rest).map_err(|message| format!("{}\n{}", message, SERVE_USAGE))?;
+    let (rom, _) = open_rom(&mut options)?;
+    let listener = TcpListener::bind(&bind)
+        .map_err(|e| Diagnostic::io(format!("Failed to listen on {}: {}", bind, e)))?;
+    let spectate = options.spectate;
+    let path = options.path.clone();
+    let mut server = Server::new(listener, rom, options)?;
+    let mut spectators = spectate
+        .map(SpectatorServer::bind)
+        .transpose()
+        .map_err(|e| Diagnostic::io(format!("Failed to listen for spectators: {}", e)))?;
+    println!("Serving {} on http://{}", path, bind);
+
+    let frame = Duration::from_secs(1) / FRAME_RATE;
+    let mut next = Instant::now();
+    loop {
+        server.poll();
+        let drawn = server.run_frame();
+        if let Some(spectators) = &mut spectators {
+            spectators.update(&server.chip8.display, drawn);
+            server.spectators = spectators.viewers();
+        }
+        // A server that fell behind, e.g. suspended, carries on from now rather than catching up
+        next += frame;
+        match next.checked_duration_since(Instant::now()) {
+            Some(wait) => thread::sleep(wait),
+            None => next = Instant::now(),
+        }
+    }
+}
+
+struct Server {
+    listener: TcpListener,
+    rom: Vec<u8>,
+    options: Options,
+    chip8: Chip8,
+    budget: FrameBudget,
+    paused: bool,
+    // The error that stopped the machine, until it's reset
+    error: Option<String>,
+    pending: Vec<Pending>,
+    responses: Vec<Response>,
+    watchers: Vec<Watcher>,
+    // Terminals watching, see --spectate
+    spectators: usize,
+    http_keys: [bool; 16],
+    // The status last sent to the watchers
+    status: Value,
+    counters: Counters,
+    started: Instant,
+}
+
+impl Server {
+    // The ROM has been through open_rom
+    fn new(listener: TcpListener, rom: Vec<u8>, options: Options) -> Result<Self, Diagnostic> {
+        listener
+            .set_nonblocking(true)
+            .map_err(|e| Diagnostic::io(e.to_string()))?;
+        let chip8 = new_core(&options, &rom);
+        let budget = FrameBudget::new(options.timing, options.ipf);
+        Ok(Server {
+            listener,
+            rom,
+            options,
+            chip8,
+            budget,
+            paused: false,
+            error: None,
+            pending: Vec::new(),
+            responses: Vec::new(),
+            watchers: Vec::new(),
+            spectators: 0,
+            http_keys: [false; 16],
+            status: Value::Null,
+            counters: Counters::default(),
+            started: Instant::now(),
+        })
+    }
+
+    // Accept connections and answer what the clients sent
+    fn poll(&mut self) {
+        while let Ok((stream, _)) = self.listener.accept() {
+            if stream.set_nonblocking(true).is_ok() {
+                self.pending.push(Pending {
+                    stream,
+                    data: Vec::new(),
+                    since: Instant::now(),
+                });
+            }
+        }
+
+        for mut pending in std::mem::take(&mut self.pending) {
+            let mut buffer = [0; 4096];
+            // Read no further than a request can go, a client that keeps writing would hold up
+            // the machine
+            let closed = loop {
+                if pending.data.len() > MAX_REQUEST_SIZE {
+                    break false;
+                }
+                match pending.stream.read(&mut buffer) {
+                    Ok(0) => break true,
+                    Ok(len) => pending.data.extend_from_slice(&buffer[..len]),
+                    Err(e) if e.kind() == ErrorKind::WouldBlock => break false,
+                    Err(e) if e.kind() == ErrorKind::Interrupted => {}
+                    Err(_) => break true,
+                }
+            };
+            match parse_request(&pending.data) {
+                Ok(Some(request)) => self.handle(pending.stream, request),
+                Err(status) => self.respond(pending.stream, status, "text/plain", status),
+                // Headers that don't end within the limit
+                Ok(None) if pending.data.len() > MAX_REQUEST_SIZE => {
+                    let status = PAYLOAD_TOO_LARGE;
+                    self.respond(pending.stream, status, "text/plain", status)
+                }
+                Ok(None) if closed || pending.since.elapsed() > REQUEST_TIMEOUT => {}
+                Ok(None) => self.pending.push(pending),
+            }
+        }
+        self.responses.retain_mut(|response| {
+            write_available(&mut response.stream, &mut response.output)
+                && !response.output.is_empty()
+                && response.since.elapsed() <= WRITE_TIMEOUT
+        });
+
+        for index in 0..self.watchers.len() {
+            for message in self.watchers[index].socket.receive() {
+                // Binary messages mean nothing to the server
+                let Message::Text(text) = message else {
+                    continue;
+                };
+                if let Some(response) = self.rpc(Client::Watcher(index), &text) {
+                    let socket = &mut self.watchers[index].socket;
+                    socket.send(Message::Text(response.to_string()));
+                }
+            }
+        }
+        let mut left = Vec::new();
+        self.watchers.retain(|watcher| {
+            if !watcher.socket.is_open() {
+                left.push(watcher.keys);
+            }
+            watcher.socket.is_open()
+        });
+        for keys in left {
+            for (key, held) in keys.into_iter().enumerate() {
+                if held {
+                    self.release(key as u8);
+                }
+            }
+        }
+    }
+
+    fn handle(&mut self, stream: TcpStream, request: Request) {
+        match (request.method, request.path) {
+            ("GET", "/") => self.respond(stream, "200 OK", "text/html; charset=utf-8", PAGE),
+            ("GET", "/metrics") => {
+                let metrics = self.metrics();
+                self.respond(stream, "200 OK", "text/plain; version=0.0.4", &metrics);
+            }
+            ("POST", "/rpc") => {
+                let body = String::from_utf8_lossy(request.body);
+                match self.rpc(Client::Http, &body) {
+                    Some(response) => {
+                        self.respond(stream, "200 OK", "application/json", &response.to_string())
+                    }
+                    // A notification has no response
+                    None => self.respond(stream, "204 No Content", "text/plain", ""),
+                }
+            }
+            ("GET", "/ws") => {
+                let upgrade = request
+                    .header("upgrade")
+                    .is_some_and(|upgrade| upgrade.eq_ignore_ascii_case("websocket"));
+                match (upgrade, request.header("sec-websocket-key")) {
+                    (true, Some(key)) => {
+                        if let Ok(socket) = WebSocket::accept(stream, key) {
+                            self.watchers.push(Watcher {
+                                socket,
+                                keys: [false; 16],
+                                needs_frame: true,
+                            });
+                        }
+                    }
+                    _ => self.respond(stream, BAD_REQUEST, "text/plain", "Expected a WebSocket"),
+                }
+            }
+            _ => self.respond(stream, "404 Not Found", "text/plain", "Not found"),
+        }
+    }
+
+    // Answer and close an HTTP connection, what the client doesn't take at once is written by poll
+    fn respond(&mut self, mut stream: TcpStream, status: &str, content_type: &str, body: &str) {
+        let mut output = format!(
+            "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            status,
+            content_type,
+            body.len(),
+            body
+        )
+        .into_bytes();
+        if write_available(&mut stream, &mut output) && !output.is_empty() {
+            self.responses.push(Response {
+                stream,
+                output,
+                since: Instant::now(),
+            });
+        }
+    }
+
+    // Run a frame unless paused or stopped, send it to the watchers, returns whether it drew
+    fn run_frame(&mut self) -> bool {
+        if !self.paused && self.error.is_none() {
+            let cycles = self.chip8.cycles_executed();
+            if let Err(e) = self.chip8.run_frame(&mut self.budget, FRAME_RATE) {
+                self.error = Some(e.to_string());
+                self.counters.errors += 1;
+            }
+            self.counters.frames += 1;
+            self.counters.cycles += self.chip8.cycles_executed() - cycles;
+        }
+
+        let drawn = self.chip8.take_frame().is_some();
+        let mut frame = None;
+        for watcher in &mut self.watchers {
+            if !drawn && !watcher.needs_frame {
+                continue;
+            }
+            // A watcher still taking the last frame skips to the latest once it's done
+            if !watcher.socket.is_idle() {
+                watcher.needs_frame = true;
+                self.counters.frames_skipped += 1;
+                continue;
+            }
+            let frame = frame.get_or_insert_with(|| encode_frame(&self.chip8));
+            watcher.socket.send(Message::Binary(frame.clone()));
+            watcher.needs_frame = false;
+            self.counters.frames_sent += 1;
+        }
+
+        let status = self.status();
+        if status != self.status {
+            let notification = json!({"jsonrpc": "2.0", "method": "status", "params": status});
+            for watcher in &mut self.watchers {
+                watcher.socket.send(Message::Text(notification.to_string()));
+            }
+            self.status = status;
+        }
+        drawn
+    }
+
+    // The response to a JSON-RPC request, none for a notification
+    fn rpc(&mut self, client: Client, text: &str) -> Option<Value> {
+        self.counters.rpc_requests += 1;
+        let Ok(request) = serde_json::from_str::<Value>(text) else {
+            return Some(error(Value::Null, PARSE_ERROR, "Parse error"));
+        };
+        let id = request.get("id").cloned();
+        let Some(method) = request.get("method").and_then(Value::as_str) else {
+            return Some(error(
+                id.unwrap_or_default(),
+                INVALID_REQUEST,
+                "Invalid request",
+            ));
+        };
+        let result = self.call(client, method, &request["params"]);
+        let id = id?;
+        Some(match result {
+            Ok(result) => json!({"jsonrpc": "2.0", "id": id, "result": result}),
+            Err((code, message)) => error(id, code, &message),
+        })
+    }
+
+    fn call(
+        &mut self,
+        client: Client,
+        method: &str,
+        params: &Value,
+    ) -> Result<Value, (i64, String)> {
+        let key = || {
+            params["key"]
+                .as_u64()
+                .filter(|key| *key < 16)
+                .map(|key| key as u8)
+                .ok_or((INVALID_PARAMS, "Expected a key from 0 to 15".to_string()))
+        };
+        match method {
+            "key_down" => {
+                let key = key()?;
+                self.keys(client)[key as usize] = true;
+                self.chip8.key_down(key);
+            }
+            "key_up" => {
+                let key = key()?;
+                self.keys(client)[key as usize] = false;
+                self.release(key);
+            }
+            "tap" => self.chip8.tap_key(key()?, TAP_FRAMES),
+            "pause" => self.paused = true,
+            "resume" => self.paused = false,
+            "reset" => self.reset(),
+            "status" => return Ok(self.status()),
+            "state" => {
+                let chip8 = &self.chip8;
+                return Ok(json!({
+                    "pc": chip8.pc,
+                    "i": chip8.i,
+                    "v": chip8.v,
+                    "stack": chip8.stack[..chip8.sp as usize],
+                    "dt": chip8.dt,
+                    "st": chip8.st,
+                    "frame": chip8.frames_elapsed(),
+                    "cycles": chip8.cycles_executed(),
+                    "hash": format!("{:016x}", chip8.state_hash()),
+                }));
+            }
+            _ => return Err((METHOD_NOT_FOUND, format!("Unknown method: {}", method))),
+        }
+        Ok(Value::Null)
+    }
+
+    fn keys(&mut self, client: Client) -> &mut [bool; 16] {
+        match client {
+            Client::Http => &mut self.http_keys,
+            Client::Watcher(index) => &mut self.watchers[index].keys,
+        }
+    }
+
+    // Release a key nobody holds anymore, held to the end of the frame so a quick press counts
+    fn release(&mut self, key: u8) {
+        let held = self.http_keys[key as usize]
+            || self
+                .watchers
+                .iter()
+                .any(|watcher| watcher.keys[key as usize]);
+        if !held && self.chip8.keyboard[key as usize] {
+            self.chip8.tap_key(key, 1);
+        }
+    }
+
+    // Start the ROM again in a new machine, the keys held are released
+    fn reset(&mut self) {
+        self.chip8 = new_core(&self.options, &self.rom);
+        self.budget = FrameBudget::new(self.options.timing, self.options.ipf);
+        self.error = None;
+        self.http_keys = [false; 16];
+        for watcher in &mut self.watchers {
+            watcher.keys = [false; 16];
+            watcher.needs_frame = true;
+        }
+        self.counters.resets += 1;
+    }
+
+    fn status(&self) -> Value {
+        json!({
+            "running": !self.paused && self.error.is_none(),
+            "paused": self.paused,
+            "error": self.error,
+            "sound": self.chip8.st > 0,
+            "watchers": self.watchers.len(),
+        })
+    }
+
+    fn metrics(&self) -> String {
+        let running = !self.paused && self.error.is_none();
+        let metrics: [(&str, &str, &str, u64); 11] = [
+            (
+                "uptime_seconds",
+                "gauge",
+                "Seconds since the server started",
+                self.started.elapsed().as_secs(),
+            ),
+            (
+                "running",
+                "gauge",
+                "1 while the machine runs",
+                running as u64,
+            ),
+            (
+                "frames_total",
+                "counter",
+                "Frames run",
+                self.counters.frames,
+            ),
+            (
+                "cycles_total",
+                "counter",
+                "Instructions run",
+                self.counters.cycles,
+            ),
+            (
+                "watchers",
+                "gauge",
+                "Browsers connected",
+                self.watchers.len() as u64,
+            ),
+            (
+                "spectators",
+                "gauge",
+                "Terminals connected with --spectate",
+                self.spectators as u64,
+            ),
+            (
+                "rpc_requests_total",
+                "counter",
+                "JSON-RPC requests",
+                self.counters.rpc_requests,
+            ),
+            (
+                "frames_sent_total",
+                "counter",
+                "Frames sent to the watchers",
+                self.counters.frames_sent,
+            ),
+            (
+                "frames_skipped_total",
+                "counter",
+                "Frames skipped by watchers that couldn't keep up",
+                self.counters.frames_skipped,
+            ),
+            (
+                "resets_total",
+                "counter",
+                "Resets of the machine",
+                self.counters.resets,
+            ),
+            (
+                "errors_total",
+                "counter",
+                "Times the machine stopped on an error",
+                self.counters.errors,
+            ),
+        ];
+        let mut text = String::new();
+        for (name, kind, help, value) in metrics {
+            let _ = writeln!(text, "# HELP rustc8_{} {}", name, help);
+            let _ = writeln!(text, "# TYPE rustc8_{} {}", name, kind);
+            let _ = writeln!(text, "rustc8_{} {}", name, value);
+        }
+        text
+    }
+}
+
+// The width and height as big endian u16, then the pixels
+fn encode_frame(chip8: &Chip8) -> Vec<u8> {
+    let mut frame = Vec::with_capacity(4 + chip8.display.len());
+    frame.extend_from_slice(&(chip8.display_width() as u16).to_be_bytes());
+    frame.extend_from_slice(&(chip8.display_height() as u16).to_be_bytes());
+    frame.extend_from_slice(&chip8.display);
+    frame
+}
+
+fn error(id: Value, code: i64, message: &str) -> Value {
+    json!({"jsonrpc": "2.0", "id": id, "error": {"code": code, "message": message}})
+}
+
+// Write what a stream that doesn't block takes of output, false once the connection is lost
+fn write_available(stream: &mut TcpStream, output: &mut Vec<u8>) -> bool {
+    while !output.is_empty() {
+        match stream.write(output) {
+            Ok(0) => return false,
+            Ok(len) => {
+                output.drain(..len);
+            }
+            Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+            Err(e) if e.kind() == ErrorKind::Interrupted => {}
+            Err(_) => return false,
+        }
+    }
+    true
+}
+
+// An HTTP request, without the query of the path
+struct Request<'a> {
+    method: &'a str,
+    path: &'a str,
+    headers: Vec<(&'a str, &'a str)>,
+    body: &'a [u8],
+}
+
+impl Request<'_> {
+    fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(header, _)| header.eq_ignore_ascii_case(name))
+            .map(|(_, value)| *value)
+    }
+}
+
+// The request at the start of data, none until all of it, body included, has arrived. A request
+// over MAX_REQUEST_SIZE, head and body together, is refused with the status to answer, rather
+// than waited for
+fn parse_request(data: &[u8]) -> Result<Option<Request<'_>>, &'static str> {
+    let Some(end) = data.windows(4).position(|window| window == b"\r\n\r\n") else {
+        return Ok(None);
+    };
+    let head = std::str::from_utf8(&data[..end]).map_err(|_| BAD_REQUEST)?;
+    let mut lines = head.split("\r\n");
+    let mut parts = lines.next().unwrap_or("").split(' ');
+    let method = parts.next().unwrap_or("");
+    let target = parts.next().unwrap_or("");
+    let path = target.split('?').next().unwrap_or(target);
+    let headers: Vec<(&str, &str)> = lines
+        .filter_map(|line| line.split_once(':'))
+        .map(|(name, value)| (name.trim(), value.trim()))
+        .collect();
+    let mut request = Request {
+        method,
+        path,
+        headers,
+        body: &[],
+    };
+    let len: usize = match request.header("content-length") {
+        Some(len) => len.parse().map_err(|_| BAD_REQUEST)?,
+        None => 0,
+    };
+    let body_end = match (end + 4).checked_add(len) {
+        Some(body_end) if body_end <= MAX_REQUEST_SIZE => body_end,
+        _ => return Err(PAYLOAD_TOO_LARGE),
+    };
+    match data.get(end + 4..body_end) {
+        Some(body) => request.body = body,
+        None => return Ok(None),
+    }
+    Ok(Some(request))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{self, BufRead};
+
+    // ADD V0, 1; LD F, V0; CLS; DRW V1, V1, 5; JP 0x200
+    const ROM: [u8; 10] = [0x70, 0x01, 0xF0, 0x29, 0x00, 0xE0, 0xD1, 0x15, 0x12, 0x00];
+
+    fn server() -> Server {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let args = ["test.ch8", "--seed", "0"].map(String::from);
+        let options = parse_args(&args).unwrap();
+        Server::new(listener, ROM.to_vec(), options).unwrap_or_else(|_| panic!())
+    }
+
+    // Send a request and poll the server until it answers
+    fn request(server: &mut Server, request: &str) -> String {
+        let mut stream = TcpStream::connect(server.listener.local_addr().unwrap()).unwrap();
+        stream.write_all(request.as_bytes()).unwrap();
+        stream.set_nonblocking(true).unwrap();
+        let mut response = Vec::new();
+        for _ in 0..100 {
+            server.poll();
+            match stream.read_to_end(&mut response) {
+                Ok(_) => break,
+                Err(e) if e.kind() == ErrorKind::WouldBlock => {
+                    thread::sleep(Duration::from_millis(5))
+                }
+                Err(e) => panic!("{}", e),
+            }
+        }
+        String::from_utf8(response).unwrap()
+    }
+
+    #[test]
+    fn test_parse_request() {
+        let data = b"POST /rpc?x=1 HTTP/1.1\r\nHost: a\r\nContent-Length: 2\r\n\r\n{}";
+        let request = parse_request(data).unwrap().unwrap();
+        assert_eq!(request.method, "POST");
+        assert_eq!(request.path, "/rpc");
+        assert_eq!(request.header("content-length"), Some("2"));
+        assert_eq!(request.body, b"{}");
+        assert!(parse_request(&data[..data.len() - 1]).unwrap().is_none());
+        assert!(parse_request(b"GET / HTTP/1.1\r\nHost: a\r\n")
+            .unwrap()
+            .is_none());
+
+        // A body too large isn't waited for
+        let huge = format!(
+            "POST /rpc HTTP/1.1\r\nContent-Length: {}\r\n\r\n",
+            usize::MAX
+        );
+        assert_eq!(
+            parse_request(huge.as_bytes()).err(),
+            Some(PAYLOAD_TOO_LARGE)
+        );
+        let data = b"POST /rpc HTTP/1.1\r\nContent-Length: 70000\r\n\r\n";
+        assert_eq!(parse_request(data).err(), Some(PAYLOAD_TOO_LARGE));
+        // The head counts towards the limit
+        let data = format!(
+            "POST /rpc HTTP/1.1\r\nContent-Length: {}\r\n\r\n",
+            MAX_REQUEST_SIZE - 10
+        );
+        assert_eq!(
+            parse_request(data.as_bytes()).err(),
+            Some(PAYLOAD_TOO_LARGE)
+        );
+        let data = b"POST /rpc HTTP/1.1\r\nContent-Length: x\r\n\r\n";
+        assert_eq!(parse_request(data).err(), Some(BAD_REQUEST));
+    }
+
+    #[test]
+    fn test_options() {
+        // Set up as when running the ROM in the terminal
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let args = ["test.ch8", "--platform", "xochip", "--ipf", "30"].map(String::from);
+        let options = parse_args(&args).unwrap();
+        let mut server = Server::new(listener, ROM.to_vec(), options).unwrap_or_else(|_| panic!());
+        assert_eq!(server.chip8.memory.len(), 0x10000);
+        server.run_frame();
+        assert_eq!(server.chip8.cycles_executed(), 30);
+        server.reset();
+        assert_eq!(server.chip8.memory.len(), 0x10000);
+        // As in the terminal, a frame must run something
+        let args = ["test.ch8", "--ipf", "0"].map(String::from);
+        assert!(parse_args(&args).is_err());
+    }
+
+    #[test]
+    fn test_rpc() {
+        let mut server = server();
+        let mut rpc = |client, text: &str| server.rpc(client, text);
+        let response = rpc(
+            Client::Http,
+            r#"{"jsonrpc":"2.0","id":1,"method":"key_down","params":{"key":5}}"#,
+        );
+        assert_eq!(
+            response,
+            Some(json!({"jsonrpc": "2.0", "id": 1, "result": null}))
+        );
+        assert_eq!(
+            rpc(Client::Http, r#"{"jsonrpc":"2.0","method":"pause"}"#),
+            None
+        );
+        let response = rpc(
+            Client::Http,
+            r#"{"jsonrpc":"2.0","id":2,"method":"tap","params":{"key":16}}"#,
+        );
+        assert_eq!(response.unwrap()["error"]["code"], INVALID_PARAMS);
+        let response = rpc(Client::Http, r#"{"jsonrpc":"2.0","id":3,"method":"fly"}"#);
+        assert_eq!(response.unwrap()["error"]["code"], METHOD_NOT_FOUND);
+        assert_eq!(
+            rpc(Client::Http, "{").unwrap()["error"]["code"],
+            PARSE_ERROR
+        );
+        assert_eq!(
+            rpc(Client::Http, "[]").unwrap()["error"]["code"],
+            INVALID_REQUEST
+        );
+        assert!(server.chip8.keyboard[5]);
+        assert!(server.paused);
+        assert_eq!(server.status()["running"], false);
+
+        // Paused, the machine doesn't run
+        server.run_frame();
+        assert_eq!(server.chip8.cycles_executed(), 0);
+        server.rpc(Client::Http, r#"{"jsonrpc":"2.0","method":"resume"}"#);
+        server.run_frame();
+        assert!(server.chip8.cycles_executed() > 0);
+        let state = server.rpc(Client::Http, r#"{"jsonrpc":"2.0","id":4,"method":"state"}"#);
+        assert!(state.unwrap()["result"]["v"][0].as_u64().unwrap() > 0);
+
+        server.rpc(
+            Client::Http,
+            r#"{"jsonrpc":"2.0","method":"key_up","params":{"key":5}}"#,
+        );
+        server.run_frame();
+        assert!(!server.chip8.keyboard[5]);
+        server.rpc(Client::Http, r#"{"jsonrpc":"2.0","method":"reset"}"#);
+        assert_eq!(server.chip8.cycles_executed(), 0);
+        assert_eq!(server.counters.resets, 1);
+    }
+
+    #[test]
+    fn test_http() {
+        let mut server = server();
+        let response = request(&mut server, "GET / HTTP/1.1\r\n\r\n");
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(response.ends_with(PAGE));
+        server.run_frame();
+        let response = request(&mut server, "GET /metrics HTTP/1.1\r\n\r\n");
+        assert!(response.contains("# TYPE rustc8_frames_total counter\nrustc8_frames_total 1\n"));
+        let body = r#"{"jsonrpc":"2.0","id":"a","method":"status"}"#;
+        let response = request(
+            &mut server,
+            &format!(
+                "POST /rpc HTTP/1.1\r\nContent-Length: {}\r\n\r\n{}",
+                body.len(),
+                body
+            ),
+        );
+        let json = response.split("\r\n\r\n").nth(1).unwrap();
+        let response: Value = serde_json::from_str(json).unwrap();
+        assert_eq!(response["id"], "a");
+        assert_eq!(response["result"]["running"], true);
+        assert!(request(&mut server, "GET /nothing HTTP/1.1\r\n\r\n").contains("404"));
+        let response = request(
+            &mut server,
+            "POST /rpc HTTP/1.1\r\nContent-Length: 99999999\r\n\r\n",
+        );
+        assert!(response.starts_with("HTTP/1.1 413 Payload Too Large\r\n"));
+    }
+
+    #[test]
+    fn test_slow_client() {
+        let mut server = server();
+        let mut stream = TcpStream::connect(server.listener.local_addr().unwrap()).unwrap();
+        let (accepted, _) = server.listener.accept().unwrap();
+        accepted.set_nonblocking(true).unwrap();
+        // Larger than the socket buffers, the rest is written as the client reads
+        let body = "x".repeat(16 * 1024 * 1024);
+        server.respond(accepted, "200 OK", "text/plain", &body);
+        assert_eq!(server.responses.len(), 1);
+
+        stream.set_nonblocking(true).unwrap();
+        let mut response = Vec::new();
+        // Closed once it's all written
+        loop {
+            server.poll();
+            match stream.read_to_end(&mut response) {
+                Ok(_) => break,
+                Err(e) if e.kind() == ErrorKind::WouldBlock => {}
+                Err(e) => panic!("{}", e),
+            }
+        }
+        assert!(server.responses.is_empty());
+        assert!(response.starts_with(b"HTTP/1.1 200 OK\r\n"));
+        assert!(response.ends_with(body.as_bytes()));
+    }
+
+    #[test]
+    fn test_request_limit() {
+        let mut server = server();
+        let stream = TcpStream::connect(server.listener.local_addr().unwrap()).unwrap();
+        // A head that never ends, written faster than the server reads it
+        let mut writer = stream.try_clone().unwrap();
+        let writer = std::thread::spawn(move || {
+            let _ = writer.write_all(&vec![b'x'; 16 * MAX_REQUEST_SIZE]);
+        });
+
+        // Reading stops at the limit, where the request is refused rather than timing out
+        let deadline = Instant::now() + REQUEST_TIMEOUT / 2;
+        let mut accepted = false;
+        loop {
+            server.poll();
+            for pending in &server.pending {
+                assert!(pending.data.len() <= MAX_REQUEST_SIZE + 4096);
+                accepted = true;
+            }
+            if accepted && server.pending.is_empty() {
+                break;
+            }
+            assert!(Instant::now() < deadline);
+        }
+        drop(stream);
+        writer.join().unwrap();
+    }
+
+    #[test]
+    fn test_websocket() {
+        let mut server = server();
+        let mut stream = TcpStream::connect(server.listener.local_addr().unwrap()).unwrap();
+        write!(
+            stream,
+            "GET /ws HTTP/1.1\r\nUpgrade: websocket\r\nConnection: Upgrade\r\nSec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\n\r\n"
+        )
+        .unwrap();
+        while server.watchers.is_empty() {
+            server.poll();
+        }
+        let mut reader = io::BufReader::new(stream.try_clone().unwrap());
+        let mut line = String::new();
+        reader.read_line(&mut line).unwrap();
+        assert_eq!(line, "HTTP/1.1 101 Switching Protocols\r\n");
+        while line != "\r\n" {
+            line.clear();
+            reader.read_line(&mut line).unwrap();
+        }
+
+        // The first frame, and the status
+        server.run_frame();
+        let mut header = [0; 4];
+        reader.read_exact(&mut header).unwrap();
+        assert_eq!(header, [0x82, 126, 0x08, 0x04]);
+        let mut frame = vec![0; 0x804];
+        reader.read_exact(&mut frame).unwrap();
+        assert_eq!(frame[..4], [0, 64, 0, 32]);
+        assert!(frame[4..].contains(&1));
+        reader.read_exact(&mut header[..2]).unwrap();
+        let mut status = vec![0; header[1] as usize];
+        reader.read_exact(&mut status).unwrap();
+        let status: Value = serde_json::from_slice(&status).unwrap();
+        assert_eq!(status["method"], "status");
+        assert_eq!(status["params"]["watchers"], 1);
+
+        // A masked request from the browser
+        let request = br#"{"jsonrpc":"2.0","id":1,"method":"key_down","params":{"key":3}}"#;
+        let mut message = vec![0x81, 0x80 | request.len() as u8, 0, 0, 0, 0];
+        message.extend_from_slice(request);
+        stream.write_all(&message).unwrap();
+        while !server.chip8.keyboard[3] {
+            server.poll();
+        }
+        reader.read_exact(&mut header[..2]).unwrap();
+        let mut response = vec![0; header[1] as usize];
+        reader.read_exact(&mut response).unwrap();
+        assert_eq!(serde_json::from_slice::<Value>(&response).unwrap()["id"], 1);
+
+        // Leaving releases the keys
+        drop(reader);
+        drop(stream);
+        while !server.watchers.is_empty() {
+            server.poll();
+        }
+        server.run_frame();
+        assert!(!server.chip8.keyboard[3]);
+    }
+}
@@ -0,0 +1,266 @@
+use crate::{
+    config::{self, Config, CONFIG_KEY},
+    dialog::Dialog,
+    i18n::{tr, trf},
+    input::{Keymap, KEYMAPS},
+    terminal::{Capabilities, ColorDepth},
+};
+use ratatui::{
+    crossterm::event::{self, Event, KeyCode, KeyEventKind},
+    DefaultTerminal,
+};
+use rustc8::{storage::Storage, widget::Renderer};
+use std::{
+    env,
+    io::{self, IsTerminal},
+    path::{Path, PathBuf},
+};
+
+/*
+Setup wizard
+The first time rustc8 is run in a terminal, before there is a config file, a few dialogs ask for
+what would otherwise take options on every run: how the display is drawn (from the renderers the
+terminal can show, the detected one first), the keyboard layout for the keypad, and the folder of
+the ROMs, opened when no ROM is given. The answers are written to config.json, see config.
+Esc skips the rest and writes what was picked so far, so the wizard doesn't come back; `rustc8
+setup` runs it again.
+*/
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Step {
+    Renderer,
+    Keymap,
+    RomDir,
+}
+
+// Whether the wizard should run by itself, not when the output is piped or scripted
+pub fn is_first_run(storage: &dyn Storage) -> bool {
+    !storage.exists(CONFIG_KEY) && io::stdin().is_terminal() && io::stdout().is_terminal()
+}
+
+// The renderers the terminal can draw, the detected one first
+fn renderers(capabilities: &Capabilities) -> Vec<Renderer> {
+    let mut renderers = vec![capabilities.renderer()];
+    let available = [
+        (Renderer::Text, capabilities.unicode),
+        (Renderer::HalfBlock, capabilities.unicode),
+        (Renderer::Braille, capabilities.braille),
+        (Renderer::Ascii, true),
+    ];
+    for (renderer, available) in available {
+        if available && !renderers.contains(&renderer) {
+            renderers.push(renderer);
+        }
+    }
+    renderers
+}
+
+fn describe(renderer: Renderer) -> &'static str {
+    match renderer {
+        Renderer::Text => tr("a block per pixel, the sharpest"),
+        Renderer::HalfBlock => tr("two pixels per character, for small windows"),
+        Renderer::Braille => tr("eight dots per character, the smallest"),
+        Renderer::Ascii => tr("# and . for any terminal"),
+    }
+}
+
+// A leading ~ is the home directory, as in the shell
+fn expand_home(dir: &str) -> PathBuf {
+    match (dir.strip_prefix("~"), env::var_os("HOME")) {
+        (Some(rest), Some(home)) if rest.is_empty() || rest.starts_with('/') => {
+            PathBuf::from(home).join(rest.trim_start_matches('/'))
+        }
+        _ => PathBuf::from(dir),
+    }
+}
+
+fn dialog(
+    step: Step,
+    capabilities: &Capabilities,
+    renderers: &[Renderer],
+    config: &Config,
+    error: Option<&str>,
+) -> Dialog {
+    match step {
+        Step::Renderer => {
+            let yes_no = |value: bool| if value { tr("yes") } else { tr("no") };
+            let colors = match capabilities.color {
+                ColorDepth::Monochrome => "2",
+                ColorDepth::Ansi16 => "16",
+                ColorDepth::Ansi256 => "256",
+                ColorDepth::TrueColor => "16777216",
+            };
+            let items = renderers
+                .iter()
+                .map(|renderer| format!("{:<10} {}", renderer.name(), describe(*renderer)))
+                .collect();
+            let selected = config
+                .renderer
+                .as_deref()
+                .and_then(|name| renderers.iter().position(|r| r.name() == name));
+            Dialog::new(tr("Welcome to rustc8"))
+                .line(tr(
+                    "A few questions to set up the emulator, Esc skips them. Run rustc8 setup to answer them again.",
+                ))
+                .line("")
+                .line(&trf("Unicode: {}", &[&yes_no(capabilities.unicode)]))
+                .line(&trf("Braille: {}", &[&yes_no(capabilities.braille)]))
+                .line(&trf("Colors: {}", &[&colors]))
+                .line("")
+                .line(tr("How should the display be drawn?"))
+                .items(items)
+                .select(selected.unwrap_or(0))
+                .choice('n', tr("Next"))
+        }
+        Step::Keymap => {
+            let selected = config
+                .keymap
+                .as_deref()
+                .and_then(|name| KEYMAPS.iter().position(|k| k.name() == name));
+            Dialog::new(tr("Keyboard"))
+                .line(tr(
+                    "Which layout is your keyboard? The keypad is played on the keys shown.",
+                ))
+                .items(KEYMAPS.iter().map(|k| k.name().to_uppercase()).collect())
+                .previews(KEYMAPS.iter().map(|k| keypad(*k)).collect())
+                .select(selected.unwrap_or(0))
+                .choice('n', tr("Next"))
+                .choice('b', tr("Back"))
+        }
+        Step::RomDir => {
+            let mut dialog = Dialog::new(tr("ROM folder"))
+                .line(tr(
+                    "Which folder are your ROMs in? It's opened when rustc8 is run without a ROM. Leave it empty to give a ROM every time.",
+                ));
+            if let Some(error) = error {
+                dialog = dialog.line("").line(error);
+            }
+            dialog
+                .input(config.rom_dir.as_deref().unwrap_or(""))
+                .choice('f', tr("Finish"))
+                .choice('b', tr("Back"))
+        }
+    }
+}
+
+// The keys of the keypad block, e.g. "1 2 3 4" over "Q W E R"
+fn keypad(keymap: Keymap) -> String {
+    keymap
+        .rows()
+        .iter()
+        .map(|row| {
+            let keys: Vec<String> = row.chars().map(|c| c.to_uppercase().to_string()).collect();
+            keys.join(" ")
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+pub fn run(terminal: &mut DefaultTerminal, storage: &mut dyn Storage) -> io::Result<()> {
+    let capabilities = Capabilities::detect();
+    let renderers = renderers(&capabilities);
+    let mut config = Config::default();
+    let mut step = Step::Renderer;
+    let mut error = None;
+
+    loop {
+        let mut current = dialog(step, &capabilities, &renderers, &config, error.as_deref());
+        let choice = loop {
+            terminal.draw(|frame| frame.render_widget(&current, frame.area()))?;
+            if let Event::Key(key) = event::read()? {
+                if key.kind != KeyEventKind::Press {
+                    continue;
+                }
+                if key.code == KeyCode::Esc {
+                    break None;
+                }
+                if let Some(choice) = current.handle_key(key.code) {
+                    break Some(choice);
+                }
+            }
+        };
+        error = None;
+        match (step, choice) {
+            // Skipped
+            (_, None) => break,
+            (Step::Renderer, _) => {
+                let renderer = renderers[current.selected_item()];
+                config.renderer = Some(renderer.name().to_string());
+                step = Step::Keymap;
+            }
+            (Step::Keymap, Some('b')) => step = Step::Renderer,
+            (Step::Keymap, _) => {
+                config.keymap = Some(KEYMAPS[current.selected_item()].name().to_string());
+                step = Step::RomDir;
+            }
+            (Step::RomDir, choice) => {
+                let dir = current.input_text().trim();
+                config.rom_dir = Some(dir.to_string()).filter(|dir| !dir.is_empty());
+                if choice == Some('b') {
+                    step = Step::Keymap;
+                    continue;
+                }
+                if !dir.is_empty() && !expand_home(dir).is_dir() {
+                    error = Some(trf("{} is not a folder", &[&dir]));
+                    continue;
+                }
+                config.rom_dir = config
+                    .rom_dir
+                    .map(|dir| expand_home(&dir).to_string_lossy().into_owned());
+                break;
+            }
+        }
+    }
+    // A folder that doesn't exist isn't kept, e.g. when the wizard is skipped after a typo
+    config.rom_dir = config.rom_dir.filter(|dir| Path::new(dir).is_dir());
+
+    let result = config::save(storage, &config);
+    let mut done = match &result {
+        Ok(()) => Dialog::new(tr("All set")).line(&trf(
+            "Saved the settings in {}",
+            &[&storage.location(CONFIG_KEY)],
+        )),
+        Err(e) => Dialog::new(tr("Setup")).line(&trf("Failed to save the settings: {}", &[&e])),
+    }
+    .choice('o', tr("OK"));
+    loop {
+        terminal.draw(|frame| frame.render_widget(&done, frame.area()))?;
+        if let Event::Key(key) = event::read()? {
+            if key.kind == KeyEventKind::Press
+                && (key.code == KeyCode::Esc || done.handle_key(key.code).is_some())
+            {
+                return Ok(());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_renderers() {
+        let mut capabilities = Capabilities {
+            dumb: false,
+            unicode: true,
+            braille: true,
+            color: ColorDepth::Ansi256,
+            kitty: false,
+            sixel: false,
+        };
+        assert_eq!(
+            renderers(&capabilities),
+            [
+                Renderer::Text,
+                Renderer::HalfBlock,
+                Renderer::Braille,
+                Renderer::Ascii
+            ]
+        );
+        capabilities.unicode = false;
+        capabilities.braille = false;
+        assert_eq!(renderers(&capabilities), [Renderer::Ascii]);
+
+        assert_eq!(keypad(Keymap::Qwertz).lines().last(), Some("Y X C V"));
+    }
+}
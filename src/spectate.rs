@@ -0,0 +1,299 @@
+use rustc8::widget::{Chip8DisplayWidget, Renderer};
+use std::{
+    io::{self, ErrorKind, Read, Write},
+    net::{SocketAddr, TcpListener, TcpStream},
+    rc::Rc,
+};
+
+const RENDERERS: &str = "text, braille, halfblock or ascii";
+// Longest line taken from a viewer, the names of the renderers are short
+const MAX_INPUT: usize = 64;
+// Clear the screen, and the end of a line as lines are sent without trailing spaces
+const CLEAR_SCREEN: &str = "\x1b[2J";
+const CLEAR_LINE: &str = "\x1b[K";
+const CURSOR_HOME: &str = "\x1b[H";
+
+/*
+Spectators, `--spectate <address>`
+Other people can watch the game from a terminal by connecting to the address with a plain TCP
+client, e.g. `nc <host> 7001`, and any number of them can watch at once. Each viewer picks its
+own renderer by typing its name and Enter, halfblock by default.
+Frames are only sent when the core reports damage, and are rendered once per renderer in use
+however many viewers use it. Sockets don't block: a viewer that can't keep up skips frames
+rather than slowing the game, only the latest frame waits while the previous one is sent.
+*/
+pub struct SpectatorServer {
+    listener: TcpListener,
+    viewers: Vec<Viewer>,
+}
+
+struct Viewer {
+    stream: TcpStream,
+    renderer: Renderer,
+    // What the viewer typed since the last new line
+    input: Vec<u8>,
+    // The frame being sent and how much of it has been
+    sending: Option<(Rc<Vec<u8>>, usize)>,
+    // The latest frame, sent after the one being sent
+    next: Option<Rc<Vec<u8>>>,
+    // Set when connecting and changing renderer, the screen is cleared for the next frame
+    needs_frame: bool,
+    // Text shown below the next frame, e.g. an unknown renderer
+    notice: Option<String>,
+    connected: bool,
+}
+
+impl SpectatorServer {
+    pub fn bind(addr: SocketAddr) -> io::Result<Self> {
+        let listener = TcpListener::bind(addr)?;
+        listener.set_nonblocking(true)?;
+        Ok(SpectatorServer {
+            listener,
+            viewers: Vec::new(),
+        })
+    }
+
+    pub fn viewers(&self) -> usize {
+        self.viewers.len()
+    }
+
+    // Accept viewers, read their renderer choices and send them the display if it changed
+    pub fn update(&mut self, display: &[u8], damaged: bool) {
+        while let Ok((stream, _)) = self.listener.accept() {
+            if let Some(viewer) = Viewer::new(stream) {
+                self.viewers.push(viewer);
+            }
+        }
+
+        let mut frames: Vec<(Renderer, Rc<Vec<u8>>)> = Vec::new();
+        for viewer in &mut self.viewers {
+            viewer.read();
+            if damaged || viewer.needs_frame {
+                let frame = match frames
+                    .iter()
+                    .find(|(renderer, _)| *renderer == viewer.renderer)
+                {
+                    Some((_, frame)) => Rc::clone(frame),
+                    None => {
+                        let frame = Rc::new(render(display, viewer.renderer));
+                        frames.push((viewer.renderer, Rc::clone(&frame)));
+                        frame
+                    }
+                };
+                viewer.queue(frame);
+            }
+            viewer.write();
+        }
+        self.viewers.retain(|viewer| viewer.connected);
+    }
+}
+
+impl Viewer {
+    fn new(stream: TcpStream) -> Option<Self> {
+        stream.set_nonblocking(true).ok()?;
+        let _ = stream.set_nodelay(true);
+        Some(Viewer {
+            stream,
+            renderer: Renderer::HalfBlock,
+            input: Vec::new(),
+            sending: None,
+            next: None,
+            needs_frame: true,
+            notice: Some(format!(
+                "Type {} and Enter to change the renderer",
+                RENDERERS
+            )),
+            connected: true,
+        })
+    }
+
+    // A read a frame, a viewer that keeps typing can't hold up the game
+    fn read(&mut self) {
+        let mut buffer = [0; 256];
+        match self.stream.read(&mut buffer) {
+            Ok(0) => {
+                self.connected = false;
+                return;
+            }
+            Ok(read) => self.input.extend_from_slice(&buffer[..read]),
+            Err(e) if e.kind() == ErrorKind::WouldBlock => {}
+            Err(_) => {
+                self.connected = false;
+                return;
+            }
+        }
+        while let Some(end) = self.input.iter().position(|byte| *byte == b'\n') {
+            let line: Vec<u8> = self.input.drain(..=end).collect();
+            let name = String::from_utf8_lossy(&line).trim().to_ascii_lowercase();
+            if name.is_empty() {
+                continue;
+            }
+            match Renderer::from_name(&name) {
+                Some(renderer) => {
+                    self.renderer = renderer;
+                    self.notice = None;
+                }
+                None => self.notice = Some(format!("Unknown renderer, use {}", RENDERERS)),
+            }
+            self.needs_frame = true;
+        }
+        // No renderer has a name this long, the line is dropped rather than kept growing
+        if self.input.len() > MAX_INPUT {
+            self.input.clear();
+            self.notice = Some(format!("Unknown renderer, use {}", RENDERERS));
+            self.needs_frame = true;
+        }
+    }
+
+    fn queue(&mut self, frame: Rc<Vec<u8>>) {
+        let mut frame = frame;
+        // Viewers joining or switching renderer get their screen cleared, with the notice below
+        if self.needs_frame {
+            let mut bytes = CLEAR_SCREEN.as_bytes().to_vec();
+            bytes.extend_from_slice(&frame);
+            if let Some(notice) = &self.notice {
+                bytes.extend_from_slice(format!("\r\n{}\r\n", notice).as_bytes());
+            }
+            frame = Rc::new(bytes);
+            self.needs_frame = false;
+        }
+        match self.sending {
+            None => self.sending = Some((frame, 0)),
+            Some(_) => self.next = Some(frame),
+        }
+    }
+
+    fn write(&mut self) {
+        while let Some((frame, sent)) = self.sending.as_mut() {
+            match self.stream.write(&frame[*sent..]) {
+                Ok(0) => {
+                    self.connected = false;
+                    return;
+                }
+                Ok(written) => {
+                    *sent += written;
+                    if *sent == frame.len() {
+                        self.sending = self.next.take().map(|frame| (frame, 0));
+                    }
+                }
+                Err(e) if e.kind() == ErrorKind::WouldBlock => return,
+                Err(_) => {
+                    self.connected = false;
+                    return;
+                }
+            }
+        }
+    }
+}
+
+// The display as text drawn from the top left of the viewer's terminal
+fn render(display: &[u8], renderer: Renderer) -> Vec<u8> {
+    let text = Chip8DisplayWidget::new(display)
+        .renderer(renderer)
+        .to_text();
+    let mut frame = String::from(CURSOR_HOME);
+    for line in text.lines() {
+        frame.push_str(line);
+        frame.push_str(CLEAR_LINE);
+        frame.push_str("\r\n");
+    }
+    frame.into_bytes()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{thread, time::Duration};
+
+    // Read what the server sent until it has sent what is expected
+    fn receive(server: &mut SpectatorServer, client: &mut TcpStream, expected: &str) -> String {
+        let mut received = Vec::new();
+        let mut buffer = [0; 4096];
+        for _ in 0..100 {
+            server.update(&[0; 64 * 32], false);
+            if let Ok(read) = client.read(&mut buffer) {
+                received.extend_from_slice(&buffer[..read]);
+            }
+            let text = String::from_utf8_lossy(&received).into_owned();
+            if text.contains(expected) {
+                return text;
+            }
+            thread::sleep(Duration::from_millis(5));
+        }
+        panic!("expected {:?}, received {:?}", expected, received);
+    }
+
+    #[test]
+    fn test_spectators() {
+        let mut server = SpectatorServer::bind("127.0.0.1:0".parse().unwrap()).unwrap();
+        let addr = server.listener.local_addr().unwrap();
+        let mut braille = TcpStream::connect(addr).unwrap();
+        let mut ascii = TcpStream::connect(addr).unwrap();
+        for client in [&braille, &ascii] {
+            client
+                .set_read_timeout(Some(Duration::from_millis(5)))
+                .unwrap();
+        }
+
+        // Both get the screen as they connect, then pick their renderers
+        receive(&mut server, &mut braille, "Enter to change the renderer");
+        receive(&mut server, &mut ascii, "Enter to change the renderer");
+        assert_eq!(server.viewers(), 2);
+        braille.write_all(b"braille\n").unwrap();
+        ascii.write_all(b"ascii\r\n").unwrap();
+        let ascii_frame = format!("{}{}", ".".repeat(64), CLEAR_LINE);
+        receive(&mut server, &mut ascii, &ascii_frame);
+        let text = receive(&mut server, &mut braille, CLEAR_SCREEN);
+        assert!(!text.contains('.'));
+
+        ascii.write_all(b"sixel\n").unwrap();
+        receive(&mut server, &mut ascii, "Unknown renderer");
+
+        drop(braille);
+        for _ in 0..100 {
+            if server.viewers() == 1 {
+                break;
+            }
+            server.update(&[0; 64 * 32], false);
+            thread::sleep(Duration::from_millis(5));
+        }
+        assert_eq!(server.viewers(), 1);
+    }
+
+    #[test]
+    fn test_long_input() {
+        let mut server = SpectatorServer::bind("127.0.0.1:0".parse().unwrap()).unwrap();
+        let addr = server.listener.local_addr().unwrap();
+        let mut client = TcpStream::connect(addr).unwrap();
+        client
+            .set_read_timeout(Some(Duration::from_millis(5)))
+            .unwrap();
+        receive(&mut server, &mut client, "Enter to change the renderer");
+
+        // A line that never ends is dropped as it grows, and the next one still picks a renderer
+        client.write_all(&[b'x'; 4096]).unwrap();
+        receive(&mut server, &mut client, "Unknown renderer");
+        assert!(server.viewers[0].input.len() <= MAX_INPUT);
+        for _ in 0..100 {
+            server.update(&[0; 64 * 32], false);
+        }
+        client.write_all(b"\nascii\n").unwrap();
+        let ascii_frame = format!("{}{}", ".".repeat(64), CLEAR_LINE);
+        receive(&mut server, &mut client, &ascii_frame);
+        assert_eq!(server.viewers[0].renderer, Renderer::Ascii);
+    }
+
+    #[test]
+    fn test_render() {
+        let mut display = [0; 64 * 32];
+        display[0] = 1;
+        let frame = String::from_utf8(render(&display, Renderer::Ascii)).unwrap();
+        assert!(frame.starts_with(&format!(
+            "{}#{}{}\r\n",
+            CURSOR_HOME,
+            ".".repeat(63),
+            CLEAR_LINE
+        )));
+        assert_eq!(frame.lines().count(), 32);
+    }
+}
@@ -0,0 +1,189 @@
+use crate::i18n::tr;
+use ratatui::{
+    buffer::Buffer,
+    layout::Rect,
+    style::{Modifier, Style},
+    text::Line,
+    widgets::{Block, Clear, Widget},
+};
+use rustc8::{
+    chip8::Chip8,
+    step::{Step, StepObserver},
+};
+
+// Most recent splits shown under the timer
+const SHOWN_SPLITS: usize = 5;
+
+// Width of the overlay, enough for "10  59:59.99" and the borders
+pub const OVERLAY_WIDTH: u16 = 16;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Start {
+    // Start on the first key press sent to the game
+    FirstInput,
+    // Start when the instruction at this address is executed, e.g. the first one after a title screen
+    Address(u16),
+}
+
+/*
+SpeedrunTimer counts emulated frames rather than wall time, so that the time doesn't depend on
+how fast the terminal draws, and stops while the emulation is paused.
+Splits are recorded as the number of frames since the start.
+*/
+pub struct SpeedrunTimer {
+    start: Start,
+    started_at: Option<u64>,
+    frame: u64,
+    splits: Vec<u64>,
+}
+
+impl SpeedrunTimer {
+    pub fn new(start: Start) -> Self {
+        SpeedrunTimer {
+            start,
+            started_at: None,
+            frame: 0,
+            splits: Vec::new(),
+        }
+    }
+
+    pub fn next_frame(&mut self) {
+        self.frame += 1;
+    }
+
+    // Called for each key press sent to the game
+    pub fn input(&mut self) {
+        if self.start == Start::FirstInput {
+            self.started_at.get_or_insert(self.frame);
+        }
+    }
+
+    // Called before each cycle with the address of the instruction about to be executed
+    pub fn step(&mut self, pc: u16) {
+        if self.start == Start::Address(pc) {
+            self.started_at.get_or_insert(self.frame);
+        }
+    }
+
+    pub fn split(&mut self) {
+        if self.started_at.is_some() {
+            self.splits.push(self.elapsed());
+        }
+    }
+
+    // Frames since the start, 0 until the timer is started
+    // Started and counting, the overlay changes every frame
+    pub fn is_running(&self) -> bool {
+        self.started_at.is_some()
+    }
+
+    pub fn elapsed(&self) -> u64 {
+        self.started_at.map(|start| self.frame - start).unwrap_or(0)
+    }
+
+    pub fn area(&self, display_area: Rect) -> Rect {
+        let height = 3 + self.splits.len().min(SHOWN_SPLITS) as u16;
+        let width = OVERLAY_WIDTH.min(display_area.width);
+        Rect {
+            x: display_area.right().saturating_sub(width),
+            y: display_area.y,
+            width,
+            height: height.min(display_area.height),
+        }
+    }
+}
+
+// Format frames at 60 FPS as minutes, seconds and hundredths
+fn format_time(frames: u64) -> String {
+    let hundredths = frames * 100 / 60;
+    format!(
+        "{:02}:{:02}.{:02}",
+        hundredths / 6000,
+        hundredths / 100 % 60,
+        hundredths % 100
+    )
+}
+
+impl Widget for &SpeedrunTimer {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        Clear.render(area, buf);
+        let title = if self.started_at.is_some() {
+            tr(" Time ")
+        } else {
+            tr(" Ready ")
+        };
+        let block = Block::bordered().title(title);
+        let inner = block.inner(area);
+        block.render(area, buf);
+
+        let mut lines = vec![Line::styled(
+            format_time(self.elapsed()),
+            Style::default().add_modifier(Modifier::BOLD),
+        )];
+        let first = self.splits.len().saturating_sub(SHOWN_SPLITS);
+        for (number, split) in self.splits.iter().enumerate().skip(first) {
+            lines.push(Line::raw(format!(
+                "{:<2}  {}",
+                number + 1,
+                format_time(*split)
+            )));
+        }
+        for (row, line) in lines.into_iter().enumerate() {
+            if row as u16 >= inner.height {
+                break;
+            }
+            line.right_aligned().render(
+                Rect {
+                    y: inner.y + row as u16,
+                    height: 1,
+                    ..inner
+                },
+                buf,
+            );
+        }
+    }
+}
+
+impl StepObserver for SpeedrunTimer {
+    fn before_step(&mut self, step: Step, _chip8: &Chip8) {
+        self.step(step.pc);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_start_on_input() {
+        let mut timer = SpeedrunTimer::new(Start::FirstInput);
+        timer.next_frame();
+        timer.split();
+        assert_eq!(timer.elapsed(), 0);
+        assert!(timer.splits.is_empty());
+
+        timer.input();
+        for _ in 0..90 {
+            timer.next_frame();
+        }
+        timer.input();
+        timer.split();
+        assert_eq!(timer.splits, [90]);
+        assert_eq!(format_time(timer.elapsed()), "00:01.50");
+    }
+
+    #[test]
+    fn test_start_on_address() {
+        let mut timer = SpeedrunTimer::new(Start::Address(0x220));
+        timer.input();
+        timer.step(0x200);
+        timer.next_frame();
+        assert_eq!(timer.started_at, None);
+
+        timer.step(0x220);
+        timer.next_frame();
+        timer.step(0x220);
+        assert_eq!(timer.elapsed(), 1);
+        assert_eq!(format_time(60 * 61 + 30), "01:01.50");
+    }
+}
@@ -0,0 +1,183 @@
+use crate::i18n::tr;
+use rustc8::{
+    chip8::Chip8,
+    savestate::{self, THUMBNAIL_HEIGHT, THUMBNAIL_WIDTH},
+    storage::Storage,
+};
+use std::{io, path::Path};
+
+pub const DIR: &str = "states";
+const EXTENSION: &str = "state";
+// Autosaves kept for each ROM, see autosave
+pub const AUTOSAVE_SLOTS: usize = 3;
+
+/*
+Save state files
+States are saved as <name>.state in the "states" directory of the storage, the config directory
+of the frontend. The name is picked when saving, the one offered is the ROM name with the first
+free number, e.g. pong-1, pong-2. States remember the ROM they were saved with, only the states
+of the current ROM are offered when loading.
+With --autosave, the game is also saved every few seconds as <ROM name>-autosave-1, the older
+autosaves moving to -2 and -3, so a crash or an accidental exit loses little of a long session.
+They're offered when loading like the other states.
+*/
+pub fn key(name: &str) -> String {
+    format!("{}/{}.{}", DIR, name, EXTENSION)
+}
+
+// Names are file names, they can't point to another directory
+pub fn is_valid_name(name: &str) -> bool {
+    !name.trim().is_empty() && !name.contains(['/', '\\']) && name != "." && name != ".."
+}
+
+pub fn next_name(storage: &dyn Storage, rom_name: &str) -> String {
+    (1..)
+        .map(|number| format!("{}-{}", rom_name, number))
+        .find(|name| !storage.exists(&key(name)))
+        .unwrap_or_default()
+}
+
+// Keys of the states saved with the ROM, sorted by name
+pub fn list(storage: &dyn Storage, rom_crc: u32) -> Vec<String> {
+    storage
+        .list(DIR)
+        .unwrap_or_default()
+        .into_iter()
+        .filter(|key| key.ends_with(&format!(".{}", EXTENSION)))
+        .filter(|key| {
+            storage
+                .read(key)
+                .ok()
+                .and_then(|data| savestate::rom_crc(&data))
+                == Some(rom_crc)
+        })
+        .collect()
+}
+
+// Name of a state shown in the dialogs, the file name without the extension
+pub fn name(key: &str) -> String {
+    Path::new(key)
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_default()
+}
+
+// The thumbnail of a state drawn with half blocks, shown in the load dialog
+pub fn preview(storage: &dyn Storage, key: &str) -> String {
+    let Some(thumbnail) = storage
+        .read(key)
+        .ok()
+        .and_then(|data| savestate::thumbnail(&data))
+    else {
+        return tr("No preview").to_string();
+    };
+    let pixel = |x: usize, y: usize| thumbnail[x + y * THUMBNAIL_WIDTH];
+    let mut text = String::new();
+    for y in (0..THUMBNAIL_HEIGHT).step_by(2) {
+        text.extend(
+            (0..THUMBNAIL_WIDTH).map(|x| match (pixel(x, y), pixel(x, y + 1)) {
+                (true, true) => '█',
+                (true, false) => '▀',
+                (false, true) => '▄',
+                (false, false) => '·',
+            }),
+        );
+        text.push('\n');
+    }
+    text
+}
+
+pub fn save(storage: &mut dyn Storage, key: &str, chip8: &Chip8, rom_crc: u32) -> io::Result<()> {
+    storage.write(key, &savestate::encode(chip8, rom_crc))
+}
+
+pub fn autosave_key(rom_name: &str, slot: usize) -> String {
+    key(&format!("{}-autosave-{}", rom_name, slot))
+}
+
+// Save in the first autosave slot, after moving each autosave to the next slot
+pub fn autosave(
+    storage: &mut dyn Storage,
+    rom_name: &str,
+    chip8: &Chip8,
+    rom_crc: u32,
+) -> io::Result<()> {
+    for slot in (1..AUTOSAVE_SLOTS).rev() {
+        if let Ok(data) = storage.read(&autosave_key(rom_name, slot)) {
+            storage.write(&autosave_key(rom_name, slot + 1), &data)?;
+        }
+    }
+    save(storage, &autosave_key(rom_name, 1), chip8, rom_crc)
+}
+
+pub fn load(
+    storage: &dyn Storage,
+    key: &str,
+    rom_crc: u32,
+    chip8: &mut Chip8,
+) -> Result<(), String> {
+    let data = storage.read(key).map_err(|e| e.to_string())?;
+    savestate::decode(&data, rom_crc, chip8).map_err(|e| e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rustc8::storage::MemoryStorage;
+
+    #[test]
+    fn test_save_and_list() {
+        let mut storage = MemoryStorage::new();
+        assert_eq!(next_name(&storage, "pong"), "pong-1");
+
+        let mut chip8 = Chip8::new();
+        chip8.v[3] = 9;
+        save(&mut storage, &key("pong-1"), &chip8, 1).unwrap();
+        save(&mut storage, &key("other"), &chip8, 2).unwrap();
+        assert_eq!(next_name(&storage, "pong"), "pong-2");
+        let keys = list(&storage, 1);
+        assert_eq!(keys, ["states/pong-1.state"]);
+        assert_eq!(name(&keys[0]), "pong-1");
+
+        let mut loaded = Chip8::new();
+        load(&storage, &keys[0], 1, &mut loaded).unwrap();
+        assert_eq!(loaded.v[3], 9);
+        assert!(load(&storage, &key("other"), 1, &mut loaded).is_err());
+
+        chip8.display[0] = 1;
+        chip8.display[64] = 1;
+        save(&mut storage, &key("drawn"), &chip8, 1).unwrap();
+        let text = preview(&storage, &key("drawn"));
+        assert_eq!(text.lines().count(), THUMBNAIL_HEIGHT / 2);
+        assert!(text.starts_with("▀··"));
+        assert_eq!(preview(&storage, &key("missing")), "No preview");
+    }
+
+    #[test]
+    fn test_autosave() {
+        let mut storage = MemoryStorage::new();
+        let mut chip8 = Chip8::new();
+        for value in 1..=4 {
+            chip8.v[0] = value;
+            autosave(&mut storage, "pong", &chip8, 1).unwrap();
+        }
+        // The newest first, the oldest dropped
+        let keys = list(&storage, 1);
+        assert_eq!(keys.len(), AUTOSAVE_SLOTS);
+        for (slot, value) in [(1, 4), (2, 3), (3, 2)] {
+            let mut loaded = Chip8::new();
+            load(&storage, &autosave_key("pong", slot), 1, &mut loaded).unwrap();
+            assert_eq!(loaded.v[0], value);
+        }
+        // Autosaves don't take the names offered when saving
+        assert_eq!(next_name(&storage, "pong"), "pong-1");
+    }
+
+    #[test]
+    fn test_is_valid_name() {
+        assert!(is_valid_name("pong-1"));
+        assert!(!is_valid_name(" "));
+        assert!(!is_valid_name("../pong"));
+        assert!(!is_valid_name(".."));
+    }
+}
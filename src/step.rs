@@ -0,0 +1,159 @@
+use crate::chip8::Chip8;
+#[cfg(feature = "std")]
+use std::sync::{Arc, Mutex};
+
+/*
+Step observers
+Tools that follow the program one instruction at a time, such as the tracer, the profiler or
+coverage, implement StepObserver rather than each adding its own calls around run_cycle:
+- before_step: the instruction is fetched and about to run, the machine is as it finds it
+- after_step: the instruction ran, the machine is as it left it. Not called when it failed with
+  an error, which run_cycle returns
+Nothing is called when run_cycle runs no instruction, e.g. waiting for the vblank, or when the
+fetch fails. Observers only see the machine, stopping it is up to the host, e.g. a breakpoint.
+Plugins register themselves with Chip8::on_step (with std) and see every instruction. Hosts that keep their
+tools, e.g. to draw them, pass them for a single instruction with Chip8::step_with, alone, in an
+array, or as an Option when they are turned off.
+*/
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Step {
+    // Address of the instruction
+    pub pc: u16,
+    pub opcode: u16,
+}
+
+pub trait StepObserver {
+    fn before_step(&mut self, _step: Step, _chip8: &Chip8) {}
+
+    fn after_step(&mut self, _step: Step, _chip8: &Chip8) {}
+}
+
+#[cfg(feature = "std")]
+pub type SharedStepObserver = Arc<Mutex<dyn StepObserver + Send>>;
+
+// No observer
+impl StepObserver for () {}
+
+impl<T: StepObserver + ?Sized> StepObserver for &mut T {
+    fn before_step(&mut self, step: Step, chip8: &Chip8) {
+        (**self).before_step(step, chip8);
+    }
+
+    fn after_step(&mut self, step: Step, chip8: &Chip8) {
+        (**self).after_step(step, chip8);
+    }
+}
+
+impl<T: StepObserver> StepObserver for Option<T> {
+    fn before_step(&mut self, step: Step, chip8: &Chip8) {
+        if let Some(observer) = self {
+            observer.before_step(step, chip8);
+        }
+    }
+
+    fn after_step(&mut self, step: Step, chip8: &Chip8) {
+        if let Some(observer) = self {
+            observer.after_step(step, chip8);
+        }
+    }
+}
+
+// Called in order
+impl<const N: usize> StepObserver for [&mut dyn StepObserver; N] {
+    fn before_step(&mut self, step: Step, chip8: &Chip8) {
+        for observer in self.iter_mut() {
+            observer.before_step(step, chip8);
+        }
+    }
+
+    fn after_step(&mut self, step: Step, chip8: &Chip8) {
+        for observer in self.iter_mut() {
+            observer.after_step(step, chip8);
+        }
+    }
+}
+
+impl Chip8 {
+    // Register an observer of every instruction, see StepObserver
+    #[cfg(feature = "std")]
+    pub fn on_step<O: StepObserver + Send + 'static>(&mut self, observer: O) -> Arc<Mutex<O>> {
+        let observer = Arc::new(Mutex::new(observer));
+        self.step_observers.push(observer.clone());
+        observer
+    }
+
+    pub(crate) fn notify_before_step(&self, step: Step, observer: &mut dyn StepObserver) {
+        #[cfg(feature = "std")]
+        for registered in &self.step_observers {
+            registered.lock().unwrap().before_step(step, self);
+        }
+        observer.before_step(step, self);
+    }
+
+    pub(crate) fn notify_after_step(&self, step: Step, observer: &mut dyn StepObserver) {
+        observer.after_step(step, self);
+        #[cfg(feature = "std")]
+        for registered in &self.step_observers {
+            registered.lock().unwrap().after_step(step, self);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::{vec, vec::Vec};
+
+    #[derive(Default)]
+    struct Recorder {
+        steps: Vec<(Step, u16, u8)>,
+    }
+
+    impl StepObserver for Recorder {
+        fn before_step(&mut self, step: Step, chip8: &Chip8) {
+            self.steps.push((step, chip8.pc, chip8.v[0]));
+        }
+
+        fn after_step(&mut self, step: Step, chip8: &Chip8) {
+            self.steps.push((step, chip8.pc, chip8.v[0]));
+        }
+    }
+
+    #[test]
+    fn test_step_observers() {
+        let mut chip8 = Chip8::new();
+        // LD V0, 7; an invalid opcode
+        chip8.load_rom(&[0x60, 0x07, 0xFF, 0xFF]).unwrap();
+        let mut first = Recorder::default();
+        let mut off: Option<Recorder> = None;
+        chip8
+            .step_with(&mut [&mut first as &mut dyn StepObserver, &mut off])
+            .unwrap();
+        let step = Step {
+            pc: 0x200,
+            opcode: 0x6007,
+        };
+        assert_eq!(first.steps, vec![(step, 0x200, 0), (step, 0x202, 7)]);
+
+        // A failed instruction is only seen before
+        assert!(chip8.step_with(&mut first).is_err());
+        assert_eq!(first.steps.len(), 3);
+        assert_eq!(first.steps[2].0.opcode, 0xFFFF);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_registered_observers() {
+        let mut chip8 = Chip8::new();
+        chip8.load_rom(&[0x60, 0x07, 0xFF, 0xFF]).unwrap();
+        let registered = chip8.on_step(Recorder::default());
+        let mut first = Recorder::default();
+        chip8.step_with(&mut first).unwrap();
+        assert_eq!(registered.lock().unwrap().steps, first.steps);
+
+        assert!(chip8.run_cycle().is_err());
+        let registered = registered.lock().unwrap();
+        assert_eq!(registered.steps.len(), 3);
+        assert_eq!(registered.steps[2].0.opcode, 0xFFFF);
+    }
+}
@@ -0,0 +1,409 @@
+use alloc::{
+    collections::BTreeMap,
+    format,
+    string::{String, ToString},
+    vec::Vec,
+};
+
+// io::Error on the web, where the core itself doesn't use std, see web.rs
+extern crate std;
+use std::io;
+#[cfg(feature = "std")]
+use std::{fs, path::PathBuf};
+
+/*
+Storage
+Where the data kept between runs lives: save states, per-ROM settings, the ROM database. Data is
+stored as entries of bytes under keys, paths relative to the root of the storage with / between
+the parts, e.g. "states/pong-1.state" or "roms.json". Keys can't leave the root, parts can't be
+empty, "." or "..".
+- FileStorage: files in a directory, what the terminal frontend uses with the config directory
+- MemoryStorage: entries in memory, for tests and hosts without a filesystem
+- LocalStorage: the localStorage of the browser, for the web frontend, built with the web feature
+Everything the frontend persists goes through the trait, so a host only has to implement it to
+keep the same data elsewhere, e.g. in another key-value store.
+*/
+pub trait Storage {
+    // The data of the entry, an error of kind NotFound if there is none
+    fn read(&self, key: &str) -> io::Result<Vec<u8>>;
+
+    // Create or replace the entry
+    fn write(&mut self, key: &str, data: &[u8]) -> io::Result<()>;
+
+    // Remove the entry, nothing if there is none
+    fn remove(&mut self, key: &str) -> io::Result<()>;
+
+    // The keys of the entries directly under the directory, sorted, e.g. "states/pong-1.state"
+    // for "states"
+    fn list(&self, dir: &str) -> io::Result<Vec<String>>;
+
+    // Where the entry or directory is kept, shown to users, e.g. a path
+    fn location(&self, key: &str) -> String;
+
+    fn exists(&self, key: &str) -> bool {
+        self.read(key).is_ok()
+    }
+}
+
+fn check_key(key: &str) -> io::Result<()> {
+    match key
+        .split('/')
+        .all(|part| !part.is_empty() && part != "." && part != ".." && !part.contains('\\'))
+    {
+        true => Ok(()),
+        false => Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("invalid storage key: {}", key),
+        )),
+    }
+}
+
+#[cfg(feature = "std")]
+#[derive(Clone, Debug)]
+pub struct FileStorage {
+    root: PathBuf,
+}
+
+#[cfg(feature = "std")]
+impl FileStorage {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        FileStorage { root: root.into() }
+    }
+
+    fn path(&self, key: &str) -> io::Result<PathBuf> {
+        check_key(key)?;
+        Ok(key
+            .split('/')
+            .fold(self.root.clone(), |path, part| path.join(part)))
+    }
+}
+
+#[cfg(feature = "std")]
+impl Storage for FileStorage {
+    fn read(&self, key: &str) -> io::Result<Vec<u8>> {
+        fs::read(self.path(key)?)
+    }
+
+    fn write(&mut self, key: &str, data: &[u8]) -> io::Result<()> {
+        let path = self.path(key)?;
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(path, data)
+    }
+
+    fn remove(&mut self, key: &str) -> io::Result<()> {
+        match fs::remove_file(self.path(key)?) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+            _ => Ok(()),
+        }
+    }
+
+    fn list(&self, dir: &str) -> io::Result<Vec<String>> {
+        let entries = match fs::read_dir(self.path(dir)?) {
+            Ok(entries) => entries,
+            // A directory that was never written to is empty
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e),
+        };
+        let mut keys = Vec::new();
+        for entry in entries {
+            let entry = entry?;
+            if entry.file_type()?.is_file() {
+                keys.push(format!("{}/{}", dir, entry.file_name().to_string_lossy()));
+            }
+        }
+        keys.sort();
+        Ok(keys)
+    }
+
+    fn location(&self, key: &str) -> String {
+        match self.path(key) {
+            Ok(path) => path.display().to_string(),
+            Err(_) => key.to_string(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct MemoryStorage {
+    entries: BTreeMap<String, Vec<u8>>,
+}
+
+impl MemoryStorage {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl Storage for MemoryStorage {
+    fn read(&self, key: &str) -> io::Result<Vec<u8>> {
+        check_key(key)?;
+        self.entries
+            .get(key)
+            .cloned()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, format!("no entry {}", key)))
+    }
+
+    fn write(&mut self, key: &str, data: &[u8]) -> io::Result<()> {
+        check_key(key)?;
+        self.entries.insert(key.to_string(), data.to_vec());
+        Ok(())
+    }
+
+    fn remove(&mut self, key: &str) -> io::Result<()> {
+        check_key(key)?;
+        self.entries.remove(key);
+        Ok(())
+    }
+
+    fn list(&self, dir: &str) -> io::Result<Vec<String>> {
+        check_key(dir)?;
+        let prefix = format!("{}/", dir);
+        Ok(self
+            .entries
+            .keys()
+            .filter(|key| {
+                key.strip_prefix(&prefix)
+                    .is_some_and(|name| !name.contains('/'))
+            })
+            .cloned()
+            .collect())
+    }
+
+    fn location(&self, key: &str) -> String {
+        format!("memory:{}", key)
+    }
+}
+
+/*
+The entries in the localStorage of the page, under a prefix so they don't mix with those of other
+pages of the site, e.g. "rustc8/" keeps "roms.json" as "rustc8/roms.json". localStorage only
+holds strings, the page keeps the bytes as base64, see web/index.html.
+The page provides them as imports of the module rustc8, with the C ABI like the exports of web.rs:
+- storage_get: looks the key up and keeps its bytes, returns their length, -1 if there's none
+- storage_keys: keeps the keys starting with the prefix, one per line, returns their length
+- storage_copy: copies the bytes kept by the last storage_get or storage_keys to the pointer
+- storage_set: creates or replaces the entry, false if the browser refused, e.g. over its quota
+- storage_remove: removes the entry
+*/
+#[cfg(feature = "web")]
+#[derive(Clone, Debug)]
+pub struct LocalStorage {
+    prefix: String,
+}
+
+#[cfg(feature = "web")]
+impl LocalStorage {
+    pub fn new(prefix: &str) -> Self {
+        LocalStorage {
+            prefix: prefix.to_string(),
+        }
+    }
+
+    fn item(&self, key: &str) -> io::Result<String> {
+        check_key(key)?;
+        Ok(format!("{}{}", self.prefix, key))
+    }
+
+    // What storage_get or storage_keys kept, of their length
+    fn take(len: usize) -> Vec<u8> {
+        let mut data = alloc::vec![0; len];
+        // SAFETY: the page writes the len bytes it kept, no more
+        unsafe { page::storage_copy(data.as_mut_ptr()) };
+        data
+    }
+}
+
+#[cfg(feature = "web")]
+impl Storage for LocalStorage {
+    fn read(&self, key: &str) -> io::Result<Vec<u8>> {
+        let item = self.item(key)?;
+        // SAFETY: the page only reads the key
+        match unsafe { page::storage_get(item.as_ptr(), item.len()) } {
+            len if len >= 0 => Ok(Self::take(len as usize)),
+            _ => Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("no entry {}", key),
+            )),
+        }
+    }
+
+    fn write(&mut self, key: &str, data: &[u8]) -> io::Result<()> {
+        let item = self.item(key)?;
+        // SAFETY: the page only reads the key and the data
+        match unsafe { page::storage_set(item.as_ptr(), item.len(), data.as_ptr(), data.len()) } {
+            true => Ok(()),
+            false => Err(io::Error::new(
+                io::ErrorKind::StorageFull,
+                format!("the browser refused to store {}", key),
+            )),
+        }
+    }
+
+    fn remove(&mut self, key: &str) -> io::Result<()> {
+        let item = self.item(key)?;
+        // SAFETY: the page only reads the key
+        unsafe { page::storage_remove(item.as_ptr(), item.len()) };
+        Ok(())
+    }
+
+    fn list(&self, dir: &str) -> io::Result<Vec<String>> {
+        let prefix = format!("{}/", self.item(dir)?);
+        // SAFETY: the page only reads the prefix
+        let len = unsafe { page::storage_keys(prefix.as_ptr(), prefix.len()) };
+        let items = Self::take(len.max(0) as usize);
+        let mut keys: Vec<String> = String::from_utf8_lossy(&items)
+            .lines()
+            .filter(|item| {
+                item.strip_prefix(&prefix)
+                    .is_some_and(|name| !name.is_empty() && !name.contains('/'))
+            })
+            .map(|item| item[self.prefix.len()..].to_string())
+            .collect();
+        keys.sort();
+        Ok(keys)
+    }
+
+    fn location(&self, key: &str) -> String {
+        format!("localStorage:{}{}", self.prefix, key)
+    }
+}
+
+// The imports of the page on the web, a map standing in for localStorage elsewhere, e.g. in tests
+#[cfg(all(feature = "web", target_arch = "wasm32"))]
+mod page {
+    #[link(wasm_import_module = "rustc8")]
+    extern "C" {
+        pub fn storage_get(key: *const u8, key_len: usize) -> isize;
+        pub fn storage_keys(prefix: *const u8, prefix_len: usize) -> isize;
+        pub fn storage_copy(dst: *mut u8);
+        pub fn storage_set(key: *const u8, key_len: usize, data: *const u8, len: usize) -> bool;
+        pub fn storage_remove(key: *const u8, key_len: usize);
+    }
+}
+
+#[cfg(all(feature = "web", not(target_arch = "wasm32")))]
+mod page {
+    use alloc::{collections::BTreeMap, string::String, vec::Vec};
+    use core::{cell::RefCell, slice};
+    extern crate std;
+    use std::thread_local;
+
+    thread_local! {
+        static ITEMS: RefCell<BTreeMap<String, Vec<u8>>> = const { RefCell::new(BTreeMap::new()) };
+        static KEPT: RefCell<Vec<u8>> = const { RefCell::new(Vec::new()) };
+    }
+
+    unsafe fn string(ptr: *const u8, len: usize) -> String {
+        String::from_utf8_lossy(slice::from_raw_parts(ptr, len)).into_owned()
+    }
+
+    fn keep(data: Vec<u8>) -> isize {
+        let len = data.len() as isize;
+        KEPT.set(data);
+        len
+    }
+
+    pub unsafe fn storage_get(key: *const u8, key_len: usize) -> isize {
+        match ITEMS.with_borrow(|items| items.get(&string(key, key_len)).cloned()) {
+            Some(data) => keep(data),
+            None => -1,
+        }
+    }
+
+    pub unsafe fn storage_keys(prefix: *const u8, prefix_len: usize) -> isize {
+        let prefix = string(prefix, prefix_len);
+        let keys = ITEMS.with_borrow(|items| {
+            items
+                .keys()
+                .filter(|item| item.starts_with(&prefix))
+                .map(|item| alloc::format!("{}\n", item))
+                .collect::<String>()
+        });
+        keep(keys.into_bytes())
+    }
+
+    pub unsafe fn storage_copy(dst: *mut u8) {
+        KEPT.with_borrow(|kept| dst.copy_from_nonoverlapping(kept.as_ptr(), kept.len()));
+    }
+
+    pub unsafe fn storage_set(key: *const u8, key_len: usize, data: *const u8, len: usize) -> bool {
+        let data = slice::from_raw_parts(data, len).to_vec();
+        ITEMS.with_borrow_mut(|items| items.insert(string(key, key_len), data));
+        true
+    }
+
+    pub unsafe fn storage_remove(key: *const u8, key_len: usize) {
+        ITEMS.with_borrow_mut(|items| items.remove(&string(key, key_len)));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The same behaviour from every backend
+    fn check(storage: &mut dyn Storage) {
+        assert_eq!(
+            storage.read("states/a.state").unwrap_err().kind(),
+            io::ErrorKind::NotFound
+        );
+        assert_eq!(storage.list("states").unwrap(), Vec::<String>::new());
+        storage.write("states/b.state", &[2]).unwrap();
+        storage.write("states/a.state", &[1]).unwrap();
+        storage.write("states/old/c.state", &[3]).unwrap();
+        storage.write("roms.json", b"{}").unwrap();
+        assert_eq!(storage.read("states/a.state").unwrap(), [1]);
+        assert_eq!(
+            storage.list("states").unwrap(),
+            ["states/a.state", "states/b.state"]
+        );
+        storage.remove("states/a.state").unwrap();
+        storage.remove("states/a.state").unwrap();
+        assert!(!storage.exists("states/a.state"));
+        assert!(storage.exists("roms.json"));
+
+        for key in ["../roms.json", "/roms.json", "states/", "states/./a"] {
+            assert_eq!(
+                storage.write(key, &[]).unwrap_err().kind(),
+                io::ErrorKind::InvalidInput
+            );
+        }
+    }
+
+    #[test]
+    fn test_memory_storage() {
+        check(&mut MemoryStorage::new());
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_file_storage() {
+        let dir = std::env::temp_dir().join("rustc8-storage-test");
+        let _ = fs::remove_dir_all(&dir);
+        let mut storage = FileStorage::new(&dir);
+        check(&mut storage);
+        assert_eq!(fs::read(dir.join("states").join("b.state")).unwrap(), [2]);
+        assert_eq!(
+            storage.location("roms.json"),
+            dir.join("roms.json").display().to_string()
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[cfg(feature = "web")]
+    #[test]
+    fn test_local_storage() {
+        let mut storage = LocalStorage::new("rustc8/");
+        check(&mut storage);
+        // Other pages of the site keep their entries
+        let mut other = LocalStorage::new("other/");
+        other.write("states/d.state", &[4]).unwrap();
+        assert_eq!(storage.list("states").unwrap(), ["states/b.state"]);
+        assert_eq!(
+            storage.location("roms.json"),
+            "localStorage:rustc8/roms.json"
+        );
+    }
+}
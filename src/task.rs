@@ -0,0 +1,152 @@
+use crate::i18n::tr;
+use std::{
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        mpsc::{self, Receiver},
+        Arc,
+    },
+    thread,
+    time::Instant,
+};
+
+const SPINNER: [char; 10] = ['⠋', '⠙', '⠹', '⠸', '⠼', '⠴', '⠦', '⠧', '⠇', '⠏'];
+const SPINNER_FRAME_MS: u128 = 80;
+// Width of the progress bar, in characters
+const BAR_WIDTH: usize = 20;
+
+/*
+Background tasks
+Long operations (downloads, scanning a directory of ROMs) run on a thread so the interface keeps
+drawing and reading keys. The work reports its progress, shown as a spinner, or as a progress bar
+once it knows how many steps it has, and checks between steps whether it was cancelled with Esc.
+Work that can't stop halfway, such as a download, carries on in the background when cancelled
+and its result is dropped.
+*/
+#[derive(Default)]
+pub struct Progress {
+    done: AtomicUsize,
+    // 0 until the number of steps is known
+    total: AtomicUsize,
+    cancelled: AtomicBool,
+}
+
+impl Progress {
+    pub fn set_total(&self, total: usize) {
+        self.done.store(0, Ordering::Relaxed);
+        self.total.store(total, Ordering::Relaxed);
+    }
+
+    pub fn advance(&self) {
+        self.done.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+}
+
+pub struct Task<T> {
+    label: String,
+    progress: Arc<Progress>,
+    result: Receiver<T>,
+    started: Instant,
+}
+
+impl<T: Send + 'static> Task<T> {
+    pub fn spawn<F>(label: &str, work: F) -> Self
+    where
+        F: FnOnce(&Progress) -> T + Send + 'static,
+    {
+        let progress = Arc::new(Progress::default());
+        let (sender, result) = mpsc::channel();
+        let worker = Arc::clone(&progress);
+        thread::spawn(move || {
+            // Nobody is waiting for the result of a cancelled task
+            let _ = sender.send(work(&worker));
+        });
+        Task {
+            label: label.to_string(),
+            progress,
+            result,
+            started: Instant::now(),
+        }
+    }
+}
+
+impl<T> Task<T> {
+    pub fn cancel(&self) {
+        self.progress.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    // The result once the work is done, taken by the first call
+    pub fn poll(&self) -> Option<T> {
+        self.result.try_recv().ok()
+    }
+
+    // "⠹ Downloading [#####     ] 5/10 (Esc to cancel)"
+    pub fn status(&self) -> String {
+        let tick = self.started.elapsed().as_millis() / SPINNER_FRAME_MS;
+        let spinner = SPINNER[tick as usize % SPINNER.len()];
+        let done = self.progress.done.load(Ordering::Relaxed);
+        let total = self.progress.total.load(Ordering::Relaxed);
+        match total {
+            0 => format!("{} {} {}", spinner, self.label, tr("(Esc to cancel)")),
+            _ => format!(
+                "{} {} {} {}/{} {}",
+                spinner,
+                self.label,
+                bar(done, total),
+                done.min(total),
+                total,
+                tr("(Esc to cancel)")
+            ),
+        }
+    }
+}
+
+fn bar(done: usize, total: usize) -> String {
+    let filled = BAR_WIDTH * done.min(total) / total.max(1);
+    format!("[{}{}]", "#".repeat(filled), " ".repeat(BAR_WIDTH - filled))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn wait<T>(task: &Task<T>) -> T {
+        loop {
+            if let Some(result) = task.poll() {
+                return result;
+            }
+            thread::sleep(Duration::from_millis(1));
+        }
+    }
+
+    #[test]
+    fn test_task() {
+        let task = Task::spawn("Counting", |progress| {
+            progress.set_total(4);
+            for _ in 0..4 {
+                progress.advance();
+            }
+            7
+        });
+        assert_eq!(wait(&task), 7);
+        assert!(task
+            .status()
+            .ends_with("Counting [####################] 4/4 (Esc to cancel)"));
+        assert_eq!(bar(1, 4), format!("[#####{}]", " ".repeat(15)));
+
+        // The work sees the cancellation
+        let (sender, receiver) = mpsc::channel::<()>();
+        let task = Task::spawn("Waiting", move |progress| {
+            let _ = receiver.recv();
+            progress.is_cancelled()
+        });
+        assert!(task.status().ends_with(" Waiting (Esc to cancel)"));
+        task.cancel();
+        sender.send(()).unwrap();
+        assert!(wait(&task));
+    }
+}
@@ -0,0 +1,348 @@
+use crate::i18n::{tr, trf};
+use ratatui::{
+    buffer::Buffer,
+    layout::Rect,
+    style::{Modifier, Style},
+    text::Line,
+    widgets::{Block, Paragraph, Widget, Wrap},
+};
+use rustc8::{
+    chip8::Chip8,
+    disasm,
+    step::{Step, StepObserver},
+};
+use std::collections::VecDeque;
+
+// Width of the teaching pane, explanations are wrapped to fit
+pub const PANE_WIDTH: u16 = 44;
+
+// Number of executed instructions kept, more than fit in the pane
+const HISTORY_SIZE: usize = 32;
+
+/*
+Teaching mode, `--teach` or F11
+A pane that explains each instruction in plain language with the values it works on, e.g.
+"Draws an 8x5 sprite from I (0x2A0) at V0, V1 (12, 8); VF is set to 1 if it erases a pixel".
+The instruction at PC, the next one to run, is explained at the top, followed by the ones
+executed most recently. Explanations take the quirks into account (which register SHR shifts,
+whether LD [I] moves I), and use the values from before the instruction ran.
+At full speed the history scrolls by too fast to read, it's meant to be followed with a
+breakpoint (--break) and stepping, or a low --ipf.
+Instructions are only explained while the pane is shown.
+*/
+struct Entry {
+    addr: u16,
+    opcode: u16,
+    explanation: String,
+}
+
+pub struct TeachingPane {
+    entries: VecDeque<Entry>,
+}
+
+impl TeachingPane {
+    pub fn new() -> Self {
+        TeachingPane {
+            entries: VecDeque::with_capacity(HISTORY_SIZE),
+        }
+    }
+
+    // Called before each cycle, records the instruction about to run
+    pub fn record(&mut self, chip8: &Chip8) {
+        let Some(entry) = Entry::at_pc(chip8) else {
+            return;
+        };
+        if self.entries.len() == HISTORY_SIZE {
+            self.entries.pop_back();
+        }
+        self.entries.push_front(entry);
+    }
+
+    pub fn render(&self, chip8: &Chip8, area: Rect, buf: &mut Buffer) {
+        let block = Block::bordered().title(tr(" Teaching mode (F11: hide) "));
+        let mut lines = Vec::new();
+        if let Some(next) = Entry::at_pc(chip8) {
+            lines.push(Line::styled(
+                trf("Next: {}", &[&next.heading()]),
+                Style::default().add_modifier(Modifier::REVERSED),
+            ));
+            lines.push(Line::raw(next.explanation));
+        }
+        for entry in &self.entries {
+            lines.push(Line::raw(""));
+            lines.push(Line::styled(
+                entry.heading(),
+                Style::default().add_modifier(Modifier::BOLD),
+            ));
+            lines.push(Line::raw(entry.explanation.clone()));
+        }
+        Paragraph::new(lines)
+            .wrap(Wrap { trim: false })
+            .block(block)
+            .render(area, buf);
+    }
+}
+
+impl Entry {
+    fn at_pc(chip8: &Chip8) -> Option<Self> {
+        let opcode = disasm::opcode_at(&chip8.memory, chip8.pc as usize)?;
+        Some(Entry {
+            addr: chip8.pc,
+            opcode,
+            explanation: explain(opcode, chip8),
+        })
+    }
+
+    // "200  LD V0, 0x05"
+    fn heading(&self) -> String {
+        format!("{:03X}  {}", self.addr, disasm::disassemble(self.opcode))
+    }
+}
+
+// What the instruction does, with the values of the machine it is about to run on
+pub fn explain(opcode: u16, chip8: &Chip8) -> String {
+    let x = ((opcode >> 8) & 0x000F) as usize;
+    let y = ((opcode >> 4) & 0x000F) as usize;
+    let kk = opcode & 0x00FF;
+    let nnn = opcode & 0x0FFF;
+    let nibble = opcode & 0x000F;
+    let quirks = &chip8.quirks;
+    // Registers by name, values in decimal and addresses in hex
+    let vx = format!("V{:X}", x);
+    let vy = format!("V{:X}", y);
+    let (x_value, y_value) = (chip8.v[x], chip8.v[y]);
+    let addr = |addr: u16| format!("{:#05X}", addr);
+    let i = addr(chip8.i);
+    let not_an_instruction = || tr("Not an instruction, the interpreter stops with an error");
+    let with = |explanation: String, note: &str, applies: bool| match applies {
+        true => format!("{}; {}", explanation, note),
+        false => explanation,
+    };
+
+    match opcode & 0xF000 {
+        0x0000 => match opcode {
+            0x00E0 => tr("Clears the screen").to_string(),
+            0x00EE => match chip8.sp.checked_sub(1) {
+                Some(top) => trf(
+                    "Returns from the subroutine to {}",
+                    &[&addr(chip8.stack[top as usize])],
+                ),
+                None => tr("Returns from a subroutine, but the stack is empty").to_string(),
+            },
+            0x00FD => tr("Ends the program, nothing else runs after it").to_string(),
+            _ => trf(
+                "Calls machine code at {} on the original computer, ignored by modern interpreters",
+                &[&addr(nnn)],
+            ),
+        },
+        0x1000 if nnn == chip8.pc => tr("Jumps to itself forever, the usual way to end a program")
+            .to_string(),
+        0x1000 => trf("Jumps to {}", &[&addr(nnn)]),
+        0x2000 => trf(
+            "Calls the subroutine at {}, saving the return address {} on the stack",
+            &[&addr(nnn), &addr(chip8.pc.wrapping_add(2))],
+        ),
+        0x3000 => trf(
+            "Skips the next instruction if {} ({}) equals {}",
+            &[&vx, &x_value, &kk],
+        ),
+        0x4000 => trf(
+            "Skips the next instruction if {} ({}) is not {}",
+            &[&vx, &x_value, &kk],
+        ),
+        0x5000 => trf(
+            "Skips the next instruction if {} ({}) equals {} ({})",
+            &[&vx, &x_value, &vy, &y_value],
+        ),
+        0x6000 => trf("Sets {} to {}", &[&vx, &kk]),
+        0x7000 => trf(
+            "Adds {} to {} ({}), VF is left unchanged",
+            &[&kk, &vx, &x_value],
+        ),
+        0x8000 => match nibble {
+            0x0 => trf("Copies {} ({}) into {}", &[&vy, &y_value, &vx]),
+            0x1..=0x3 => {
+                let operation = ["OR", "AND", "XOR"][nibble as usize - 1];
+                with(
+                    trf(
+                        "Sets {} to the bitwise {} of {} ({}) and {} ({})",
+                        &[&vx, &operation, &vx, &x_value, &vy, &y_value],
+                    ),
+                    tr("VF is reset to 0"),
+                    quirks.vf_reset,
+                )
+            }
+            0x4 => trf(
+                "Adds {} ({}) to {} ({}), VF is set to 1 on a carry",
+                &[&vy, &y_value, &vx, &x_value],
+            ),
+            0x5 => trf(
+                "Subtracts {} ({}) from {} ({}), VF is set to 0 on a borrow",
+                &[&vy, &y_value, &vx, &x_value],
+            ),
+            0x7 => trf(
+                "Sets {} to {} ({}) minus {} ({}), VF is set to 0 on a borrow",
+                &[&vx, &vy, &y_value, &vx, &x_value],
+            ),
+            0x6 | 0xE => {
+                let (source, value) = match quirks.shift_vy {
+                    true => (&vy, y_value),
+                    false => (&vx, x_value),
+                };
+                match nibble {
+                    0x6 => trf(
+                        "Shifts {} ({}) right by one into {}, VF gets the bit shifted out",
+                        &[source, &value, &vx],
+                    ),
+                    _ => trf(
+                        "Shifts {} ({}) left by one into {}, VF gets the bit shifted out",
+                        &[source, &value, &vx],
+                    ),
+                }
+            }
+            _ => not_an_instruction().to_string(),
+        },
+        0x9000 => trf(
+            "Skips the next instruction if {} ({}) is not {} ({})",
+            &[&vx, &x_value, &vy, &y_value],
+        ),
+        0xA000 => trf("Sets I to {}", &[&addr(nnn)]),
+        0xB000 => {
+            let (register, value) = match quirks.jump_vx {
+                true => (vx.as_str(), x_value),
+                false => ("V0", chip8.v[0]),
+            };
+            trf(
+                "Jumps to {} plus {} ({})",
+                &[&addr(nnn), &register, &value],
+            )
+        }
+        0xC000 => trf(
+            "Sets {} to a random number ANDed with {}",
+            &[&vx, &format!("{:#04X}", kk)],
+        ),
+        0xD000 => trf(
+            "Draws an 8x{} sprite from I ({}) at {}, {} ({}, {}); VF is set to 1 if it erases a pixel",
+            &[&nibble, &i, &vx, &vy, &x_value, &y_value],
+        ),
+        0xE000 => match kk {
+            0x9E => trf(
+                "Skips the next instruction if key {} ({}) is pressed",
+                &[&vx, &format!("{:X}", x_value & 0xF)],
+            ),
+            0xA1 => trf(
+                "Skips the next instruction if key {} ({}) is not pressed",
+                &[&vx, &format!("{:X}", x_value & 0xF)],
+            ),
+            _ => not_an_instruction().to_string(),
+        },
+        0xF000 => match kk {
+            0x07 => trf("Sets {} to the delay timer ({})", &[&vx, &chip8.dt]),
+            0x0A => trf("Waits for a key press and stores the key in {}", &[&vx]),
+            0x15 => trf(
+                "Sets the delay timer to {} ({}), it counts down at 60Hz",
+                &[&vx, &x_value],
+            ),
+            0x18 => trf(
+                "Sets the sound timer to {} ({}), the buzzer sounds until it reaches 0",
+                &[&vx, &x_value],
+            ),
+            0x1E => trf("Adds {} ({}) to I ({})", &[&vx, &x_value, &i]),
+            0x29 => trf(
+                "Points I to the font sprite of digit {} ({})",
+                &[&vx, &format!("{:X}", x_value & 0xF)],
+            ),
+            0x33 => trf(
+                "Stores the hundreds, tens and ones digits of {} ({}) at I ({}), I+1 and I+2",
+                &[&vx, &x_value, &i],
+            ),
+            0x55 => with(
+                trf(
+                    "Stores V0 to {} in memory starting at I ({})",
+                    &[&vx, &i],
+                ),
+                tr("I moves past the last register"),
+                quirks.increment_i,
+            ),
+            0x65 => with(
+                trf(
+                    "Loads V0 to {} from memory starting at I ({})",
+                    &[&vx, &i],
+                ),
+                tr("I moves past the last register"),
+                quirks.increment_i,
+            ),
+            0x75 => trf(
+                "Saves V0 to {} in the RPL user flags, which are kept after the game is closed",
+                &[&vx],
+            ),
+            0x85 => trf("Loads V0 to {} from the RPL user flags", &[&vx]),
+            _ => not_an_instruction().to_string(),
+        },
+        _ => not_an_instruction().to_string(),
+    }
+}
+
+impl StepObserver for TeachingPane {
+    fn before_step(&mut self, _step: Step, chip8: &Chip8) {
+        self.record(chip8);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rustc8::chip8::Quirks;
+
+    #[test]
+    fn test_explain() {
+        let mut chip8 = Chip8::new();
+        chip8.v[0] = 12;
+        chip8.v[1] = 8;
+        chip8.i = 0x2A0;
+        assert_eq!(
+            explain(0xD015, &chip8),
+            "Draws an 8x5 sprite from I (0x2A0) at V0, V1 (12, 8); VF is set to 1 if it erases a pixel"
+        );
+        assert_eq!(explain(0x6A05, &chip8), "Sets VA to 5");
+        assert_eq!(
+            explain(0xFFFF, &chip8),
+            "Not an instruction, the interpreter stops with an error"
+        );
+        assert_eq!(
+            explain(0x00EE, &chip8),
+            "Returns from a subroutine, but the stack is empty"
+        );
+
+        // The quirks change what is explained
+        chip8.quirks = Quirks::CHIP8;
+        assert_eq!(
+            explain(0x8016, &chip8),
+            "Shifts V1 (8) right by one into V0, VF gets the bit shifted out"
+        );
+        assert!(explain(0xF155, &chip8).ends_with("; I moves past the last register"));
+        chip8.quirks.increment_i = false;
+        assert!(!explain(0xF155, &chip8).contains("I moves"));
+    }
+
+    #[test]
+    fn test_record() {
+        let mut chip8 = Chip8::new();
+        // LD V0, 5; JP 0x200
+        chip8.load_rom(&[0x60, 0x05, 0x12, 0x00]).unwrap();
+        let mut pane = TeachingPane::new();
+        for _ in 0..HISTORY_SIZE + 1 {
+            pane.record(&chip8);
+            chip8.run_cycle().unwrap();
+        }
+        assert_eq!(pane.entries.len(), HISTORY_SIZE);
+        assert_eq!(pane.entries[0].heading(), "200  LD V0, 0x05");
+        assert_eq!(pane.entries[1].explanation, "Jumps to 0x200");
+
+        let area = Rect::new(0, 0, PANE_WIDTH, 10);
+        let mut buf = Buffer::empty(area);
+        pane.render(&chip8, area, &mut buf);
+        let line: String = (1..PANE_WIDTH - 1).map(|x| buf[(x, 1)].symbol()).collect();
+        assert_eq!(line.trim_end(), "Next: 202  JP 0x200");
+    }
+}
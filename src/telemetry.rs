@@ -0,0 +1,114 @@
+use crate::i18n::trf;
+use ratatui::{
+    buffer::Buffer,
+    layout::{Constraint, Layout, Rect},
+    widgets::{Block, RenderDirection, Sparkline, Widget},
+};
+use rustc8::{
+    chip8::Chip8,
+    step::{Step, StepObserver},
+};
+use std::{collections::VecDeque, time::Instant};
+
+// Number of frames kept in the history, enough to fill a wide terminal
+const HISTORY_SIZE: usize = 256;
+
+// Height of the telemetry pane, two bordered sparklines of 2 rows each
+pub const PANE_HEIGHT: u16 = 8;
+
+/*
+Telemetry records, for each frame drawn:
+- The frame time, i.e. the wall time since the previous frame in microseconds
+- The number of cycles executed by the core since the previous frame
+Most recent samples are at the back of the queues.
+*/
+pub struct Telemetry {
+    frame_times: VecDeque<u64>,
+    cycles_per_frame: VecDeque<u64>,
+    last_frame: Instant,
+    cycles: u64,
+}
+
+impl Telemetry {
+    pub fn new() -> Self {
+        Telemetry {
+            frame_times: VecDeque::with_capacity(HISTORY_SIZE),
+            cycles_per_frame: VecDeque::with_capacity(HISTORY_SIZE),
+            last_frame: Instant::now(),
+            cycles: 0,
+        }
+    }
+
+    pub fn record_cycle(&mut self) {
+        self.cycles += 1;
+    }
+
+    pub fn record_frame(&mut self) {
+        let now = Instant::now();
+        let frame_time = now.duration_since(self.last_frame).as_micros() as u64;
+        push_sample(&mut self.frame_times, frame_time);
+        push_sample(&mut self.cycles_per_frame, self.cycles);
+        self.last_frame = now;
+        self.cycles = 0;
+    }
+}
+
+fn push_sample(samples: &mut VecDeque<u64>, sample: u64) {
+    if samples.len() == HISTORY_SIZE {
+        samples.pop_front();
+    }
+    samples.push_back(sample);
+}
+
+impl Widget for &Telemetry {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        let [frame_area, cycles_area] =
+            Layout::vertical([Constraint::Fill(1), Constraint::Fill(1)]).areas(area);
+
+        let last_frame_time = self.frame_times.back().copied().unwrap_or(0);
+        let last_cycles = self.cycles_per_frame.back().copied().unwrap_or(0);
+
+        // Newest sample first and drawn from the right, so the graph scrolls to the left
+        Sparkline::default()
+            .block(Block::bordered().title(trf(
+                "Frame time: {} ms",
+                &[&format!("{:.1}", last_frame_time as f64 / 1000.0)],
+            )))
+            .data(self.frame_times.iter().rev().copied())
+            .direction(RenderDirection::RightToLeft)
+            .render(frame_area, buf);
+        Sparkline::default()
+            .block(Block::bordered().title(trf("Cycles per frame: {}", &[&last_cycles])))
+            .data(self.cycles_per_frame.iter().rev().copied())
+            .direction(RenderDirection::RightToLeft)
+            .render(cycles_area, buf);
+    }
+}
+
+impl StepObserver for Telemetry {
+    fn after_step(&mut self, _step: Step, _chip8: &Chip8) {
+        self.record_cycle();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_record_frame() {
+        let mut telemetry = Telemetry::new();
+        telemetry.record_cycle();
+        telemetry.record_cycle();
+        telemetry.record_frame();
+        telemetry.record_frame();
+        assert_eq!(telemetry.cycles_per_frame, [2, 0]);
+        assert_eq!(telemetry.frame_times.len(), 2);
+
+        for _ in 0..HISTORY_SIZE {
+            telemetry.record_frame();
+        }
+        assert_eq!(telemetry.frame_times.len(), HISTORY_SIZE);
+        assert_eq!(telemetry.cycles_per_frame.len(), HISTORY_SIZE);
+    }
+}
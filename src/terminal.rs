@@ -0,0 +1,179 @@
+use ratatui::{buffer::Buffer, style::Color};
+use rustc8::widget::Renderer;
+use std::{env, fmt};
+
+/*
+Terminal capabilities, probed from the environment at startup
+Terminals don't reliably answer queries about what they support, so like most terminal
+programs we go by the variables they set:
+- TERM=dumb (or unset) has no cursor addressing or Unicode, e.g. the output pane of an editor
+- TERM=linux and vt* consoles have box drawing and block glyphs but no braille
+- LC_ALL, LC_CTYPE or LANG ending in UTF-8 means Unicode glyphs can be printed
+- COLORTERM=truecolor/24bit or a TERM with 256color for the color depth
+- kitty sets KITTY_WINDOW_ID, and a few terminals known to support sixel graphics by name
+Graphics protocols are only detected for now, the renderers all draw with text.
+*/
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ColorDepth {
+    Monochrome,
+    Ansi16,
+    Ansi256,
+    TrueColor,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Capabilities {
+    pub dumb: bool,
+    pub unicode: bool,
+    pub braille: bool,
+    pub color: ColorDepth,
+    pub kitty: bool,
+    pub sixel: bool,
+}
+
+// Terminals with sixel support, matched against TERM and TERM_PROGRAM
+const SIXEL_TERMINALS: &[&str] = &["foot", "mlterm", "wezterm", "contour", "yaft"];
+
+impl Capabilities {
+    pub fn detect() -> Self {
+        Self::from_env(|name| env::var(name).ok().filter(|value| !value.is_empty()))
+    }
+
+    fn from_env(var: impl Fn(&str) -> Option<String>) -> Self {
+        let term = var("TERM").unwrap_or_default().to_ascii_lowercase();
+        let program = var("TERM_PROGRAM").unwrap_or_default().to_ascii_lowercase();
+        let dumb = term.is_empty() || term == "dumb";
+        let console = term == "linux" || term.starts_with("vt");
+
+        // The first of these that is set decides the character set
+        let locale = ["LC_ALL", "LC_CTYPE", "LANG"]
+            .iter()
+            .find_map(|name| var(name))
+            .unwrap_or_default()
+            .to_ascii_lowercase();
+        let unicode = !dumb && (locale.contains("utf-8") || locale.contains("utf8"));
+
+        let colorterm = var("COLORTERM").unwrap_or_default();
+        let color = if dumb {
+            ColorDepth::Monochrome
+        } else if colorterm == "truecolor" || colorterm == "24bit" {
+            ColorDepth::TrueColor
+        } else if term.contains("256color") {
+            ColorDepth::Ansi256
+        } else {
+            ColorDepth::Ansi16
+        };
+
+        let kitty = var("KITTY_WINDOW_ID").is_some() || term == "xterm-kitty";
+        let sixel = SIXEL_TERMINALS
+            .iter()
+            .any(|name| term.contains(name) || program.contains(name));
+
+        Capabilities {
+            dumb,
+            unicode,
+            braille: unicode && !console,
+            color,
+            kitty,
+            sixel,
+        }
+    }
+
+    // Best renderer the terminal can draw, used unless one is picked with --renderer
+    pub fn renderer(&self) -> Renderer {
+        if self.unicode {
+            Renderer::Text
+        } else {
+            Renderer::Ascii
+        }
+    }
+
+    // Renderer for --remote, halfblock draws the display with a quarter of the cells of text
+    pub fn remote_renderer(&self) -> Renderer {
+        if self.unicode {
+            Renderer::HalfBlock
+        } else {
+            Renderer::Ascii
+        }
+    }
+}
+
+// Drops the colors of a frame, keeping bold and reversed text, for --remote where every color
+// change is more bytes to send
+pub fn strip_colors(buf: &mut Buffer) {
+    for cell in &mut buf.content {
+        cell.set_fg(Color::Reset).set_bg(Color::Reset);
+    }
+}
+
+// Shown by `rustc8 terminal`, to check what was detected
+impl fmt::Display for Capabilities {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let yes_no = |value: bool| if value { "yes" } else { "no" };
+        writeln!(f, "Dumb terminal: {}", yes_no(self.dumb))?;
+        writeln!(f, "Unicode: {}", yes_no(self.unicode))?;
+        writeln!(f, "Braille: {}", yes_no(self.braille))?;
+        writeln!(f, "Colors: {:?}", self.color)?;
+        writeln!(f, "Kitty graphics: {}", yes_no(self.kitty))?;
+        writeln!(f, "Sixel graphics: {}", yes_no(self.sixel))?;
+        write!(f, "Renderer: {:?}", self.renderer())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ratatui::style::{Modifier, Style};
+
+    fn detect(vars: &[(&str, &str)]) -> Capabilities {
+        Capabilities::from_env(|name| {
+            vars.iter()
+                .find(|(key, _)| *key == name)
+                .map(|(_, value)| value.to_string())
+        })
+    }
+
+    #[test]
+    fn test_detect() {
+        let dumb = detect(&[("TERM", "dumb"), ("LANG", "en_US.UTF-8")]);
+        assert!(dumb.dumb);
+        assert!(!dumb.unicode);
+        assert_eq!(dumb.color, ColorDepth::Monochrome);
+        assert_eq!(dumb.renderer(), Renderer::Ascii);
+        assert_eq!(dumb.remote_renderer(), Renderer::Ascii);
+
+        let console = detect(&[("TERM", "linux"), ("LANG", "C.UTF-8")]);
+        assert!(console.unicode);
+        assert!(!console.braille);
+
+        let kitty = detect(&[
+            ("TERM", "xterm-kitty"),
+            ("COLORTERM", "truecolor"),
+            ("LC_ALL", "en_GB.utf8"),
+            ("LANG", "C"),
+        ]);
+        assert!(kitty.kitty && kitty.unicode && kitty.braille);
+        assert_eq!(kitty.color, ColorDepth::TrueColor);
+        assert_eq!(kitty.renderer(), Renderer::Text);
+        assert_eq!(kitty.remote_renderer(), Renderer::HalfBlock);
+
+        let foot = detect(&[("TERM", "foot"), ("LANG", "C")]);
+        assert!(foot.sixel);
+        assert!(!foot.unicode);
+        assert_eq!(foot.color, ColorDepth::Ansi16);
+    }
+
+    #[test]
+    fn test_strip_colors() {
+        let mut buf = Buffer::empty(ratatui::layout::Rect::new(0, 0, 2, 1));
+        let style = Style::default()
+            .fg(Color::Yellow)
+            .bg(Color::Blue)
+            .add_modifier(Modifier::REVERSED);
+        buf.set_style(buf.area, style);
+        strip_colors(&mut buf);
+        let cell = &buf[(1, 0)];
+        assert_eq!((cell.fg, cell.bg), (Color::Reset, Color::Reset));
+        assert_eq!(cell.modifier, Modifier::REVERSED);
+    }
+}
@@ -0,0 +1,265 @@
+use crate::i18n::tr;
+use ratatui::{
+    buffer::Buffer,
+    layout::{Constraint, Layout, Rect},
+    style::{Modifier, Style},
+    text::Line,
+    widgets::{Block, Widget},
+};
+use rustc8::{
+    chip8::Chip8,
+    disasm,
+    step::{Step, StepObserver},
+};
+use std::collections::VecDeque;
+
+// Width of the timeline pane, enough for "123456  63,31  I=FFF  n=15  C" and the borders
+pub const PANE_WIDTH: u16 = 32;
+
+// Number of draw calls kept, older ones are dropped
+const HISTORY_SIZE: usize = 1024;
+
+// Height of the sprite preview under the list, up to 15 rows and the borders
+const PREVIEW_HEIGHT: u16 = 17;
+
+/*
+DrawTimeline records every DRW instruction executed, with the frame it was executed in:
+- The coordinates (Vx, Vy), the sprite address (I) and its height
+- The sprite bytes as they were at the time of the draw
+- Whether it erased any pixel (VF set to 1)
+Flicker shows up as the same sprite being erased and drawn again in consecutive entries,
+and overlaps as collisions, which is easier to follow by stepping through the history
+than by watching the screen.
+The selection follows the newest entry until it is moved back in the history.
+*/
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DrawRecord {
+    pub frame: u64,
+    pub x: u8,
+    pub y: u8,
+    pub i: u16,
+    pub sprite: Vec<u8>,
+    pub collision: bool,
+}
+
+pub struct DrawTimeline {
+    records: VecDeque<DrawRecord>,
+    frame: u64,
+    // Number of entries back from the newest one, 0 follows the newest entry
+    selected: usize,
+    // The draw call of the instruction running, recorded once it ran
+    pending: Option<DrawRecord>,
+}
+
+impl DrawTimeline {
+    pub fn new() -> Self {
+        DrawTimeline {
+            records: VecDeque::with_capacity(HISTORY_SIZE),
+            frame: 0,
+            selected: 0,
+            pending: None,
+        }
+    }
+
+    pub fn next_frame(&mut self) {
+        self.frame += 1;
+    }
+
+    // Called before each cycle, returns the pending draw call if the next instruction is DRW
+    pub fn decode(&self, chip8: &Chip8) -> Option<DrawRecord> {
+        let opcode = disasm::opcode_at(&chip8.memory, chip8.pc as usize)?;
+        if opcode & 0xF000 != 0xD000 {
+            return None;
+        }
+        let x = ((opcode >> 8) & 0x000F) as usize;
+        let y = ((opcode >> 4) & 0x000F) as usize;
+        let size = (opcode & 0x000F) as usize;
+        let sprite = (0..size)
+            .map(|line| chip8.memory[(chip8.i as usize + line) % chip8.memory.len()])
+            .collect();
+        Some(DrawRecord {
+            frame: self.frame,
+            x: chip8.v[x],
+            y: chip8.v[y],
+            i: chip8.i,
+            sprite,
+            collision: false,
+        })
+    }
+
+    // Called after the cycle with the draw call returned by decode
+    pub fn record(&mut self, mut draw: DrawRecord, chip8: &Chip8) {
+        draw.collision = chip8.v[0xF] == 1;
+        if self.records.len() == HISTORY_SIZE {
+            self.records.pop_front();
+        }
+        self.records.push_back(draw);
+        // Keep the same entry selected while new ones come in
+        if self.selected > 0 {
+            self.selected = (self.selected + 1).min(self.records.len() - 1);
+        }
+    }
+
+    // Move the selection back (older) or forward (newer) in the history
+    pub fn step(&mut self, entries: isize) {
+        let last = self.records.len().saturating_sub(1) as isize;
+        self.selected = (self.selected as isize - entries).clamp(0, last) as usize;
+    }
+
+    pub fn select_newest(&mut self) {
+        self.selected = 0;
+    }
+
+    fn selected_index(&self) -> Option<usize> {
+        self.records.len().checked_sub(self.selected + 1)
+    }
+}
+
+impl Widget for &DrawTimeline {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        let [list_area, preview_area] =
+            Layout::vertical([Constraint::Fill(1), Constraint::Length(PREVIEW_HEIGHT)]).areas(area);
+
+        let title = if self.selected == 0 {
+            tr(" Draw timeline (live) ")
+        } else {
+            tr(" Draw timeline (PgDn/Home) ")
+        };
+        let block = Block::bordered().title(title);
+        let inner = block.inner(list_area);
+        block.render(list_area, buf);
+
+        // The selected entry is kept on the last row, with the entries before it above
+        let Some(selected) = self.selected_index() else {
+            return;
+        };
+        let rows = inner.height as usize;
+        let first = (selected + 1).saturating_sub(rows);
+        for (row, index) in (first..=selected).enumerate() {
+            let draw = &self.records[index];
+            let text = format!(
+                "{:>6}  {:>2},{:>2}  I={:03X}  n={:<2} {}",
+                draw.frame,
+                draw.x,
+                draw.y,
+                draw.i,
+                draw.sprite.len(),
+                if draw.collision { 'C' } else { ' ' }
+            );
+            let style = if index == selected {
+                Style::default().add_modifier(Modifier::REVERSED)
+            } else {
+                Style::default()
+            };
+            Line::styled(text, style).render(
+                Rect {
+                    y: inner.y + row as u16,
+                    height: 1,
+                    ..inner
+                },
+                buf,
+            );
+        }
+
+        let block = Block::bordered().title(tr(" Sprite "));
+        let inner = block.inner(preview_area);
+        block.render(preview_area, buf);
+        for (row, byte) in self.records[selected].sprite.iter().enumerate() {
+            if row as u16 >= inner.height {
+                break;
+            }
+            let line: String = (0..8)
+                .map(|bit| {
+                    if byte & (0x80 >> bit) != 0 {
+                        "██"
+                    } else {
+                        "  "
+                    }
+                })
+                .collect();
+            Line::raw(format!("{}  {:02X}", line, byte)).render(
+                Rect {
+                    y: inner.y + row as u16,
+                    height: 1,
+                    ..inner
+                },
+                buf,
+            );
+        }
+    }
+}
+
+impl StepObserver for DrawTimeline {
+    fn before_step(&mut self, _step: Step, chip8: &Chip8) {
+        self.pending = self.decode(chip8);
+    }
+
+    fn after_step(&mut self, _step: Step, chip8: &Chip8) {
+        if let Some(draw) = self.pending.take() {
+            self.record(draw, chip8);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn draw_sprite(timeline: &mut DrawTimeline, chip8: &mut Chip8) {
+        chip8.pc = 0x200;
+        let draw = timeline.decode(chip8).unwrap();
+        chip8.run_cycle().unwrap();
+        timeline.record(draw, chip8);
+    }
+
+    #[test]
+    fn test_record_draw() {
+        let mut chip8 = Chip8::new();
+        // DRW V0, V1, 5 with I pointing to the font sprite for 0
+        chip8.load_rom(&[0xD0, 0x15]).unwrap();
+        chip8.v[0] = 10;
+        chip8.v[1] = 20;
+
+        let mut timeline = DrawTimeline::new();
+        draw_sprite(&mut timeline, &mut chip8);
+        timeline.next_frame();
+        draw_sprite(&mut timeline, &mut chip8);
+
+        assert_eq!(
+            timeline.records[1],
+            DrawRecord {
+                frame: 1,
+                x: 10,
+                y: 20,
+                i: 0,
+                sprite: vec![0xF0, 0x90, 0x90, 0x90, 0xF0],
+                collision: true,
+            }
+        );
+        assert!(!timeline.records[0].collision);
+
+        chip8.pc = 0x202;
+        assert_eq!(timeline.decode(&chip8), None);
+    }
+
+    #[test]
+    fn test_step() {
+        let mut chip8 = Chip8::new();
+        chip8.load_rom(&[0xD0, 0x15]).unwrap();
+        let mut timeline = DrawTimeline::new();
+        for _ in 0..3 {
+            draw_sprite(&mut timeline, &mut chip8);
+        }
+
+        timeline.step(-5);
+        assert_eq!(timeline.selected_index(), Some(0));
+        // New entries don't move the selection away from the entry being looked at
+        draw_sprite(&mut timeline, &mut chip8);
+        assert_eq!(timeline.selected_index(), Some(0));
+
+        timeline.step(1);
+        assert_eq!(timeline.selected_index(), Some(1));
+        timeline.select_newest();
+        assert_eq!(timeline.selected_index(), Some(3));
+    }
+}
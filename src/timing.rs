@@ -0,0 +1,187 @@
+use crate::{
+    chip8::{Chip8, Chip8Error},
+    disasm,
+};
+
+/*
+Instruction timing
+How much a frame runs. By default the frontends run a fixed number of instructions per frame
+(ipf), every instruction taking the same time. With the Vip timing each instruction costs about
+what it took the interpreter of the COSMAC VIP, in machine cycles of its CPU, and a frame runs
+as many as fit in the cycles the VIP had per frame. Games that pace themselves by counting
+instructions then run at the speed they had, e.g. a loop of DRW runs far fewer times per frame
+than a loop of LD.
+
+The costs are approximations rounded from published measurements of the VIP interpreter, not
+a cycle-exact emulation of the CPU: DRW is charged per row drawn, but not for the bits it
+shifts or for waiting for the display interrupt, which is the display_wait quirk. The cycles an
+instruction runs past the end of a frame are taken from the next one.
+*/
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Timing {
+    #[default]
+    Fixed,
+    Vip,
+}
+
+impl Timing {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "fixed" => Some(Timing::Fixed),
+            "vip" => Some(Timing::Vip),
+            _ => None,
+        }
+    }
+}
+
+// The frontends run in frames at 60Hz, the refresh rate of the original COSMAC VIP, and clock
+// the timers once a frame
+pub const FRAME_RATE: u32 = 60;
+// Machine cycles of a 60Hz frame on the VIP, 8 clock cycles of its 1.76MHz CPU each
+pub const VIP_FRAME_CYCLES: u32 = 3668;
+// Cycles of each frame taken by the display, the DMA of the screen and its interrupt routine
+const VIP_DISPLAY_CYCLES: u32 = 1150;
+// Cycles to fetch and decode an instruction, on top of running it
+const VIP_FETCH_CYCLES: u32 = 68;
+
+// Machine cycles the VIP interpreter took to fetch and run the instruction
+pub fn vip_cycles(opcode: u16) -> u32 {
+    let x = (opcode >> 8) & 0xF;
+    let n = opcode & 0xF;
+    let cycles = match opcode & 0xF000 {
+        0x0000 if opcode == 0x00E0 => 109,
+        0x0000 | 0x1000 | 0x2000 | 0xB000 => 105,
+        0x3000 | 0x4000 | 0xA000 => 55,
+        0x5000 | 0x9000 | 0xE000 => 73,
+        0x6000 => 27,
+        0x7000 => 45,
+        0x8000 => 200,
+        0xC000 => 164,
+        0xD000 => 200 + 60 * n as u32,
+        _ => match opcode & 0xFF {
+            0x1E => 86,
+            0x29 => 91,
+            0x33 => 927,
+            // The registers are copied one by one
+            0x55 | 0x65 => 64 + 34 * (x as u32 + 1),
+            _ => 45,
+        },
+    };
+    VIP_FETCH_CYCLES + cycles
+}
+
+// What has been spent of the current frame, see Timing
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FrameBudget {
+    pub timing: Timing,
+    // Instructions per frame of the Fixed timing
+    pub ipf: u32,
+    // Instructions run, or VIP cycles spent, in the current frame
+    spent: u32,
+}
+
+impl FrameBudget {
+    pub fn new(timing: Timing, ipf: u32) -> Self {
+        FrameBudget {
+            timing,
+            ipf,
+            spent: 0,
+        }
+    }
+
+    fn limit(&self) -> u32 {
+        match self.timing {
+            Timing::Fixed => self.ipf,
+            Timing::Vip => VIP_FRAME_CYCLES - VIP_DISPLAY_CYCLES,
+        }
+    }
+
+    // Charge the instruction that was just run
+    pub fn spend(&mut self, opcode: u16) {
+        self.spent += match self.timing {
+            Timing::Fixed => 1,
+            Timing::Vip => vip_cycles(opcode),
+        };
+    }
+
+    // Whether the frame has run all it can
+    pub fn is_spent(&self) -> bool {
+        self.spent >= self.limit()
+    }
+
+    // Give up the rest of the frame, e.g. to wait for the vertical blank
+    pub fn end_frame(&mut self) {
+        self.spent = self.spent.max(self.limit());
+    }
+
+    // Start the next frame, with what the last instruction ran over taken from it
+    pub fn next_frame(&mut self) {
+        self.spent = self.spent.saturating_sub(self.limit());
+    }
+}
+
+impl Chip8 {
+    /*
+    Run a frame as the frontends do: instructions until the budget is spent, or the program waits
+    for a key, the next frame or has ended, then the timers, clocked at frame_rate, and the
+    vertical blank. An error stops the frame where it happened, without clocking the timers.
+    */
+    pub fn run_frame(
+        &mut self,
+        budget: &mut FrameBudget,
+        frame_rate: u32,
+    ) -> Result<(), Chip8Error> {
+        while !budget.is_spent()
+            && !self.is_waiting_for_key()
+            && !self.is_waiting_for_vblank()
+            && !self.is_halted()
+        {
+            let opcode = disasm::opcode_at(&self.memory, self.pc as usize).unwrap_or(0);
+            self.run_cycle()?;
+            budget.spend(opcode);
+        }
+        budget.end_frame();
+        budget.next_frame();
+        self.clock_timers(frame_rate);
+        self.vblank();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fixed() {
+        let mut budget = FrameBudget::new(Timing::Fixed, 2);
+        budget.spend(0xD015);
+        assert!(!budget.is_spent());
+        budget.spend(0x6001);
+        assert!(budget.is_spent());
+        budget.next_frame();
+        assert!(!budget.is_spent());
+    }
+
+    #[test]
+    fn test_vip() {
+        // Drawing costs more than loading a register, and more for taller sprites
+        assert!(vip_cycles(0xD011) > vip_cycles(0x6001));
+        assert!(vip_cycles(0xD01F) > vip_cycles(0xD011));
+
+        let mut budget = FrameBudget::new(Timing::Vip, 2);
+        let mut loads = 0;
+        while !budget.is_spent() {
+            budget.spend(0x6001);
+            loads += 1;
+        }
+        assert_eq!(loads, 27);
+        // The cycles run past the end of the frame are taken from the next one
+        budget.next_frame();
+        budget.spend(0xD01F);
+        budget.spend(0xD01F);
+        budget.end_frame();
+        budget.next_frame();
+        assert!(!budget.is_spent());
+    }
+}
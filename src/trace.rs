@@ -0,0 +1,272 @@
+use crate::{
+    chip8::Chip8,
+    step::{Step, StepObserver},
+};
+use std::{
+    fmt,
+    io::{self, Write},
+};
+
+/*
+Tracepoints
+A tracepoint logs a message every time the instruction at its address is about to be executed,
+without stopping the emulation, so that real time games can be observed without breaking their timing.
+
+The message is a format string where fields in braces are replaced by the current values:
+- {V0} to {VF}, {I}, {PC}, {SP}, {DT}, {ST}: registers
+- {[addr]}: the byte in memory at a hexadecimal address, e.g. {[0x3F0]}
+- {[I]}, {[I+n]}: the byte in memory at I, or at I plus a hexadecimal offset
+All values are shown in hexadecimal. Use {{ and }} for literal braces.
+*/
+#[derive(Debug, PartialEq, Eq)]
+pub enum TraceError {
+    UnknownField(String),
+    UnclosedField,
+    InvalidAddress(String),
+}
+
+impl fmt::Display for TraceError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TraceError::UnknownField(field) => write!(f, "Unknown tracepoint field: {{{}}}", field),
+            TraceError::UnclosedField => write!(f, "Unclosed brace in tracepoint message"),
+            TraceError::InvalidAddress(addr) => write!(f, "Invalid tracepoint address: {}", addr),
+        }
+    }
+}
+
+impl std::error::Error for TraceError {}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Segment {
+    Text(String),
+    Register(usize),
+    I,
+    Pc,
+    Sp,
+    Dt,
+    St,
+    Memory(u16),
+    MemoryAtI(u16),
+}
+
+#[derive(Debug, Clone)]
+pub struct Tracepoint {
+    pub addr: u16,
+    segments: Vec<Segment>,
+}
+
+impl Tracepoint {
+    pub fn new(addr: u16, message: &str) -> Result<Self, TraceError> {
+        Ok(Tracepoint {
+            addr,
+            segments: parse_message(message)?,
+        })
+    }
+
+    pub fn format(&self, chip8: &Chip8) -> String {
+        let memory = |addr: u16| chip8.memory[addr as usize % chip8.memory.len()];
+        self.segments
+            .iter()
+            .map(|segment| match segment {
+                Segment::Text(text) => text.clone(),
+                Segment::Register(x) => format!("{:02X}", chip8.v[*x]),
+                Segment::I => format!("{:03X}", chip8.i),
+                Segment::Pc => format!("{:03X}", chip8.pc),
+                Segment::Sp => format!("{:X}", chip8.sp),
+                Segment::Dt => format!("{:02X}", chip8.dt),
+                Segment::St => format!("{:02X}", chip8.st),
+                Segment::Memory(addr) => format!("{:02X}", memory(*addr)),
+                Segment::MemoryAtI(offset) => {
+                    format!("{:02X}", memory(chip8.i.wrapping_add(*offset)))
+                }
+            })
+            .collect()
+    }
+}
+
+/*
+Tracer
+Writes the messages of the tracepoints to a log as the instructions are reached, see
+StepObserver. A write error stops the tracing and is returned by the next flush.
+*/
+pub struct Tracer<W: Write> {
+    tracepoints: Vec<Tracepoint>,
+    out: W,
+    error: Option<io::Error>,
+}
+
+impl<W: Write> Tracer<W> {
+    pub fn new(tracepoints: Vec<Tracepoint>, out: W) -> Self {
+        Tracer {
+            tracepoints,
+            out,
+            error: None,
+        }
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        if let Some(e) = self.error.take() {
+            return Err(e);
+        }
+        self.out.flush()
+    }
+}
+
+impl<W: Write> StepObserver for Tracer<W> {
+    fn before_step(&mut self, step: Step, chip8: &Chip8) {
+        if self.error.is_some() {
+            return;
+        }
+        for tracepoint in self.tracepoints.iter().filter(|t| t.addr == step.pc) {
+            if let Err(e) = writeln!(self.out, "{}", tracepoint.format(chip8)) {
+                self.error = Some(e);
+                return;
+            }
+        }
+    }
+}
+
+// Parse a hexadecimal address, with or without the 0x prefix
+pub fn parse_address(text: &str) -> Result<u16, TraceError> {
+    parse_memory_address(text)
+        .filter(|addr| *addr < 0x1000)
+        .ok_or_else(|| TraceError::InvalidAddress(text.to_string()))
+}
+
+// parse_address anywhere in 64K of memory, for the platforms with more than 4K
+pub fn parse_memory_address(text: &str) -> Option<u16> {
+    let digits = text.trim_start_matches("0x").trim_start_matches("0X");
+    u16::from_str_radix(digits, 16).ok()
+}
+
+fn parse_message(message: &str) -> Result<Vec<Segment>, TraceError> {
+    let mut segments = Vec::new();
+    let mut text = String::new();
+    let mut chars = message.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '{' if chars.peek() == Some(&'{') => {
+                chars.next();
+                text.push('{');
+            }
+            '}' if chars.peek() == Some(&'}') => {
+                chars.next();
+                text.push('}');
+            }
+            '{' => {
+                let mut field = String::new();
+                loop {
+                    match chars.next() {
+                        Some('}') => break,
+                        Some(c) => field.push(c),
+                        None => return Err(TraceError::UnclosedField),
+                    }
+                }
+                if !text.is_empty() {
+                    segments.push(Segment::Text(std::mem::take(&mut text)));
+                }
+                segments.push(parse_field(&field)?);
+            }
+            _ => text.push(c),
+        }
+    }
+    if !text.is_empty() {
+        segments.push(Segment::Text(text));
+    }
+    Ok(segments)
+}
+
+fn parse_field(field: &str) -> Result<Segment, TraceError> {
+    let unknown = || TraceError::UnknownField(field.to_string());
+    let name = field.trim().to_ascii_uppercase();
+    match name.as_str() {
+        "I" => return Ok(Segment::I),
+        "PC" => return Ok(Segment::Pc),
+        "SP" => return Ok(Segment::Sp),
+        "DT" => return Ok(Segment::Dt),
+        "ST" => return Ok(Segment::St),
+        _ => {}
+    }
+    if let Some(register) = name.strip_prefix('V') {
+        return match u8::from_str_radix(register, 16) {
+            Ok(x) if register.len() == 1 => Ok(Segment::Register(x as usize)),
+            _ => Err(unknown()),
+        };
+    }
+    let addr = name
+        .strip_prefix('[')
+        .and_then(|name| name.strip_suffix(']'))
+        .ok_or_else(unknown)?
+        .trim();
+    if addr == "I" {
+        return Ok(Segment::MemoryAtI(0));
+    }
+    if let Some(offset) = addr.strip_prefix("I+") {
+        return parse_address(offset.trim())
+            .map(Segment::MemoryAtI)
+            .map_err(|_| unknown());
+    }
+    parse_address(addr)
+        .map(Segment::Memory)
+        .map_err(|_| unknown())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format() {
+        let mut chip8 = Chip8::new();
+        chip8.pc = 0x21A;
+        chip8.i = 0x300;
+        chip8.v[0x0] = 0x05;
+        chip8.v[0xF] = 0x01;
+        chip8.memory[0x300] = 0xAB;
+        chip8.memory[0x302] = 0xCD;
+        chip8.memory[0x3F0] = 0xEF;
+
+        let tracepoint = Tracepoint::new(
+            0x21A,
+            "pc={PC} v0={V0} vf={vf} i={I} [i]={[I]} [i+2]={[I+2]} {{x}} {[0x3F0]}",
+        )
+        .unwrap();
+        assert_eq!(
+            tracepoint.format(&chip8),
+            "pc=21A v0=05 vf=01 i=300 [i]=AB [i+2]=CD {x} EF"
+        );
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert_eq!(
+            Tracepoint::new(0x200, "{V10}").unwrap_err(),
+            TraceError::UnknownField("V10".to_string())
+        );
+        assert_eq!(
+            Tracepoint::new(0x200, "{[0x1000]}").unwrap_err(),
+            TraceError::UnknownField("[0x1000]".to_string())
+        );
+        assert_eq!(
+            Tracepoint::new(0x200, "{PC").unwrap_err(),
+            TraceError::UnclosedField
+        );
+        assert_eq!(parse_address("0x21a"), Ok(0x21A));
+        assert!(parse_address("zz").is_err());
+    }
+
+    #[test]
+    fn test_tracer() {
+        let mut chip8 = Chip8::new();
+        // ADD V0, 1; JP 0x200
+        chip8.load_rom(&[0x70, 0x01, 0x12, 0x00]).unwrap();
+        let tracepoint = Tracepoint::new(0x200, "v0={V0}").unwrap();
+        let mut tracer = Tracer::new(vec![tracepoint], Vec::new());
+        for _ in 0..4 {
+            chip8.step_with(&mut tracer).unwrap();
+        }
+        tracer.flush().unwrap();
+        assert_eq!(String::from_utf8(tracer.out).unwrap(), "v0=00\nv0=01\n");
+    }
+}
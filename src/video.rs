@@ -0,0 +1,335 @@
+use ratatui::style::Color;
+use rustc8::audio::{AudioSink, SAMPLE_RATE};
+use std::{
+    io::{self, Read, Write},
+    net::TcpListener,
+    process::{Child, ChildStdin, Command, Stdio},
+    sync::mpsc::{self, Receiver, Sender, SyncSender, TryRecvError},
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+/*
+Video recording, `--record-video <file>`
+Every frame the game runs is piped to ffmpeg, which encodes the session into a video of the format
+of the file extension, e.g. out.mp4 or out.mkv:
+- the display as raw RGB, each pixel scaled up to a square in the colors it's drawn in, centered
+  in a frame of VIDEO_WIDTH x VIDEO_HEIGHT so it keeps its size when the resolution changes
+- the beep of the buzzer as 16-bit PCM, silence while it's off
+The video follows the emulated frames rather than the clock: pauses and dialogs are left out, and
+an encoder slower than the game slows it down instead of dropping frames.
+ffmpeg reads the video on its stdin and the audio from a local TCP port it connects to. It opens
+and reads its inputs in its own order, so each is written by a thread of its own.
+*/
+pub const VIDEO_WIDTH: usize = 768;
+pub const VIDEO_HEIGHT: usize = 384;
+// The colors of the terminal, for the default palette
+const DEFAULT_BACKGROUND: [u8; 3] = [0x00, 0x00, 0x00];
+const DEFAULT_FOREGROUND: [u8; 3] = [0xFF, 0xFF, 0xFF];
+// The 16 colors of xterm
+const ANSI: [[u8; 3]; 16] = [
+    [0x00, 0x00, 0x00],
+    [0x80, 0x00, 0x00],
+    [0x00, 0x80, 0x00],
+    [0x80, 0x80, 0x00],
+    [0x00, 0x00, 0x80],
+    [0x80, 0x00, 0x80],
+    [0x00, 0x80, 0x80],
+    [0xC0, 0xC0, 0xC0],
+    [0x80, 0x80, 0x80],
+    [0xFF, 0x00, 0x00],
+    [0x00, 0xFF, 0x00],
+    [0xFF, 0xFF, 0x00],
+    [0x00, 0x00, 0xFF],
+    [0xFF, 0x00, 0xFF],
+    [0x00, 0xFF, 0xFF],
+    [0xFF, 0xFF, 0xFF],
+];
+// How often the audio thread checks whether ffmpeg connected
+const CONNECT_POLL: Duration = Duration::from_millis(10);
+
+pub struct VideoRecorder {
+    ffmpeg: Child,
+    // The frames and the audio to the writer threads, None once closed
+    frames: Option<SyncSender<Vec<u8>>>,
+    audio: Option<Sender<Vec<u8>>>,
+    writers: Vec<JoinHandle<io::Result<()>>>,
+    // What the buzzer played in the current frame
+    samples: Vec<f32>,
+}
+
+impl VideoRecorder {
+    pub fn start(path: &str, frame_rate: u32) -> io::Result<Self> {
+        let listener = TcpListener::bind("127.0.0.1:0")?;
+        let audio_input = format!("tcp://{}", listener.local_addr()?);
+        let mut ffmpeg = Command::new("ffmpeg")
+            .args(["-y", "-loglevel", "error"])
+            .args(["-f", "rawvideo", "-pixel_format", "rgb24", "-video_size"])
+            .arg(format!("{}x{}", VIDEO_WIDTH, VIDEO_HEIGHT))
+            .args(["-framerate", &frame_rate.to_string(), "-i", "pipe:0"])
+            .args(["-f", "s16le", "-ar", &SAMPLE_RATE.to_string(), "-ac", "1"])
+            .args(["-i", &audio_input])
+            // Players that don't support the 4:4:4 of RGB are the norm
+            .args(["-pix_fmt", "yuv420p"])
+            .arg(path)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(|e| match e.kind() {
+                io::ErrorKind::NotFound => io::Error::new(
+                    e.kind(),
+                    "ffmpeg not found, it has to be installed to record videos",
+                ),
+                _ => e,
+            })?;
+        let stdin = ffmpeg.stdin.take().ok_or(io::ErrorKind::BrokenPipe)?;
+        // A second of frames ahead of the encoder at most
+        let (frames, frame_receiver) = mpsc::sync_channel(frame_rate as usize);
+        let (audio, audio_receiver) = mpsc::channel();
+        let writers = vec![
+            thread::spawn(move || write_video(stdin, frame_receiver)),
+            thread::spawn(move || write_audio(listener, audio_receiver)),
+        ];
+        Ok(VideoRecorder {
+            ffmpeg,
+            frames: Some(frames),
+            audio: Some(audio),
+            writers,
+            samples: Vec::new(),
+        })
+    }
+
+    // Record the frame that ended, with the colors of its pixel values and the samples it lasts.
+    // The buzzer feeds the sound through AudioSink before
+    pub fn end_frame(
+        &mut self,
+        display: &[u8],
+        size: (usize, usize),
+        colors: &[[u8; 3]],
+        samples: usize,
+    ) -> io::Result<()> {
+        let frame = render(display, size, colors);
+        self.samples.resize(samples, 0.0);
+        let audio = pcm(&self.samples);
+        self.samples.clear();
+        let sent = match (&self.frames, &self.audio) {
+            (Some(frames), Some(sender)) => {
+                frames.send(frame).is_ok() && sender.send(audio).is_ok()
+            }
+            _ => false,
+        };
+        match sent {
+            true => Ok(()),
+            // A writer stopped, the error is that of ffmpeg or the writer
+            false => Err(self
+                .close()
+                .err()
+                .unwrap_or_else(|| io::ErrorKind::BrokenPipe.into())),
+        }
+    }
+
+    // Let ffmpeg write the end of the video and wait for it
+    pub fn finish(mut self) -> io::Result<()> {
+        self.close()
+    }
+
+    fn close(&mut self) -> io::Result<()> {
+        self.frames = None;
+        self.audio = None;
+        let mut result = Ok(());
+        for writer in self.writers.drain(..) {
+            let written = writer
+                .join()
+                .unwrap_or_else(|_| Err(io::Error::other("the video writer panicked")));
+            result = result.and(written);
+        }
+        let mut errors = String::new();
+        if let Some(mut stderr) = self.ffmpeg.stderr.take() {
+            stderr.read_to_string(&mut errors)?;
+        }
+        let status = self.ffmpeg.wait()?;
+        if !status.success() {
+            let error = errors.lines().last().unwrap_or("it failed");
+            return Err(io::Error::other(format!("ffmpeg: {}", error)));
+        }
+        result
+    }
+}
+
+impl AudioSink for VideoRecorder {
+    fn start_beep(&mut self) {}
+
+    fn stop_beep(&mut self) {}
+
+    fn push_samples(&mut self, samples: &[f32]) {
+        self.samples.extend_from_slice(samples);
+    }
+
+    fn wants_samples(&self) -> bool {
+        true
+    }
+}
+
+// Stopping without finish, e.g. on an error, still ends the video properly
+impl Drop for VideoRecorder {
+    fn drop(&mut self) {
+        let _ = self.close();
+    }
+}
+
+fn write_video(mut stdin: ChildStdin, frames: Receiver<Vec<u8>>) -> io::Result<()> {
+    for frame in frames {
+        stdin.write_all(&frame)?;
+    }
+    Ok(())
+}
+
+fn write_audio(listener: TcpListener, audio: Receiver<Vec<u8>>) -> io::Result<()> {
+    // ffmpeg connects once it opened the video, the sound is kept until then
+    listener.set_nonblocking(true)?;
+    let mut pending = Vec::new();
+    let mut stream = loop {
+        match listener.accept() {
+            Ok((stream, _)) => break stream,
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => {}
+            Err(e) => return Err(e),
+        }
+        match audio.try_recv() {
+            Ok(samples) => pending.extend(samples),
+            Err(TryRecvError::Empty) => thread::sleep(CONNECT_POLL),
+            // Closed before ffmpeg got there, e.g. it failed to start
+            Err(TryRecvError::Disconnected) => return Ok(()),
+        }
+    };
+    stream.set_nonblocking(false)?;
+    stream.write_all(&pending)?;
+    for samples in audio {
+        stream.write_all(&samples)?;
+    }
+    Ok(())
+}
+
+// The colors of the pixel values: those of MEGA-CHIP, or the palette
+pub fn colors(pixel_colors: &[Color], foreground: Color, background: Color) -> Vec<[u8; 3]> {
+    match pixel_colors.is_empty() {
+        true => vec![
+            rgb(background).unwrap_or(DEFAULT_BACKGROUND),
+            rgb(foreground).unwrap_or(DEFAULT_FOREGROUND),
+        ],
+        false => pixel_colors
+            .iter()
+            .map(|color| rgb(*color).unwrap_or(DEFAULT_FOREGROUND))
+            .collect(),
+    }
+}
+
+// The RGB of a terminal color, None for the default colors of the terminal
+fn rgb(color: Color) -> Option<[u8; 3]> {
+    let index = match color {
+        Color::Reset => return None,
+        Color::Rgb(r, g, b) => return Some([r, g, b]),
+        Color::Indexed(index) => index,
+        Color::Black => 0,
+        Color::Red => 1,
+        Color::Green => 2,
+        Color::Yellow => 3,
+        Color::Blue => 4,
+        Color::Magenta => 5,
+        Color::Cyan => 6,
+        Color::Gray => 7,
+        Color::DarkGray => 8,
+        Color::LightRed => 9,
+        Color::LightGreen => 10,
+        Color::LightYellow => 11,
+        Color::LightBlue => 12,
+        Color::LightMagenta => 13,
+        Color::LightCyan => 14,
+        Color::White => 15,
+    } as usize;
+    // The 256 colors: the 16 colors, a 6x6x6 cube and 24 grays
+    let level = |value: usize| match value {
+        0 => 0,
+        _ => (55 + value * 40) as u8,
+    };
+    Some(match index {
+        0..=15 => ANSI[index],
+        16..=231 => {
+            let cube = index - 16;
+            [level(cube / 36), level(cube / 6 % 6), level(cube % 6)]
+        }
+        _ => [(8 + (index - 232) * 10) as u8; 3],
+    })
+}
+
+// The display scaled up as much as it fits in the frame, centered on the background. Values
+// past the end of the colors are drawn in the last one
+fn render(display: &[u8], (width, height): (usize, usize), colors: &[[u8; 3]]) -> Vec<u8> {
+    let scale = (VIDEO_WIDTH / width).min(VIDEO_HEIGHT / height).max(1);
+    let (image_width, image_height) = (
+        (width * scale).min(VIDEO_WIDTH),
+        (height * scale).min(VIDEO_HEIGHT),
+    );
+    let left = (VIDEO_WIDTH - image_width) / 2;
+    let top = (VIDEO_HEIGHT - image_height) / 2;
+    let mut frame = colors[0].repeat(VIDEO_WIDTH * VIDEO_HEIGHT);
+    for y in 0..image_height {
+        for x in 0..image_width {
+            let value = display[y / scale * width + x / scale] as usize;
+            let color = colors.get(value).unwrap_or(&colors[colors.len() - 1]);
+            let offset = ((top + y) * VIDEO_WIDTH + left + x) * 3;
+            frame[offset..offset + 3].copy_from_slice(color);
+        }
+    }
+    frame
+}
+
+// Samples as 16-bit little-endian PCM
+fn pcm(samples: &[f32]) -> Vec<u8> {
+    samples
+        .iter()
+        .flat_map(|sample| ((sample.clamp(-1.0, 1.0) * i16::MAX as f32) as i16).to_le_bytes())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render() {
+        let mut display = vec![0u8; 64 * 32];
+        display[0] = 1;
+        display[64 * 32 - 1] = 3;
+        let colors = colors(&[], Color::Rgb(1, 2, 3), Color::Black);
+        let frame = render(&display, (64, 32), &colors);
+        assert_eq!(frame.len(), VIDEO_WIDTH * VIDEO_HEIGHT * 3);
+        // 64x32 fills the frame with pixels of 12x12
+        let pixel = |x: usize, y: usize| &frame[(y * VIDEO_WIDTH + x) * 3..][..3];
+        assert_eq!(pixel(11, 11), [1, 2, 3]);
+        assert_eq!(pixel(12, 0), [0, 0, 0]);
+        assert_eq!(pixel(VIDEO_WIDTH - 1, VIDEO_HEIGHT - 1), [1, 2, 3]);
+
+        // 256x192 is centered
+        let display = vec![1u8; 256 * 192];
+        let colors = [[0, 0, 0], [9, 9, 9]];
+        let frame = render(&display, (256, 192), &colors);
+        let pixel = |x: usize, y: usize| &frame[(y * VIDEO_WIDTH + x) * 3..][..3];
+        assert_eq!(pixel(127, 0), [0, 0, 0]);
+        assert_eq!(pixel(128, 0), [9, 9, 9]);
+        assert_eq!(pixel(128 + 511, 383), [9, 9, 9]);
+        assert_eq!(pixel(128 + 512, 383), [0, 0, 0]);
+    }
+
+    #[test]
+    fn test_colors() {
+        assert_eq!(
+            colors(&[], Color::Reset, Color::Reset),
+            vec![DEFAULT_BACKGROUND, DEFAULT_FOREGROUND]
+        );
+        assert_eq!(rgb(Color::Green), Some([0x00, 0x80, 0x00]));
+        assert_eq!(rgb(Color::Indexed(196)), Some([0xFF, 0x00, 0x00]));
+        assert_eq!(rgb(Color::Indexed(232)), Some([8, 8, 8]));
+        assert_eq!(pcm(&[0.25, -2.0]), [0xFF, 0x1F, 0x01, 0x80]);
+    }
+}
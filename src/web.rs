@@ -0,0 +1,273 @@
+use crate::{
+    chip8::Chip8,
+    timing::{FrameBudget, Timing, FRAME_RATE},
+};
+use alloc::{format, vec::Vec};
+
+// The allocator and panic handler of the target, the core itself doesn't use std
+extern crate std;
+use std::sync::{Mutex, MutexGuard};
+
+/*
+Web frontend
+The machine behind an HTML canvas, for the wasm32-unknown-unknown target, built with the web
+feature (see the README). It's exported with the C ABI rather than through a bindings generator,
+so the page only needs the WebAssembly API of the browser, see web/index.html:
+- rustc8_start: a new machine running ipf instructions per frame, with RND seeded by the page as
+  the target has no source of entropy of its own
+- rustc8_buffer: room for len bytes in the memory of the module, for the page to write a ROM or
+  the code of a key into before rustc8_load or rustc8_key
+- rustc8_frame: runs a frame and draws the display, called 60 times a second
+- rustc8_pixels, rustc8_width and rustc8_height: the display as RGBA for an ImageData
+- rustc8_sound: whether the buzzer is on
+- rustc8_error: the error that stopped the machine, a NUL-terminated string
+The page has a single machine, as it has a single thread.
+Keys are mapped by KeyboardEvent.code, the position of the key rather than its letter, so the
+block of 1234 to ZXCV is the keypad on every layout.
+*/
+pub struct WebFrontend {
+    chip8: Chip8,
+    budget: FrameBudget,
+    seed: u64,
+    // Written by the page, see rustc8_buffer
+    buffer: Vec<u8>,
+    // RGBA, 4 bytes a pixel of the display
+    pixels: Vec<u8>,
+    foreground: [u8; 4],
+    background: [u8; 4],
+    // NUL-terminated, empty while running
+    error: Vec<u8>,
+}
+
+// KeyboardEvent.code of the keypad keys, row by row as on the COSMAC VIP, see input::Keymap
+const KEY_CODES: [(&str, u8); 16] = [
+    ("Digit1", 0x1),
+    ("Digit2", 0x2),
+    ("Digit3", 0x3),
+    ("Digit4", 0xC),
+    ("KeyQ", 0x4),
+    ("KeyW", 0x5),
+    ("KeyE", 0x6),
+    ("KeyR", 0xD),
+    ("KeyA", 0x7),
+    ("KeyS", 0x8),
+    ("KeyD", 0x9),
+    ("KeyF", 0xE),
+    ("KeyZ", 0xA),
+    ("KeyX", 0x0),
+    ("KeyC", 0xB),
+    ("KeyV", 0xF),
+];
+
+// Instructions per frame when the page gives none, as the terminal frontend
+const DEFAULT_IPF: u32 = 11;
+
+static FRONTEND: Mutex<Option<WebFrontend>> = Mutex::new(None);
+
+impl WebFrontend {
+    pub fn new(ipf: u32, seed: u64) -> Self {
+        let mut chip8 = Chip8::new();
+        chip8.seed_rng(seed);
+        WebFrontend {
+            chip8,
+            budget: FrameBudget::new(Timing::Fixed, ipf),
+            seed,
+            buffer: Vec::new(),
+            pixels: Vec::new(),
+            foreground: [0xFF; 4],
+            background: [0x00, 0x00, 0x00, 0xFF],
+            error: Vec::new(),
+        }
+    }
+
+    // Load a ROM into a new machine
+    pub fn load(&mut self, rom: &[u8]) -> bool {
+        self.chip8 = Chip8::new();
+        self.chip8.seed_rng(self.seed);
+        self.error.clear();
+        // Drawn by the first frame, even if the program doesn't draw
+        self.chip8.redraw_all();
+        match self.chip8.load_rom(rom) {
+            Ok(()) => true,
+            Err(e) => {
+                self.set_error(e);
+                false
+            }
+        }
+    }
+
+    // Press or release the key with the KeyboardEvent.code, returns whether it's on the keypad
+    pub fn key(&mut self, code: &str, down: bool) -> bool {
+        let Some(key) = keypad_key(code) else {
+            return false;
+        };
+        match down {
+            true => self.chip8.key_down(key),
+            // Held to the end of the frame, as in the terminal
+            false if self.chip8.keyboard[key as usize] => self.chip8.tap_key(key, 1),
+            false => {}
+        }
+        true
+    }
+
+    // Run a frame and draw the display, false once the machine has stopped with an error
+    pub fn frame(&mut self) -> bool {
+        if !self.error.is_empty() {
+            return false;
+        }
+        let result = self.chip8.run_frame(&mut self.budget, FRAME_RATE);
+        if let Some(display) = self.chip8.take_frame() {
+            self.pixels.clear();
+            self.pixels
+                .extend(display.iter().flat_map(|pixel| match pixel {
+                    0 => self.background,
+                    _ => self.foreground,
+                }));
+        }
+        match result {
+            Ok(()) => true,
+            Err(e) => {
+                self.set_error(e);
+                false
+            }
+        }
+    }
+
+    fn set_error(&mut self, e: impl core::fmt::Display) {
+        self.error = format!("{}\0", e).into_bytes();
+    }
+}
+
+pub fn keypad_key(code: &str) -> Option<u8> {
+    KEY_CODES
+        .iter()
+        .find(|(name, _)| *name == code)
+        .map(|(_, key)| *key)
+}
+
+fn lock() -> MutexGuard<'static, Option<WebFrontend>> {
+    FRONTEND.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+// Call f with the machine of the page, started with the defaults if the page didn't start one
+fn with<R>(f: impl FnOnce(&mut WebFrontend) -> R) -> R {
+    f(lock().get_or_insert_with(|| WebFrontend::new(DEFAULT_IPF, 0)))
+}
+
+// 0 for the default speed
+#[no_mangle]
+pub extern "C" fn rustc8_start(ipf: u32, seed: u32) {
+    let ipf = match ipf {
+        0 => DEFAULT_IPF,
+        ipf => ipf,
+    };
+    *lock() = Some(WebFrontend::new(ipf, seed.into()));
+}
+
+#[no_mangle]
+pub extern "C" fn rustc8_buffer(len: usize) -> *mut u8 {
+    with(|web| {
+        web.buffer.resize(len, 0);
+        web.buffer.as_mut_ptr()
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn rustc8_load() -> bool {
+    with(|web| {
+        let rom = core::mem::take(&mut web.buffer);
+        web.load(&rom)
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn rustc8_key(down: bool) -> bool {
+    with(|web| {
+        let code = core::mem::take(&mut web.buffer);
+        core::str::from_utf8(&code).is_ok_and(|code| web.key(code, down))
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn rustc8_frame() -> bool {
+    with(WebFrontend::frame)
+}
+
+#[no_mangle]
+pub extern "C" fn rustc8_pixels() -> *const u8 {
+    with(|web| web.pixels.as_ptr())
+}
+
+#[no_mangle]
+pub extern "C" fn rustc8_width() -> usize {
+    with(|web| web.chip8.display_width())
+}
+
+#[no_mangle]
+pub extern "C" fn rustc8_height() -> usize {
+    with(|web| web.chip8.display_height())
+}
+
+#[no_mangle]
+pub extern "C" fn rustc8_sound() -> bool {
+    with(|web| web.chip8.st > 0)
+}
+
+// Colors as 0xRRGGBB
+#[no_mangle]
+pub extern "C" fn rustc8_colors(foreground: u32, background: u32) {
+    let rgba = |color: u32| {
+        let [_, r, g, b] = color.to_be_bytes();
+        [r, g, b, 0xFF]
+    };
+    with(|web| {
+        web.foreground = rgba(foreground);
+        web.background = rgba(background);
+        web.chip8.redraw_all();
+    });
+}
+
+#[no_mangle]
+pub extern "C" fn rustc8_error() -> *const u8 {
+    with(|web| match web.error.is_empty() {
+        true => c"".as_ptr().cast(),
+        false => web.error.as_ptr(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_frontend() {
+        let mut web = WebFrontend::new(DEFAULT_IPF, 1);
+        // LD F, V0; DRW V0, V0, 5; an invalid opcode
+        assert!(web.load(&[0xF0, 0x29, 0xD0, 0x05, 0xFF, 0xFF]));
+        assert!(!web.frame());
+        assert_eq!(web.pixels.len(), 64 * 32 * 4);
+        assert_eq!(web.pixels[..4], [0xFF; 4]);
+        assert_eq!(web.pixels[4 * 4..5 * 4], [0x00, 0x00, 0x00, 0xFF]);
+        assert!(web.error.ends_with(b"\0"));
+        assert!(!web.frame());
+
+        assert!(web.key("KeyX", true));
+        assert!(web.chip8.keyboard[0x0]);
+        assert!(web.key("KeyX", false));
+        assert!(!web.key("KeyY", true));
+        assert!(web.load(&[0x12, 0x00]));
+        assert!(web.error.is_empty());
+        assert!(web.frame());
+        assert!(!web.load(&[]));
+    }
+
+    #[test]
+    fn test_key_codes() {
+        let mut keys: Vec<u8> = KEY_CODES.iter().map(|(_, key)| *key).collect();
+        keys.sort();
+        assert_eq!(keys, (0..16).collect::<Vec<u8>>());
+        assert_eq!(keypad_key("Digit4"), Some(0xC));
+        assert_eq!(keypad_key("KeyV"), Some(0xF));
+        assert_eq!(keypad_key("Escape"), None);
+    }
+}
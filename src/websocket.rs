@@ -0,0 +1,402 @@
+use std::{
+    io::{self, ErrorKind, Read, Write},
+    net::TcpStream,
+};
+
+// Appended to the key of the client in the handshake, see RFC 6455
+const GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+const BASE64: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+// Opcodes of the frames
+const CONTINUATION: u8 = 0x0;
+const TEXT: u8 = 0x1;
+const BINARY: u8 = 0x2;
+const CLOSE: u8 = 0x8;
+const PING: u8 = 0x9;
+const PONG: u8 = 0xA;
+
+// Largest message taken from a client, the server only expects small JSON-RPC requests
+const MAX_PAYLOAD: usize = 64 * 1024;
+// Largest frame taken from a client, MAX_PAYLOAD with the longest header and the mask
+const MAX_FRAME: usize = MAX_PAYLOAD + 14;
+// Most queued for a client before it's dropped as not reading, a few of the largest frames
+const MAX_QUEUE: usize = 1024 * 1024;
+
+pub enum Message {
+    Text(String),
+    Binary(Vec<u8>),
+}
+
+/*
+WebSocket
+The server side of a WebSocket connection (RFC 6455) over a TCP stream that doesn't block, for
+serve. The stream is polled like the spectators' (see spectate): receive reads what arrived and
+send queues a message that is written as the client takes it, is_idle tells whether it has
+caught up, so a slow client can be sent fewer frames rather than slowing the server. A client
+that lets more than MAX_QUEUE pile up is dropped.
+Pings are answered and a close is answered and ends the connection, as are frames the server
+doesn't take: fragmented messages (browsers don't fragment messages this small), unmasked frames
+and messages over MAX_PAYLOAD. No more than a frame is read ahead, the rest waits in the socket,
+so a client that keeps writing can't hold up the server.
+*/
+pub struct WebSocket {
+    stream: TcpStream,
+    // Received but not yet a whole frame
+    input: Vec<u8>,
+    // Queued but not yet written
+    output: Vec<u8>,
+    open: bool,
+}
+
+// Sec-WebSocket-Accept answering the Sec-WebSocket-Key of a client
+pub fn accept_key(key: &str) -> String {
+    base64(&sha1(format!("{}{}", key.trim(), GUID).as_bytes()))
+}
+
+impl WebSocket {
+    // Answer the upgrade request of a client that sent key, the stream no longer blocks
+    pub fn accept(stream: TcpStream, key: &str) -> io::Result<Self> {
+        stream.set_nonblocking(true)?;
+        let handshake = format!(
+            "HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\nConnection: Upgrade\r\nSec-WebSocket-Accept: {}\r\n\r\n",
+            accept_key(key)
+        );
+        let mut socket = WebSocket {
+            stream,
+            input: Vec::new(),
+            output: handshake.into_bytes(),
+            open: true,
+        };
+        socket.flush();
+        Ok(socket)
+    }
+
+    pub fn is_open(&self) -> bool {
+        self.open
+    }
+
+    // Whether everything sent has been written
+    pub fn is_idle(&self) -> bool {
+        self.output.is_empty()
+    }
+
+    pub fn send(&mut self, message: Message) {
+        if !self.open {
+            return;
+        }
+        let frame = match message {
+            Message::Text(text) => encode(TEXT, text.as_bytes()),
+            Message::Binary(data) => encode(BINARY, &data),
+        };
+        self.output.extend_from_slice(&frame);
+        self.flush();
+        if self.output.len() > MAX_QUEUE {
+            self.output.clear();
+            self.open = false;
+        }
+    }
+
+    // The messages received since the last call
+    pub fn receive(&mut self) -> Vec<Message> {
+        let mut buffer = [0; 4096];
+        while self.open && self.input.len() < MAX_FRAME {
+            match self.stream.read(&mut buffer) {
+                Ok(0) => self.open = false,
+                Ok(len) => self.input.extend_from_slice(&buffer[..len]),
+                Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+                Err(e) if e.kind() == ErrorKind::Interrupted => {}
+                Err(_) => self.open = false,
+            }
+        }
+
+        let mut messages = Vec::new();
+        let mut start = 0;
+        while self.open {
+            let (opcode, payload, len) = match decode(&self.input[start..]) {
+                Ok(Some(frame)) => frame,
+                Ok(None) => break,
+                Err(_) => {
+                    self.close();
+                    break;
+                }
+            };
+            start += len;
+            match opcode {
+                TEXT => match String::from_utf8(payload) {
+                    Ok(text) => messages.push(Message::Text(text)),
+                    Err(_) => self.close(),
+                },
+                BINARY => messages.push(Message::Binary(payload)),
+                PING => self.output.extend_from_slice(&encode(PONG, &payload)),
+                PONG => {}
+                _ => self.close(),
+            }
+        }
+        self.input.drain(..start);
+        self.flush();
+        messages
+    }
+
+    fn close(&mut self) {
+        self.output.extend_from_slice(&encode(CLOSE, &[]));
+        self.flush();
+        self.open = false;
+    }
+
+    // Write what the client takes without blocking
+    fn flush(&mut self) {
+        while !self.output.is_empty() {
+            match self.stream.write(&self.output) {
+                Ok(0) => self.open = false,
+                Ok(len) => {
+                    self.output.drain(..len);
+                    continue;
+                }
+                Err(e) if e.kind() == ErrorKind::WouldBlock => {}
+                Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+                Err(_) => self.open = false,
+            }
+            break;
+        }
+    }
+}
+
+// A frame from the server, which doesn't mask them
+fn encode(opcode: u8, payload: &[u8]) -> Vec<u8> {
+    let mut frame = vec![0x80 | opcode];
+    match payload.len() {
+        len @ 0..=125 => frame.push(len as u8),
+        len @ 126..=0xFFFF => {
+            frame.push(126);
+            frame.extend_from_slice(&(len as u16).to_be_bytes());
+        }
+        len => {
+            frame.push(127);
+            frame.extend_from_slice(&(len as u64).to_be_bytes());
+        }
+    }
+    frame.extend_from_slice(payload);
+    frame
+}
+
+// The opcode, the unmasked payload and the bytes taken by a frame
+type Frame = (u8, Vec<u8>, usize);
+
+// The frame from a client at the start of data, None until it has all arrived
+fn decode(data: &[u8]) -> Result<Option<Frame>, &'static str> {
+    let [first, second, ..] = data[..] else {
+        return Ok(None);
+    };
+    let opcode = first & 0x0F;
+    if first & 0x80 == 0 || opcode == CONTINUATION {
+        return Err("Fragmented messages aren't supported");
+    }
+    if second & 0x80 == 0 {
+        return Err("Frames from clients must be masked");
+    }
+    let (len, mut offset) = match second & 0x7F {
+        126 => match data.get(2..4) {
+            Some(bytes) => (u16::from_be_bytes([bytes[0], bytes[1]]) as u64, 4),
+            None => return Ok(None),
+        },
+        127 => match data.get(2..10) {
+            Some(bytes) => (u64::from_be_bytes(bytes.try_into().unwrap()), 10),
+            None => return Ok(None),
+        },
+        len => (len as u64, 2),
+    };
+    if len > MAX_PAYLOAD as u64 {
+        return Err("Message too large");
+    }
+    let len = len as usize;
+    let Some(mask) = data.get(offset..offset + 4) else {
+        return Ok(None);
+    };
+    offset += 4;
+    let Some(payload) = data.get(offset..offset + len) else {
+        return Ok(None);
+    };
+    let payload = payload
+        .iter()
+        .enumerate()
+        .map(|(index, byte)| byte ^ mask[index % 4])
+        .collect();
+    Ok(Some((opcode, payload, offset + len)))
+}
+
+fn sha1(data: &[u8]) -> [u8; 20] {
+    let mut state: [u32; 5] = [0x67452301, 0xEFCDAB89, 0x98BADCFE, 0x10325476, 0xC3D2E1F0];
+    let mut message = data.to_vec();
+    message.push(0x80);
+    while message.len() % 64 != 56 {
+        message.push(0);
+    }
+    message.extend_from_slice(&(data.len() as u64 * 8).to_be_bytes());
+
+    for block in message.chunks(64) {
+        let mut words = [0u32; 80];
+        for (word, bytes) in words.iter_mut().zip(block.chunks(4)) {
+            *word = u32::from_be_bytes(bytes.try_into().unwrap());
+        }
+        for t in 16..80 {
+            words[t] = (words[t - 3] ^ words[t - 8] ^ words[t - 14] ^ words[t - 16]).rotate_left(1);
+        }
+        let [mut a, mut b, mut c, mut d, mut e] = state;
+        for (t, word) in words.iter().enumerate() {
+            let (f, k) = match t {
+                0..=19 => ((b & c) | (!b & d), 0x5A827999),
+                20..=39 => (b ^ c ^ d, 0x6ED9EBA1),
+                40..=59 => ((b & c) | (b & d) | (c & d), 0x8F1BBCDC),
+                _ => (b ^ c ^ d, 0xCA62C1D6),
+            };
+            let temp = a
+                .rotate_left(5)
+                .wrapping_add(f)
+                .wrapping_add(e)
+                .wrapping_add(k)
+                .wrapping_add(*word);
+            e = d;
+            d = c;
+            c = b.rotate_left(30);
+            b = a;
+            a = temp;
+        }
+        for (value, add) in state.iter_mut().zip([a, b, c, d, e]) {
+            *value = value.wrapping_add(add);
+        }
+    }
+
+    let mut digest = [0; 20];
+    for (bytes, value) in digest.chunks_mut(4).zip(state) {
+        bytes.copy_from_slice(&value.to_be_bytes());
+    }
+    digest
+}
+
+fn base64(data: &[u8]) -> String {
+    let mut text = String::new();
+    for chunk in data.chunks(3) {
+        let bits = chunk.iter().enumerate().fold(0u32, |bits, (index, byte)| {
+            bits | (*byte as u32) << (16 - 8 * index)
+        });
+        for index in 0..4 {
+            text.push(match index <= chunk.len() {
+                true => BASE64[(bits >> (18 - 6 * index) & 0x3F) as usize] as char,
+                false => '=',
+            });
+        }
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_accept_key() {
+        let hex = |digest: [u8; 20]| -> String {
+            digest.iter().map(|byte| format!("{:02x}", byte)).collect()
+        };
+        assert_eq!(
+            hex(sha1(b"abc")),
+            "a9993e364706816aba3e25717850c26c9cd0d89d"
+        );
+        assert_eq!(
+            hex(sha1(&[b'a'; 100])),
+            "7f9000257a4918d7072655ea468540cdcbd42e0c"
+        );
+        assert_eq!(base64(b"ab"), "YWI=");
+        assert_eq!(base64(b"abcd"), "YWJjZA==");
+        // The example of RFC 6455
+        assert_eq!(
+            accept_key("dGhlIHNhbXBsZSBub25jZQ=="),
+            "s3pPLMBiTxaQ9kYGzzhZRbK+xOo="
+        );
+    }
+
+    // A frame as a client sends it, masked
+    fn client_frame(opcode: u8, payload: &[u8]) -> Vec<u8> {
+        let mask = [0x12, 0x34, 0x56, 0x78];
+        let mut frame = encode(opcode, payload);
+        let header = frame.len() - payload.len();
+        frame[1] |= 0x80;
+        frame.truncate(header);
+        frame.extend_from_slice(&mask);
+        frame.extend(payload.iter().zip(mask.iter().cycle()).map(|(b, m)| b ^ m));
+        frame
+    }
+
+    #[test]
+    fn test_frames() {
+        assert_eq!(encode(TEXT, b"hi"), [0x81, 2, b'h', b'i']);
+        assert_eq!(encode(BINARY, &[0; 300])[..4], [0x82, 126, 0x01, 0x2C]);
+
+        let frame = client_frame(TEXT, b"hello");
+        assert_eq!(
+            decode(&frame),
+            Ok(Some((TEXT, b"hello".to_vec(), frame.len())))
+        );
+        assert_eq!(decode(&frame[..frame.len() - 1]), Ok(None));
+        let frame = client_frame(BINARY, &[7; 200]);
+        assert_eq!(
+            decode(&frame),
+            Ok(Some((BINARY, vec![7; 200], frame.len())))
+        );
+        assert_eq!(decode(&frame[..3]), Ok(None));
+
+        assert!(decode(&encode(TEXT, b"unmasked")).is_err());
+        let mut fragment = client_frame(TEXT, b"part");
+        fragment[0] &= 0x7F;
+        assert!(decode(&fragment).is_err());
+        assert!(decode(&[0x81, 0xFF, 0, 0, 0, 0, 0, 2, 0, 0]).is_err());
+    }
+
+    #[test]
+    fn test_queue_limit() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (stream, _) = listener.accept().unwrap();
+        let mut socket = WebSocket::accept(stream, "dGhlIHNhbXBsZSBub25jZQ==").unwrap();
+        // A client that never reads is dropped once the queue is full, not buffered forever
+        let text = "x".repeat(MAX_PAYLOAD);
+        for _ in 0..10_000 {
+            if !socket.is_open() {
+                break;
+            }
+            socket.send(Message::Text(text.clone()));
+        }
+        assert!(!socket.is_open());
+        assert!(socket.is_idle());
+        drop(client);
+    }
+
+    #[test]
+    fn test_read_ahead_limit() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let mut client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (stream, _) = listener.accept().unwrap();
+        let mut socket = WebSocket::accept(stream, "dGhlIHNhbXBsZSBub25jZQ==").unwrap();
+        let frame = client_frame(BINARY, &[7; MAX_PAYLOAD]);
+        let writer = std::thread::spawn(move || {
+            for _ in 0..4 {
+                client.write_all(&frame).unwrap();
+            }
+            client
+        });
+
+        // The messages are taken a frame at a time however fast they're written, the rest are
+        // left in the socket for later calls
+        let deadline = std::time::Instant::now() + std::time::Duration::from_secs(5);
+        let mut received = 0;
+        while received < 4 && std::time::Instant::now() < deadline {
+            let messages = socket.receive();
+            assert!(messages.len() <= 1);
+            assert!(socket.input.len() < MAX_FRAME + 4096);
+            received += messages.len();
+        }
+        assert_eq!(received, 4);
+        assert!(socket.is_open());
+        drop(writer.join().unwrap());
+    }
+}
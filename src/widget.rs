@@ -0,0 +1,594 @@
+use ratatui::{
+    buffer::Buffer,
+    layout::Rect,
+    style::{Color, Style},
+    symbols::Marker,
+    widgets::{
+        canvas::{Canvas, Points},
+        Block, Paragraph, StatefulWidget, Widget,
+    },
+};
+
+use crate::chip8::{display_size, Chip8, DISPLAY_HEIGHT, DISPLAY_WIDTH};
+
+/*
+Renderers:
+- Text: one terminal cell per pixel, drawn as a Paragraph of "█" and " "
+- Braille: ratatui Canvas with braille markers (2x4 dots per cell)
+- HalfBlock: ratatui Canvas with half block markers (1x2 pixels per cell)
+- Ascii: like Text with "#" and ".", for terminals without Unicode
+The Canvas renderers map the 64x32 display (or 64x64, 256x192) onto the available area,
+so scaling and aspect ratio are handled by ratatui rather than by us.
+Terminal cells are about twice as tall as they are wide, so Text and Ascii can draw each pixel
+over several columns (see pixel_width) to keep square pixels square.
+*/
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Renderer {
+    #[default]
+    Text,
+    Braille,
+    HalfBlock,
+    Ascii,
+}
+
+impl Renderer {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "text" => Some(Renderer::Text),
+            "braille" => Some(Renderer::Braille),
+            "halfblock" => Some(Renderer::HalfBlock),
+            "ascii" => Some(Renderer::Ascii),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Renderer::Text => "text",
+            Renderer::Braille => "braille",
+            Renderer::HalfBlock => "halfblock",
+            Renderer::Ascii => "ascii",
+        }
+    }
+}
+
+/*
+Rotation of the display, clockwise
+Rotating by 90 or 270 degrees turns the 64x32 display into a 32x64 one,
+which fits tall and narrow terminals (e.g. a tmux side pane) better.
+*/
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Rotation {
+    #[default]
+    None,
+    Clockwise90,
+    Clockwise270,
+}
+
+impl Rotation {
+    pub fn from_degrees(degrees: u32) -> Option<Self> {
+        match degrees {
+            0 => Some(Rotation::None),
+            90 => Some(Rotation::Clockwise90),
+            270 => Some(Rotation::Clockwise270),
+            _ => None,
+        }
+    }
+
+    // Size of a display of this size (width, height) once rotated
+    fn size(&self, (width, height): (usize, usize)) -> (usize, usize) {
+        match self {
+            Rotation::None => (width, height),
+            Rotation::Clockwise90 | Rotation::Clockwise270 => (height, width),
+        }
+    }
+
+    // Maps a coordinate of the rotated display back to the framebuffer
+    fn source(&self, x: usize, y: usize, (width, height): (usize, usize)) -> (usize, usize) {
+        match self {
+            Rotation::None => (x, y),
+            Rotation::Clockwise90 => (y, height - 1 - x),
+            Rotation::Clockwise270 => (width - 1 - y, x),
+        }
+    }
+}
+
+/*
+Chip8DisplayWidget draws a Chip-8 framebuffer into any ratatui layout.
+It can be used in two ways:
+- As a Widget over a borrowed framebuffer:
+    frame.render_widget(Chip8DisplayWidget::new(&chip8.display), area);
+- As a StatefulWidget over the core itself, reading the framebuffer at render time:
+    frame.render_stateful_widget(Chip8DisplayWidget::default(), area, &mut chip8);
+*/
+#[derive(Clone, Debug)]
+pub struct Chip8DisplayWidget<'a> {
+    // Framebuffer row by row, sized as in chip8::display_size, a pixel is lit when it isn't 0
+    // Left empty when the widget is rendered as a StatefulWidget
+    display: &'a [u8],
+    renderer: Renderer,
+    rotation: Rotation,
+    // Number of terminal columns per pixel for the Text and Ascii renderers
+    pixel_width: usize,
+    // Colors of the lit pixels and of the rest of the display, Reset for those of the terminal
+    foreground: Color,
+    background: Color,
+    // Colors of the lit pixels by their value, see pixel_colors
+    pixel_colors: &'a [Color],
+    block: Option<Block<'a>>,
+}
+
+impl Default for Chip8DisplayWidget<'_> {
+    fn default() -> Self {
+        Chip8DisplayWidget {
+            display: &[],
+            renderer: Renderer::default(),
+            rotation: Rotation::default(),
+            pixel_width: 1,
+            foreground: Color::Reset,
+            background: Color::Reset,
+            pixel_colors: &[],
+            block: None,
+        }
+    }
+}
+
+impl<'a> Chip8DisplayWidget<'a> {
+    pub fn new(display: &'a [u8]) -> Self {
+        Chip8DisplayWidget {
+            display,
+            ..Default::default()
+        }
+    }
+
+    pub fn renderer(mut self, renderer: Renderer) -> Self {
+        self.renderer = renderer;
+        self
+    }
+
+    pub fn rotation(mut self, rotation: Rotation) -> Self {
+        self.rotation = rotation;
+        self
+    }
+
+    // Draw each pixel over this many columns, 2 makes them roughly square in most terminals
+    pub fn pixel_width(mut self, pixel_width: usize) -> Self {
+        self.pixel_width = pixel_width.max(1);
+        self
+    }
+
+    // The block is drawn in its own colors, only the display inside it is colored
+    pub fn colors(mut self, foreground: Color, background: Color) -> Self {
+        self.foreground = foreground;
+        self.background = background;
+        self
+    }
+
+    /*
+    Colors of the pixels by their value, for displays of indexed colors such as MEGA-CHIP's,
+    e.g. pixel_colors(&megachip.colors()). Values past the end are drawn in the foreground color.
+    The Braille renderer colors a character by one of its dots, and to_text has no colors.
+    */
+    pub fn pixel_colors(mut self, pixel_colors: &'a [Color]) -> Self {
+        self.pixel_colors = pixel_colors;
+        self
+    }
+
+    pub fn block(mut self, block: Block<'a>) -> Self {
+        self.block = Some(block);
+        self
+    }
+
+    /*
+    Plain text art of the display, using the glyphs of the renderer:
+    - Text: "█" per lit pixel, repeated pixel_width times
+    - Ascii: "#" per lit pixel and "." per unlit one, repeated pixel_width times
+    - HalfBlock: "▀", "▄" and "█" for 1x2 pixels per character
+    - Braille: braille patterns for 2x4 pixels per character
+    Trailing spaces are removed from each line, which keeps pasted text tidy.
+    */
+    pub fn to_text(&self) -> String {
+        let (width, height) = self.rotation.size(self.size());
+        let (cell_width, cell_height, repeat) = match self.renderer {
+            Renderer::Text | Renderer::Ascii => (1, 1, self.pixel_width),
+            Renderer::HalfBlock => (1, 2, 1),
+            Renderer::Braille => (2, 4, 1),
+        };
+        let mut text = String::new();
+        for row in (0..height).step_by(cell_height) {
+            let mut line = String::new();
+            for column in (0..width).step_by(cell_width) {
+                let pixel = |dx: usize, dy: usize| self.pixel(column + dx, row + dy);
+                let glyph = match self.renderer {
+                    Renderer::Text => {
+                        if pixel(0, 0) {
+                            '█'
+                        } else {
+                            ' '
+                        }
+                    }
+                    Renderer::Ascii => {
+                        if pixel(0, 0) {
+                            '#'
+                        } else {
+                            '.'
+                        }
+                    }
+                    Renderer::HalfBlock => match (pixel(0, 0), pixel(0, 1)) {
+                        (true, true) => '█',
+                        (true, false) => '▀',
+                        (false, true) => '▄',
+                        (false, false) => ' ',
+                    },
+                    Renderer::Braille => {
+                        // Dot numbering of the braille patterns block, column by column then the bottom row
+                        const DOTS: [(usize, usize, u32); 8] = [
+                            (0, 0, 0x01),
+                            (0, 1, 0x02),
+                            (0, 2, 0x04),
+                            (1, 0, 0x08),
+                            (1, 1, 0x10),
+                            (1, 2, 0x20),
+                            (0, 3, 0x40),
+                            (1, 3, 0x80),
+                        ];
+                        let bits = DOTS
+                            .iter()
+                            .filter(|(dx, dy, _)| pixel(*dx, *dy))
+                            .fold(0, |bits, (_, _, bit)| bits | bit);
+                        // Blank cells as spaces rather than the empty pattern, so they are trimmed too
+                        match bits {
+                            0 => ' ',
+                            _ => char::from_u32(0x2800 + bits).unwrap_or(' '),
+                        }
+                    }
+                };
+                line.extend(std::iter::repeat_n(glyph, repeat));
+            }
+            text.push_str(line.trim_end_matches(' '));
+            text.push('\n');
+        }
+        text
+    }
+
+    // Draw the block and return the area left inside it for the display
+    fn render_block(&mut self, area: Rect, buf: &mut Buffer) -> Rect {
+        match self.block.take() {
+            Some(block) => {
+                let inner = block.inner(area);
+                block.render(area, buf);
+                inner
+            }
+            None => area,
+        }
+    }
+
+    fn render_text(mut self, area: Rect, buf: &mut Buffer) {
+        let area = self.render_block(area, buf);
+        Paragraph::new(self.to_text())
+            .style(Style::default().fg(self.foreground).bg(self.background))
+            .render(area, buf);
+        if self.pixel_colors.is_empty() {
+            return;
+        }
+        let (width, height) = self.rotation.size(self.size());
+        let columns = (width * self.pixel_width).min(area.width as usize);
+        for y in 0..height.min(area.height as usize) {
+            for column in 0..columns {
+                let color = self.color(column / self.pixel_width, y);
+                buf[(area.x + column as u16, area.y + y as u16)].set_fg(color);
+            }
+        }
+    }
+
+    fn render_canvas(mut self, area: Rect, buf: &mut Buffer, marker: Marker) {
+        let area = self.render_block(area, buf);
+        // Canvas coordinates have the origin at the bottom left, so the display rows are flipped
+        // Each pixel is placed at the centre of its cell so that rounding never pushes it into a neighbour
+        let (width, height) = self.rotation.size(self.size());
+        // The points of each color, in the order the colors first appear
+        let mut points: Vec<(Color, Vec<(f64, f64)>)> = Vec::new();
+        for y in 0..height {
+            for x in 0..width {
+                if !self.pixel(x, y) {
+                    continue;
+                }
+                let color = self.color(x, y);
+                let coord = (x as f64 + 0.5, (height - 1 - y) as f64 + 0.5);
+                match points.iter_mut().find(|(c, _)| *c == color) {
+                    Some((_, coords)) => coords.push(coord),
+                    None => points.push((color, vec![coord])),
+                }
+            }
+        }
+
+        // The half block grid leaves cells painted with Reset blank, it needs a color of its own
+        let color = |color: Color| match (marker, color) {
+            (Marker::HalfBlock, Color::Reset) => Color::White,
+            (_, color) => color,
+        };
+        Canvas::default()
+            .marker(marker)
+            .background_color(self.background)
+            .x_bounds([0.0, width as f64])
+            .y_bounds([0.0, height as f64])
+            .paint(|ctx| {
+                for (c, coords) in &points {
+                    ctx.draw(&Points {
+                        coords,
+                        color: color(*c),
+                    });
+                }
+            })
+            .render(area, buf);
+    }
+
+    // Width and height of the framebuffer, an empty one is drawn as a blank 64x32 display
+    fn size(&self) -> (usize, usize) {
+        match display_size(self.display.len()) {
+            (_, 0) => (DISPLAY_WIDTH, DISPLAY_HEIGHT),
+            size => size,
+        }
+    }
+
+    // Value of the pixel at a coordinate of the rotated display
+    fn value(&self, x: usize, y: usize) -> u8 {
+        let size = self.size();
+        let (x, y) = self.rotation.source(x, y, size);
+        // Out of range reads are treated as unlit so an empty framebuffer renders as a blank screen
+        self.display.get(y * size.0 + x).copied().unwrap_or(0)
+    }
+
+    fn pixel(&self, x: usize, y: usize) -> bool {
+        self.value(x, y) != 0
+    }
+
+    // Color of the pixel at a coordinate of the rotated display
+    fn color(&self, x: usize, y: usize) -> Color {
+        match self.value(x, y) {
+            0 => self.background,
+            value => self
+                .pixel_colors
+                .get(value as usize)
+                .copied()
+                .unwrap_or(self.foreground),
+        }
+    }
+}
+
+impl Widget for Chip8DisplayWidget<'_> {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        match self.renderer {
+            Renderer::Text | Renderer::Ascii => self.render_text(area, buf),
+            Renderer::Braille => self.render_canvas(area, buf, Marker::Braille),
+            Renderer::HalfBlock => self.render_canvas(area, buf, Marker::HalfBlock),
+        }
+    }
+}
+
+impl StatefulWidget for Chip8DisplayWidget<'_> {
+    type State = Chip8;
+
+    fn render(self, area: Rect, buf: &mut Buffer, state: &mut Self::State) {
+        let widget = Chip8DisplayWidget {
+            display: &state.display,
+            renderer: self.renderer,
+            rotation: self.rotation,
+            pixel_width: self.pixel_width,
+            foreground: self.foreground,
+            background: self.background,
+            pixel_colors: self.pixel_colors,
+            block: self.block,
+        };
+        Widget::render(widget, area, buf);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_text() {
+        let mut display = [0; DISPLAY_WIDTH * DISPLAY_HEIGHT];
+        display[0] = 1;
+        display[DISPLAY_WIDTH + 2] = 1;
+
+        let area = Rect::new(0, 0, DISPLAY_WIDTH as u16, DISPLAY_HEIGHT as u16);
+        let mut buf = Buffer::empty(area);
+        Widget::render(Chip8DisplayWidget::new(&display), area, &mut buf);
+
+        assert_eq!(buf[(0, 0)].symbol(), "█");
+        assert_eq!(buf[(1, 0)].symbol(), " ");
+        assert_eq!(buf[(2, 1)].symbol(), "█");
+    }
+
+    #[test]
+    fn test_render_halfblock() {
+        let mut display = [0; DISPLAY_WIDTH * DISPLAY_HEIGHT];
+        display[0] = 1;
+
+        let area = Rect::new(0, 0, DISPLAY_WIDTH as u16, DISPLAY_HEIGHT as u16 / 2);
+        let mut buf = Buffer::empty(area);
+        let widget = Chip8DisplayWidget::new(&display).renderer(Renderer::HalfBlock);
+        Widget::render(widget, area, &mut buf);
+
+        assert_eq!(buf[(0, 0)].symbol(), "▀");
+        assert_eq!(buf[(1, 0)].symbol(), " ");
+    }
+
+    #[test]
+    fn test_colors() {
+        let mut display = [0; DISPLAY_WIDTH * DISPLAY_HEIGHT];
+        display[0] = 1;
+        let area = Rect::new(0, 0, DISPLAY_WIDTH as u16 + 2, DISPLAY_HEIGHT as u16 + 2);
+
+        for renderer in [Renderer::Text, Renderer::HalfBlock] {
+            let mut buf = Buffer::empty(area);
+            let widget = Chip8DisplayWidget::new(&display)
+                .renderer(renderer)
+                .colors(Color::Yellow, Color::Blue)
+                .block(Block::bordered());
+            Widget::render(widget, area, &mut buf);
+            assert_eq!(buf[(1, 1)].fg, Color::Yellow);
+            assert_eq!(buf[(2, 1)].bg, Color::Blue);
+            // The border keeps the colors of the terminal
+            assert_eq!(
+                (buf[(0, 0)].fg, buf[(0, 0)].bg),
+                (Color::Reset, Color::Reset)
+            );
+        }
+    }
+
+    #[test]
+    fn test_pixel_colors() {
+        let mut display = vec![0; 256 * 192];
+        display[0] = 1;
+        display[1] = 2;
+        display[2] = 3;
+        let colors = [Color::Black, Color::Red, Color::Green];
+        let area = Rect::new(0, 0, 256, 192);
+
+        for renderer in [Renderer::Text, Renderer::HalfBlock] {
+            let mut buf = Buffer::empty(area);
+            let widget = Chip8DisplayWidget::new(&display)
+                .renderer(renderer)
+                .colors(Color::Yellow, Color::Reset)
+                .pixel_colors(&colors);
+            Widget::render(widget, area, &mut buf);
+            assert_eq!(buf[(0, 0)].fg, Color::Red);
+            assert_eq!(buf[(1, 0)].fg, Color::Green);
+            // Past the colors
+            assert_eq!(buf[(2, 0)].fg, Color::Yellow);
+        }
+        let text = Chip8DisplayWidget::new(&display).to_text();
+        assert_eq!(text.lines().count(), 192);
+        assert_eq!(text.lines().next(), Some("███"));
+    }
+
+    #[test]
+    fn test_to_text() {
+        let mut display = [0; DISPLAY_WIDTH * DISPLAY_HEIGHT];
+        display[0] = 1;
+        display[DISPLAY_WIDTH + 1] = 1;
+        display[3 * DISPLAY_WIDTH + 1] = 1;
+
+        let text = Chip8DisplayWidget::new(&display).to_text();
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(lines.len(), DISPLAY_HEIGHT);
+        assert_eq!(lines[0], "█");
+        assert_eq!(lines[1], " █");
+        assert_eq!(lines[2], "");
+
+        let text = Chip8DisplayWidget::new(&display)
+            .renderer(Renderer::HalfBlock)
+            .to_text();
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(lines.len(), DISPLAY_HEIGHT / 2);
+        assert_eq!(lines[0], "▀▄");
+        assert_eq!(lines[1], " ▄");
+
+        let text = Chip8DisplayWidget::new(&display)
+            .renderer(Renderer::Braille)
+            .to_text();
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(lines.len(), DISPLAY_HEIGHT / 4);
+        // Dots 1, 5 and 8
+        assert!(lines[0].starts_with('\u{2891}'));
+
+        let text = Chip8DisplayWidget::new(&display)
+            .renderer(Renderer::Ascii)
+            .to_text();
+        assert!(text.starts_with("#....."));
+    }
+
+    #[test]
+    fn test_pixel_width() {
+        let mut display = [0; DISPLAY_WIDTH * DISPLAY_HEIGHT];
+        display[1] = 1;
+
+        let area = Rect::new(0, 0, 2 * DISPLAY_WIDTH as u16, DISPLAY_HEIGHT as u16);
+        let mut buf = Buffer::empty(area);
+        Widget::render(
+            Chip8DisplayWidget::new(&display).pixel_width(2),
+            area,
+            &mut buf,
+        );
+        assert_eq!(buf[(1, 0)].symbol(), " ");
+        assert_eq!(buf[(2, 0)].symbol(), "█");
+        assert_eq!(buf[(3, 0)].symbol(), "█");
+        assert_eq!(buf[(4, 0)].symbol(), " ");
+
+        let text = Chip8DisplayWidget::new(&display)
+            .renderer(Renderer::Ascii)
+            .pixel_width(2)
+            .to_text();
+        assert!(text.starts_with("..##.."));
+        assert_eq!(text.lines().next().unwrap().len(), 2 * DISPLAY_WIDTH);
+
+        // Canvas renderers already have square pixels
+        let text = Chip8DisplayWidget::new(&display)
+            .renderer(Renderer::HalfBlock)
+            .pixel_width(2)
+            .to_text();
+        assert_eq!(text.lines().next().unwrap(), " ▀");
+    }
+
+    #[test]
+    fn test_render_rotated() {
+        let mut display = [0; DISPLAY_WIDTH * DISPLAY_HEIGHT];
+        // Top left and top right corners
+        display[0] = 1;
+        display[DISPLAY_WIDTH - 1] = 1;
+
+        let area = Rect::new(0, 0, DISPLAY_HEIGHT as u16, DISPLAY_WIDTH as u16);
+        let last_x = DISPLAY_HEIGHT as u16 - 1;
+        let last_y = DISPLAY_WIDTH as u16 - 1;
+
+        // Clockwise, the top row becomes the right column
+        let mut buf = Buffer::empty(area);
+        let widget = Chip8DisplayWidget::new(&display).rotation(Rotation::Clockwise90);
+        Widget::render(widget, area, &mut buf);
+        assert_eq!(buf[(last_x, 0)].symbol(), "█");
+        assert_eq!(buf[(last_x, last_y)].symbol(), "█");
+        assert_eq!(buf[(0, 0)].symbol(), " ");
+
+        // Anticlockwise, the top row becomes the left column
+        let mut buf = Buffer::empty(area);
+        let widget = Chip8DisplayWidget::new(&display).rotation(Rotation::Clockwise270);
+        Widget::render(widget, area, &mut buf);
+        assert_eq!(buf[(0, 0)].symbol(), "█");
+        assert_eq!(buf[(0, last_y)].symbol(), "█");
+        assert_eq!(buf[(last_x, 0)].symbol(), " ");
+    }
+
+    #[test]
+    fn test_hires() {
+        let mut display = vec![0; DISPLAY_WIDTH * 64];
+        display[63 * DISPLAY_WIDTH] = 1;
+
+        let text = Chip8DisplayWidget::new(&display).to_text();
+        assert_eq!(text.lines().count(), 64);
+        assert_eq!(text.lines().last(), Some("█"));
+
+        // Rotated, the bottom row becomes the left column
+        let text = Chip8DisplayWidget::new(&display)
+            .rotation(Rotation::Clockwise90)
+            .to_text();
+        assert_eq!(text.lines().next(), Some("█"));
+        assert_eq!(text.lines().count(), DISPLAY_WIDTH);
+    }
+
+    #[test]
+    fn test_render_stateful() {
+        let mut chip8 = Chip8::new();
+        chip8.display[DISPLAY_WIDTH - 1] = 1;
+
+        let area = Rect::new(0, 0, DISPLAY_WIDTH as u16, DISPLAY_HEIGHT as u16);
+        let mut buf = Buffer::empty(area);
+        StatefulWidget::render(Chip8DisplayWidget::default(), area, &mut buf, &mut chip8);
+
+        assert_eq!(buf[(DISPLAY_WIDTH as u16 - 1, 0)].symbol(), "█");
+        assert_eq!(buf[(0, 0)].symbol(), " ");
+    }
+}